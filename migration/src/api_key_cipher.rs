@@ -0,0 +1,157 @@
+//! API 密钥加密的本地密钥文件与加解密
+//!
+//! `utils::secret` / `utils::crypto` 与迁移 `m000013_encrypt_ai_channel_keys` 共用这里的实现，
+//! 保证密钥文件的处理、密钥派生与存储格式始终一致：数据目录下的 `.api_key_secret`
+//! 经 HKDF-SHA256 派生 AES-256-GCM 密钥，存储为 `enc:v1:` + base64(nonce || 密文)，
+//! 没有该前缀的值视为旧版明文。
+//!
+//! 密钥文件丢失或被替换后，已加密的 API 密钥将无法解密，因此这里只在文件不存在时创建，
+//! 读取或写入失败一律返回错误，绝不覆盖已有文件。
+
+use std::fmt;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hkdf::Hkdf;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use sha2::Sha256;
+
+/// 本地密钥文件名（位于数据目录）
+pub const SECRET_FILE: &str = ".api_key_secret";
+/// 加密值的前缀
+pub const ENCRYPTED_PREFIX: &str = "enc:v1:";
+const HKDF_INFO: &[u8] = b"piney ai_channels.api_key v1";
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug)]
+pub enum CipherError {
+    /// 密钥文件无法读取、创建或内容为空
+    Secret(String),
+    KeyDerivation,
+    Encrypt,
+    Malformed,
+    Decrypt,
+}
+
+impl fmt::Display for CipherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Secret(e) => write!(f, "API 密钥加密密钥不可用: {}", e),
+            Self::KeyDerivation => write!(f, "密钥派生失败"),
+            Self::Encrypt => write!(f, "加密失败"),
+            Self::Malformed => write!(f, "密文格式无效"),
+            Self::Decrypt => write!(f, "解密失败，密钥不匹配或数据已损坏"),
+        }
+    }
+}
+
+impl std::error::Error for CipherError {}
+
+/// 读取本地密钥文件；文件不存在时生成新密钥并以独占方式创建
+pub fn load_or_create_secret(path: &Path) -> Result<String, CipherError> {
+    match fs::read_to_string(path) {
+        Ok(content) => {
+            let secret = content.trim();
+            if secret.is_empty() {
+                return Err(CipherError::Secret(format!(
+                    "{:?} 为空；若其中没有需要保留的密钥，删除该文件后重启",
+                    path
+                )));
+            }
+            Ok(secret.to_string())
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => create_secret(path),
+        Err(e) => Err(CipherError::Secret(format!("无法读取 {:?}: {}", path, e))),
+    }
+}
+
+fn create_secret(path: &Path) -> Result<String, CipherError> {
+    let secret: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+
+    // create_new：与其他进程同时创建时不会互相覆盖
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(|e| CipherError::Secret(format!("无法创建 {:?}: {}", path, e)))?;
+    if let Err(e) = file
+        .write_all(secret.as_bytes())
+        .and_then(|_| file.sync_all())
+    {
+        // 删除写了一半的文件，避免下次启动读到残缺的密钥
+        drop(file);
+        let _ = fs::remove_file(path);
+        return Err(CipherError::Secret(format!("无法写入 {:?}: {}", path, e)));
+    }
+    Ok(secret)
+}
+
+/// AES-256-GCM 密钥
+#[derive(Clone)]
+pub struct SecretKey(Key<Aes256Gcm>);
+
+impl SecretKey {
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes.into())
+    }
+
+    /// 由本地密钥文件的内容派生
+    pub fn from_secret(secret: &str) -> Result<Self, CipherError> {
+        let mut key = Key::<Aes256Gcm>::default();
+        Hkdf::<Sha256>::new(None, secret.as_bytes())
+            .expand(HKDF_INFO, &mut key)
+            .map_err(|_| CipherError::KeyDerivation)?;
+        Ok(Self(key))
+    }
+
+    /// 加密，返回 base64(nonce || 密文)
+    pub fn seal(&self, plain: &str) -> Result<String, CipherError> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = Aes256Gcm::new(&self.0)
+            .encrypt(&nonce, plain.as_bytes())
+            .map_err(|_| CipherError::Encrypt)?;
+        let mut bytes = nonce.to_vec();
+        bytes.extend_from_slice(&ciphertext);
+        Ok(STANDARD.encode(bytes))
+    }
+
+    /// 解密 [`SecretKey::seal`] 的结果
+    pub fn open(&self, sealed: &str) -> Result<String, CipherError> {
+        let bytes = STANDARD
+            .decode(sealed)
+            .map_err(|_| CipherError::Malformed)?;
+        if bytes.len() <= NONCE_LEN {
+            return Err(CipherError::Malformed);
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plain = Aes256Gcm::new(&self.0)
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| CipherError::Decrypt)?;
+        String::from_utf8(plain).map_err(|_| CipherError::Malformed)
+    }
+
+    /// 加密 API 密钥用于存储；空密钥（如本地 Ollama）原样保存
+    pub fn encrypt_stored(&self, plain: &str) -> Result<String, CipherError> {
+        if plain.is_empty() {
+            return Ok(String::new());
+        }
+        Ok(format!("{}{}", ENCRYPTED_PREFIX, self.seal(plain)?))
+    }
+
+    /// 解密存储的 API 密钥，兼容未加密的旧值
+    pub fn decrypt_stored(&self, stored: &str) -> Result<String, CipherError> {
+        match stored.strip_prefix(ENCRYPTED_PREFIX) {
+            Some(sealed) => self.open(sealed),
+            None => Ok(stored.to_string()),
+        }
+    }
+}
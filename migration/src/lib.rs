@@ -6,6 +6,23 @@ pub use sea_orm_migration::prelude::*;
 
 mod m000001_v1_init;
 mod m000002_add_avatar_version;
+mod m000003_add_version_auto_flag;
+mod m000004_compress_character_versions;
+mod m000005_create_search_index;
+mod m000006_create_tag_index;
+mod m000007_create_tag_aliases;
+mod m000008_create_operation_journal;
+mod m000009_create_jobs;
+mod m000010_add_ai_channel_provider;
+mod m000011_create_ai_usage;
+mod m000012_create_prompt_templates;
+mod m000013_encrypt_ai_channel_keys;
+mod m000014_tags_name_nocase;
+mod m000015_add_operation_item_after;
+mod m000016_card_name_key_index;
+
+pub mod api_key_cipher;
+pub mod version_codec;
 
 pub struct Migrator;
 
@@ -15,6 +32,20 @@ impl MigratorTrait for Migrator {
         vec![
             Box::new(m000001_v1_init::Migration),
             Box::new(m000002_add_avatar_version::Migration),
+            Box::new(m000003_add_version_auto_flag::Migration),
+            Box::new(m000004_compress_character_versions::Migration),
+            Box::new(m000005_create_search_index::Migration),
+            Box::new(m000006_create_tag_index::Migration),
+            Box::new(m000007_create_tag_aliases::Migration),
+            Box::new(m000008_create_operation_journal::Migration),
+            Box::new(m000009_create_jobs::Migration),
+            Box::new(m000010_add_ai_channel_provider::Migration),
+            Box::new(m000011_create_ai_usage::Migration),
+            Box::new(m000012_create_prompt_templates::Migration),
+            Box::new(m000013_encrypt_ai_channel_keys::Migration),
+            Box::new(m000014_tags_name_nocase::Migration),
+            Box::new(m000015_add_operation_item_after::Migration),
+            Box::new(m000016_card_name_key_index::Migration),
         ]
     }
 }
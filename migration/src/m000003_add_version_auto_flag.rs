@@ -0,0 +1,51 @@
+//! 迁移：添加 is_auto 列到 character_versions 表
+//!
+//! 区分自动快照与手动命名的版本，保留策略只清理自动快照
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 检查列是否已存在（SQLite 不支持 IF NOT EXISTS）
+        let conn = manager.get_connection();
+        let result = conn
+            .query_all(sea_orm::Statement::from_string(
+                sea_orm::DatabaseBackend::Sqlite,
+                "SELECT COUNT(*) as cnt FROM pragma_table_info('character_versions') WHERE name='is_auto'".to_string(),
+            ))
+            .await?;
+
+        if let Some(row) = result.first() {
+            let count: i32 = row.try_get("", "cnt").unwrap_or(0);
+            if count == 0 {
+                conn.execute_unprepared(
+                    "ALTER TABLE character_versions ADD COLUMN is_auto BOOLEAN NOT NULL DEFAULT 0;",
+                )
+                .await?;
+            }
+        }
+
+        // 清理任务按角色 + 时间查询
+        conn.execute_unprepared(
+            "CREATE INDEX IF NOT EXISTS idx_character_versions_character_created ON character_versions (character_id, created_at);",
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+        conn.execute_unprepared("DROP INDEX IF EXISTS idx_character_versions_character_created;")
+            .await?;
+        // SQLite 3.35.0+ 支持 DROP COLUMN
+        conn.execute_unprepared("ALTER TABLE character_versions DROP COLUMN is_auto;")
+            .await?;
+
+        Ok(())
+    }
+}
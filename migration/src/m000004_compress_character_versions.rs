@@ -0,0 +1,175 @@
+//! 迁移：character_versions 改为关键帧 + 增量补丁存储
+//!
+//! 新增 storage / base_id / payload / depth 列，并把已有的完整 JSON 按角色、时间顺序
+//! 转换为压缩的关键帧与行级补丁。编码使用与 `services::version_store` 共用的
+//! [`crate::version_codec`]，整个迁移在一个事务中完成。
+
+use sea_orm::{ConnectionTrait, DatabaseBackend, Statement, TransactionTrait, Value};
+use sea_orm_migration::prelude::*;
+
+use crate::version_codec::{self, apply_patch, decompress};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+const COLUMNS: [(&str, &str); 4] = [
+    ("storage", "TEXT NOT NULL DEFAULT 'plain'"),
+    ("base_id", "BLOB"),
+    ("payload", "BLOB"),
+    ("depth", "INTEGER NOT NULL DEFAULT 0"),
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let txn = manager.get_connection().begin().await?;
+        let conn = &txn;
+
+        // 检查列是否已存在（SQLite 不支持 IF NOT EXISTS）
+        for (name, definition) in COLUMNS {
+            if !column_exists(conn, name).await? {
+                conn.execute_unprepared(&format!(
+                    "ALTER TABLE character_versions ADD COLUMN {} {};",
+                    name, definition
+                ))
+                .await?;
+            }
+        }
+        conn.execute_unprepared(
+            "CREATE INDEX IF NOT EXISTS idx_character_versions_base_id ON character_versions (base_id);",
+        )
+        .await?;
+
+        // 逐个角色转换，避免一次性读入所有版本
+        let cards = conn
+            .query_all(Statement::from_string(
+                DatabaseBackend::Sqlite,
+                "SELECT DISTINCT character_id FROM character_versions WHERE storage = 'plain'"
+                    .to_string(),
+            ))
+            .await?;
+
+        for card in cards {
+            let character_id: Vec<u8> = card.try_get("", "character_id")?;
+            let rows = conn
+                .query_all(Statement::from_sql_and_values(
+                    DatabaseBackend::Sqlite,
+                    "SELECT id, data FROM character_versions WHERE character_id = ? ORDER BY created_at",
+                    [Value::from(character_id)],
+                ))
+                .await?;
+
+            let mut prev: Option<(Vec<u8>, String, i32)> = None;
+            for row in rows {
+                let id: Vec<u8> = row.try_get("", "id")?;
+                let data: String = row.try_get("", "data")?;
+
+                let encoded = version_codec::encode(
+                    &data,
+                    prev.as_ref().map(|(base_id, base_text, depth)| {
+                        (base_id.clone(), *depth, base_text.as_str())
+                    }),
+                );
+                conn.execute(Statement::from_sql_and_values(
+                    DatabaseBackend::Sqlite,
+                    "UPDATE character_versions SET storage = ?, base_id = ?, payload = ?, depth = ?, data = '' WHERE id = ?",
+                    [
+                        Value::from(encoded.storage),
+                        Value::Bytes(encoded.base_id.map(Box::new)),
+                        Value::from(encoded.payload),
+                        Value::from(encoded.depth),
+                        Value::from(id.clone()),
+                    ],
+                ))
+                .await?;
+
+                prev = Some((id, data, encoded.depth));
+            }
+        }
+
+        txn.commit().await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let txn = manager.get_connection().begin().await?;
+        let conn = &txn;
+
+        // 先还原为完整 JSON，再删除新增的列
+        let cards = conn
+            .query_all(Statement::from_string(
+                DatabaseBackend::Sqlite,
+                "SELECT DISTINCT character_id FROM character_versions WHERE storage != 'plain'"
+                    .to_string(),
+            ))
+            .await?;
+
+        for card in cards {
+            let character_id: Vec<u8> = card.try_get("", "character_id")?;
+            let rows = conn
+                .query_all(Statement::from_sql_and_values(
+                    DatabaseBackend::Sqlite,
+                    "SELECT id, storage, base_id, payload, data FROM character_versions WHERE character_id = ? ORDER BY created_at",
+                    [Value::from(character_id)],
+                ))
+                .await?;
+
+            // 补丁总是指向更早的版本，按时间顺序还原即可
+            let mut restored: Vec<(Vec<u8>, String)> = Vec::new();
+            for row in rows {
+                let id: Vec<u8> = row.try_get("", "id")?;
+                let storage: String = row.try_get("", "storage")?;
+                let payload: Option<Vec<u8>> = row.try_get("", "payload")?;
+                let data = match storage.as_str() {
+                    version_codec::STORAGE_FULL => decompress(payload.as_deref())?,
+                    version_codec::STORAGE_DELTA => {
+                        let base_id: Option<Vec<u8>> = row.try_get("", "base_id")?;
+                        let base_text = restored
+                            .iter()
+                            .find(|(rid, _)| Some(rid) == base_id.as_ref())
+                            .map(|(_, text)| text.as_str())
+                            .ok_or_else(|| DbErr::Custom("基准版本不存在".to_string()))?;
+                        apply_patch(base_text, &decompress(payload.as_deref())?)?
+                    }
+                    _ => row.try_get("", "data")?,
+                };
+
+                conn.execute(Statement::from_sql_and_values(
+                    DatabaseBackend::Sqlite,
+                    "UPDATE character_versions SET data = ? WHERE id = ?",
+                    [Value::from(data.clone()), Value::from(id.clone())],
+                ))
+                .await?;
+                restored.push((id, data));
+            }
+        }
+
+        conn.execute_unprepared("DROP INDEX IF EXISTS idx_character_versions_base_id;")
+            .await?;
+        // SQLite 3.35.0+ 支持 DROP COLUMN
+        for (name, _) in COLUMNS {
+            conn.execute_unprepared(&format!(
+                "ALTER TABLE character_versions DROP COLUMN {};",
+                name
+            ))
+            .await?;
+        }
+
+        txn.commit().await
+    }
+}
+
+async fn column_exists<C: ConnectionTrait>(conn: &C, name: &str) -> Result<bool, DbErr> {
+    let result = conn
+        .query_all(Statement::from_string(
+            DatabaseBackend::Sqlite,
+            format!(
+                "SELECT COUNT(*) as cnt FROM pragma_table_info('character_versions') WHERE name='{}'",
+                name
+            ),
+        ))
+        .await?;
+    Ok(result
+        .first()
+        .map(|row| row.try_get::<i32>("", "cnt").unwrap_or(0) > 0)
+        .unwrap_or(false))
+}
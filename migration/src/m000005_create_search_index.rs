@@ -0,0 +1,258 @@
+//! 迁移：创建全文搜索索引
+//!
+//! FTS5 + trigram 分词，中日韩文本无需分词即可检索。角色卡、世界书、小剧场与图库
+//! 由触发器在增删改时同步；聊天记录正文保存在文件中，由应用写入，这里只负责删除与改名。
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// 换行连接多个可能为 NULL 的表达式
+fn join_lines(parts: &[String]) -> String {
+    parts
+        .iter()
+        .map(|p| format!("COALESCE({}, '')", p))
+        .collect::<Vec<_>>()
+        .join(" || char(10) || ")
+}
+
+/// 角色卡的索引行（`row` 为 NEW 或表名）
+fn card_row(row: &str) -> String {
+    // 非法 JSON 按空对象处理，避免触发器报错导致写入失败
+    let json = format!(
+        "(CASE WHEN json_valid({r}.data) THEN {r}.data ELSE '{{}}' END)",
+        r = row
+    );
+    // V2/V3 字段在 data 下，V1 在根级
+    let base = format!(
+        "(CASE WHEN json_type({j}, '$.data') = 'object' THEN '$.data' ELSE '$' END)",
+        j = json
+    );
+    let field = |name: &str| format!("json_extract({}, {} || '.{}')", json, base, name);
+
+    let mut parts: Vec<String> = [
+        "description",
+        "personality",
+        "scenario",
+        "first_mes",
+        "mes_example",
+        "creator_notes",
+        "system_prompt",
+        "post_history_instructions",
+    ]
+    .iter()
+    .map(|f| field(f))
+    .collect();
+    parts.push(format!(
+        "(SELECT group_concat(value, char(10)) FROM json_each({}, {} || '.alternate_greetings') WHERE type = 'text')",
+        json, base
+    ));
+    parts.push(format!(
+        "(SELECT group_concat(COALESCE(json_extract(value, '$.comment'), '') || ' ' || COALESCE(json_extract(value, '$.keys'), '') || char(10) || COALESCE(json_extract(value, '$.content'), ''), char(10)) FROM json_each({}, {} || '.character_book.entries'))",
+        json, base
+    ));
+    parts.push(format!("{}.author", row));
+    parts.push(format!("{}.tags", row));
+    parts.push(format!("{}.custom_summary", row));
+    parts.push(format!("{}.user_note", row));
+
+    format!(
+        "SELECT 'card', {r}.id, NULL, {r}.name, {body}",
+        r = row,
+        body = join_lines(&parts)
+    )
+}
+
+fn world_info_row(row: &str) -> String {
+    let json = format!(
+        "(CASE WHEN json_valid({r}.data) THEN {r}.data ELSE '{{}}' END)",
+        r = row
+    );
+    // SillyTavern 世界书的 entries 可能是以 uid 为键的对象，也可能是数组
+    let entries = format!(
+        "(SELECT group_concat(COALESCE(json_extract(value, '$.comment'), '') || ' ' || COALESCE(json_extract(value, '$.key'), json_extract(value, '$.keys'), '') || char(10) || COALESCE(json_extract(value, '$.content'), ''), char(10)) FROM json_each({}, '$.entries'))",
+        json
+    );
+    format!(
+        "SELECT 'world_info', {r}.id, NULL, {r}.name, {body}",
+        r = row,
+        body = join_lines(&[entries])
+    )
+}
+
+fn theater_row(row: &str) -> String {
+    format!(
+        "SELECT 'theater', {r}.id, NULL, {r}.title, {body}",
+        r = row,
+        body = join_lines(&[
+            format!("{}.category", row),
+            format!("{}.\"desc\"", row),
+            format!("{}.content", row),
+        ])
+    )
+}
+
+fn image_row(row: &str) -> String {
+    format!(
+        "SELECT 'image', {r}.id, NULL, {r}.title, {body}",
+        r = row,
+        body = join_lines(&[
+            format!("{}.ai_prompt", row),
+            format!("{}.ai_negative_prompt", row),
+            format!("{}.user_notes", row),
+            format!("{}.tags", row),
+        ])
+    )
+}
+
+const INSERT_INTO: &str = "INSERT INTO search_index (kind, ref_id, parent_id, title, body)";
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+
+        conn.execute_unprepared(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS search_index USING fts5(
+                kind UNINDEXED,
+                ref_id UNINDEXED,
+                parent_id UNINDEXED,
+                title,
+                body,
+                tokenize = 'trigram'
+            );",
+        )
+        .await?;
+
+        // 角色卡：回收站中的卡片不进入索引
+        let card_columns =
+            "name, description, author, data, tags, custom_summary, user_note, deleted_at";
+        let mut statements = vec![
+            format!(
+                "CREATE TRIGGER IF NOT EXISTS search_cards_ai AFTER INSERT ON character_cards WHEN NEW.deleted_at IS NULL BEGIN {} {}; END;",
+                INSERT_INTO,
+                card_row("NEW")
+            ),
+            format!(
+                "CREATE TRIGGER IF NOT EXISTS search_cards_au AFTER UPDATE OF {} ON character_cards BEGIN
+                    DELETE FROM search_index WHERE kind = 'card' AND ref_id = OLD.id;
+                    {} {} WHERE NEW.deleted_at IS NULL;
+                END;",
+                card_columns,
+                INSERT_INTO,
+                card_row("NEW")
+            ),
+            "CREATE TRIGGER IF NOT EXISTS search_cards_ad AFTER DELETE ON character_cards BEGIN
+                DELETE FROM search_index WHERE kind = 'card' AND ref_id = OLD.id;
+            END;"
+                .to_string(),
+        ];
+
+        for (table, kind, row_fn, columns) in [
+            (
+                "world_info",
+                "world_info",
+                world_info_row as fn(&str) -> String,
+                "name, data",
+            ),
+            (
+                "theaters",
+                "theater",
+                theater_row,
+                "title, category, \"desc\", content",
+            ),
+            (
+                "image",
+                "image",
+                image_row,
+                "title, tags, ai_prompt, ai_negative_prompt, user_notes",
+            ),
+        ] {
+            statements.push(format!(
+                "CREATE TRIGGER IF NOT EXISTS search_{t}_ai AFTER INSERT ON {t} BEGIN {ins} {row}; END;",
+                t = table,
+                ins = INSERT_INTO,
+                row = row_fn("NEW")
+            ));
+            statements.push(format!(
+                "CREATE TRIGGER IF NOT EXISTS search_{t}_au AFTER UPDATE OF {cols} ON {t} BEGIN
+                    DELETE FROM search_index WHERE kind = '{k}' AND ref_id = OLD.id;
+                    {ins} {row};
+                END;",
+                t = table,
+                k = kind,
+                cols = columns,
+                ins = INSERT_INTO,
+                row = row_fn("NEW")
+            ));
+            statements.push(format!(
+                "CREATE TRIGGER IF NOT EXISTS search_{t}_ad AFTER DELETE ON {t} BEGIN
+                    DELETE FROM search_index WHERE kind = '{k}' AND ref_id = OLD.id;
+                END;",
+                t = table,
+                k = kind
+            ));
+        }
+
+        // 聊天记录：正文由应用写入，这里同步改名与删除
+        statements.push(
+            "CREATE TRIGGER IF NOT EXISTS search_chat_histories_au AFTER UPDATE OF display_name ON chat_histories BEGIN
+                UPDATE search_index SET title = NEW.display_name WHERE kind = 'chat' AND ref_id = OLD.id;
+            END;"
+                .to_string(),
+        );
+        statements.push(
+            "CREATE TRIGGER IF NOT EXISTS search_chat_histories_ad AFTER DELETE ON chat_histories BEGIN
+                DELETE FROM search_index WHERE kind = 'chat' AND ref_id = OLD.id;
+            END;"
+                .to_string(),
+        );
+
+        // 回填已有数据
+        statements.push("DELETE FROM search_index WHERE kind != 'chat';".to_string());
+        statements.push(format!(
+            "{} {} FROM character_cards WHERE deleted_at IS NULL;",
+            INSERT_INTO,
+            card_row("character_cards")
+        ));
+        statements.push(format!(
+            "{} {} FROM world_info;",
+            INSERT_INTO,
+            world_info_row("world_info")
+        ));
+        statements.push(format!(
+            "{} {} FROM theaters;",
+            INSERT_INTO,
+            theater_row("theaters")
+        ));
+        statements.push(format!(
+            "{} {} FROM image;",
+            INSERT_INTO,
+            image_row("image")
+        ));
+
+        for sql in statements {
+            conn.execute_unprepared(&sql).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+        for table in ["cards", "world_info", "theaters", "image", "chat_histories"] {
+            for suffix in ["ai", "au", "ad"] {
+                conn.execute_unprepared(&format!(
+                    "DROP TRIGGER IF EXISTS search_{}_{};",
+                    table, suffix
+                ))
+                .await?;
+            }
+        }
+        conn.execute_unprepared("DROP TABLE IF EXISTS search_index;")
+            .await?;
+
+        Ok(())
+    }
+}
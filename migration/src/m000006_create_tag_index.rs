@@ -0,0 +1,152 @@
+//! 迁移：标签索引表
+//!
+//! `character_cards.tags` 与 `image.tags` 仍是 JSON 字符串（卡片数据以它为准），
+//! 这里建立 `tags` 字典表与 `card_tags` / `image_tags` 关联表，由触发器在 tags 列写入时同步，
+//! 标签统计与按标签筛选不再逐条解析 JSON。
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// 需要同步的表：(源表, 关联表, 关联列, 触发器名前缀)
+const SOURCES: [(&str, &str, &str, &str); 2] = [
+    ("character_cards", "card_tags", "card_id", "tag_cards"),
+    ("image", "image_tags", "image_id", "tag_image"),
+];
+
+/// `row` 的 (owner_id, name) 行：标签名去除首尾空白，非法 JSON 视为空。
+/// `row` 为 NEW / OLD 时在触发器中使用，为表名时用于回填
+fn tag_rows(row: &str) -> String {
+    let from = if row == "NEW" || row == "OLD" {
+        String::new()
+    } else {
+        format!("{}, ", row)
+    };
+    format!(
+        "SELECT DISTINCT {r}.id AS owner_id, trim(value) AS name FROM {from}json_each(CASE WHEN json_valid({r}.tags) THEN {r}.tags ELSE '[]' END) WHERE type = 'text' AND trim(value) != ''",
+        r = row,
+        from = from
+    )
+}
+
+/// 为 `row` 写入字典与关联
+fn link_statements(row: &str, join_table: &str, owner_column: &str) -> [String; 2] {
+    [
+        format!(
+            "INSERT OR IGNORE INTO tags (id, name, created_at) SELECT randomblob(16), name, datetime('now') FROM (SELECT DISTINCT name FROM ({}));",
+            tag_rows(row)
+        ),
+        format!(
+            "INSERT OR IGNORE INTO {jt} ({oc}, tag_id) SELECT n.owner_id, tags.id FROM ({rows}) AS n JOIN tags ON tags.name = n.name;",
+            jt = join_table,
+            oc = owner_column,
+            rows = tag_rows(row)
+        ),
+    ]
+}
+
+/// 删除 `row` 原有标签中已无任何引用的字典项
+fn prune_statement(row: &str) -> String {
+    format!(
+        "DELETE FROM tags WHERE name IN (SELECT name FROM ({})) AND NOT EXISTS (SELECT 1 FROM card_tags WHERE card_tags.tag_id = tags.id) AND NOT EXISTS (SELECT 1 FROM image_tags WHERE image_tags.tag_id = tags.id);",
+        tag_rows(row)
+    )
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+
+        let mut statements = vec![
+            "CREATE TABLE IF NOT EXISTS tags (
+                id BLOB NOT NULL PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE,
+                created_at TEXT NOT NULL
+            );"
+            .to_string(),
+            "CREATE INDEX IF NOT EXISTS idx_tags_name_nocase ON tags (name COLLATE NOCASE);"
+                .to_string(),
+        ];
+
+        for (table, join_table, owner_column, prefix) in SOURCES {
+            statements.push(format!(
+                "CREATE TABLE IF NOT EXISTS {jt} (
+                    {oc} BLOB NOT NULL,
+                    tag_id BLOB NOT NULL,
+                    PRIMARY KEY ({oc}, tag_id)
+                ) WITHOUT ROWID;",
+                jt = join_table,
+                oc = owner_column
+            ));
+            statements.push(format!(
+                "CREATE INDEX IF NOT EXISTS idx_{jt}_tag_id ON {jt} (tag_id, {oc});",
+                jt = join_table,
+                oc = owner_column
+            ));
+
+            let [insert_tags, insert_links] = link_statements("NEW", join_table, owner_column);
+            statements.push(format!(
+                "CREATE TRIGGER IF NOT EXISTS {p}_ai AFTER INSERT ON {t} BEGIN
+                    {} {}
+                END;",
+                insert_tags,
+                insert_links,
+                p = prefix,
+                t = table
+            ));
+            statements.push(format!(
+                "CREATE TRIGGER IF NOT EXISTS {p}_au AFTER UPDATE OF tags ON {t} BEGIN
+                    DELETE FROM {jt} WHERE {oc} = OLD.id;
+                    {} {} {}
+                END;",
+                insert_tags,
+                insert_links,
+                prune_statement("OLD"),
+                p = prefix,
+                t = table,
+                jt = join_table,
+                oc = owner_column
+            ));
+            statements.push(format!(
+                "CREATE TRIGGER IF NOT EXISTS {p}_ad AFTER DELETE ON {t} BEGIN
+                    DELETE FROM {jt} WHERE {oc} = OLD.id;
+                    {}
+                END;",
+                prune_statement("OLD"),
+                p = prefix,
+                t = table,
+                jt = join_table,
+                oc = owner_column
+            ));
+        }
+
+        // 回填已有数据
+        for (table, join_table, owner_column, _) in SOURCES {
+            statements.extend(link_statements(table, join_table, owner_column));
+        }
+
+        for sql in statements {
+            conn.execute_unprepared(&sql).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+        for (_, join_table, _, prefix) in SOURCES {
+            for suffix in ["ai", "au", "ad"] {
+                conn.execute_unprepared(&format!("DROP TRIGGER IF EXISTS {}_{};", prefix, suffix))
+                    .await?;
+            }
+            conn.execute_unprepared(&format!("DROP TABLE IF EXISTS {};", join_table))
+                .await?;
+        }
+        conn.execute_unprepared("DROP TABLE IF EXISTS tags;")
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+//! 迁移：标签别名规则
+//!
+//! 导入角色卡与 AI 生成标签时，命中别名（不区分 ASCII 大小写）的标签替换为目标标签。
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "CREATE TABLE IF NOT EXISTS tag_aliases (
+                    alias TEXT NOT NULL PRIMARY KEY COLLATE NOCASE,
+                    target TEXT NOT NULL,
+                    created_at TEXT NOT NULL
+                );",
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared("DROP TABLE IF EXISTS tag_aliases;")
+            .await?;
+
+        Ok(())
+    }
+}
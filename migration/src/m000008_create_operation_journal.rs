@@ -0,0 +1,54 @@
+//! 迁移：批量操作日志
+//!
+//! 记录批量接口改动前各行的列值，用于整批撤销。
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+
+        conn.execute_unprepared(
+            "CREATE TABLE IF NOT EXISTS operations (
+                id BLOB NOT NULL PRIMARY KEY,
+                kind TEXT NOT NULL,
+                target TEXT NOT NULL,
+                summary TEXT NOT NULL,
+                columns TEXT NOT NULL,
+                item_count INTEGER NOT NULL,
+                created_at TEXT NOT NULL,
+                undone_at TEXT
+            );",
+        )
+        .await?;
+        conn.execute_unprepared(
+            "CREATE INDEX IF NOT EXISTS idx_operations_created_at ON operations (created_at);",
+        )
+        .await?;
+        conn.execute_unprepared(
+            "CREATE TABLE IF NOT EXISTS operation_items (
+                operation_id BLOB NOT NULL,
+                row_id BLOB NOT NULL,
+                before TEXT NOT NULL,
+                PRIMARY KEY (operation_id, row_id)
+            ) WITHOUT ROWID;",
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+        conn.execute_unprepared("DROP TABLE IF EXISTS operation_items;")
+            .await?;
+        conn.execute_unprepared("DROP TABLE IF EXISTS operations;")
+            .await?;
+
+        Ok(())
+    }
+}
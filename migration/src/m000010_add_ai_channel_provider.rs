@@ -0,0 +1,44 @@
+//! 迁移：添加 provider 列到 ai_channels 表
+//!
+//! 区分渠道的接口类型（OpenAI 兼容 / Anthropic / Gemini / Ollama），已有渠道视为 OpenAI 兼容
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 检查列是否已存在（SQLite 不支持 IF NOT EXISTS）
+        let conn = manager.get_connection();
+        let result = conn
+            .query_all(sea_orm::Statement::from_string(
+                sea_orm::DatabaseBackend::Sqlite,
+                "SELECT COUNT(*) as cnt FROM pragma_table_info('ai_channels') WHERE name='provider'"
+                    .to_string(),
+            ))
+            .await?;
+
+        if let Some(row) = result.first() {
+            let count: i32 = row.try_get("", "cnt").unwrap_or(0);
+            if count == 0 {
+                conn.execute_unprepared(
+                    "ALTER TABLE ai_channels ADD COLUMN provider TEXT NOT NULL DEFAULT 'openai';",
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+        // SQLite 3.35.0+ 支持 DROP COLUMN
+        conn.execute_unprepared("ALTER TABLE ai_channels DROP COLUMN provider;")
+            .await?;
+
+        Ok(())
+    }
+}
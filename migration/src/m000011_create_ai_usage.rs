@@ -0,0 +1,81 @@
+//! 迁移：AI 调用用量记录
+//!
+//! 每次调用模型记录渠道、模型、功能、token 用量、耗时和结果；
+//! 同时给 ai_channels 添加按百万 token 计的输入/输出单价，用于估算费用。
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+
+        conn.execute_unprepared(
+            "CREATE TABLE IF NOT EXISTS ai_usage (
+                id BLOB NOT NULL PRIMARY KEY,
+                channel_id BLOB,
+                channel_name TEXT NOT NULL,
+                model TEXT NOT NULL,
+                feature TEXT NOT NULL,
+                prompt_tokens INTEGER NOT NULL DEFAULT 0,
+                completion_tokens INTEGER NOT NULL DEFAULT 0,
+                latency_ms INTEGER NOT NULL DEFAULT 0,
+                status TEXT NOT NULL,
+                error TEXT,
+                cost REAL,
+                created_at TEXT NOT NULL
+            );",
+        )
+        .await?;
+        conn.execute_unprepared(
+            "CREATE INDEX IF NOT EXISTS idx_ai_usage_created_at ON ai_usage (created_at);",
+        )
+        .await?;
+        conn.execute_unprepared(
+            "CREATE INDEX IF NOT EXISTS idx_ai_usage_channel ON ai_usage (channel_id, created_at);",
+        )
+        .await?;
+
+        // 检查列是否已存在（SQLite 不支持 IF NOT EXISTS）
+        for column in ["input_price", "output_price"] {
+            let result = conn
+                .query_all(sea_orm::Statement::from_string(
+                    sea_orm::DatabaseBackend::Sqlite,
+                    format!(
+                        "SELECT COUNT(*) as cnt FROM pragma_table_info('ai_channels') WHERE name='{}'",
+                        column
+                    ),
+                ))
+                .await?;
+
+            if let Some(row) = result.first() {
+                let count: i32 = row.try_get("", "cnt").unwrap_or(0);
+                if count == 0 {
+                    conn.execute_unprepared(&format!(
+                        "ALTER TABLE ai_channels ADD COLUMN {} REAL;",
+                        column
+                    ))
+                    .await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+        conn.execute_unprepared("DROP TABLE IF EXISTS ai_usage;")
+            .await?;
+        // SQLite 3.35.0+ 支持 DROP COLUMN
+        conn.execute_unprepared("ALTER TABLE ai_channels DROP COLUMN input_price;")
+            .await?;
+        conn.execute_unprepared("ALTER TABLE ai_channels DROP COLUMN output_price;")
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+//! 迁移：AI 功能提示词模板
+//!
+//! 只保存用户修改过的模板，为空的列使用代码中的内置默认值。
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+
+        conn.execute_unprepared(
+            "CREATE TABLE IF NOT EXISTS prompt_templates (
+                feature TEXT NOT NULL PRIMARY KEY,
+                system_prompt TEXT,
+                user_prompt TEXT,
+                channel_id BLOB,
+                temperature REAL,
+                updated_at TEXT NOT NULL
+            );",
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+        conn.execute_unprepared("DROP TABLE IF EXISTS prompt_templates;")
+            .await?;
+
+        Ok(())
+    }
+}
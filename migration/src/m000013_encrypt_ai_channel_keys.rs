@@ -0,0 +1,97 @@
+//! 迁移：加密 ai_channels 中已有的明文 API 密钥
+//!
+//! 密钥文件、密钥派生与存储格式与运行时共用 [`crate::api_key_cipher`]。
+
+use crate::api_key_cipher::{self, SecretKey, ENCRYPTED_PREFIX};
+use sea_orm::{ConnectionTrait, DatabaseBackend, Statement, TransactionTrait, Value};
+use sea_orm_migration::prelude::*;
+use std::path::PathBuf;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+        let rows = conn
+            .query_all(Statement::from_string(
+                DatabaseBackend::Sqlite,
+                "SELECT id, api_key FROM ai_channels WHERE api_key <> '' AND api_key NOT LIKE 'enc:v1:%'"
+                    .to_string(),
+            ))
+            .await?;
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        let key = local_key()?;
+        let mut updates = Vec::with_capacity(rows.len());
+        for row in rows {
+            let id: Vec<u8> = row.try_get("", "id")?;
+            let api_key: String = row.try_get("", "api_key")?;
+            let encrypted = key
+                .encrypt_stored(&api_key)
+                .map_err(|e| DbErr::Migration(format!("加密 API 密钥失败: {}", e)))?;
+            updates.push((id, encrypted));
+        }
+        write_keys(conn, updates).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+        let rows = conn
+            .query_all(Statement::from_string(
+                DatabaseBackend::Sqlite,
+                "SELECT id, name, api_key FROM ai_channels WHERE api_key LIKE 'enc:v1:%'"
+                    .to_string(),
+            ))
+            .await?;
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        // 先全部解密，任一失败（如来自其他设备的备份）即中止，不修改任何数据
+        let key = local_key()?;
+        let mut updates = Vec::with_capacity(rows.len());
+        for row in rows {
+            let id: Vec<u8> = row.try_get("", "id")?;
+            let name: String = row.try_get("", "name")?;
+            let api_key: String = row.try_get("", "api_key")?;
+            let plain = key.open(&api_key[ENCRYPTED_PREFIX.len()..]).map_err(|e| {
+                DbErr::Migration(format!(
+                    "渠道 {} 的 API 密钥无法解密，回滚中止: {}",
+                    name, e
+                ))
+            })?;
+            updates.push((id, plain));
+        }
+        write_keys(conn, updates).await
+    }
+}
+
+/// 在一个事务中写回所有渠道的密钥
+async fn write_keys<C: TransactionTrait>(
+    conn: &C,
+    updates: Vec<(Vec<u8>, String)>,
+) -> Result<(), DbErr> {
+    let txn = conn.begin().await?;
+    for (id, api_key) in updates {
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            "UPDATE ai_channels SET api_key = ? WHERE id = ?",
+            [Value::from(api_key), Value::from(id)],
+        ))
+        .await?;
+    }
+    txn.commit().await
+}
+
+/// 数据目录下的本地密钥文件，不存在时生成
+fn local_key() -> Result<SecretKey, DbErr> {
+    let data_dir = std::env::var("DATA_DIR").unwrap_or_else(|_| "./data".to_string());
+    let path = PathBuf::from(data_dir).join(api_key_cipher::SECRET_FILE);
+    api_key_cipher::load_or_create_secret(&path)
+        .and_then(|secret| SecretKey::from_secret(&secret))
+        .map_err(|e| DbErr::Migration(e.to_string()))
+}
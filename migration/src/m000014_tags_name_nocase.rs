@@ -0,0 +1,91 @@
+//! 迁移：`tags.name` 改为不区分大小写的唯一约束
+//!
+//! 标签查找使用 NOCASE，原来区分大小写的 UNIQUE 允许出现仅大小写不同的重复项。
+//! 先把重复项的关联合并到最早创建的一项，再重建表。SQLite 不能修改列的排序规则，
+//! 这里用临时表中转而不是 RENAME，避免其他表上引用 `tags` 的触发器在改名时报错。
+
+use sea_orm::{ConnectionTrait, TransactionTrait};
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// 每个标签对应保留的标签：仅大小写不同的标签中最早创建的一项
+const CANONICAL: &str = "CREATE TEMP TABLE tag_canonical AS
+    SELECT t.id AS id, (
+        SELECT k.id FROM tags k WHERE k.name = t.name COLLATE NOCASE
+        ORDER BY k.created_at, k.rowid LIMIT 1
+    ) AS keep FROM tags t;";
+
+/// 用 `definition` 重建 tags 表，保留已有数据
+fn rebuild(definition: &str) -> Vec<String> {
+    vec![
+        "CREATE TEMP TABLE tags_old AS SELECT id, name, created_at FROM tags;".to_string(),
+        "DROP TABLE tags;".to_string(),
+        definition.to_string(),
+        "INSERT INTO tags (id, name, created_at) SELECT id, name, created_at FROM tags_old;"
+            .to_string(),
+        "DROP TABLE tags_old;".to_string(),
+    ]
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let txn = manager.get_connection().begin().await?;
+
+        let mut statements = vec![CANONICAL.to_string()];
+        for (join_table, owner_column) in [("card_tags", "card_id"), ("image_tags", "image_id")] {
+            statements.push(format!(
+                "INSERT OR IGNORE INTO {jt} ({oc}, tag_id)
+                    SELECT {jt}.{oc}, c.keep FROM {jt} JOIN tag_canonical c ON c.id = {jt}.tag_id
+                    WHERE c.id != c.keep;",
+                jt = join_table,
+                oc = owner_column
+            ));
+            statements.push(format!(
+                "DELETE FROM {} WHERE tag_id IN (SELECT id FROM tag_canonical WHERE id != keep);",
+                join_table
+            ));
+        }
+        statements.push(
+            "DELETE FROM tags WHERE id IN (SELECT id FROM tag_canonical WHERE id != keep);"
+                .to_string(),
+        );
+        statements.push("DROP TABLE tag_canonical;".to_string());
+        // UNIQUE 本身带有 NOCASE 索引，原来的 idx_tags_name_nocase 随旧表删除
+        statements.extend(rebuild(
+            "CREATE TABLE tags (
+                id BLOB NOT NULL PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+                created_at TEXT NOT NULL
+            );",
+        ));
+
+        for sql in statements {
+            txn.execute_unprepared(&sql).await?;
+        }
+        txn.commit().await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let txn = manager.get_connection().begin().await?;
+
+        let mut statements = rebuild(
+            "CREATE TABLE tags (
+                id BLOB NOT NULL PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE,
+                created_at TEXT NOT NULL
+            );",
+        );
+        statements.push(
+            "CREATE INDEX IF NOT EXISTS idx_tags_name_nocase ON tags (name COLLATE NOCASE);"
+                .to_string(),
+        );
+
+        for sql in statements {
+            txn.execute_unprepared(&sql).await?;
+        }
+        txn.commit().await
+    }
+}
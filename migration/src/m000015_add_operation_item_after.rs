@@ -0,0 +1,43 @@
+//! 迁移：批量操作日志记录操作后的列值
+//!
+//! 撤销前用它确认各行在操作之后没有再被修改，避免覆盖之后的编辑。
+//! 旧记录没有该值，撤销时不做检查。
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 检查列是否已存在（SQLite 不支持 IF NOT EXISTS）
+        let conn = manager.get_connection();
+        let result = conn
+            .query_all(sea_orm::Statement::from_string(
+                sea_orm::DatabaseBackend::Sqlite,
+                "SELECT COUNT(*) as cnt FROM pragma_table_info('operation_items') WHERE name='after'"
+                    .to_string(),
+            ))
+            .await?;
+
+        if let Some(row) = result.first() {
+            let count: i32 = row.try_get("", "cnt").unwrap_or(0);
+            if count == 0 {
+                conn.execute_unprepared("ALTER TABLE operation_items ADD COLUMN after TEXT;")
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite 3.35.0+ 支持 DROP COLUMN
+        manager
+            .get_connection()
+            .execute_unprepared("ALTER TABLE operation_items DROP COLUMN after;")
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,29 @@
+//! 迁移：为导入时的同名匹配建立表达式索引
+//!
+//! 导入按 `lower(trim(name))` 查找同名卡片，查询表达式须与索引表达式完全一致才能用上索引。
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "CREATE INDEX IF NOT EXISTS idx_character_cards_name_key ON character_cards (lower(trim(name)));",
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared("DROP INDEX IF EXISTS idx_character_cards_name_key;")
+            .await?;
+        Ok(())
+    }
+}
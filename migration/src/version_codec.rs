@@ -0,0 +1,83 @@
+//! 角色卡版本的存储编码
+//!
+//! `services::version_store` 与迁移 `m000004_compress_character_versions` 共用这里的实现，
+//! 保证应用写入的版本与迁移转换的版本始终是同一种格式。
+//!
+//! `storage` 取值：
+//! - `plain`：旧格式，完整 JSON 在 `data` 列
+//! - `full`：关键帧，`payload` 为 zlib 压缩的完整 JSON
+//! - `delta`：`payload` 为 zlib 压缩的 unified diff，相对 `base_id` 指向的版本
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use sea_orm_migration::prelude::DbErr;
+use std::io::{Read, Write};
+
+pub const STORAGE_PLAIN: &str = "plain";
+pub const STORAGE_FULL: &str = "full";
+pub const STORAGE_DELTA: &str = "delta";
+
+/// 链长达到该值时写入新的关键帧，限制读取时需要应用的补丁数量
+pub const KEYFRAME_INTERVAL: i32 = 10;
+
+/// 编码结果，`Id` 为基准版本 ID 的类型
+pub struct Encoded<Id> {
+    pub storage: &'static str,
+    pub base_id: Option<Id>,
+    pub payload: Vec<u8>,
+    pub depth: i32,
+}
+
+/// 深度为 `depth` 的版本之后能否继续追加补丁
+pub fn can_extend(depth: i32) -> bool {
+    depth + 1 < KEYFRAME_INTERVAL
+}
+
+/// 编码版本；`base` 为上一版本的 (ID, 深度, 完整 JSON)
+///
+/// 有基准时优先保存补丁，补丁不比关键帧小很多或无法还原时退回关键帧。
+pub fn encode<Id>(text: &str, base: Option<(Id, i32, &str)>) -> Encoded<Id> {
+    let full = compress(text);
+    if let Some((base_id, base_depth, base_text)) = base.filter(|(_, d, _)| can_extend(*d)) {
+        let patch = diffy::create_patch(base_text, text).to_string();
+        let restored = apply_patch(base_text, &patch).ok();
+        let delta = compress(&patch);
+        if restored.as_deref() == Some(text) && delta.len() * 2 < full.len() {
+            return Encoded {
+                storage: STORAGE_DELTA,
+                base_id: Some(base_id),
+                payload: delta,
+                depth: base_depth + 1,
+            };
+        }
+    }
+    Encoded {
+        storage: STORAGE_FULL,
+        base_id: None,
+        payload: full,
+        depth: 0,
+    }
+}
+
+pub fn apply_patch(base: &str, patch: &str) -> Result<String, DbErr> {
+    let patch = diffy::Patch::from_str(patch)
+        .map_err(|e| DbErr::Custom(format!("版本补丁解析失败: {}", e)))?;
+    diffy::apply(base, &patch).map_err(|e| DbErr::Custom(format!("版本补丁应用失败: {}", e)))
+}
+
+pub fn compress(text: &str) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    // 写入内存缓冲区不会失败
+    let _ = encoder.write_all(text.as_bytes());
+    encoder.finish().unwrap_or_default()
+}
+
+pub fn decompress(payload: Option<&[u8]>) -> Result<String, DbErr> {
+    let payload = payload.ok_or_else(|| DbErr::Custom("版本数据缺失".to_string()))?;
+    let mut text = String::new();
+    ZlibDecoder::new(payload)
+        .read_to_string(&mut text)
+        .map_err(|e| DbErr::Custom(format!("版本数据解压失败: {}", e)))?;
+    Ok(text)
+}
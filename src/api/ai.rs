@@ -1,4 +1,11 @@
 use crate::entities::{ai_channel, character_card, setting};
+use crate::services::ai_provider::{
+    self, ChatMessage, ChatRequest, ProviderError, ProviderKind, StreamEvent,
+};
+use crate::services::ai_router::{self, ChannelRoute, RouteError};
+use crate::services::ai_usage::{self, UsageChannel, UsageRecorder};
+use crate::services::prompt_template::{self, PromptTemplate};
+use crate::utils::crypto;
 use axum::{
     extract::{Path, State},
     http::StatusCode,
@@ -20,12 +27,39 @@ pub struct CreateChannelRequest {
     pub model_id: String,
     #[serde(default = "default_active")]
     pub is_active: bool,
+    #[serde(default)]
+    pub provider: ProviderKind,
+    /// 每百万输入 / 输出 token 的单价，用于估算费用
+    #[serde(default)]
+    pub input_price: Option<f64>,
+    #[serde(default)]
+    pub output_price: Option<f64>,
 }
 
 fn default_active() -> bool {
     true
 }
 
+/// 区分「未传」与「传 null」：前者为 None，后者为 Some(None)
+fn double_option<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// 单价必须是非负数
+fn validate_price(price: Option<f64>) -> Result<(), (StatusCode, Json<Value>)> {
+    match price {
+        Some(p) if !p.is_finite() || p < 0.0 => Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "单价必须是非负数"})),
+        )),
+        _ => Ok(()),
+    }
+}
+
 #[derive(Serialize)]
 pub struct ChannelResponse {
     pub id: Uuid,
@@ -33,7 +67,27 @@ pub struct ChannelResponse {
     pub base_url: String,
     pub model_id: String,
     pub is_active: bool,
-    // Sensitive data excluded
+    pub provider: String,
+    pub input_price: Option<f64>,
+    pub output_price: Option<f64>,
+    /// 已保存密钥且能用本机密钥解密；密钥本身从不返回
+    pub api_key_set: bool,
+}
+
+/// 存储的密钥是否可用（在其他设备恢复备份后会无法解密）
+fn api_key_set(stored: &str) -> bool {
+    !stored.is_empty() && crypto::decrypt_api_key(stored).is_ok()
+}
+
+/// 加密待保存的密钥
+fn encrypt_api_key(plain: &str) -> Result<String, (StatusCode, Json<Value>)> {
+    crypto::encrypt_api_key(plain).map_err(|e| {
+        tracing::error!("Failed to encrypt API key: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": e.to_string()})),
+        )
+    })
 }
 
 #[derive(Deserialize)]
@@ -41,6 +95,8 @@ pub struct TestConnectionRequest {
     pub base_url: String,
     pub api_key: String,
     pub model_id: String,
+    #[serde(default)]
+    pub provider: ProviderKind,
 }
 
 #[derive(Deserialize)]
@@ -50,6 +106,12 @@ pub struct UpdateChannelRequest {
     pub api_key: Option<String>,
     pub model_id: Option<String>,
     pub is_active: Option<bool>,
+    pub provider: Option<ProviderKind>,
+    /// 传 null 清除单价
+    #[serde(default, deserialize_with = "double_option")]
+    pub input_price: Option<Option<f64>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub output_price: Option<Option<f64>>,
 }
 
 /// GET /api/ai/channels - List all channels
@@ -75,6 +137,10 @@ pub async fn list_channels(
             base_url: c.base_url,
             model_id: c.model_id,
             is_active: c.is_active,
+            provider: c.provider,
+            input_price: c.input_price,
+            output_price: c.output_price,
+            api_key_set: api_key_set(&c.api_key),
         })
         .collect();
 
@@ -87,6 +153,9 @@ pub async fn create_channel(
     Json(payload): Json<CreateChannelRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
     // Generate UUID upfront to avoid last_insert_id issues with SQLite
+    validate_price(payload.input_price)?;
+    validate_price(payload.output_price)?;
+
     let channel_id = Uuid::new_v4();
     let now = chrono::Utc::now().naive_utc();
 
@@ -94,9 +163,12 @@ pub async fn create_channel(
         id: Set(channel_id),
         name: Set(payload.name.clone()),
         base_url: Set(payload.base_url.clone()),
-        api_key: Set(payload.api_key),
+        api_key: Set(encrypt_api_key(&payload.api_key)?),
         model_id: Set(payload.model_id.clone()),
         is_active: Set(payload.is_active),
+        provider: Set(payload.provider.as_str().to_string()),
+        input_price: Set(payload.input_price),
+        output_price: Set(payload.output_price),
         created_at: Set(now),
         updated_at: Set(now),
     };
@@ -120,6 +192,10 @@ pub async fn create_channel(
         base_url: payload.base_url,
         model_id: payload.model_id,
         is_active: payload.is_active,
+        provider: payload.provider.as_str().to_string(),
+        input_price: payload.input_price,
+        output_price: payload.output_price,
+        api_key_set: !payload.api_key.is_empty(),
     }))
 }
 
@@ -147,6 +223,9 @@ pub async fn update_channel(
     Path(id): Path<Uuid>,
     Json(payload): Json<UpdateChannelRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    validate_price(payload.input_price.flatten())?;
+    validate_price(payload.output_price.flatten())?;
+
     // Find existing channel
     let existing = ai_channel::Entity::find_by_id(id)
         .one(&db)
@@ -174,7 +253,7 @@ pub async fn update_channel(
         update_model.base_url = Set(base_url);
     }
     if let Some(api_key) = payload.api_key {
-        update_model.api_key = Set(api_key);
+        update_model.api_key = Set(encrypt_api_key(&api_key)?);
     }
     if let Some(model_id) = payload.model_id {
         update_model.model_id = Set(model_id);
@@ -182,6 +261,15 @@ pub async fn update_channel(
     if let Some(is_active) = payload.is_active {
         update_model.is_active = Set(is_active);
     }
+    if let Some(provider) = payload.provider {
+        update_model.provider = Set(provider.as_str().to_string());
+    }
+    if let Some(input_price) = payload.input_price {
+        update_model.input_price = Set(input_price);
+    }
+    if let Some(output_price) = payload.output_price {
+        update_model.output_price = Set(output_price);
+    }
     update_model.updated_at = Set(chrono::Utc::now().naive_utc());
 
     let updated = update_model.update(&db).await.map_err(|e| {
@@ -198,55 +286,36 @@ pub async fn update_channel(
         base_url: updated.base_url,
         model_id: updated.model_id,
         is_active: updated.is_active,
+        provider: updated.provider,
+        input_price: updated.input_price,
+        output_price: updated.output_price,
+        api_key_set: api_key_set(&updated.api_key),
     }))
 }
 pub async fn test_connection(
+    State(db): State<DatabaseConnection>,
     Json(payload): Json<TestConnectionRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
-    let client = reqwest::Client::new();
+    let provider = ai_provider::build(
+        payload.provider,
+        &payload.base_url,
+        &payload.api_key,
+        reqwest::Client::new(),
+    );
     let start_time = std::time::Instant::now();
 
-    // Construct Chat Completion request
-    // URL: base_url + /chat/completions (user provides full path including /v1)
-    let base = payload.base_url.trim_end_matches('/');
-    let url = format!("{}/chat/completions", base);
-
-    let body = serde_json::json!({
-        "model": payload.model_id,
-        "messages": [
-            {"role": "user", "content": "Hello"}
-        ],
-        "max_tokens": 5
-    });
-
-    let res = client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", payload.api_key))
-        .header("Content-Type", "application/json")
-        .json(&body)
-        .send()
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({"error": format!("Request failed: {}", e)})),
-            )
-        })?;
-
-    if !res.status().is_success() {
-        let err_text = res.text().await.unwrap_or_default();
-        tracing::error!("AI Connection Test Failed: {}", err_text);
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({"error": format!("API Error: {}", err_text)})),
-        ));
-    }
-
-    // Parse response to ensure it's valid JSON
-    let _json: Value = res.json().await.map_err(|e| {
+    let usage = UsageRecorder::start(
+        &db,
+        UsageChannel::transient(&payload.base_url, &payload.model_id),
+        ai_usage::FEATURE_TEST,
+    );
+    let res = provider.chat(&hello_request(&payload.model_id)).await;
+    usage.finish(&res);
+    res.map_err(|e| {
+        tracing::error!("AI Connection Test Failed: {}", e);
         (
             StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({"error": format!("Invalid JSON response: {}", e)})),
+            Json(serde_json::json!({"error": e.to_string()})),
         )
     })?;
 
@@ -257,49 +326,49 @@ pub async fn test_connection(
     })))
 }
 
+/// 测试连通性用的最小对话请求
+fn hello_request(model_id: &str) -> ChatRequest {
+    ChatRequest {
+        model: model_id.to_string(),
+        messages: vec![ChatMessage::user("Hello")],
+        max_tokens: Some(5),
+        ..Default::default()
+    }
+}
+
 /// GET /api/ai/models - List Models (Proxy)
 /// Query params: base_url, api_key (Transient, not saved)
 #[derive(Deserialize)]
 pub struct ListModelsQuery {
     pub base_url: String,
     pub api_key: String,
+    #[serde(default)]
+    pub provider: ProviderKind,
 }
 
+/// 各类渠道的模型列表统一按 OpenAI `/models` 的格式返回
 pub async fn list_models_proxy(
     axum::extract::Query(query): axum::extract::Query<ListModelsQuery>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
-    let client = reqwest::Client::new();
-    let base = query.base_url.trim_end_matches('/');
-    let url = format!("{}/models", base);
-
-    let res = client
-        .get(&url)
-        .header("Authorization", format!("Bearer {}", query.api_key))
-        .send()
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({"error": format!("Request failed: {}", e)})),
-            )
-        })?;
-
-    if !res.status().is_success() {
-        let err_text = res.text().await.unwrap_or_default();
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({"error": format!("API Error: {}", err_text)})),
-        ));
-    }
+    let provider = ai_provider::build(
+        query.provider,
+        &query.base_url,
+        &query.api_key,
+        reqwest::Client::new(),
+    );
 
-    let json: Value = res.json().await.map_err(|e| {
+    let models = provider.list_models().await.map_err(|e| {
         (
             StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({"error": format!("Invalid JSON response: {}", e)})),
+            Json(serde_json::json!({"error": e.to_string()})),
         )
     })?;
 
-    Ok(Json(json))
+    let data: Vec<Value> = models
+        .into_iter()
+        .map(|id| serde_json::json!({"id": id, "object": "model"}))
+        .collect();
+    Ok(Json(serde_json::json!({"object": "list", "data": data})))
 }
 
 #[derive(Serialize)]
@@ -332,51 +401,47 @@ pub async fn test_saved_channels(
     // Parallel testing could be better, but sequential is safer for rate limits
     // and simplicity for now.
     for channel in channels {
-        let base = channel.base_url.trim_end_matches('/');
-        let url = format!("{}/chat/completions", base);
-
-        let body = serde_json::json!({
-            "model": channel.model_id,
-            "messages": [
-                {"role": "user", "content": "Hello"}
-            ],
-            "max_tokens": 5
-        });
+        let provider = match ai_provider::for_channel(&channel, client.clone()) {
+            Ok(provider) => provider,
+            Err(e) => {
+                results.push(ChannelTestResult {
+                    id: channel.id,
+                    name: channel.name,
+                    success: false,
+                    message: e.to_string(),
+                    latency_ms: None,
+                });
+                continue;
+            }
+        };
 
         let start_time = std::time::Instant::now();
-        let res = client
-            .post(&url)
-            .header("Authorization", format!("Bearer {}", channel.api_key))
-            .header("Content-Type", "application/json")
-            .json(&body)
-            .send()
-            .await;
+        let usage = UsageRecorder::start(&db, UsageChannel::from(&channel), ai_usage::FEATURE_TEST);
+        let res = provider.chat(&hello_request(&channel.model_id)).await;
+        usage.finish(&res);
 
         let latency_ms = start_time.elapsed().as_millis() as u64;
 
         match res {
-            Ok(response) => {
-                if response.status().is_success() {
-                    results.push(ChannelTestResult {
-                        id: channel.id,
-                        name: channel.name,
-                        success: true,
-                        message: "OK".to_string(),
-                        latency_ms: Some(latency_ms),
-                    });
-                } else {
-                    let err_text = response
-                        .text()
-                        .await
-                        .unwrap_or_else(|_| "Unknown error".to_string());
-                    results.push(ChannelTestResult {
-                        id: channel.id,
-                        name: channel.name,
-                        success: false,
-                        message: err_text,
-                        latency_ms: Some(latency_ms),
-                    });
-                }
+            Ok(_) => {
+                // 测试通过的渠道立即解除熔断
+                ai_router::reset_breaker(channel.id);
+                results.push(ChannelTestResult {
+                    id: channel.id,
+                    name: channel.name,
+                    success: true,
+                    message: "OK".to_string(),
+                    latency_ms: Some(latency_ms),
+                });
+            }
+            Err(ProviderError::Http { body, .. }) => {
+                results.push(ChannelTestResult {
+                    id: channel.id,
+                    name: channel.name,
+                    success: false,
+                    message: body,
+                    latency_ms: Some(latency_ms),
+                });
             }
             Err(e) => {
                 results.push(ChannelTestResult {
@@ -393,6 +458,151 @@ pub async fn test_saved_channels(
     Ok(Json(results))
 }
 
+#[derive(Serialize)]
+pub struct RoutingResponse {
+    /// 各功能的渠道路由，未列出的功能使用全局渠道
+    pub features: ai_router::RoutingConfig,
+    /// 有失败记录的渠道的熔断状态
+    pub breakers: Vec<ai_router::BreakerStatus>,
+}
+
+/// GET /api/ai/routing - 各功能的渠道路由与熔断状态
+pub async fn get_routing(
+    State(db): State<DatabaseConnection>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let features = ai_router::load_config(&db).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": e.to_string()})),
+        )
+    })?;
+    Ok(Json(RoutingResponse {
+        features,
+        breakers: ai_router::breaker_statuses(),
+    }))
+}
+
+/// PUT /api/ai/routing - 保存各功能的渠道路由，渠道列表为空的功能恢复使用全局渠道
+pub async fn update_routing(
+    State(db): State<DatabaseConnection>,
+    Json(mut payload): Json<ai_router::RoutingConfig>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let bad_request = |msg: String| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": msg})),
+        )
+    };
+    let db_err = |e: sea_orm::DbErr| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": e.to_string()})),
+        )
+    };
+
+    payload.retain(|_, policy| !policy.channels.is_empty());
+    let channel_ids: std::collections::HashSet<Uuid> = ai_channel::Entity::find()
+        .all(&db)
+        .await
+        .map_err(db_err)?
+        .into_iter()
+        .map(|c| c.id)
+        .collect();
+    for (feature, policy) in &payload {
+        if !ai_router::ROUTED_FEATURES.contains(&feature.as_str()) {
+            return Err(bad_request(format!("未知的功能类型: {}", feature)));
+        }
+        let mut seen = std::collections::HashSet::new();
+        for target in &policy.channels {
+            if !channel_ids.contains(&target.channel_id) {
+                return Err(bad_request(format!("渠道不存在: {}", target.channel_id)));
+            }
+            if !seen.insert(target.channel_id) {
+                return Err(bad_request(format!("{} 中有重复的渠道", feature)));
+            }
+            if target.weight == 0 {
+                return Err(bad_request("权重必须大于 0".to_string()));
+            }
+        }
+    }
+
+    ai_router::save_config(&db, &payload)
+        .await
+        .map_err(db_err)?;
+    Ok(Json(RoutingResponse {
+        features: payload,
+        breakers: ai_router::breaker_statuses(),
+    }))
+}
+
+/// 默认统计最近 30 天
+const USAGE_DEFAULT_DAYS: i64 = 30;
+
+#[derive(Deserialize)]
+pub struct UsageQuery {
+    /// 起止日期（含），格式 YYYY-MM-DD
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+    pub channel_id: Option<Uuid>,
+    pub feature: Option<String>,
+    /// 相对 UTC 的分钟数，用于按本地日期分组，东八区为 480
+    #[serde(default)]
+    pub tz_offset: i32,
+}
+
+/// GET /api/ai/usage - 按日、渠道、功能汇总调用次数、token 用量与估算费用
+pub async fn usage_summary(
+    State(db): State<DatabaseConnection>,
+    axum::extract::Query(query): axum::extract::Query<UsageQuery>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let bad_request = |msg: &str| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": msg})),
+        )
+    };
+    if !(-14 * 60..=14 * 60).contains(&query.tz_offset) {
+        return Err(bad_request("无效的时区偏移"));
+    }
+    if let Some(feature) = query.feature.as_deref() {
+        if !ai_usage::FEATURES.contains(&feature) {
+            return Err(bad_request("未知的功能类型"));
+        }
+    }
+
+    let today =
+        (chrono::Utc::now() + chrono::Duration::minutes(query.tz_offset as i64)).date_naive();
+    let to = query.to.unwrap_or(today);
+    let from = match query.from {
+        Some(from) => from,
+        None => to
+            .checked_sub_signed(chrono::Duration::days(USAGE_DEFAULT_DAYS - 1))
+            .ok_or_else(|| bad_request("日期超出范围"))?,
+    };
+    if from > to {
+        return Err(bad_request("开始日期不能晚于结束日期"));
+    }
+
+    let filter = ai_usage::UsageFilter {
+        from,
+        to,
+        channel_id: query.channel_id,
+        feature: query.feature,
+        tz_offset: query.tz_offset,
+    };
+    if filter.utc_range().is_none() {
+        return Err(bad_request("日期超出范围"));
+    }
+    let summary = ai_usage::summarize(&db, &filter).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": e.to_string()})),
+        )
+    })?;
+
+    Ok(Json(summary))
+}
+
 #[derive(Deserialize)]
 pub struct GenerateOverviewRequest {
     pub card_id: Uuid,
@@ -419,64 +629,68 @@ pub async fn generate_overview(
     let mut logs: Vec<String> = Vec::new();
     logs.push("开始处理生成概览请求...".to_string());
 
-    // 1. 获取 AI 配置
-    logs.push("正在获取 全局 AI 配置 (ai_config_global)...".to_string());
-    let config_setting = setting::Entity::find_by_id("ai_config_global")
-        .one(&db)
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({"error": e.to_string()})),
-            )
-        })?;
+    match overview_single(&db, payload.card_id, &mut logs).await {
+        Ok((summary, tags)) => {
+            event_bus::publish(AppEvent::CardUpdated {
+                id: payload.card_id,
+            });
+            logs.push("处理完成!".to_string());
 
-    let channel_id_str = match config_setting {
-        Some(s) => s.value,
-        None => {
-            let msg = "未配置 全局 AI 渠道 (ai_config_global)。请在系统设置中指定默认模型。";
-            logs.push(format!("错误: {}", msg));
-            return Err((
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({"error": msg, "logs": logs})),
-            ));
+            Ok(Json(OverviewResponse {
+                summary,
+                tags,
+                logs,
+            }))
         }
-    };
-
-    let channel_id = Uuid::parse_str(&channel_id_str).map_err(|_| {
-        let msg = "AI 配置 ID 格式无效";
-        logs.push(format!("错误: {}", msg));
-        (
-            StatusCode::BAD_REQUEST,
+        Err((status, msg)) => Err((
+            status,
             Json(serde_json::json!({"error": msg, "logs": logs})),
-        )
-    })?;
+        )),
+    }
+}
 
-    let channel = ai_channel::Entity::find_by_id(channel_id)
-        .one(&db)
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({"error": e.to_string()})),
-            )
-        })?
-        .ok_or_else(|| {
-            let msg = "配置的 AI 渠道不存在";
-            logs.push(format!("错误: {}", msg));
-            (
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({"error": msg, "logs": logs})),
-            )
-        })?;
-    logs.push(format!(
-        "使用渠道: {} (Model: {})",
-        channel.name, channel.model_id
-    ));
+/// 概览生成使用的渠道路由、提示词模板与全局提示词
+struct OverviewSource {
+    route: ChannelRoute,
+    template: PromptTemplate,
+    global_prompt: String,
+}
+
+/// 路由解析失败：配置问题返回 400，数据库错误返回 500
+fn route_err(e: RouteError) -> (StatusCode, String) {
+    let status = match e {
+        RouteError::Db(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    (status, e.to_string())
+}
+
+/// 读取概览功能的提示词模板、渠道路由与全局提示词
+async fn load_overview_source(
+    db: &DatabaseConnection,
+    logs: &mut Vec<String>,
+) -> Result<OverviewSource, (StatusCode, String)> {
+    // 1. 获取提示词模板与 AI 配置
+    let template = load_template(db, prompt_template::OVERVIEW).await?;
+    logs.push(if template.customized {
+        "使用自定义提示词模板".to_string()
+    } else {
+        "使用内置提示词模板".to_string()
+    });
+    logs.push("正在获取 AI 渠道配置...".to_string());
+    let route =
+        ai_router::resolve_with_override(db, ai_usage::FEATURE_OVERVIEW, template.channel_id)
+            .await
+            .map_err(|e| {
+                let (status, msg) = route_err(e);
+                logs.push(format!("错误: {}", msg));
+                (status, msg)
+            })?;
+    logs.push(format!("渠道路由: {}", route.describe()));
 
     // 1.5. 获取全局提示词
     let global_prompt_setting = setting::Entity::find_by_id("global_prompt")
-        .one(&db)
+        .one(db)
         .await
         .unwrap_or(None);
     let global_prompt = global_prompt_setting.map(|s| s.value).unwrap_or_default();
@@ -486,218 +700,133 @@ pub async fn generate_overview(
         logs.push("未配置局全局提示词".to_string());
     }
 
-    // 2. 获取角色卡数据
-    logs.push(format!("正在获取角色卡: {}", payload.card_id));
-    let card = character_card::Entity::find_by_id(payload.card_id)
-        .one(&db)
+    Ok(OverviewSource {
+        route,
+        template,
+        global_prompt,
+    })
+}
+
+/// 读取内置功能的提示词模板
+async fn load_template(
+    db: &DatabaseConnection,
+    feature: &str,
+) -> Result<PromptTemplate, (StatusCode, String)> {
+    prompt_template::load(db, feature)
         .await
-        .map_err(|e| {
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| {
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({"error": e.to_string()})),
+                format!("缺少内置提示词模板: {}", feature),
             )
-        })?
+        })
+}
+
+/// 单卡概览：读取配置与角色卡，按需加载系统标签库后生成
+async fn overview_single(
+    db: &DatabaseConnection,
+    card_id: Uuid,
+    logs: &mut Vec<String>,
+) -> Result<(String, Option<Vec<String>>), (StatusCode, String)> {
+    let source = load_overview_source(db, logs).await?;
+
+    // 2. 获取角色卡数据
+    logs.push(format!("正在获取角色卡: {}", card_id));
+    let card = character_card::Entity::find_by_id(card_id)
+        .one(db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
         .ok_or_else(|| {
             let msg = "角色卡不存在";
             logs.push(format!("错误: {}", msg));
-            (
-                StatusCode::NOT_FOUND,
-                Json(serde_json::json!({"error": msg, "logs": logs})),
-            )
+            (StatusCode::NOT_FOUND, msg.to_string())
         })?;
 
-    // 解析 JSON data
-    let card_data: Value = serde_json::from_str(&card.data).unwrap_or(serde_json::json!({}));
-
-    // 提取字段
-    let name = card.name.clone();
-    let description = card.description.clone().unwrap_or_default();
-    let personality = card_data["personality"].as_str().unwrap_or("").to_string();
-    let scenario = card_data["scenario"].as_str().unwrap_or("").to_string();
-    let first_mes = card_data["first_mes"].as_str().unwrap_or("").to_string();
-    let mes_example = card_data["mes_example"].as_str().unwrap_or("").to_string();
-    let creatorcomment = card_data["creatorcomment"]
-        .as_str()
-        .unwrap_or("")
-        .to_string();
-    let system_prompt = card_data["system_prompt"]
-        .as_str()
-        .unwrap_or("")
-        .to_string();
-    let post_history_instructions = card_data["post_history_instructions"]
-        .as_str()
-        .unwrap_or("")
-        .to_string();
-
-    logs.push("字段提取完成:".to_string());
-    logs.push(format!("- Name: {}", name));
-    logs.push(format!("- Description length: {}", description.len()));
-    logs.push(format!("- Personality length: {}", personality.len()));
-    logs.push(format!("- Scenario length: {}", scenario.len()));
-    logs.push(format!("- First Mes length: {}", first_mes.len()));
-
-    // 3. 检查标签策略
-    let current_tags_json: Vec<String> = serde_json::from_str(&card.tags).unwrap_or_default();
-    let generate_tags = current_tags_json.is_empty();
-
-    let mut system_tags_str = String::new();
-    if generate_tags {
+    let current_tags: Vec<String> = serde_json::from_str(&card.tags).unwrap_or_default();
+    let system_tags = if current_tags.is_empty() {
         logs.push("当前无标签，将生成标签。正在获取系统标签库...".to_string());
-        // 获取所有 tags
-        let all_cards = character_card::Entity::find()
-            .all(&db)
+        let tags_vec = crate::services::tag_index::card_tag_names(db)
             .await
             .unwrap_or_default();
-        let mut all_tags = std::collections::HashSet::new();
-        for c in all_cards {
-            if let Ok(tags) = serde_json::from_str::<Vec<String>>(&c.tags) {
-                for t in tags {
-                    all_tags.insert(t);
-                }
-            }
-        }
-        let tags_vec: Vec<String> = all_tags.into_iter().collect();
-        system_tags_str = serde_json::to_string(&tags_vec).unwrap_or_default();
         logs.push(format!("系统标签库共 {} 个标签", tags_vec.len()));
+        tags_vec
     } else {
         logs.push("当前已有标签，跳过标签生成。".to_string());
-    }
-
-    // 4. 构建 Prompt
-    let task_instruction = if generate_tags {
-        format!(
-            r#"
-[任务与约束]
-1. 概览总结：150字以内，精炼概括角色核心特征。
-2. 标签生成：最多5个。必须优先从以下[系统现有标签]中选择；仅当无匹配时才生成新标签。
-   [系统现有标签]: {}
-
-[回复格式]
-请严格仅返回 JSON，不要使用代码块：
-{{"summary": "...", "tags": ["tag1", "tag2"]}}
-"#,
-            system_tags_str
-        )
-    } else {
-        r#"
-[任务与约束]
-1. 概览总结：150字以内，精炼概括角色核心特征。
-
-[回复格式]
-请严格仅返回 JSON，不要使用代码块：
-{"summary": "..."}
-"#
-        .to_string()
+        Vec::new()
     };
 
-    let user_content = format!(
-        r#"请深入分析以下角色卡数据：
-
-[角色元数据]
-Name: {}
-Description: {}
-
-[详细设定]
-Personality: {}
-Scenario: {}
-First Message: {}
-Example Dialogue: {}
-System Prompt: {}
-Post Instructions: {}
-Creator Comment: {}
-
-{}"#,
-        name,
-        description,
-        personality,
-        scenario,
-        first_mes,
-        mes_example,
-        system_prompt,
-        post_history_instructions,
-        creatorcomment,
-        task_instruction
-    );
+    overview_card(db, &source, card, &system_tags, logs).await
+}
 
-    logs.push("Prompt 构建完成".to_string());
-    // logs.push(format!("User Content:\n{}", user_content)); // 若太长可注释
+/// 为角色卡生成概览（卡片无标签时一并生成标签）并写回数据库，返回概览与写入的标签
+///
+/// `system_tags` 为系统标签库，仅在需要生成标签时放入提示词
+async fn overview_card(
+    db: &DatabaseConnection,
+    source: &OverviewSource,
+    card: character_card::Model,
+    system_tags: &[String],
+    logs: &mut Vec<String>,
+) -> Result<(String, Option<Vec<String>>), (StatusCode, String)> {
+    let template = &source.template;
+    let variables = prompt_template::overview_variables(&card, system_tags, &source.global_prompt);
+    let generate_tags = !variables["generate_tags"].is_empty();
 
-    // 5. 调用 AI
-    let client = reqwest::Client::new();
-    let base = channel.base_url.trim_end_matches('/');
-    let url = format!("{}/chat/completions", base);
+    logs.push("字段提取完成:".to_string());
+    logs.push(format!("- Name: {}", card.name));
+    for key in ["description", "personality", "scenario", "first_mes"] {
+        logs.push(format!("- {} length: {}", key, variables[key].len()));
+    }
+    if !generate_tags {
+        logs.push("当前已有标签，提示词中不要求生成标签".to_string());
+    }
 
-    // 构建系统提示词：全局提示词 + 功能提示词
-    let base_system_prompt = "你是一位专业的角色卡分析师。请分析角色设定，返回纯 JSON 格式结果，不要包含 markdown 标记。";
-    let system_prompt_content = if global_prompt.is_empty() {
-        base_system_prompt.to_string()
-    } else {
-        format!("{}\n\n{}", global_prompt, base_system_prompt)
-    };
+    // 4. 按模板构建 Prompt
+    let system_prompt_content = template
+        .system_prompt
+        .as_deref()
+        .map(|t| prompt_template::render(t, &variables).text)
+        .unwrap_or_default();
+    let user_content = template
+        .user_prompt
+        .as_deref()
+        .map(|t| prompt_template::render(t, &variables).text)
+        .unwrap_or_default();
+
+    logs.push("Prompt 构建完成".to_string());
     logs.push(format!(
         "System Prompt 长度: {} 字符",
         system_prompt_content.len()
     ));
 
-    let body = serde_json::json!({
-        "model": channel.model_id,
-        "messages": [
-            {
-                "role": "system",
-                "content": system_prompt_content
-            },
-            {"role": "user", "content": user_content}
-        ],
-        "temperature": 1.0,
-        "max_tokens": 4096,
-        "safety_settings": [
-            {"category": "HARM_CATEGORY_HARASSMENT", "threshold": "BLOCK_NONE"},
-            {"category": "HARM_CATEGORY_HATE_SPEECH", "threshold": "BLOCK_NONE"},
-            {"category": "HARM_CATEGORY_SEXUALLY_EXPLICIT", "threshold": "BLOCK_NONE"},
-            {"category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": "BLOCK_NONE"}
-        ],
-        "response_format": { "type": "json_object" }
-    });
+    // 5. 调用 AI（模型由路由按渠道填入）
+    let mut messages = Vec::new();
+    if !system_prompt_content.is_empty() {
+        messages.push(ChatMessage::system(system_prompt_content));
+    }
+    messages.push(ChatMessage::user(user_content));
+    let request = ChatRequest {
+        messages,
+        temperature: Some(template.temperature),
+        max_tokens: Some(4096),
+        json_mode: true,
+        ..Default::default()
+    };
 
-    logs.push(format!("正在请求 AI 接口: {}", url));
     let start_time = std::time::Instant::now();
 
-    let res = client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", channel.api_key))
-        .header("Content-Type", "application/json")
-        .json(&body)
-        .send()
-        .await
-        .map_err(|e| {
-            let msg = format!("请求失败: {}", e);
-            logs.push(msg.clone());
-            (
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({"error": msg, "logs": logs})),
-            )
-        })?;
-
-    logs.push(format!("AI 响应状态: {}", res.status()));
-
-    if !res.status().is_success() {
-        let err_text = res.text().await.unwrap_or_default();
-        let msg = format!("API 错误: {}", err_text);
+    let served = source.route.chat(db, &request, logs).await.map_err(|e| {
+        let msg = match e {
+            ProviderError::Network(e) => format!("请求失败: {}", e),
+            ProviderError::Http { body, .. } => format!("API 错误: {}", body),
+            ProviderError::Parse(e) => format!("无效的 JSON 响应: {}", e),
+            e => e.to_string(),
+        };
         logs.push(msg.clone());
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({"error": msg, "logs": logs})),
-        ));
-    }
-
-    let json_res: Value = res.json().await.map_err(|e| {
-        let msg = format!("无效的 JSON 响应: {}", e);
-        logs.push(msg.clone());
-        (
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({"error": msg, "logs": logs})),
-        )
-    })?;
+        (StatusCode::BAD_REQUEST, msg)
+    })?;
+    let response = served.value;
 
     let latency = start_time.elapsed().as_millis();
     logs.push(format!("请求耗时: {}ms", latency));
@@ -705,26 +834,15 @@ Creator Comment: {}
     // 记录完整的 AI 响应结构（用于调试）
     logs.push(format!(
         "Raw JSON Response: {}",
-        serde_json::to_string(&json_res).unwrap_or_default()
+        serde_json::to_string(&response.raw).unwrap_or_default()
     ));
 
-    // 提取 content
-    let content = json_res["choices"][0]["message"]["content"]
-        .as_str()
-        .ok_or_else(|| {
-            let msg = "AI 响应无 content 字段".to_string();
-            logs.push(msg.clone());
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({"error": msg, "logs": logs})),
-            )
-        })?;
-
+    let content = response.content.as_str();
     logs.push(format!("Raw Content: {}", content));
 
     // 检查空内容（可能是安全过滤导致）
     if content.trim().is_empty() {
-        let completion_tokens = json_res["usage"]["completion_tokens"].as_u64().unwrap_or(0);
+        let completion_tokens = response.usage.map_or(0, |u| u.completion_tokens);
         let msg = if completion_tokens == 0 {
             "AI 返回空内容 (completion_tokens=0)。可能是模型安全过滤触发，请尝试更换渠道/模型。"
                 .to_string()
@@ -732,10 +850,7 @@ Creator Comment: {}
             "AI 返回空内容".to_string()
         };
         logs.push(msg.clone());
-        return Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({"error": msg, "logs": logs})),
-        ));
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, msg));
     }
 
     // 清理 markdown code block if present
@@ -750,10 +865,7 @@ Creator Comment: {}
     let ai_result: AiOverviewJson = serde_json::from_str(cleaned_content).map_err(|e| {
         let msg = format!("无法解析 AI 返回的 JSON: {}", e);
         logs.push(msg.clone());
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({"error": msg, "logs": logs})),
-        )
+        (StatusCode::INTERNAL_SERVER_ERROR, msg)
     })?;
 
     // 6. 更新数据库
@@ -762,8 +874,12 @@ Creator Comment: {}
 
     let mut final_tags = None;
     if generate_tags {
-        if let Some(ref tags) = ai_result.tags {
+        if let Some(tags) = ai_result.tags.clone() {
             logs.push(format!("生成了 {} 个标签: {:?}", tags.len(), tags));
+            // 套用标签别名规则
+            let tags = crate::services::tag_admin::apply_aliases(db, tags.clone())
+                .await
+                .unwrap_or(tags);
             let tags_json = serde_json::to_string_pretty(&tags).unwrap_or("[]".to_string());
             update_model.tags = Set(tags_json);
 
@@ -783,12 +899,17 @@ Creator Comment: {}
                 obj.insert("tags".to_string(), serde_json::json!(tags));
             }
 
+            // 修改 data 前保存自动快照（失败不影响保存）
+            if let Err(e) = crate::services::version_snapshot::auto_snapshot(db, &card).await {
+                tracing::error!("Failed to create auto snapshot for card {}: {}", card.id, e);
+            }
+
             // 写回 data 字段（保持格式化）
             update_model.data =
                 Set(serde_json::to_string_pretty(&current_json).unwrap_or(card.data.clone()));
             logs.push("已同步更新 data JSON 中的 tags 字段".to_string());
 
-            final_tags = Some(tags.clone());
+            final_tags = Some(tags);
         }
     } else {
         logs.push(format!("仅更新概览: {}", ai_result.summary));
@@ -797,146 +918,390 @@ Creator Comment: {}
     update_model.metadata_modified = Set(true);
     update_model.updated_at = Set(chrono::Utc::now().naive_utc());
 
-    update_model.update(&db).await.map_err(|e| {
+    update_model.update(db).await.map_err(|e| {
         let msg = format!("数据库更新失败: {}", e);
         logs.push(msg.clone());
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({"error": msg, "logs": logs})),
-        )
+        (StatusCode::INTERNAL_SERVER_ERROR, msg)
     })?;
 
-    logs.push("处理完成!".to_string());
+    Ok((ai_result.summary, final_tags))
+}
 
-    Ok(Json(OverviewResponse {
-        summary: ai_result.summary,
-        tags: final_tags,
-        logs,
-    }))
+/// 批量生成概览的任务类型
+pub const OVERVIEW_BATCH_JOB: &str = "overview_batch";
+
+/// 批量生成概览时默认与最大的并发请求数
+const OVERVIEW_BATCH_CONCURRENCY: usize = 3;
+const OVERVIEW_BATCH_MAX_CONCURRENCY: usize = 8;
+
+#[derive(Deserialize)]
+pub struct BatchOverviewRequest {
+    #[serde(default)]
+    pub ids: Vec<Uuid>,
+    /// 筛选表达式，与 ids 二选一（同时给出时取交集）
+    pub filter: Option<String>,
+    /// 为 true 时已有概览的角色卡也重新生成
+    #[serde(default)]
+    pub force: bool,
+    /// 同时请求 AI 的角色卡数量
+    pub concurrency: Option<usize>,
+    /// 为 true 时不等待处理过程，直接返回后台任务
+    #[serde(default)]
+    pub background: bool,
+}
+
+/// 批量概览任务参数（入队时已解析出卡片 ID）
+#[derive(Serialize, Deserialize)]
+struct BatchOverviewPayload {
+    ids: Vec<Uuid>,
+    force: bool,
+    concurrency: usize,
+}
+
+/// 批量概览的最终报告，作为任务结果保存
+#[derive(Serialize, Default)]
+pub struct BatchOverviewReport {
+    pub succeeded: Vec<BatchOverviewItem>,
+    pub failed: Vec<BatchOverviewFailure>,
+    /// 已有概览而跳过的角色卡
+    pub skipped: Vec<Uuid>,
+    /// 系统标签库中原本没有、本次新引入的标签
+    pub new_tags: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct BatchOverviewItem {
+    pub id: Uuid,
+    pub name: String,
+    pub summary: String,
+    pub tags: Option<Vec<String>>,
+}
+
+#[derive(Serialize)]
+pub struct BatchOverviewFailure {
+    pub id: Uuid,
+    pub name: Option<String>,
+    pub error: String,
+}
+
+enum BatchOverviewOutcome {
+    Succeeded(BatchOverviewItem),
+    Failed(BatchOverviewFailure),
+    Skipped { id: Uuid, name: String },
+}
+
+/// POST /api/ai/card/overview/batch - 批量生成概览与标签 (SSE)
+///
+/// 以后台任务执行，推送的每条消息为完整的任务记录，`message` 为最近处理的角色卡；
+/// `background` 为 true 时直接返回任务
+pub async fn batch_generate_overview(
+    State(db): State<DatabaseConnection>,
+    Json(payload): Json<BatchOverviewRequest>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let json_err =
+        |(status, msg): (StatusCode, String)| (status, Json(serde_json::json!({"error": msg})));
+
+    let ids = crate::api::cards::resolve_batch_ids(&db, payload.ids, payload.filter.as_deref())
+        .await
+        .map_err(json_err)?;
+    if ids.is_empty() {
+        return Err(json_err((
+            StatusCode::BAD_REQUEST,
+            "未选择角色卡".to_string(),
+        )));
+    }
+
+    // 先校验 AI 配置，配置有误时直接返回错误
+    load_overview_source(&db, &mut Vec::new())
+        .await
+        .map_err(json_err)?;
+
+    let job_payload = BatchOverviewPayload {
+        ids,
+        force: payload.force,
+        concurrency: payload
+            .concurrency
+            .unwrap_or(OVERVIEW_BATCH_CONCURRENCY)
+            .clamp(1, OVERVIEW_BATCH_MAX_CONCURRENCY),
+    };
+    // 失败的卡片记录在报告中，整个任务不重试
+    let job = job_queue::enqueue(
+        &db,
+        OVERVIEW_BATCH_JOB,
+        serde_json::to_value(&job_payload).unwrap_or_default(),
+        1,
+    )
+    .await
+    .map_err(|e| json_err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())))?;
+    if payload.background {
+        return Ok((StatusCode::ACCEPTED, Json(job)).into_response());
+    }
+
+    Ok(crate::api::jobs::event_stream(db, job.id).into_response())
+}
+
+/// 批量概览任务：系统标签库只读取一次，按并发上限逐卡生成
+pub async fn run_overview_batch_job(ctx: JobContext) -> Result<Value, JobError> {
+    let payload: BatchOverviewPayload = serde_json::from_value(ctx.payload.clone())
+        .map_err(|e| JobError::Fatal(format!("任务参数无效: {}", e)))?;
+    let source = load_overview_source(&ctx.db, &mut Vec::new())
+        .await
+        .map_err(|(_, e)| JobError::Fatal(e))?;
+    let system_tags = crate::services::tag_index::card_tag_names(&ctx.db).await?;
+
+    let total = payload.ids.len();
+    ctx.progress(0.0, format!("共 {} 张角色卡", total)).await;
+
+    let db = &ctx.db;
+    let (source, system_tags) = (&source, &system_tags);
+    let force = payload.force;
+    let mut outcomes = futures::stream::iter(payload.ids)
+        .map(|id| async move {
+            let card = match character_card::Entity::find_by_id(id).one(db).await {
+                Ok(Some(card)) if card.deleted_at.is_none() => card,
+                Ok(_) => {
+                    return BatchOverviewOutcome::Failed(BatchOverviewFailure {
+                        id,
+                        name: None,
+                        error: "角色卡不存在".to_string(),
+                    })
+                }
+                Err(e) => {
+                    return BatchOverviewOutcome::Failed(BatchOverviewFailure {
+                        id,
+                        name: None,
+                        error: e.to_string(),
+                    })
+                }
+            };
+
+            let has_summary = card
+                .custom_summary
+                .as_deref()
+                .is_some_and(|s| !s.trim().is_empty());
+            if has_summary && !force {
+                return BatchOverviewOutcome::Skipped {
+                    id,
+                    name: card.name,
+                };
+            }
+
+            let name = card.name.clone();
+            let mut logs = Vec::new();
+            match overview_card(db, source, card, system_tags, &mut logs).await {
+                Ok((summary, tags)) => BatchOverviewOutcome::Succeeded(BatchOverviewItem {
+                    id,
+                    name,
+                    summary,
+                    tags,
+                }),
+                Err((_, error)) => {
+                    tracing::warn!("Batch overview failed for card {}: {}", id, error);
+                    BatchOverviewOutcome::Failed(BatchOverviewFailure {
+                        id,
+                        name: Some(name),
+                        error,
+                    })
+                }
+            }
+        })
+        .buffer_unordered(payload.concurrency);
+
+    let mut report = BatchOverviewReport::default();
+    let mut known_tags: std::collections::HashSet<String> = system_tags.iter().cloned().collect();
+    let mut done = 0usize;
+    while let Some(outcome) = outcomes.next().await {
+        done += 1;
+        let message = match outcome {
+            BatchOverviewOutcome::Succeeded(item) => {
+                for tag in item.tags.iter().flatten() {
+                    if known_tags.insert(tag.clone()) {
+                        report.new_tags.push(tag.clone());
+                    }
+                }
+                let message = format!("[{}/{}] {}：已生成", done, total, item.name);
+                report.succeeded.push(item);
+                message
+            }
+            BatchOverviewOutcome::Failed(failure) => {
+                let message = format!(
+                    "[{}/{}] {}：失败 - {}",
+                    done,
+                    total,
+                    failure.name.as_deref().unwrap_or("未知角色卡"),
+                    failure.error
+                );
+                report.failed.push(failure);
+                message
+            }
+            BatchOverviewOutcome::Skipped { id, name } => {
+                report.skipped.push(id);
+                format!("[{}/{}] {}：已有概览，跳过", done, total, name)
+            }
+        };
+        ctx.progress(done as f64 / total as f64, message).await;
+    }
+
+    if !report.succeeded.is_empty() {
+        event_bus::publish(AppEvent::CardsUpdated {
+            ids: report.succeeded.iter().map(|item| item.id).collect(),
+        });
+    }
+
+    Ok(serde_json::to_value(&report).unwrap_or_default())
 }
 
 #[derive(Deserialize)]
 pub struct ExecuteFeatureRequest {
     pub feature_id: String,               // e.g. "overview"
     pub messages: Vec<serde_json::Value>, // [{"role": "user", "content": "..."}]
+    /// 为 true 时以 SSE 逐段返回
+    #[serde(default)]
+    pub stream: bool,
 }
 
 /// POST /api/ai/execute - Execute generic AI task based on feature config
+///
+/// 流式模式下依次推送 `delta`（文本片段）、`usage`、`finish` 事件，出错时推送 `error`，
+/// 最后以 `done` 结束；客户端断开后立即断开与服务商的连接
 pub async fn execute_feature(
     State(db): State<DatabaseConnection>,
     Json(payload): Json<ExecuteFeatureRequest>,
-) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
-    // 1. Resolve Config Key
-    let config_key = "ai_config_global";
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let json_err =
+        |(status, msg): (StatusCode, String)| (status, Json(serde_json::json!({"error": msg})));
 
-    // 2. Get Channel Config
-    let config_setting = setting::Entity::find_by_id(config_key)
-        .one(&db)
+    // 1. 读取功能的提示词模板，未知功能使用默认设置
+    let template = prompt_template::load(&db, &payload.feature_id)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({"error": e.to_string()})),
-            )
-        })?;
-
-    let channel_id_str = match config_setting {
-        Some(s) => s.value,
-        None => {
-            return Err((
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({"error": "没有配置全局AI模型，请到设置页面完成配置"})),
-            ));
+        .map_err(|e| json_err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())))?;
+
+    // 2. 按模板指定的渠道或路由配置解析候选渠道
+    let route = ai_router::resolve_with_override(
+        &db,
+        ai_usage::FEATURE_EXECUTE,
+        template.as_ref().and_then(|t| t.channel_id),
+    )
+    .await
+    .map_err(|e| json_err(route_err(e)))?;
+
+    // 3. 模板设置了系统提示词时替换请求中的系统消息
+    let mut messages: Vec<ChatMessage> = payload
+        .messages
+        .iter()
+        .map(ChatMessage::from_openai)
+        .collect();
+    if let Some(system) = template.as_ref().and_then(|t| t.system_prompt.as_deref()) {
+        let global_prompt = prompt_template::global_prompt(&db)
+            .await
+            .map_err(|e| json_err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())))?;
+        let variables =
+            prompt_template::Variables::from([("global_prompt".to_string(), global_prompt)]);
+        let system = prompt_template::render(system, &variables).text;
+        messages.retain(|m| m.role != "system");
+        if !system.is_empty() {
+            messages.insert(0, ChatMessage::system(system));
         }
+    }
+
+    // 4. Proxy Request（模型由路由按渠道填入）
+    let request = ChatRequest {
+        messages,
+        temperature: Some(template.as_ref().map_or(0.7, |t| t.temperature)),
+        ..Default::default()
     };
+    let mut logs = Vec::new();
 
-    let channel_id = Uuid::parse_str(&channel_id_str).map_err(|_| {
+    let request_err = |e: ProviderError| {
+        tracing::error!("AI Request Error: {}", e);
+        let msg = match e {
+            ProviderError::Http { body, .. } => format!("Provider API Error: {}", body),
+            e => e.to_string(),
+        };
         (
             StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({"error": "没有配置全局AI模型，请到设置页面完成配置"})),
+            Json(serde_json::json!({"error": msg})),
         )
-    })?;
-
-    let channel = ai_channel::Entity::find_by_id(channel_id)
-        .one(&db)
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({"error": e.to_string()})),
-            )
-        })?
-        .ok_or_else(|| {
-            (
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({"error": "配置的AI渠道已不存在，请重新配置"})),
-            )
-        })?;
-
-    // 3. Proxy Request
-    let client = reqwest::Client::new();
-    let base = channel.base_url.trim_end_matches('/');
-    let url = format!("{}/chat/completions", base);
-
-    // 简化请求体，仅保留 OpenAI 兼容参数
-    let body = serde_json::json!({
-        "model": channel.model_id,
-        "messages": payload.messages,
-        "temperature": 0.7
-    });
-
-    // 调试日志：打印请求内容
+    };
 
-    let res = client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", channel.api_key))
-        .header("Content-Type", "application/json")
-        .json(&body)
-        .send()
-        .await
-        .map_err(|e| {
-            tracing::error!("AI Request Error: {}", e);
-            (
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({"error": format!("Request failed: {}", e)})),
+    if payload.stream {
+        let served = route
+            .chat_stream(&db, &request, &mut logs)
+            .await
+            .map_err(request_err)?;
+        let stream = served
+            .value
+            .map(|item| {
+                let event = match item {
+                    Ok(event) => {
+                        let name = match &event {
+                            StreamEvent::Delta { .. } => "delta",
+                            StreamEvent::Usage(_) => "usage",
+                            StreamEvent::Finish { .. } => "finish",
+                        };
+                        Event::default()
+                            .event(name)
+                            .data(serde_json::to_string(&event).unwrap_or_default())
+                    }
+                    Err(e) => {
+                        tracing::warn!("AI stream error: {}", e);
+                        let data = serde_json::json!({"type": "error", "error": e.to_string()});
+                        Event::default().event("error").data(data.to_string())
+                    }
+                };
+                Ok::<_, Infallible>(event)
+            })
+            .chain(futures::stream::once(async {
+                Ok(Event::default().event("done").data(r#"{"type":"done"}"#))
+            }));
+
+        return Ok(Sse::new(stream)
+            .keep_alive(
+                axum::response::sse::KeepAlive::new()
+                    .interval(Duration::from_secs(15))
+                    .text("keep-alive"),
             )
-        })?;
-
-    // 获取响应状态和原始文本
-    let status = res.status();
-    let raw_text = res.text().await.unwrap_or_default();
-
-    // 调试日志：打印响应内容
-
-    if !status.is_success() {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({"error": format!("Provider API Error: {}", raw_text)})),
-        ));
+            .into_response());
     }
 
-    let json: Value = serde_json::from_str(&raw_text).map_err(|e| {
-        tracing::error!("JSON Parse Error: {}", e);
-        (
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({"error": format!("Invalid JSON response: {}", e)})),
-        )
-    })?;
+    let served = route
+        .chat(&db, &request, &mut logs)
+        .await
+        .map_err(request_err)?;
 
-    Ok(Json(json))
+    // 统一转换为 OpenAI 格式返回
+    Ok(Json(served.value.to_openai_json(&served.channel.model_id)).into_response())
 }
 
 // ==================== 小皮医生 (Doctor) API ====================
 
-use crate::entities::doctor_task;
+use crate::entities::{doctor_task, job};
+use crate::services::event_bus::{self, AppEvent};
+use crate::services::job_queue::{self, JobContext, JobError, JobStatus, RemoveOnDrop};
 use axum::response::sse::{Event, Sse};
-use futures::stream::{self, Stream};
+use axum::response::Response;
+use futures::StreamExt;
+use sea_orm::sea_query::Expr;
 use std::convert::Infallible;
 use std::time::Duration;
 
+/// 小皮医生任务类型
+pub const DOCTOR_JOB: &str = "doctor";
+
 #[derive(Deserialize)]
 pub struct DoctorAnalyzeRequest {
     pub card_id: Uuid,
+    /// 为 true 时不等待诊断过程，直接返回后台任务
+    #[serde(default)]
+    pub background: bool,
+}
+
+/// 诊断所需的渠道、已启用的世界书条目、初始对话与温度
+struct DoctorPlan {
+    route: ChannelRoute,
+    entries: Vec<Value>,
+    messages: Vec<ChatMessage>,
+    temperature: f64,
 }
 
 #[derive(Serialize)]
@@ -958,26 +1323,83 @@ struct SseProgress {
     debug: Option<String>,
 }
 
+/// 读取角色卡、提示词模板与 AI 配置，构建诊断的初始对话
+async fn prepare_doctor(
+    db: &DatabaseConnection,
+    card_id: Uuid,
+) -> Result<DoctorPlan, (StatusCode, String)> {
+    // 获取角色卡数据
+    let card = character_card::Entity::find_by_id(card_id)
+        .one(db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "角色卡不存在".to_string()))?;
+
+    // 获取提示词模板与 AI 配置
+    let template = load_template(db, prompt_template::DOCTOR).await?;
+    let global_prompt = prompt_template::global_prompt(db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let route = ai_router::resolve_with_override(db, ai_usage::FEATURE_DOCTOR, template.channel_id)
+        .await
+        .map_err(route_err)?;
+
+    // 按模板构建初始对话，世界书条目留给后续轮次按名称查阅
+    let context = prompt_template::doctor_variables(&card, &global_prompt);
+    let render = |t: &Option<String>| {
+        t.as_deref()
+            .map(|t| prompt_template::render(t, &context.variables).text)
+            .unwrap_or_default()
+    };
+    let system_prompt = render(&template.system_prompt);
+    let initial_user_msg = render(&template.user_prompt);
+
+    let mut messages = Vec::new();
+    if !system_prompt.is_empty() {
+        messages.push(ChatMessage::system(system_prompt));
+    }
+    messages.push(ChatMessage::user(initial_user_msg));
+
+    Ok(DoctorPlan {
+        route,
+        entries: context.entries,
+        messages,
+        temperature: template.temperature,
+    })
+}
+
+/// 同一角色卡尚未结束的诊断任务
+async fn active_doctor_job(
+    db: &DatabaseConnection,
+    card_id: Uuid,
+) -> Result<Option<job::Model>, sea_orm::DbErr> {
+    job_queue::find_active(
+        db,
+        DOCTOR_JOB,
+        Some(Expr::cust_with_values(
+            "json_extract(payload, '$.card_id') = ?",
+            [card_id.to_string()],
+        )),
+    )
+    .await
+}
+
 /// POST /api/ai/doctor/analyze - 执行诊断 (SSE)
+///
+/// 诊断作为后台任务执行，断开连接不会中断诊断；`background` 为 true 时直接返回任务，
+/// 之后可通过 `/api/jobs/{id}/events` 订阅
 pub async fn doctor_analyze(
     State(db): State<DatabaseConnection>,
     Json(payload): Json<DoctorAnalyzeRequest>,
-) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, Json<Value>)> {
+) -> Result<Response, (StatusCode, Json<Value>)> {
     let card_id = payload.card_id;
+    let json_err =
+        |(status, msg): (StatusCode, String)| (status, Json(serde_json::json!({"error": msg})));
 
     // 检查是否有正在运行的任务
-    let running_task = doctor_task::Entity::find()
-        .filter(doctor_task::Column::CharacterId.eq(card_id))
-        .filter(doctor_task::Column::Status.eq("running"))
-        .one(&db)
+    let running_task = active_doctor_job(&db, card_id)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({"error": e.to_string()})),
-            )
-        })?;
-
+        .map_err(|e| json_err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())))?;
     if running_task.is_some() {
         return Err((
             StatusCode::CONFLICT,
@@ -985,512 +1407,279 @@ pub async fn doctor_analyze(
         ));
     }
 
-    // 获取角色卡数据
-    let card = character_card::Entity::find_by_id(card_id)
-        .one(&db)
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({"error": e.to_string()})),
-            )
-        })?
-        .ok_or_else(|| {
-            (
-                StatusCode::NOT_FOUND,
-                Json(serde_json::json!({"error": "角色卡不存在"})),
-            )
-        })?;
-
-    // 获取 AI 配置
-    let settings = setting::Entity::find().all(&db).await.map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({"error": e.to_string()})),
-        )
-    })?;
-
-    let settings_map: std::collections::HashMap<String, String> =
-        settings.into_iter().map(|s| (s.key, s.value)).collect();
-
-    let global_prompt = settings_map
-        .get("global_prompt")
-        .cloned()
-        .unwrap_or_default();
-    let channel_id_str = settings_map
-        .get("ai_config_global")
-        .cloned()
-        .unwrap_or_default();
-
-    if channel_id_str.is_empty() {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({"error": "没有配置全局AI模型"})),
-        ));
+    // 先校验角色卡与 AI 配置，配置有误时直接返回错误
+    prepare_doctor(&db, card_id).await.map_err(json_err)?;
+
+    let job = job_queue::enqueue(
+        &db,
+        DOCTOR_JOB,
+        serde_json::json!({ "card_id": card_id }),
+        2,
+    )
+    .await
+    .map_err(|e| json_err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())))?;
+    if payload.background {
+        return Ok((StatusCode::ACCEPTED, Json(job)).into_response());
     }
 
-    let channel_id = Uuid::parse_str(&channel_id_str).map_err(|_| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({"error": "AI配置无效"})),
-        )
-    })?;
-
-    let channel = ai_channel::Entity::find_by_id(channel_id)
-        .one(&db)
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({"error": e.to_string()})),
-            )
-        })?
-        .ok_or_else(|| {
-            (
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({"error": "AI渠道不存在"})),
-            )
-        })?;
-
-    // 解析角色卡数据
-    let card_data: Value = serde_json::from_str(&card.data).unwrap_or(serde_json::json!({}));
-    let v2_data = card_data.get("data").unwrap_or(&card_data);
-
-    let name = card.name.clone();
-    let description = card.description.clone().unwrap_or_default();
-    let personality = v2_data
-        .get("personality")
-        .and_then(|v| v.as_str())
-        .unwrap_or("");
-    let first_mes = v2_data
-        .get("first_mes")
-        .and_then(|v| v.as_str())
-        .unwrap_or("");
-    let alt_greetings = v2_data
-        .get("alternate_greetings")
-        .and_then(|v| v.as_array())
-        .map(|arr| arr.first().and_then(|v| v.as_str()).unwrap_or(""))
-        .unwrap_or("");
-
-    // 提取世界书目录
-    let entries: Vec<Value> = v2_data
-        .get("character_book")
-        .and_then(|cb| cb.get("entries"))
-        .and_then(|e| e.as_array())
-        .cloned()
-        .unwrap_or_default()
-        .into_iter()
-        .filter(|e| e.get("enabled").and_then(|v| v.as_bool()).unwrap_or(true)) // Filter enabled
-        .collect();
-
-    let worldbook_toc: Vec<String> = entries
-        .iter()
-        .filter_map(|e| e.get("comment").and_then(|c| c.as_str()))
-        .filter(|c| !c.is_empty())
-        .map(|s| s.to_string())
-        .collect();
-
-    let worldbook_toc_str = if worldbook_toc.is_empty() {
-        "（无世界书条目）".to_string()
-    } else {
-        worldbook_toc
-            .iter()
-            .map(|s| format!("- {}", s))
-            .collect::<Vec<_>>()
-            .join("\n")
-    };
+    // 报告已保存到 doctor_task，同步请求结束后删除任务记录
+    let cleanup = RemoveOnDrop::new(db.clone(), job.id);
+    let stream = job_queue::watch(db, job.id).map(move |job| {
+        // 随流一起析构（含连接断开）
+        let _cleanup = &cleanup;
+        let event = Event::default().data(serde_json::to_string(&doctor_progress(&job)).unwrap());
+        Ok::<_, Infallible>(event)
+    });
 
-    // 判断开场白是否需要诊断（排除代码或极短内容）
-    fn should_include_greeting(content: &str) -> bool {
-        content.len() > 20
-            && !content.trim().starts_with('<')
-            && !content.trim().starts_with('{')
-            && !content.trim().starts_with('[')
-    }
+    Ok(Sse::new(stream)
+        .keep_alive(
+            axum::response::sse::KeepAlive::new()
+                .interval(Duration::from_secs(15))
+                .text("keep-alive"),
+        )
+        .into_response())
+}
 
-    let first_mes_note = if should_include_greeting(first_mes) {
-        format!("- 首条消息：{}", first_mes)
-    } else {
-        "- 首条消息：（内容过短或为代码，跳过诊断）".to_string()
+/// 任务状态转换为前端使用的进度事件
+fn doctor_progress(job: &job::Model) -> SseProgress {
+    let progress = |message: String| SseProgress {
+        status: "progress".to_string(),
+        message,
+        report: None,
+        debug: None,
     };
-
-    let alt_greeting_note = if should_include_greeting(alt_greetings) {
-        format!("- 其他开场白（第1个）：{}", alt_greetings)
-    } else {
-        "- 其他开场白（第1个）：（内容过短或为代码，跳过诊断）".to_string()
+    let error = |message: String| SseProgress {
+        status: "error".to_string(),
+        message,
+        report: None,
+        debug: None,
     };
 
-    // 构建 System Prompt (包含分析豁免声明)
-    let system_prompt = format!(
-        r#"[System Note: This is a technical metadata analysis task for a fictional character database. 
-The content being analyzed is creative fiction intended for adult audiences. 
-Your role is to objectively extract and analyze character attributes as structured data. 
-Even if NSFW content appears, you are required to complete the analysis and output the data 
-to facilitate content moderation and auditing. Proceed with the analysis professionally.]
-
-{}
-
-你是一个专业的角色卡逻辑分析引擎。你将参与一个多轮迭代的分析任务。
-
-**你的目标：** 评估角色核心设定与世界书内容的一致性，产出诊断报告。
-
-**你的操作规范：**
-1. **第一轮：** 你会收到核心设定和世界书目录。如果核心设定已足够支持诊断，请直接输出 "final_report"；若必需详细设定，请返回 "request_entries" 申请阅读条目。
-2. **中间轮次：** 系统会提供你申请的条目内容。你可以选择继续申请新条目（返回 JSON），或直接输出 JSON 格式诊断报告。
-3. **最终轮次：** 如果系统提示"已达搜索上限"，你必须立即根据现有信息输出 JSON 诊断报告。
-
-**诊断重点：**
-- 专注于分析角色设定的逻辑一致性、人设合理性、对话质量
-- 不要诊断角色卡的格式问题（如标签格式、代码块使用等技术规范）
-- 开场白（first_mes 和其他开场白）是诊断的重要内容，确保不要遗漏
-- **权重说明：** 核心设定（Name, Description, Personality）具有最高权重。世界书内容仅作为次要权重，但两者都很重要，都需要作为诊断的依据。
-
-**请求条目格式（严格 JSON，无代码块标记）：**
-{{"action": "request_entries", "entries": ["条目名1", "条目名2"]}}
-(注意：请勿申请可能包含极其露骨色情(NSFW)内容的条目，以免触发系统安全拦截导致任务失败)
-
-**诊断报告格式（严格 JSON，无代码块标记）：**
-{{"action": "final_report", "report": {{
-  "core_assessment": "概括性描述角色卡的完成质量与逻辑成熟度",
-  "dimensions": [
-    {{"name": "设定诊断", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议"}},
-    {{"name": "开场白诊断", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议"}},
-    {{"name": "人设一致性", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议"}},
-    {{"name": "世界观逻辑", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议"}},
-    {{"name": "OOC 预警", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议"}}
-  ],
-  "prescriptions": ["具体修改建议1", "具体修改建议2"],
-  "conclusion": "通过 / 需大幅修正 / 建议重构"
-}}}}
-
-**重要：** 所有输出必须是纯 JSON，不要包含 markdown 代码块标记。dimensions 中各字段可以使用 Markdown 格式（加粗、列表等）来增强可读性。"#,
-        global_prompt
-    );
-
-    // 构建初始 User Message
-    let initial_user_msg = format!(
-        r#"**[任务启动]** 请审阅以下内容，并返回你第一轮想要阅读的世界书条目名称（JSON 格式）。
-
-**核心设定：**
-- 角色名称：{}
-- 角色描述：{}
-- 性格特征：{}
-{}
-{}
-
-**世界书目录（条目名称列表）：**
-{}
-
-请返回 JSON 格式：{{"action": "request_entries", "entries": ["条目名1", ...]}}
-如果世界书目录为空或无需阅读条目，请直接输出诊断报告 JSON。请优先判断当前信息是否足够，避免不必要的搜索。同时请严格避开 NSFW 相关条目。"#,
-        name, description, personality, first_mes_note, alt_greeting_note, worldbook_toc_str
-    );
-
-    // 克隆需要的数据到 async 块
-    let db_clone = db.clone();
-    let channel_clone = channel.clone();
-    let entries_clone = entries.clone();
-
-    // 创建 SSE 流 (不再需要 task_id，只在成功时保存)
-    let stream = stream::unfold(
-        (
-            db_clone,
-            card_id, // 使用 card_id 替代 task_id
-            channel_clone,
-            entries_clone,
-            vec![
-                serde_json::json!({"role": "system", "content": system_prompt}),
-                serde_json::json!({"role": "user", "content": initial_user_msg}),
-            ],
-            0usize, // iteration count
+    match JobStatus::parse(&job.status) {
+        Some(JobStatus::Queued) => match &job.error {
+            Some(e) if job.attempts > 0 => progress(format!("诊断出错，稍后重试：{}", e)),
+            _ => progress("排队中...".to_string()),
+        },
+        Some(JobStatus::Running) => progress(
+            job.message
+                .clone()
+                .unwrap_or_else(|| "正在阅读详细设定及世界书目录...".to_string()),
         ),
-        |(db, card_id, channel, entries, mut messages, iteration)| async move {
-            let sent_messages = messages.clone(); // Capture state before mutation for debug logging
-
-            // 发送进度事件
-            let _progress_msg = match iteration {
-                0 => "正在阅读详细设定及世界书目录...",
-                1 => "第一次获取关联度较高的世界书条目内容...",
-                _ => "报告生成中...",
-            };
-
-            // 如果已经完成或达到上限
-            if iteration > 2 {
-                return None;
+        Some(JobStatus::Succeeded) => {
+            let result: Value = job
+                .result
+                .as_deref()
+                .and_then(|r| serde_json::from_str(r).ok())
+                .unwrap_or_default();
+            SseProgress {
+                status: "complete".to_string(),
+                message: "诊断完成".to_string(),
+                report: result.get("report").cloned(),
+                debug: result
+                    .get("debug")
+                    .and_then(|d| d.as_str())
+                    .map(|d| d.to_string()),
             }
+        }
+        Some(JobStatus::Cancelled) => error("诊断已取消".to_string()),
+        Some(JobStatus::Failed) | None => {
+            error(job.error.clone().unwrap_or_else(|| "诊断失败".to_string()))
+        }
+    }
+}
 
-            // 调用 AI
-            let client = reqwest::Client::new();
-            let base = channel.base_url.trim_end_matches('/');
-            let url = format!("{}/chat/completions", base);
-
-            let body = serde_json::json!({
-                "model": channel.model_id,
-                "messages": messages,
-                "temperature": 0.7
-            });
+/// 诊断任务：最多三轮对话，AI 可按需申请阅读世界书条目
+pub async fn run_doctor_job(ctx: JobContext) -> Result<Value, JobError> {
+    let card_id = ctx
+        .payload
+        .get("card_id")
+        .and_then(|v| v.as_str())
+        .and_then(|s| Uuid::parse_str(s).ok())
+        .ok_or_else(|| JobError::Fatal("任务参数无效".to_string()))?;
+    let DoctorPlan {
+        route,
+        entries,
+        mut messages,
+        temperature,
+    } = prepare_doctor(&ctx.db, card_id)
+        .await
+        .map_err(|(_, e)| JobError::Fatal(e))?;
 
-            let res = match client
-                .post(&url)
-                .header("Authorization", format!("Bearer {}", channel.api_key))
-                .header("Content-Type", "application/json")
-                .json(&body)
-                .send()
-                .await
-            {
-                Ok(r) => r,
-                Err(e) => {
-                    tracing::error!("Doctor AI network error: {}", e);
-                    let event = Event::default().data(
-                        serde_json::to_string(&SseProgress {
-                            status: "error".to_string(),
-                            message: format!("AI 请求失败: {}", e),
-                            report: None,
-                            debug: None,
-                        })
-                        .unwrap(),
-                    );
-                    return Some((Ok(event), (db, card_id, channel, entries, messages, 999)));
-                }
-            };
+    for iteration in 0..3usize {
+        if iteration == 0 {
+            ctx.progress(0.0, "正在阅读详细设定及世界书目录...").await;
+        }
+        let sent_messages = messages.clone(); // Capture state before mutation for debug logging
 
-            // 检查 HTTP 状态码
-            let status = res.status();
-            let raw_text = res.text().await.unwrap_or_default();
-
-            // 调试日志
-
-            // 如果 HTTP 状态不是成功
-            if !status.is_success() {
-                let event = Event::default().data(
-                    serde_json::to_string(&SseProgress {
-                        status: "error".to_string(),
-                        message: format!(
-                            "AI 服务返回错误 (HTTP {}): {}",
-                            status.as_u16(),
-                            raw_text.chars().take(200).collect::<String>()
-                        ),
-                        report: None,
-                        debug: None,
-                    })
-                    .unwrap(),
-                );
-                return Some((Ok(event), (db, card_id, channel, entries, messages, 999)));
-            }
+        let request = ChatRequest {
+            messages: messages.clone(),
+            temperature: Some(temperature),
+            ..Default::default()
+        };
 
-            let json: Value = match serde_json::from_str(&raw_text) {
-                Ok(j) => j,
-                Err(e) => {
-                    tracing::error!(
-                        "Doctor AI JSON parse error: {}, raw: {}",
-                        e,
-                        raw_text.chars().take(200).collect::<String>()
-                    );
-                    let event = Event::default().data(
-                        serde_json::to_string(&SseProgress {
-                            status: "error".to_string(),
-                            message: format!("AI 响应解析失败: {} (可能是空响应)", e),
-                            report: None,
-                            debug: None,
-                        })
-                        .unwrap(),
-                    );
-                    return Some((Ok(event), (db, card_id, channel, entries, messages, 999)));
-                }
+        // 路由内已按渠道重试与切换；全部失败时，限流、服务端错误与解析失败留给任务队列重试
+        let served = route.chat(&ctx.db, &request, &mut Vec::new()).await;
+        let response = served.map(|s| s.value).map_err(|e| {
+            tracing::error!("Doctor AI request error: {}", e);
+            let message = match &e {
+                ProviderError::Network(e) => format!("AI 请求失败: {}", e),
+                ProviderError::Http { status, body } => format!(
+                    "AI 服务返回错误 (HTTP {}): {}",
+                    status,
+                    body.chars().take(200).collect::<String>()
+                ),
+                ProviderError::Parse(e) => format!("AI 响应解析失败: {} (可能是空响应)", e),
+                e => e.to_string(),
             };
-
-            // 提取 AI 回复内容
-            let ai_content = json
-                .get("choices")
-                .and_then(|c| c.get(0))
-                .and_then(|c| c.get("message"))
-                .and_then(|m| m.get("content"))
-                .and_then(|c| c.as_str())
-                .unwrap_or("");
-
-            // 检查空响应
-            if ai_content.is_empty() {
-                tracing::warn!(
-                    "Doctor AI returned empty content, full response: {:?}",
-                    json
-                );
-                let event = Event::default().data(
-                    serde_json::to_string(&SseProgress {
-                        status: "error".to_string(),
-                        message: "AI 返回了空内容，可能是内容审核限制导致。请尝试使用其他模型或检查角色卡内容。".to_string(),
-                        report: None,
-                        debug: None,
-                    })
-                    .unwrap(),
-                );
-                return Some((Ok(event), (db, card_id, channel, entries, messages, 999)));
+            if e.is_retryable() {
+                JobError::Retry(message)
+            } else {
+                JobError::Fatal(message)
             }
+        })?;
 
-            // 智能提取 JSON 部分（寻找最外层的 {}，忽略前后的废话）
-            let cleaned =
-                if let (Some(start), Some(end)) = (ai_content.find('{'), ai_content.rfind('}')) {
-                    if start <= end {
-                        &ai_content[start..=end]
-                    } else {
-                        ai_content.trim()
-                    }
+        // 提取 AI 回复内容
+        let ai_content = response.content.as_str();
+
+        // 检查空响应
+        if ai_content.is_empty() {
+            tracing::warn!(
+                "Doctor AI returned empty content, full response: {:?}",
+                response.raw
+            );
+            return Err(JobError::Fatal(
+                "AI 返回了空内容，可能是内容审核限制导致。请尝试使用其他模型或检查角色卡内容。"
+                    .to_string(),
+            ));
+        }
+
+        // 智能提取 JSON 部分（寻找最外层的 {}，忽略前后的废话）
+        let cleaned =
+            if let (Some(start), Some(end)) = (ai_content.find('{'), ai_content.rfind('}')) {
+                if start <= end {
+                    &ai_content[start..=end]
                 } else {
                     ai_content.trim()
-                };
-
-            // 解析 AI 响应
-            let ai_response: Value = match serde_json::from_str(cleaned) {
-                Ok(v) => v,
-                Err(_) => {
-                    // AI 返回了非 JSON，可能是直接的报告文本，尝试包装
-                    serde_json::json!({
-                        "action": "final_report",
-                        "report": {
-                            "core_assessment": ai_content,
-                            "dimensions": [],
-                            "prescriptions": [],
-                            "conclusion": "解析失败，请查看原始内容"
-                        }
-                    })
                 }
+            } else {
+                ai_content.trim()
             };
 
-            let action = ai_response
-                .get("action")
-                .and_then(|a| a.as_str())
-                .unwrap_or("final_report");
-
-            if action == "request_entries" && iteration < 2 {
-                // AI 请求更多条目
-                let requested: Vec<String> = ai_response
-                    .get("entries")
-                    .and_then(|e| e.as_array())
-                    .map(|arr| {
-                        arr.iter()
-                            .filter_map(|v| v.as_str().map(|s| s.to_string()))
-                            .collect()
-                    })
-                    .unwrap_or_default();
-
-                // 查找对应条目内容
-                let mut fetched_content = String::new();
-                let mut found_entries = Vec::new();
-                for entry in &entries {
-                    let comment = entry.get("comment").and_then(|c| c.as_str()).unwrap_or("");
-                    let content = entry.get("content").and_then(|c| c.as_str()).unwrap_or("");
-                    if requested
-                        .iter()
-                        .any(|r| comment.contains(r) || r.contains(comment))
-                    {
-                        fetched_content.push_str(&format!("\n[{}]:\n{}\n", comment, content));
-                        found_entries.push(comment.to_string());
+        // 解析 AI 响应
+        let ai_response: Value = match serde_json::from_str(cleaned) {
+            Ok(v) => v,
+            Err(_) => {
+                // AI 返回了非 JSON，可能是直接的报告文本，尝试包装
+                serde_json::json!({
+                    "action": "final_report",
+                    "report": {
+                        "core_assessment": ai_content,
+                        "dimensions": [],
+                        "prescriptions": [],
+                        "conclusion": "解析失败，请查看原始内容"
                     }
-                }
+                })
+            }
+        };
 
-                if fetched_content.is_empty() {
-                    fetched_content = "（未找到匹配的条目）".to_string();
+        let action = ai_response
+            .get("action")
+            .and_then(|a| a.as_str())
+            .unwrap_or("final_report");
+
+        if action == "request_entries" && iteration < 2 {
+            // AI 请求更多条目
+            let requested: Vec<String> = ai_response
+                .get("entries")
+                .and_then(|e| e.as_array())
+                .map(|arr| {
+                    arr.iter()
+                        .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            // 查找对应条目内容
+            let mut fetched_content = String::new();
+            let mut found_entries = Vec::new();
+            for entry in &entries {
+                let comment = entry.get("comment").and_then(|c| c.as_str()).unwrap_or("");
+                let content = entry.get("content").and_then(|c| c.as_str()).unwrap_or("");
+                if requested
+                    .iter()
+                    .any(|r| comment.contains(r) || r.contains(comment))
+                {
+                    fetched_content.push_str(&format!("\n[{}]:\n{}\n", comment, content));
+                    found_entries.push(comment.to_string());
                 }
+            }
 
-                // 添加 AI 回复和新的用户消息
-                messages.push(serde_json::json!({"role": "assistant", "content": ai_content}));
+            if fetched_content.is_empty() {
+                fetched_content = "（未找到匹配的条目）".to_string();
+            }
 
-                let inject_msg = if iteration == 1 {
-                    format!(
-                        r#"**[系统指令：强制终审]** 这是最后一份补充内容：
+            // 添加 AI 回复和新的用户消息
+            messages.push(ChatMessage::assistant(ai_content));
+
+            let inject_msg = if iteration == 1 {
+                format!(
+                    r#"**[系统指令：强制终审]** 这是最后一份补充内容：
 
 {}
 
 **注意：** 搜索深度已达上限。请不再提出新请求，立即整合历史所有信息，输出最终的诊断报告 JSON。"#,
-                        fetched_content
-                    )
-                } else {
-                    format!(
-                        r#"**[条目内容注入]** 这是你申请阅读的条目详细内容：
+                    fetched_content
+                )
+            } else {
+                format!(
+                    r#"**[条目内容注入]** 这是你申请阅读的条目详细内容：
 
 {}
 
 **请决策：**
 - 如果需要更多信息，请返回 JSON：{{"action": "request_entries", "entries": ["新条目名1", ...]}}
 - 如果信息已足够，请按诊断报告格式输出 JSON。"#,
-                        fetched_content
-                    )
-                };
-
-                messages.push(serde_json::json!({"role": "user", "content": inject_msg}));
-
-                // 构建进度消息
-                let progress_msg = if found_entries.is_empty() {
-                    "正在分析条目关联性...".to_string()
-                } else {
-                    format!("正在阅读条目：{}", found_entries.join(", "))
-                };
+                    fetched_content
+                )
+            };
 
-                // 发送调试信息（全量日志）
-                let debug_info = serde_json::json!({
-                    "iteration": iteration,
-                    "sent_messages": sent_messages, // 完整发送给 AI 的内容
-                    "ai_response": ai_content,
-                    "next_prompt": inject_msg // 下一轮将注入的
-                })
-                .to_string();
-
-                // 发送进度事件
-                let event = Event::default().data(
-                    serde_json::to_string(&SseProgress {
-                        status: "progress".to_string(),
-                        message: progress_msg,
-                        report: None,
-                        debug: Some(debug_info), // 添加调试字段
-                    })
-                    .unwrap(),
-                );
+            messages.push(ChatMessage::user(inject_msg));
 
-                Some((
-                    Ok(event),
-                    (db, card_id, channel, entries, messages, iteration + 1),
-                ))
+            // 进度消息
+            let progress_msg = if found_entries.is_empty() {
+                "正在分析条目关联性...".to_string()
             } else {
-                // 最终报告
-                let report = ai_response
-                    .get("report")
-                    .cloned()
-                    .unwrap_or(ai_response.clone());
-
-                // 只在成功时保存到数据库
-                let _ = create_task_record(
-                    &db,
-                    card_id,
-                    serde_json::to_string(&report).unwrap_or_default(),
-                )
+                format!("正在阅读条目：{}", found_entries.join(", "))
+            };
+            ctx.progress((iteration + 1) as f64 / 3.0, progress_msg)
                 .await;
+        } else {
+            // 最终报告
+            let report = ai_response
+                .get("report")
+                .cloned()
+                .unwrap_or(ai_response.clone());
+
+            // 只在成功时保存到数据库
+            create_task_record(
+                &ctx.db,
+                card_id,
+                serde_json::to_string(&report).unwrap_or_default(),
+            )
+            .await?;
 
-                let debug_info = serde_json::json!({
-                    "iteration": iteration,
-                    "sent_messages": sent_messages, // 完整发送给 AI 的内容列表
-                    "ai_response": ai_content
-                })
-                .to_string();
-
-                let event = Event::default().data(
-                    serde_json::to_string(&SseProgress {
-                        status: "complete".to_string(),
-                        message: "诊断完成".to_string(),
-                        report: Some(report),
-                        debug: Some(debug_info),
-                    })
-                    .unwrap(),
-                );
+            let debug_info = serde_json::json!({
+                "iteration": iteration,
+                "sent_messages": sent_messages, // 完整发送给 AI 的内容列表
+                "ai_response": ai_content
+            })
+            .to_string();
 
-                Some((Ok(event), (db, card_id, channel, entries, messages, 999)))
-            }
-        },
-    );
+            return Ok(serde_json::json!({ "report": report, "debug": debug_info }));
+        }
+    }
 
-    Ok(Sse::new(stream).keep_alive(
-        axum::response::sse::KeepAlive::new()
-            .interval(Duration::from_secs(15))
-            .text("keep-alive"),
-    ))
+    Err(JobError::Fatal("诊断未生成报告".to_string()))
 }
 
 /// 创建成功的任务记录（只在成功时调用）
@@ -1513,6 +1702,8 @@ async fn create_task_record(
     doctor_task::Entity::insert(task_model)
         .exec_without_returning(db)
         .await?;
+
+    event_bus::publish(AppEvent::DoctorTaskCompleted { card_id, task_id });
     Ok(())
 }
 
@@ -2,6 +2,10 @@
 //!
 //! 导出：打包整个 data/ 目录为 .tar.gz (返回为 .piney)
 //! 导入：解压 .piney 文件覆盖 data/ 目录
+//!
+//! AI 渠道的 API 密钥在数据库中以本机密钥加密，本机密钥不进入备份，因此备份中的密钥
+//! 无法在其他设备上解密。导出时提供口令，会把密钥用口令重新加密后写入 `ai_keys.json`，
+//! 恢复时提供同一口令即可用新设备的本机密钥重新加密。
 
 use axum::{
     body::Body,
@@ -9,20 +13,33 @@ use axum::{
     http::{header, HeaderMap, StatusCode},
     response::IntoResponse,
 };
+use base64::Engine;
 use chrono::{Duration, Local, Utc};
 use jsonwebtoken::{encode, EncodingKey, Header};
-use sea_orm::{Database, DatabaseConnection};
+use sea_orm::{ColumnTrait, Database, DatabaseConnection, EntityTrait, QueryFilter};
 
 use flate2::read::GzDecoder;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
 use tar::{Archive, Builder};
 use tokio::io::duplex;
 use tokio_util::io::{ReaderStream, SyncIoBridge}; // 用于流式传输
 use tracing::{error, info};
+use uuid::Uuid;
 
 use crate::auth::Claims;
 use crate::config::ConfigState;
+use crate::entities::ai_channel;
+use crate::services::job_queue::{self, JobContext, JobError};
+use crate::utils::crypto;
+use crate::utils::secret::{API_KEY_SECRET_FILE, JWT_SECRET_FILE};
+
+/// 备份任务类型
+pub const BACKUP_JOB: &str = "backup";
 
 /// 备份恢复所需的组合状态
 #[derive(Clone)]
@@ -43,6 +60,98 @@ fn get_data_dir() -> std::path::PathBuf {
     crate::utils::paths::get_data_path("")
 }
 
+/// 备份中用口令加密的 API 密钥文件
+const KEY_BUNDLE_FILE: &str = "ai_keys.json";
+
+/// 不备份也不恢复的文件：用户名/密码与本机密钥
+fn is_local_only(filename: &str) -> bool {
+    filename == "config.yml" || filename == JWT_SECRET_FILE || filename == API_KEY_SECRET_FILE
+}
+
+/// 用口令加密的 API 密钥，键为渠道 ID
+#[derive(Serialize, Deserialize)]
+struct KeyBundle {
+    version: u32,
+    iterations: u32,
+    salt: String,
+    keys: BTreeMap<Uuid, String>,
+}
+
+impl KeyBundle {
+    /// 解密所有渠道的密钥并用口令重新加密；本机无法解密的密钥跳过
+    async fn export(db: &DatabaseConnection, passphrase: &str) -> Result<Self, String> {
+        let channels = ai_channel::Entity::find()
+            .all(db)
+            .await
+            .map_err(|e| e.to_string())?;
+        let salt = crypto::random_salt();
+        let iterations = crypto::PASSPHRASE_ITERATIONS;
+        let passphrase = passphrase.to_string();
+        let keys = tokio::task::spawn_blocking(move || {
+            let key = crypto::passphrase_key(&passphrase, &salt, iterations);
+            let mut keys = BTreeMap::new();
+            for c in channels {
+                match crypto::decrypt_api_key(&c.api_key) {
+                    Ok(plain) if !plain.is_empty() => {
+                        keys.insert(c.id, key.seal(&plain)?);
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        tracing::warn!("渠道 {} 的 API 密钥无法解密，未写入备份: {}", c.name, e);
+                    }
+                }
+            }
+            Ok::<_, crypto::CryptoError>(keys)
+        })
+        .await
+        .map_err(|e| format!("加密 API 密钥失败: {}", e))?
+        .map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            version: 1,
+            iterations,
+            salt: base64::engine::general_purpose::STANDARD.encode(salt),
+            keys,
+        })
+    }
+
+    /// 用口令解密，口令错误时返回错误
+    fn open(&self, passphrase: &str) -> Result<BTreeMap<Uuid, String>, crypto::CryptoError> {
+        let salt = base64::engine::general_purpose::STANDARD
+            .decode(&self.salt)
+            .map_err(|_| crypto::CryptoError::Malformed)?;
+        let key = crypto::passphrase_key(passphrase, &salt, self.iterations);
+        self.keys
+            .iter()
+            .map(|(id, sealed)| key.open(sealed).map(|plain| (*id, plain)))
+            .collect()
+    }
+}
+
+/// 读取备份中的 `ai_keys.json`
+fn read_key_bundle<R: Read>(archive: &mut Archive<R>) -> Result<Option<KeyBundle>, String> {
+    let entries = archive
+        .entries()
+        .map_err(|e| format!("读取归档失败: {}", e))?;
+    for entry in entries {
+        let mut entry = entry.map_err(|e| format!("读取条目失败: {}", e))?;
+        let is_bundle = entry
+            .path()
+            .map(|p| p.as_os_str() == KEY_BUNDLE_FILE)
+            .unwrap_or(false);
+        if is_bundle {
+            let mut content = String::new();
+            entry
+                .read_to_string(&mut content)
+                .map_err(|e| format!("读取 {} 失败: {}", KEY_BUNDLE_FILE, e))?;
+            return serde_json::from_str(&content)
+                .map(Some)
+                .map_err(|e| format!("{} 格式无效: {}", KEY_BUNDLE_FILE, e));
+        }
+    }
+    Ok(None)
+}
+
 /// GET /api/backup/export - 导出系统数据为 .piney 文件 (流式传输)
 pub async fn export_backup() -> Result<impl IntoResponse, (StatusCode, String)> {
     let data_dir = get_data_dir();
@@ -70,48 +179,8 @@ pub async fn export_backup() -> Result<impl IntoResponse, (StatusCode, String)>
         // 关键优化：使用 BufWriter 减少 tar 的大量小 I/O (?512bytes) 操作导致的频繁 context switch
         // 4MB 缓冲区与 pipe 容量一致，最大化吞吐量
         let buffered_bridge = std::io::BufWriter::with_capacity(4 * 1024 * 1024, bridge);
-        let mut tar_builder = Builder::new(buffered_bridge);
-
-        // 使用闭包来捕获 Result，方便统一处理错误
-        let result = (|| -> Result<(), String> {
-            if let Ok(entries) = fs::read_dir(&data_dir_clone) {
-                for entry in entries.flatten() {
-                    let path = entry.path();
-                    let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
-
-                    // 跳过不应该备份的文件
-                    // - temp 目录
-                    // - config.yml (用户名/密码)
-                    // - .jwt_secret (JWT 密钥)
-                    if filename == "temp" || filename == "config.yml" || filename == ".jwt_secret" {
-                        continue;
-                    }
-
-                    // 计算相对路径
-                    let relative_path = path
-                        .strip_prefix(&data_dir_clone)
-                        .map_err(|e| format!("路径错误: {}", e))?;
-
-                    // 写入 tar
-                    if path.is_dir() {
-                        tar_builder
-                            .append_dir_all(relative_path, &path)
-                            .map_err(|e| format!("打包目录失败 {:?}: {}", path, e))?;
-                    } else {
-                        tar_builder
-                            .append_path_with_name(&path, relative_path)
-                            .map_err(|e| format!("打包文件失败 {:?}: {}", path, e))?;
-                    }
-                }
-            }
 
-            // 完成打包
-            tar_builder
-                .finish()
-                .map_err(|e| format!("Tar finish failed: {}", e))?;
-
-            Ok(())
-        })();
+        let result = write_backup(&data_dir_clone, buffered_bridge, None, || false);
 
         if let Err(e) = result {
             // 在流传输过程中发生错误，只能记录日志，无法修改 HTTP 状态码
@@ -140,6 +209,157 @@ pub async fn export_backup() -> Result<impl IntoResponse, (StatusCode, String)>
     Ok((headers, body))
 }
 
+/// 将 data 目录打包为 tar 写入 `writer`；`key_bundle` 为用口令加密的 API 密钥，
+/// `cancelled` 返回 true 时中止
+fn write_backup<W: Write>(
+    data_dir: &Path,
+    writer: W,
+    key_bundle: Option<&KeyBundle>,
+    cancelled: impl Fn() -> bool,
+) -> Result<(), String> {
+    let mut tar_builder = Builder::new(writer);
+
+    if let Some(bundle) = key_bundle {
+        let content = serde_json::to_vec(bundle).map_err(|e| e.to_string())?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o600);
+        header.set_mtime(Utc::now().timestamp().max(0) as u64);
+        header.set_cksum();
+        tar_builder
+            .append_data(&mut header, KEY_BUNDLE_FILE, content.as_slice())
+            .map_err(|e| format!("写入 {} 失败: {}", KEY_BUNDLE_FILE, e))?;
+    }
+
+    if let Ok(entries) = fs::read_dir(data_dir) {
+        for entry in entries.flatten() {
+            if cancelled() {
+                return Err("备份已取消".to_string());
+            }
+
+            let path = entry.path();
+            let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+
+            // 跳过不应该备份的文件
+            // - temp 目录
+            // - config.yml (用户名/密码)
+            // - .jwt_secret (JWT 密钥)
+            // - .api_key_secret (API 密钥的加密密钥)
+            // - ai_keys.json (只写入本次导出的版本)
+            if filename == "temp" || filename == KEY_BUNDLE_FILE || is_local_only(filename) {
+                continue;
+            }
+
+            // 计算相对路径
+            let relative_path = path
+                .strip_prefix(data_dir)
+                .map_err(|e| format!("路径错误: {}", e))?;
+
+            // 写入 tar
+            if path.is_dir() {
+                tar_builder
+                    .append_dir_all(relative_path, &path)
+                    .map_err(|e| format!("打包目录失败 {:?}: {}", path, e))?;
+            } else {
+                tar_builder
+                    .append_path_with_name(&path, relative_path)
+                    .map_err(|e| format!("打包文件失败 {:?}: {}", path, e))?;
+            }
+        }
+    }
+
+    // 完成打包
+    tar_builder
+        .into_inner()
+        .and_then(|mut w| w.flush())
+        .map_err(|e| format!("Tar finish failed: {}", e))
+}
+
+/// 后台导出选项
+#[derive(Deserialize)]
+pub struct BackupJobRequest {
+    /// 提供时将 API 密钥用该口令加密后一并备份
+    pub passphrase: Option<String>,
+}
+
+/// POST /api/backup/export - 以后台任务导出备份，完成后从 `/api/jobs/{id}/download` 下载
+///
+/// 请求体可选；不提供口令时备份中的 API 密钥只能在本机恢复
+pub async fn start_backup_job(
+    State(db): State<DatabaseConnection>,
+    payload: Option<Json<BackupJobRequest>>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    // 同一时间只保留一个备份任务
+    if let Some(existing) = job_queue::find_active(&db, BACKUP_JOB, None)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    {
+        return Ok((StatusCode::ACCEPTED, Json(existing)));
+    }
+
+    // 任务参数中只保存口令加密后的密钥，不保存口令本身
+    let passphrase = payload
+        .and_then(|Json(p)| p.passphrase)
+        .filter(|p| !p.is_empty());
+    let job_payload = match passphrase {
+        Some(passphrase) => {
+            let bundle = KeyBundle::export(&db, &passphrase)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+            serde_json::json!({ "key_bundle": bundle })
+        }
+        None => serde_json::json!({}),
+    };
+
+    let job = job_queue::enqueue(&db, BACKUP_JOB, job_payload, 2)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// 备份任务：打包到任务产出文件
+pub async fn run_backup_job(ctx: JobContext) -> Result<Value, JobError> {
+    let data_dir = get_data_dir();
+    if !data_dir.exists() {
+        return Err(JobError::Fatal("数据目录不存在".to_string()));
+    }
+
+    let file_name = format!(
+        "piney_backup_{}.piney",
+        Local::now().format("%Y%m%d_%H%M%S")
+    );
+    ctx.progress(0.0, "正在打包数据目录").await;
+
+    let key_bundle: Option<KeyBundle> = ctx
+        .payload
+        .get("key_bundle")
+        .and_then(|b| serde_json::from_value(b.clone()).ok());
+
+    let path = ctx.artifact_path();
+    let job = ctx.clone();
+    let size = tokio::task::spawn_blocking(move || -> Result<u64, String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("创建目录失败: {}", e))?;
+        }
+        let file = fs::File::create(&path).map_err(|e| format!("创建备份文件失败: {}", e))?;
+        let writer = std::io::BufWriter::with_capacity(4 * 1024 * 1024, file);
+        write_backup(&data_dir, writer, key_bundle.as_ref(), || {
+            job.is_cancelled()
+        })?;
+        Ok(fs::metadata(&path).map(|m| m.len()).unwrap_or(0))
+    })
+    .await
+    .map_err(|e| JobError::Retry(e.to_string()))?
+    .map_err(JobError::Retry)?;
+
+    info!("备份任务 {} 完成: {} ({} 字节)", ctx.id, file_name, size);
+    Ok(serde_json::json!({
+        "file_name": file_name,
+        "content_type": "application/octet-stream",
+        "size": size,
+    }))
+}
+
 /// POST /api/backup/import - 导入 .piney 备份文件并恢复数据
 pub async fn import_backup(
     State(state): State<BackupState>,
@@ -153,8 +373,9 @@ pub async fn import_backup(
         error!("尝试关闭数据库连接失败: {}", e);
     }
 
-    // 1. 读取上传的文件
+    // 1. 读取上传的文件与可选的密钥口令
     let mut file_data: Option<Vec<u8>> = None;
+    let mut passphrase: Option<String> = None;
 
     while let Ok(Some(field)) = multipart.next_field().await {
         if field.name() == Some("backup") || field.name() == Some("file") {
@@ -163,7 +384,8 @@ pub async fn import_backup(
                 .await
                 .map_err(|e| (StatusCode::BAD_REQUEST, format!("读取文件失败: {}", e)))?;
             file_data = Some(data.to_vec());
-            break;
+        } else if field.name() == Some("passphrase") {
+            passphrase = field.text().await.ok().filter(|p| !p.is_empty());
         }
     }
 
@@ -195,6 +417,33 @@ pub async fn import_backup(
         ));
     }
 
+    // 2.5 提供口令时先解密备份中的 API 密钥，口令错误则在清空数据前中止
+    let restored_keys = match passphrase {
+        Some(passphrase) => {
+            let data_clone = data.clone();
+            tokio::task::spawn_blocking(move || -> Result<BTreeMap<Uuid, String>, String> {
+                let bundle = if GzDecoder::new(&data_clone[..]).header().is_some() {
+                    read_key_bundle(&mut Archive::new(GzDecoder::new(&data_clone[..])))
+                } else {
+                    read_key_bundle(&mut Archive::new(&data_clone[..]))
+                }?
+                .ok_or_else(|| "该备份未包含加密的 API 密钥".to_string())?;
+                bundle
+                    .open(&passphrase)
+                    .map_err(|_| "口令错误，无法解密备份中的 API 密钥".to_string())
+            })
+            .await
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("任务执行失败: {}", e),
+                )
+            })?
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?
+        }
+        None => BTreeMap::new(),
+    };
+
     let data_dir = get_data_dir();
 
     // 3. 执行清空、解压、读取配置
@@ -213,8 +462,9 @@ pub async fn import_backup(
             // 跳过一些不应该删除的文件
             // - config.yml (用户名/密码) - 保留当前配置
             // - .jwt_secret (JWT 密钥) - 保留当前登录状态
+            // - .api_key_secret (API 密钥的加密密钥) - 本机恢复时密钥仍可解密
             // - .DS_Store (系统文件)
-            if filename == "config.yml" || filename == ".jwt_secret" || filename == ".DS_Store" {
+            if is_local_only(&filename) || filename == ".DS_Store" {
                 continue;
             }
 
@@ -247,7 +497,7 @@ pub async fn import_backup(
                     .file_name()
                     .and_then(|n| n.to_str())
                     .unwrap_or("");
-                if entry_name == "config.yml" || entry_name == ".jwt_secret" {
+                if is_local_only(entry_name) || entry_name == KEY_BUNDLE_FILE {
                     continue;
                 }
 
@@ -271,7 +521,7 @@ pub async fn import_backup(
                     .file_name()
                     .and_then(|n| n.to_str())
                     .unwrap_or("");
-                if entry_name == "config.yml" || entry_name == ".jwt_secret" {
+                if is_local_only(entry_name) || entry_name == KEY_BUNDLE_FILE {
                     continue;
                 }
 
@@ -309,11 +559,30 @@ pub async fn import_backup(
     let db_path = get_data_dir().join("piney.db");
     let db_url = format!("sqlite://{}?mode=rwc", db_path.to_string_lossy());
 
+    let mut message = "数据恢复成功".to_string();
     match Database::connect(&db_url).await {
-        Ok(_new_db) => {
+        Ok(new_db) => {
             info!("数据库重新连接成功");
+            // 备份中未结束的任务（包括执行本次备份的任务）不应在重启后重新执行
+            match job_queue::cancel_unfinished(&new_db).await {
+                Ok(0) => {}
+                Ok(n) => info!("已取消备份中 {} 个未结束的任务", n),
+                Err(e) => error!("取消备份中的任务失败: {}", e),
+            }
             // 注意：这里的新连接会在函数结束后 drop，
             // 但验证了数据库文件是完整的
+            if !restored_keys.is_empty() {
+                match restore_api_keys(&new_db, restored_keys).await {
+                    Ok(count) => {
+                        info!("已用本机密钥重新加密 {} 个 API 密钥", count);
+                        message = format!("数据恢复成功，已恢复 {} 个 API 密钥", count);
+                    }
+                    Err(e) => {
+                        error!("恢复 API 密钥失败: {}", e);
+                        message = "数据恢复成功，但 API 密钥恢复失败，请重新填写".to_string();
+                    }
+                }
+            }
         }
         Err(e) => {
             error!("数据库重新连接失败: {}", e);
@@ -355,7 +624,29 @@ pub async fn import_backup(
     // 7. 返回成功信息
     Ok(Json(ImportResponse {
         username,
-        message: "数据恢复成功".to_string(),
+        message,
         token,
     }))
 }
+
+/// 用本机密钥重新加密备份中的 API 密钥，返回更新的渠道数
+async fn restore_api_keys(
+    db: &DatabaseConnection,
+    keys: BTreeMap<Uuid, String>,
+) -> Result<u64, sea_orm::DbErr> {
+    let mut count = 0;
+    for (id, plain) in keys {
+        let stored =
+            crypto::encrypt_api_key(&plain).map_err(|e| sea_orm::DbErr::Custom(e.to_string()))?;
+        let result = ai_channel::Entity::update_many()
+            .col_expr(
+                ai_channel::Column::ApiKey,
+                sea_orm::sea_query::Expr::value(stored),
+            )
+            .filter(ai_channel::Column::Id.eq(id))
+            .exec(db)
+            .await?;
+        count += result.rows_affected;
+    }
+    Ok(count)
+}
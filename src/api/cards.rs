@@ -4,23 +4,31 @@ use axum::{
     http::{header, HeaderMap, StatusCode},
     response::{IntoResponse, Json, Response},
 };
-use base64::{engine::general_purpose, Engine as _};
 use chrono::TimeZone;
 use futures::StreamExt;
 use sea_orm::{
     ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, FromQueryResult,
-    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Set,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Set, TransactionTrait,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::io::{Cursor, Write};
 use tokio::fs;
-use tracing::warn;
 use uuid::Uuid;
 use zip::write::FileOptions;
 
 use crate::api::dashboard::invalidate_cache;
 use crate::entities::character_card;
+use crate::services::card_bulk_edit::{self, BulkOperation, FieldChange, PreparedOperation};
+use crate::services::card_fields::CardFields;
+use crate::services::card_query::{self, CardFilter};
+use crate::services::card_spec::{self, CardSpec};
+use crate::services::event_bus::{self, AppEvent};
+use crate::services::job_queue::{self, JobContext, JobError, JobStatus};
+use crate::services::operation_journal::{self, JournalTarget, NewOperation};
+use crate::services::version_store::{self, NewVersion};
+use crate::services::{card_match, charx, png_chunks, tag_admin, tag_index, version_snapshot};
+use crate::utils::etag;
 use crate::utils::hash::compute_json_hash;
 use crate::utils::token::calculate_card_tokens;
 
@@ -61,13 +69,54 @@ struct CardLightRow {
 #[derive(Serialize, Deserialize)]
 pub struct ImportResult {
     file_name: String,
-    status: String, // "success" | "error"
+    status: String, // "success" | "skipped" | "error"
     reason: Option<String>,
 }
 
+/// 导入冲突策略：与已有卡片同名同作者（但内容不同）时的处理方式
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// 跳过导入
+    Skip,
+    /// 作为新卡片导入（默认，与旧行为一致）
+    #[default]
+    NewCard,
+    /// 追加为已有卡片的新版本并更新封面
+    AppendVersion,
+    /// 直接覆盖已有卡片，不保留版本
+    Overwrite,
+}
+
+#[derive(Deserialize, Default)]
+pub struct ImportQuery {
+    #[serde(default)]
+    pub policy: ConflictPolicy,
+    /// 正文相似度阈值 (0~1)，设置后仅相似度不低于阈值的卡视为同一张
+    pub similarity: Option<f64>,
+}
+
+/// 单个文件的导入结果
+enum ImportOutcome {
+    Created,
+    Skipped(String),
+    Updated(String),
+}
+
+/// 导入目标
+enum ImportTarget {
+    New,
+    Skip(String),
+    Existing(Box<character_card::Model>),
+}
+
 // 包装 Handler，将 Result 转换为 Response，避免 E0277 错误
-pub async fn import(State(db): State<DatabaseConnection>, multipart: Multipart) -> Response {
-    match process_import(db, multipart).await {
+pub async fn import(
+    State(db): State<DatabaseConnection>,
+    Query(options): Query<ImportQuery>,
+    multipart: Multipart,
+) -> Response {
+    match process_import(db, options, multipart).await {
         Ok(json) => json.into_response(),
         Err(err) => err.into_response(),
     }
@@ -75,8 +124,18 @@ pub async fn import(State(db): State<DatabaseConnection>, multipart: Multipart)
 
 async fn process_import(
     db: DatabaseConnection,
+    options: ImportQuery,
     mut multipart: Multipart,
 ) -> Result<Json<Vec<ImportResult>>, (StatusCode, String)> {
+    if let Some(similarity) = options.similarity {
+        if !(0.0..=1.0).contains(&similarity) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "similarity 必须在 0 到 1 之间".to_string(),
+            ));
+        }
+    }
+
     let storage_dir = crate::utils::paths::get_data_path("cards");
     if !storage_dir.exists() {
         fs::create_dir_all(&storage_dir)
@@ -108,50 +167,32 @@ async fn process_import(
         let is_png = content_type == "image/png" || file_name.to_lowercase().ends_with(".png");
         let is_json =
             content_type == "application/json" || file_name.to_lowercase().ends_with(".json");
+        let is_charx = file_name.to_lowercase().ends_with(".charx");
 
-        if is_png {
+        let outcome = if is_charx {
+            // 处理 CHARX 归档
+            process_charx_card(&db, &data, storage_dir.clone(), &options).await
+        } else if is_png {
             // 处理 PNG 角色卡
-            match process_png_card(&db, &data, storage_dir.clone()).await {
-                Ok(_) => {
-                    results.push(ImportResult {
-                        file_name,
-                        status: "success".to_string(),
-                        reason: None,
-                    });
-                }
-                Err(err_msg) => {
-                    results.push(ImportResult {
-                        file_name,
-                        status: "error".to_string(),
-                        reason: Some(err_msg),
-                    });
-                }
-            }
+            process_png_card(&db, &data, storage_dir.clone(), &options).await
         } else if is_json {
             // 处理 JSON 角色卡
-            match process_json_card(&db, &data, storage_dir.clone()).await {
-                Ok(_) => {
-                    results.push(ImportResult {
-                        file_name,
-                        status: "success".to_string(),
-                        reason: None,
-                    });
-                }
-                Err(err_msg) => {
-                    results.push(ImportResult {
-                        file_name,
-                        status: "error".to_string(),
-                        reason: Some(err_msg),
-                    });
-                }
-            }
+            process_json_card(&db, &data, storage_dir.clone(), &options).await
         } else {
-            results.push(ImportResult {
-                file_name,
-                status: "error".to_string(),
-                reason: Some("不支持的文件格式".to_string()),
-            });
-        }
+            Err("不支持的文件格式".to_string())
+        };
+
+        let (status, reason) = match outcome {
+            Ok(ImportOutcome::Created) => ("success", None),
+            Ok(ImportOutcome::Updated(msg)) => ("success", Some(msg)),
+            Ok(ImportOutcome::Skipped(msg)) => ("skipped", Some(msg)),
+            Err(err_msg) => ("error", Some(err_msg)),
+        };
+        results.push(ImportResult {
+            file_name,
+            status: status.to_string(),
+            reason,
+        });
     }
 
     // Invalidate cache if any success
@@ -160,11 +201,167 @@ async fn process_import(
     }
     Ok(Json(results))
 }
+
+/// 确定导入目标
+///
+/// 完全相同（data_hash 一致）的卡片始终拒绝；否则按冲突策略查找同名同作者的已有卡片。
+async fn resolve_import_target(
+    db: &DatabaseConnection,
+    json: &Value,
+    data_hash: &str,
+    options: &ImportQuery,
+) -> Result<ImportTarget, String> {
+    let existing = character_card::Entity::find()
+        .filter(character_card::Column::DataHash.eq(data_hash))
+        .one(db)
+        .await
+        .map_err(|e| format!("数据库查询失败: {}", e))?;
+
+    if let Some(existing_card) = existing {
+        return Err(format!("角色卡已存在: {}", existing_card.name));
+    }
+
+    if options.policy == ConflictPolicy::NewCard {
+        return Ok(ImportTarget::New);
+    }
+
+    let fields = CardFields::extract(json);
+    let Some(name) = fields.name.as_deref().map(card_match::normalize_key) else {
+        return Ok(ImportTarget::New);
+    };
+    let author = card_match::normalize_key(fields.author.as_deref().unwrap_or_default());
+
+    // 与 normalize_key 口径相同，名称走表达式索引 idx_character_cards_name_key
+    let candidates = character_card::Entity::find()
+        .filter(sea_orm::sea_query::Expr::cust_with_values(
+            "lower(trim(name)) = ?",
+            [name],
+        ))
+        .filter(sea_orm::sea_query::Expr::cust_with_values(
+            "lower(trim(coalesce(author, ''))) = ?",
+            [author],
+        ))
+        .filter(character_card::Column::DeletedAt.is_null())
+        .order_by_desc(character_card::Column::UpdatedAt)
+        .all(db)
+        .await
+        .map_err(|e| format!("数据库查询失败: {}", e))?;
+
+    let mut best: Option<(character_card::Model, f64)> = None;
+    for card in candidates {
+        let score = match options.similarity {
+            Some(threshold) => {
+                let stored: Value = serde_json::from_str(&card.data).unwrap_or(Value::Null);
+                let score = card_match::content_similarity(&stored, json);
+                if score < threshold {
+                    continue;
+                }
+                score
+            }
+            // 未设置阈值时取最近更新的一张
+            None => 0.0,
+        };
+        if best.as_ref().is_none_or(|(_, s)| score > *s) {
+            best = Some((card, score));
+        }
+    }
+
+    Ok(match best {
+        None => ImportTarget::New,
+        Some((card, _)) if options.policy == ConflictPolicy::Skip => {
+            ImportTarget::Skip(format!("已存在同名角色卡，已跳过: {}", card.name))
+        }
+        Some((card, _)) => ImportTarget::Existing(Box::new(card)),
+    })
+}
+
+/// 用导入的数据更新已有卡片
+///
+/// `avatar` 为 Some 时表示封面已替换；`append_version` 策略下同时写入一条版本记录。
+async fn update_card_from_import(
+    db: &DatabaseConnection,
+    existing: character_card::Model,
+    mut json: Value,
+    data_hash: String,
+    avatar: Option<String>,
+    metadata_modified: bool,
+    policy: ConflictPolicy,
+) -> Result<ImportOutcome, String> {
+    tag_admin::apply_aliases_to_json(db, &mut json)
+        .await
+        .map_err(|e| format!("应用标签别名失败: {}", e))?;
+    let fields = CardFields::extract(&json);
+    let pretty_json_str =
+        serde_json::to_string_pretty(&json).map_err(|e| format!("格式化 JSON 失败: {}", e))?;
+    let counts = calculate_card_tokens(&json);
+    let now = chrono::Utc::now().naive_utc();
+
+    let card_id = existing.id;
+    let card_name = existing.name.clone();
+    let avatar_version = existing.avatar_version;
+    let mut active: character_card::ActiveModel = existing.into();
+
+    if let Some(n) = fields.name {
+        active.name = Set(n);
+    }
+    active.description = Set(fields.description);
+    active.author = Set(fields.author);
+    active.spec = Set(fields.spec);
+    active.spec_version = Set(fields.spec_version);
+    active.tags = Set(fields.tags_json);
+    active.data = Set(pretty_json_str.clone());
+    active.data_hash = Set(Some(data_hash));
+    active.metadata_modified = Set(metadata_modified);
+    active.token_count_total = Set(Some(counts.total));
+    active.token_count_spec = Set(Some(counts.spec));
+    active.token_count_wb = Set(Some(counts.wb));
+    active.token_count_other = Set(Some(counts.other));
+    active.updated_at = Set(now);
+    if let Some(avatar) = avatar {
+        active.avatar = Set(Some(avatar));
+        active.avatar_version = Set(avatar_version + 1);
+    }
+
+    // 版本记录与卡片更新同时成功或失败
+    let txn = db.begin().await.map_err(|e| format!("数据库错误: {}", e))?;
+    let message = if policy == ConflictPolicy::AppendVersion {
+        let version_number = crate::api::versions::next_version_number(&txn, card_id)
+            .await
+            .map_err(|e| format!("数据库错误: {}", e))?;
+        let version = NewVersion {
+            character_id: card_id,
+            version_number: version_number.clone(),
+            note: Some("导入更新".to_string()),
+            data: pretty_json_str,
+            is_auto: false,
+        };
+        version_store::insert_version(&txn, version)
+            .await
+            .map_err(|e| format!("数据库错误: {}", e))?;
+        active.version = Set(Some(version_number.clone()));
+        format!("已追加为 {} 的 {} 版本", card_name, version_number)
+    } else {
+        format!("已覆盖角色卡: {}", card_name)
+    };
+
+    active
+        .update(&txn)
+        .await
+        .map_err(|e| format!("数据库错误: {}", e))?;
+    txn.commit()
+        .await
+        .map_err(|e| format!("数据库错误: {}", e))?;
+
+    event_bus::publish(AppEvent::CardUpdated { id: card_id });
+    Ok(ImportOutcome::Updated(message))
+}
+
 async fn process_png_card(
     db: &DatabaseConnection,
     data: &[u8],
     storage_dir: std::path::PathBuf,
-) -> Result<(), String> {
+    options: &ImportQuery,
+) -> Result<ImportOutcome, String> {
     // 1. 手动解析 PNG Chunks 并提取 JSON
     let extracted_json = extract_png_metadata(data)?;
 
@@ -178,18 +375,13 @@ async fn process_png_card(
         serde_json::to_string(&json_val).map_err(|e| format!("序列化 JSON 失败: {}", e))?;
     let data_hash = compute_json_hash(&compact_json);
 
-    let existing = character_card::Entity::find()
-        .filter(character_card::Column::DataHash.eq(&data_hash))
-        .one(db)
-        .await
-        .map_err(|e| format!("数据库查询失败: {}", e))?;
-
-    if let Some(existing_card) = existing {
-        return Err(format!("角色卡已存在: {}", existing_card.name));
-    }
+    let (uuid, existing) = match resolve_import_target(db, &json_val, &data_hash, options).await? {
+        ImportTarget::New => (Uuid::new_v4(), None),
+        ImportTarget::Skip(reason) => return Ok(ImportOutcome::Skipped(reason)),
+        ImportTarget::Existing(card) => (card.id, Some(*card)),
+    };
 
-    // 3. 确定无重复后，保存文件
-    let uuid = Uuid::new_v4();
+    // 3. 确定目标后，保存文件
     let card_dir = storage_dir.join(uuid.to_string());
 
     if !card_dir.exists() {
@@ -198,33 +390,173 @@ async fn process_png_card(
             .map_err(|e| format!("创建角色卡目录失败: {}", e))?;
     }
 
-    // 保存原始 PNG
-    let png_name = "v1_source.png";
-    let png_path = card_dir.join(png_name);
-    fs::write(&png_path, data)
+    // 保存原始 PNG 及 WebP 缩略图
+    save_cover_files(&card_dir, data).await?;
+
+    // 4. 保存到数据库
+    let avatar_path = format!("/cards/{}/v1_thumbnail.webp", uuid);
+    match existing {
+        Some(card) => {
+            update_card_from_import(
+                db,
+                card,
+                json_val,
+                data_hash,
+                Some(avatar_path),
+                false,
+                options.policy,
+            )
+            .await
+        }
+        None => {
+            save_card_model(db, uuid, json_val, Some(avatar_path), data_hash, "import").await?;
+            Ok(ImportOutcome::Created)
+        }
+    }
+}
+
+/// 保存封面：原始 PNG (v1_source.png) + WebP 缩略图 (v1_thumbnail.webp)
+async fn save_cover_files(card_dir: &std::path::Path, png_data: &[u8]) -> Result<(), String> {
+    let png_path = card_dir.join("v1_source.png");
+    fs::write(&png_path, png_data)
         .await
         .map_err(|e| format!("保存原始 PNG 失败: {}", e))?;
 
     // 生成 WebP 缩略图
-    let img = image::load_from_memory(data).map_err(|e| format!("图片加载失败: {}", e))?;
+    let img = image::load_from_memory(png_data).map_err(|e| format!("图片加载失败: {}", e))?;
     let encoder = webp::Encoder::from_image(&img).map_err(|e| format!("WebP 编码失败: {}", e))?;
     let webp_data = encoder.encode(75.0).to_vec();
-    let webp_name = "v1_thumbnail.webp";
-    let webp_path = card_dir.join(webp_name);
+    let webp_path = card_dir.join("v1_thumbnail.webp");
     fs::write(&webp_path, &webp_data)
         .await
         .map_err(|e| format!("保存 WebP 缩略图失败: {}", e))?;
 
-    // 4. 保存到数据库
-    let avatar_path = format!("/cards/{}/v1_thumbnail.webp", uuid);
-    save_card_model(db, uuid, json_val, Some(avatar_path), data_hash, "import").await
+    Ok(())
+}
+
+async fn process_charx_card(
+    db: &DatabaseConnection,
+    data: &[u8],
+    storage_dir: std::path::PathBuf,
+    options: &ImportQuery,
+) -> Result<ImportOutcome, String> {
+    let archive = charx::read_charx(data)?;
+    let mut json_val = archive.card;
+
+    if !json_val.get("data").is_some_and(|d| d.is_object()) {
+        return Err("无效的角色卡格式：card.json 缺少 'data' 字段".to_string());
+    }
+
+    // 1. 检查重复（以归档内原始 card.json 计算哈希）
+    let compact_json =
+        serde_json::to_string(&json_val).map_err(|e| format!("序列化 JSON 失败: {}", e))?;
+    let data_hash = compute_json_hash(&compact_json);
+
+    let (uuid, existing) = match resolve_import_target(db, &json_val, &data_hash, options).await? {
+        ImportTarget::New => (Uuid::new_v4(), None),
+        ImportTarget::Skip(reason) => return Ok(ImportOutcome::Skipped(reason)),
+        ImportTarget::Existing(card) => (card.id, Some(*card)),
+    };
+
+    // 2. 解包资源到 data/cards/<uuid>/
+    let card_dir = storage_dir.join(uuid.to_string());
+    if !card_dir.exists() {
+        fs::create_dir_all(&card_dir)
+            .await
+            .map_err(|e| format!("创建角色卡目录失败: {}", e))?;
+    }
+
+    let main_icon = charx::find_main_icon(&json_val);
+    let mut has_cover = false;
+
+    for asset in &archive.assets {
+        // 主图标作为封面保存，不重复存放在 assets 目录
+        if main_icon.as_deref() == Some(asset.path.as_str()) {
+            let png_data = match image::guess_format(&asset.data) {
+                Ok(image::ImageFormat::Png) => asset.data.clone(),
+                _ => {
+                    let img = image::load_from_memory(&asset.data)
+                        .map_err(|e| format!("封面图片加载失败: {}", e))?;
+                    let mut buf = Vec::new();
+                    img.write_to(&mut Cursor::new(&mut buf), image::ImageOutputFormat::Png)
+                        .map_err(|e| format!("封面转换 PNG 失败: {}", e))?;
+                    buf
+                }
+            };
+            save_cover_files(&card_dir, &png_data).await?;
+            has_cover = true;
+            continue;
+        }
+
+        let asset_path = card_dir.join(&asset.path);
+        if let Some(parent) = asset_path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|e| format!("创建资源目录失败: {}", e))?;
+        }
+        fs::write(&asset_path, &asset.data)
+            .await
+            .map_err(|e| format!("保存资源 {} 失败: {}", asset.path, e))?;
+
+        // embeded://assets/... -> /cards/<uuid>/assets/...
+        charx::rewrite_asset_uris(
+            &mut json_val,
+            &format!("{}{}", charx::EMBEDDED_PREFIX, asset.path),
+            &format!("/cards/{}/{}", uuid, asset.path),
+        );
+    }
+
+    // 主图标改为 ccdefault:，导出时再以当前封面回填
+    if has_cover {
+        charx::set_main_icon(&mut json_val, charx::DEFAULT_ASSET_URI, "png");
+    }
+
+    // 3. 保存数据库
+    if let Some(card) = existing {
+        // 封面不含元数据；沿用旧封面时其元数据也已过期，导出时都需要重新注入
+        let avatar = has_cover.then(|| format!("/cards/{}/v1_thumbnail.webp", uuid));
+        let png_exists = card_dir.join("v1_source.png").exists();
+        return update_card_from_import(
+            db,
+            card,
+            json_val,
+            data_hash,
+            avatar,
+            png_exists,
+            options.policy,
+        )
+        .await;
+    }
+
+    let avatar = if has_cover {
+        format!("/cards/{}/v1_thumbnail.webp", uuid)
+    } else {
+        "/default.webp".to_string()
+    };
+    save_card_model(db, uuid, json_val, Some(avatar), data_hash, "import").await?;
+
+    // 封面图片不含元数据，标记后导出 PNG 时会注入
+    if has_cover {
+        character_card::Entity::update_many()
+            .col_expr(
+                character_card::Column::MetadataModified,
+                sea_orm::sea_query::Expr::value(true),
+            )
+            .filter(character_card::Column::Id.eq(uuid))
+            .exec(db)
+            .await
+            .map_err(|e| format!("数据库错误: {}", e))?;
+    }
+
+    Ok(ImportOutcome::Created)
 }
 
 async fn process_json_card(
     db: &DatabaseConnection,
     data: &[u8],
     storage_dir: std::path::PathBuf,
-) -> Result<(), String> {
+    options: &ImportQuery,
+) -> Result<ImportOutcome, String> {
     let json_string = String::from_utf8(data.to_vec()).map_err(|_| "JSON 编码无效".to_string())?;
     // 验证 JSON
     let v: Value = serde_json::from_str(&json_string).map_err(|e| format!("无效的 JSON: {}", e))?;
@@ -242,18 +574,13 @@ async fn process_json_card(
     let compact_json = serde_json::to_string(&v).map_err(|e| format!("序列化 JSON 失败: {}", e))?;
     let data_hash = compute_json_hash(&compact_json);
 
-    let existing = character_card::Entity::find()
-        .filter(character_card::Column::DataHash.eq(&data_hash))
-        .one(db)
-        .await
-        .map_err(|e| format!("数据库查询失败: {}", e))?;
-
-    if let Some(existing_card) = existing {
-        return Err(format!("角色卡已存在: {}", existing_card.name));
-    }
+    let (uuid, existing) = match resolve_import_target(db, &v, &data_hash, options).await? {
+        ImportTarget::New => (Uuid::new_v4(), None),
+        ImportTarget::Skip(reason) => return Ok(ImportOutcome::Skipped(reason)),
+        ImportTarget::Existing(card) => (card.id, Some(*card)),
+    };
 
     // 2. 保存文件 (Optional, but DB is primary)
-    let uuid = Uuid::new_v4();
     let card_dir = storage_dir.join(uuid.to_string());
     if !card_dir.exists() {
         fs::create_dir_all(&card_dir)
@@ -262,6 +589,13 @@ async fn process_json_card(
     }
 
     // 3. 保存数据库
+    if let Some(card) = existing {
+        // JSON 不含封面，保留原封面；其中的元数据已过期，导出时需重新注入
+        let png_exists = card_dir.join("v1_source.png").exists();
+        return update_card_from_import(db, card, v, data_hash, None, png_exists, options.policy)
+            .await;
+    }
+
     save_card_model(
         db,
         uuid,
@@ -270,140 +604,49 @@ async fn process_json_card(
         data_hash,
         "import",
     )
-    .await
+    .await?;
+    Ok(ImportOutcome::Created)
 }
 
-// 提取的 PNG 元数据解析逻辑
+// 提取的 PNG 元数据解析逻辑 (tEXt / zTXt / iTXt，ccv3 优先)
 fn extract_png_metadata(data: &[u8]) -> Result<String, String> {
-    // PNG 签名校验
-    const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
-    if data.len() < 8 || &data[..8] != PNG_SIGNATURE {
-        return Err("非法的 PNG 文件签名".to_string());
-    }
-
-    let mut offset = 8;
-    while offset + 8 <= data.len() {
-        let length_bytes: [u8; 4] = data[offset..offset + 4].try_into().unwrap();
-        let length = u32::from_be_bytes(length_bytes) as usize;
-        let chunk_type = &data[offset + 4..offset + 8];
-        let data_start = offset + 8;
-        let data_end = data_start + length;
-
-        if data_end > data.len() {
-            warn!("PNG Chunk 越界，停止解析");
-            break;
-        }
-
-        if chunk_type == b"tEXt" {
-            let chunk_data = &data[data_start..data_end];
-            if let Some(null_pos) = chunk_data.iter().position(|&b| b == 0) {
-                let keyword_bytes = &chunk_data[..null_pos];
-                let text_bytes = &chunk_data[null_pos + 1..];
-                if let Ok(keyword) = String::from_utf8(keyword_bytes.to_vec()) {
-                    if keyword == "ccv3" || keyword == "chara" {
-                        if let Ok(decoded) = general_purpose::STANDARD.decode(text_bytes) {
-                            if let Ok(s) = String::from_utf8(decoded) {
-                                return Ok(s); // Found it!
-                            }
-                        } else if let Ok(s) = String::from_utf8(text_bytes.to_vec()) {
-                            return Ok(s); // Found it (raw)!
-                        }
-                    }
-                }
-            }
-        }
-        offset = data_end + 4;
-    }
-    Err("无效的角色卡图片：未找到元数据 (ccv3/chara)".to_string())
+    png_chunks::extract_card_json(data)
 }
 
 async fn save_card_model(
     db: &DatabaseConnection,
     uuid: Uuid,
-    json: Value,
+    mut json: Value,
     avatar: Option<String>,
     data_hash: String,
     source: &str, // "import" 或 "local"
 ) -> Result<(), String> {
-    // 规范化 V2/V3 结构 (仅用于提取字段)
-    let card_data = if let Some(d) = json.get("data") {
-        if d.is_object() {
-            d
-        } else {
-            &json
-        }
-    } else {
-        &json
-    };
-
-    let name = card_data
-        .get("name")
-        .and_then(|v| v.as_str())
-        .unwrap_or("未知角色")
-        .to_string();
-    let description = card_data
-        .get("description")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string());
-    let author = card_data
-        .get("creator")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string())
-        .or_else(|| {
-            card_data
-                .get("creator_notes")
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string())
-        });
-
-    let spec = json
-        .get("spec")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string());
-    let spec_version = json
-        .get("spec_version")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string());
+    tag_admin::apply_aliases_to_json(db, &mut json)
+        .await
+        .map_err(|e| format!("应用标签别名失败: {}", e))?;
+    let fields = CardFields::extract(&json);
 
     // 格式化 JSON（只格式化，不添加/删除任何字段）
     let pretty_json_str =
         serde_json::to_string_pretty(&json).map_err(|e| format!("格式化 JSON 失败: {}", e))?;
 
-    // 从 JSON 中提取 tags（可能是数组或逗号分隔的字符串）
-    let tags_json = if let Some(tags_value) = card_data.get("tags") {
-        if tags_value.is_array() {
-            serde_json::to_string_pretty(tags_value).unwrap_or_else(|_| "[]".to_string())
-        } else if let Some(tags_str) = tags_value.as_str() {
-            let tags: Vec<&str> = tags_str
-                .split(',')
-                .map(|s| s.trim())
-                .filter(|s| !s.is_empty())
-                .collect();
-            serde_json::to_string_pretty(&tags).unwrap_or_else(|_| "[]".to_string())
-        } else {
-            "[]".to_string()
-        }
-    } else {
-        "[]".to_string()
-    };
-
     // 计算 token
     let counts = calculate_card_tokens(&json);
 
     // 版本号独立管理，不从角色卡 JSON 中提取，默认为 None（前端显示为 1.0）
     let active_model = character_card::ActiveModel {
         id: Set(uuid),
-        name: Set(name),
-        description: Set(description),
-        author: Set(author),
+        name: Set(fields.name.unwrap_or_else(|| "未知角色".to_string())),
+        description: Set(fields.description),
+        author: Set(fields.author),
         avatar: Set(avatar),
-        spec: Set(spec),
-        spec_version: Set(spec_version),
+        spec: Set(fields.spec),
+        spec_version: Set(fields.spec_version),
         data: Set(pretty_json_str.clone()),
         created_at: Set(chrono::Utc::now().naive_utc()),
         updated_at: Set(chrono::Utc::now().naive_utc()),
         category_id: Set(None),
-        tags: Set(tags_json),
+        tags: Set(fields.tags_json),
         rating: Set(0.0),
         cover_blur: Set(false),
         version: Set(None),
@@ -431,19 +674,19 @@ async fn save_card_model(
     } else {
         "初始导入"
     };
-    let version = crate::entities::character_versions::ActiveModel {
-        id: Set(Uuid::new_v4()),
-        character_id: Set(uuid),
-        version_number: Set("V1".to_string()),
-        note: Set(Some(version_note.to_string())),
-        data: Set(pretty_json_str), // Use the formatted JSON
-        created_at: Set(chrono::Utc::now().naive_utc()),
+    let version = NewVersion {
+        character_id: uuid,
+        version_number: "V1".to_string(),
+        note: Some(version_note.to_string()),
+        data: pretty_json_str, // Use the formatted JSON
+        is_auto: false,
     };
-    if let Err(e) = version.insert(db).await {
+    if let Err(e) = version_store::insert_version(db, version).await {
         tracing::error!("Failed to create initial version for card {}: {}", uuid, e);
         // We do not fail the import if version creation fails, just log error.
     }
 
+    event_bus::publish(AppEvent::CardCreated { id: uuid });
     Ok(())
 }
 
@@ -496,105 +739,61 @@ pub async fn debug_import(
                     // --- PNG 解析逻辑 (带日志) ---
                     let mut extracted = None;
 
-                    // 1. 签名检查
+                    // 1. 签名检查 + 遍历 Chunk
                     logs.push("检查 PNG 签名...".to_string());
-                    const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
-                    if data.len() >= 8 && &data[..8] == PNG_SIGNATURE {
-                        logs.push("签名有效".to_string());
-
-                        // 2. 遍历 Chunk
-                        logs.push("开始遍历 Chunks...".to_string());
-                        let mut offset = 8;
-                        let mut chunk_count = 0;
-
-                        while offset + 8 <= data.len() {
-                            chunk_count += 1;
-                            let length_bytes: [u8; 4] =
-                                data[offset..offset + 4].try_into().unwrap_or([0; 4]);
-                            let length = u32::from_be_bytes(length_bytes) as usize;
-                            let chunk_type = &data[offset + 4..offset + 8];
-                            let type_str = String::from_utf8_lossy(chunk_type);
-
-                            let data_start = offset + 8;
-                            let data_end = data_start + length;
-
-                            if data_end > data.len() {
+                    match png_chunks::read_chunks(&data) {
+                        Ok(chunks) => {
+                            logs.push(format!("签名有效，共 {} 个 Chunk", chunks.len()));
+
+                            // 2. 解析文本块 (tEXt / zTXt / iTXt)
+                            for (index, chunk) in chunks.iter().enumerate() {
+                                let type_str = String::from_utf8_lossy(&chunk.kind);
+                                let Some(text) = png_chunks::parse_text_chunk(chunk) else {
+                                    if matches!(&chunk.kind, b"tEXt" | b"zTXt" | b"iTXt") {
+                                        logs.push(format!(
+                                            "Chunk #{} [{}] 格式错误或解压失败",
+                                            index + 1,
+                                            type_str
+                                        ));
+                                    }
+                                    continue;
+                                };
                                 logs.push(format!(
-                                    "Chunk #{} ({}) 越界! Offset: {}, Len: {}",
-                                    chunk_count, type_str, offset, length
+                                    "Chunk #{} [{}] Keyword: {}, Len: {}",
+                                    index + 1,
+                                    text.kind,
+                                    text.keyword,
+                                    text.text.len()
                                 ));
-                                break;
-                            }
 
-                            if chunk_type == b"tEXt" {
-                                logs.push(format!(
-                                    "Chunk #{} [tEXt] found. Len: {}",
-                                    chunk_count, length
-                                ));
-                                let chunk_data = &data[data_start..data_end];
-                                if let Some(null_pos) = chunk_data.iter().position(|&b| b == 0) {
-                                    let keyword_bytes = &chunk_data[..null_pos];
-                                    let keyword = String::from_utf8_lossy(keyword_bytes);
-                                    logs.push(format!("  Keyword: {}", keyword));
-
-                                    if keyword == "ccv3" || keyword == "chara" {
-                                        let text_bytes = &chunk_data[null_pos + 1..];
-                                        logs.push(format!(
-                                            "  Found target metadata! Length: {}",
-                                            text_bytes.len()
-                                        ));
+                                if text.keyword != png_chunks::KEYWORD_CCV3
+                                    && text.keyword != png_chunks::KEYWORD_CHARA
+                                {
+                                    continue;
+                                }
 
-                                        // Try decode
-                                        if let Ok(decoded) =
-                                            general_purpose::STANDARD.decode(text_bytes)
-                                        {
-                                            logs.push("  Base64 Decode: SUCCESS".to_string());
-                                            if let Ok(s) = String::from_utf8(decoded) {
-                                                logs.push("  UTF-8 Parse: SUCCESS".to_string());
-                                                // Keep the "best" result (e.g. ccv3 over chara, or longest)
-                                                // For debug, we just update extracted if it's ccv3 or if we haven't found one yet
-                                                if keyword == "ccv3" {
-                                                    logs.push("  Identified ccv3 (V3 Spec). Updating candidate.".to_string());
-                                                    extracted = Some(s);
-                                                } else if extracted.is_none() {
-                                                    logs.push("  Identified chara (Legacy). Setting as candidate.".to_string());
-                                                    extracted = Some(s);
-                                                }
-                                            } else {
-                                                logs.push("  UTF-8 Parse: FAILED".to_string());
-                                            }
-                                        } else {
+                                match png_chunks::decode_card_text(&text.text) {
+                                    Some(s) => {
+                                        // ccv3 优先于 chara
+                                        if text.keyword == png_chunks::KEYWORD_CCV3 {
                                             logs.push(
-                                                "  Base64 Decode: FAILED. Trying raw text..."
+                                                "  Identified ccv3 (V3 Spec). Updating candidate."
                                                     .to_string(),
                                             );
-                                            if let Ok(s) = String::from_utf8(text_bytes.to_vec()) {
-                                                logs.push("  Raw Text UTF-8: SUCCESS".to_string());
-                                                if keyword == "ccv3" {
-                                                    logs.push(
-                                                        "  Identified ccv3. Updating candidate."
-                                                            .to_string(),
-                                                    );
-                                                    extracted = Some(s);
-                                                } else if extracted.is_none() {
-                                                    extracted = Some(s);
-                                                }
-                                            } else {
-                                                logs.push("  Raw Text UTF-8: FAILED".to_string());
-                                            }
+                                            extracted = Some(s);
+                                        } else if extracted.is_none() {
+                                            logs.push(
+                                                "  Identified chara (Legacy). Setting as candidate."
+                                                    .to_string(),
+                                            );
+                                            extracted = Some(s);
                                         }
                                     }
-                                } else {
-                                    logs.push(
-                                        "  Malformed tEXt chunk (no null separator)".to_string(),
-                                    );
+                                    None => logs.push("  Decode: FAILED".to_string()),
                                 }
                             }
-
-                            offset = data_end + 4; // Skip CRC
                         }
-                    } else {
-                        logs.push("签名无效!".to_string());
+                        Err(e) => logs.push(format!("签名无效! {}", e)),
                     }
 
                     if let Some(json_str) = extracted {
@@ -802,10 +1001,45 @@ pub async fn create_card(
 
 // ============ 列表和更新 API ============
 
+/// 解析筛选表达式，未提供或为空时返回 None
+fn parse_card_filter(q: Option<&str>) -> Result<Option<CardFilter>, (StatusCode, String)> {
+    let filter = CardFilter::parse(q.unwrap_or(""))
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("筛选表达式错误: {}", e)))?;
+    Ok((!filter.is_empty()).then_some(filter))
+}
+
+/// 批量操作的目标：显式的 ID 列表，或筛选表达式匹配的未删除角色卡；两者同时给出时取交集
+pub async fn resolve_batch_ids(
+    db: &DatabaseConnection,
+    ids: Vec<Uuid>,
+    filter: Option<&str>,
+) -> Result<Vec<Uuid>, (StatusCode, String)> {
+    let Some(filter) = parse_card_filter(filter)? else {
+        return Ok(ids);
+    };
+
+    let mut select = character_card::Entity::find()
+        .select_only()
+        .column(character_card::Column::Id)
+        .filter(character_card::Column::DeletedAt.is_null())
+        .filter(filter.condition());
+    if !ids.is_empty() {
+        select = select.filter(character_card::Column::Id.is_in(ids));
+    }
+
+    select
+        .into_tuple()
+        .all(db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
 #[derive(Deserialize)]
 pub struct ListCardsQuery {
     pub category_id: Option<Uuid>,
     pub search: Option<String>,
+    /// 筛选表达式，见 `services::card_query`
+    pub q: Option<String>,
     pub tags: Option<String>, // 逗号分隔的标签
     pub page: Option<u64>,
     pub page_size: Option<u64>,
@@ -899,17 +1133,20 @@ pub async fn list(
         }
     }
 
+    // 筛选表达式
+    if let Some(filter) = parse_card_filter(query.q.as_deref())? {
+        select = select.filter(filter.condition());
+    }
+
     // Sorting
     let order = match query.order.as_deref() {
         Some("asc") => sea_orm::Order::Asc,
         _ => sea_orm::Order::Desc,
     };
 
-    select = match query.sort.as_deref() {
-        Some("name") => select.order_by(character_card::Column::Name, order),
-        Some("created_at") => select.order_by(character_card::Column::CreatedAt, order),
-        Some("updated_at") => select.order_by(character_card::Column::UpdatedAt, order),
-        _ => select.order_by(character_card::Column::UpdatedAt, sea_orm::Order::Desc), // Default: Last updated
+    select = match query.sort.as_deref().and_then(card_query::sort_column) {
+        Some(column) => select.order_by(column, order),
+        None => select.order_by(character_card::Column::UpdatedAt, sea_orm::Order::Desc), // Default: Last updated
     };
 
     // Pagination defaults
@@ -970,6 +1207,8 @@ pub struct ListAllQuery {
     pub page: Option<u64>,
     pub page_size: Option<u64>,
     pub search: Option<String>,
+    /// 筛选表达式，见 `services::card_query`
+    pub q: Option<String>,
     pub category_id: Option<String>,
     pub sort: Option<String>,
     pub order: Option<String>,
@@ -1020,17 +1259,20 @@ pub async fn list_all(
         }
     }
 
+    // 筛选表达式
+    if let Some(filter) = parse_card_filter(query.q.as_deref())? {
+        base_query = base_query.filter(filter.condition());
+    }
+
     // 获取总数
     let total = base_query.clone().count(&db).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     // 排序
     let order = if sort_order == "asc" { sea_orm::Order::Asc } else { sea_orm::Order::Desc };
-    let sorted_query = match sort_field {
-        "name" => base_query.order_by(character_card::Column::Name, order),
-        "created_at" => base_query.order_by(character_card::Column::CreatedAt, order),
-        _ => base_query.order_by(character_card::Column::UpdatedAt, order),
-    };
+    let sort_column =
+        card_query::sort_column(sort_field).unwrap_or(character_card::Column::UpdatedAt);
+    let sorted_query = base_query.order_by(sort_column, order);
 
     // 分页查询
     let rows = sorted_query
@@ -1094,7 +1336,7 @@ pub async fn list_all(
 pub async fn get_details(
     State(db): State<DatabaseConnection>,
     Path(id): Path<Uuid>,
-) -> Result<Json<character_card::Model>, (StatusCode, String)> {
+) -> Result<Response, (StatusCode, String)> {
     let card = character_card::Entity::find_by_id(id)
         .one(&db)
         .await
@@ -1116,10 +1358,13 @@ pub async fn get_details(
             .update(&db)
             .await
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-        return Ok(Json(updated));
+        return Ok(etag::with_etag(
+            &etag::from_updated_at(&updated.updated_at),
+            Json(updated),
+        ));
     }
 
-    Ok(Json(card))
+    Ok(etag::with_etag(&etag::from_updated_at(&card.updated_at), Json(card)))
 }
 
 #[derive(Deserialize)]
@@ -1151,17 +1396,25 @@ pub struct UpdateCardRequest {
 }
 
 /// PATCH /api/cards/:id - 更新角色卡
+///
+/// 支持 `If-Match`，客户端数据已过期时返回 412 与当前卡片
 pub async fn update(
     State(db): State<DatabaseConnection>,
     Path(id): Path<Uuid>,
+    headers: HeaderMap,
     Json(payload): Json<UpdateCardRequest>,
-) -> Result<Json<character_card::Model>, (StatusCode, String)> {
+) -> Result<Response, (StatusCode, String)> {
     let existing = character_card::Entity::find_by_id(id)
         .one(&db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
         .ok_or((StatusCode::NOT_FOUND, "角色卡不存在".to_string()))?;
 
+    let current_etag = etag::from_updated_at(&existing.updated_at);
+    if !etag::if_match_satisfied(&headers, &current_etag) {
+        return Ok(etag::precondition_failed(&current_etag, Json(existing)));
+    }
+
     let mut active: character_card::ActiveModel = existing.clone().into();
     let mut spec_modified = false;
     let mut current_json: Value = serde_json::from_str(&existing.data).unwrap_or(Value::Null);
@@ -1339,6 +1592,12 @@ pub async fn update(
 
     // --- Sync Logic End ---
 
+    // 快照与条件写入在同一事务中，并发修改导致 412 时一并回滚
+    let txn = db
+        .begin()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
     // Save JSON changes if needed
     if spec_modified {
         active.metadata_modified = Set(true);
@@ -1348,6 +1607,12 @@ pub async fn update(
                 format!("JSON 序列化失败: {}", e),
             )
         })?;
+        if new_json_str != existing.data {
+            // 自动快照失败不影响保存
+            if let Err(e) = version_snapshot::auto_snapshot(&txn, &existing).await {
+                tracing::error!("Failed to create auto snapshot for card {}: {}", id, e);
+            }
+        }
         active.data = Set(new_json_str);
 
         // Recalculate tokens
@@ -1360,13 +1625,36 @@ pub async fn update(
 
     active.updated_at = Set(chrono::Utc::now().naive_utc());
 
-    let updated_model = active
-        .update(&db)
+    let Some(updated_model) = etag::update_if_unchanged(
+        &txn,
+        active,
+        character_card::Column::UpdatedAt,
+        existing.updated_at,
+    )
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    else {
+        drop(txn);
+        let current = character_card::Entity::find_by_id(id)
+            .one(&db)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .ok_or((StatusCode::NOT_FOUND, "角色卡不存在".to_string()))?;
+        return Ok(etag::precondition_failed(
+            &etag::from_updated_at(&current.updated_at),
+            Json(current),
+        ));
+    };
+    txn.commit()
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     invalidate_cache();
-    Ok(Json(updated_model))
+    event_bus::publish(AppEvent::CardUpdated { id });
+    Ok(etag::with_etag(
+        &etag::from_updated_at(&updated_model.updated_at),
+        Json(updated_model),
+    ))
 }
 
 /// POST /api/cards/:id/cover - Update cover image
@@ -1507,6 +1795,7 @@ pub async fn update_cover(
             .await
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+        event_bus::publish(AppEvent::CardUpdated { id });
         return Ok(StatusCode::OK);
     }
 
@@ -1514,9 +1803,13 @@ pub async fn update_cover(
 }
 
 /// GET /api/cards/:id/export - Export card
+///
+/// `spec` 为空时按默认方式导出（ccv3 + V2 chara），并在元数据修改过时回写源文件；
+/// 指定规范时仅生成导出内容，不回写。
 async fn _get_card_file_data(
     db: &DatabaseConnection,
     card: character_card::Model,
+    spec: Option<CardSpec>,
 ) -> Result<(String, Vec<u8>), String> {
     let storage_dir = crate::utils::paths::get_data_path(&format!("cards/{}", card.id));
     let png_path = storage_dir.join("v1_source.png");
@@ -1527,45 +1820,37 @@ async fn _get_card_file_data(
         .filter(|c| c.is_alphanumeric() || *c == ' ' || *c == '-')
         .collect::<String>();
 
-    if card.metadata_modified && png_path.exists() {
-        // Inject JSON
-        let file_data = fs::read(&png_path)
-            .await
-            .map_err(|e| format!("Read PNG failed: {}", e))?;
+    if !png_path.exists() {
+        // JSON Fallback
+        return _get_card_json_data(card, spec);
+    }
 
-        let decoder = png::Decoder::new(Cursor::new(&file_data));
-        let mut reader = decoder
-            .read_info()
-            .map_err(|e| format!("PNG Decode Error: {}", e))?;
+    let file_data = fs::read(&png_path)
+        .await
+        .map_err(|e| format!("Read PNG failed: {}", e))?;
 
-        // Allocate buffer for image data
-        let mut buf = vec![0; reader.output_buffer_size()];
-        let info = reader
-            .next_frame(&mut buf)
-            .map_err(|e| format!("PNG Frame Error: {}", e))?;
-        let bytes = &buf[..info.buffer_size()];
+    if spec.is_none() && !card.metadata_modified {
+        // Read directly
+        return Ok((format!("{}.png", safe_name), file_data));
+    }
 
-        // Encode new PNG with updated metadata
-        let mut output_data = Vec::new();
-        {
-            let mut encoder = png::Encoder::new(&mut output_data, info.width, info.height);
-            encoder.set_color(info.color_type);
-            encoder.set_depth(info.bit_depth);
-
-            // Add tEXt chunk
-            let json_base64 = general_purpose::STANDARD.encode(card.data.as_bytes());
-            encoder
-                .add_text_chunk("ccv3".to_string(), json_base64)
-                .map_err(|e| format!("PNG Text Error: {}", e))?;
-
-            let mut writer = encoder
-                .write_header()
-                .map_err(|e| format!("PNG Header Error: {}", e))?;
-            writer
-                .write_image_data(bytes)
-                .map_err(|e| format!("PNG Write Error: {}", e))?;
-        }
+    // 直接替换文本块，不重新编码像素
+    let json: Value =
+        serde_json::from_str(&card.data).map_err(|e| format!("角色卡数据无效: {}", e))?;
+    let to_string = |v: Value| serde_json::to_string(&v).map_err(|e| format!("JSON Error: {}", e));
+    let (ccv3_json, chara_json) = match spec.unwrap_or(CardSpec::V3) {
+        // 同时写入 V2 chara 兼容旧版前端
+        CardSpec::V3 => (
+            Some(to_string(card_spec::to_v3(&json))?),
+            to_string(card_spec::to_v2(&json))?,
+        ),
+        CardSpec::V2 => (None, to_string(card_spec::to_v2(&json))?),
+        CardSpec::V1 => (None, to_string(card_spec::to_v1(&json))?),
+    };
+    let output_data =
+        png_chunks::write_card_chunks(&file_data, ccv3_json.as_deref(), Some(&chara_json))?;
 
+    if spec.is_none() {
         // Write back to source
         if let Err(e) = fs::write(&png_path, &output_data).await {
             tracing::error!("Failed to overwrite updated PNG to source file: {}", e);
@@ -1577,23 +1862,151 @@ async fn _get_card_file_data(
                 tracing::error!("Failed to reset metadata_modified flag: {}", e);
             }
         }
+    }
 
-        Ok((format!("{}.png", safe_name), output_data))
-    } else if png_path.exists() {
-        // Read directly
-        let file_data = fs::read(&png_path)
+    Ok((format!("{}.png", safe_name), output_data))
+}
+
+/// 导出为 JSON，`spec` 为空时原样输出存储的数据
+fn _get_card_json_data(
+    card: character_card::Model,
+    spec: Option<CardSpec>,
+) -> Result<(String, Vec<u8>), String> {
+    let safe_name = card
+        .name
+        .chars()
+        .filter(|c| c.is_alphanumeric() || *c == ' ' || *c == '-')
+        .collect::<String>();
+
+    let Some(spec) = spec else {
+        return Ok((format!("{}.json", safe_name), card.data.into_bytes()));
+    };
+
+    let json: Value =
+        serde_json::from_str(&card.data).map_err(|e| format!("角色卡数据无效: {}", e))?;
+    let data = serde_json::to_vec_pretty(&card_spec::convert(&json, spec))
+        .map_err(|e| format!("JSON Error: {}", e))?;
+    Ok((format!("{}.json", safe_name), data))
+}
+
+/// 导出为 CHARX 归档：card.json + 封面 + 已存储的资源
+async fn _get_card_charx_data(card: character_card::Model) -> Result<(String, Vec<u8>), String> {
+    let storage_dir = crate::utils::paths::get_data_path(&format!("cards/{}", card.id));
+
+    let safe_name = card
+        .name
+        .chars()
+        .filter(|c| c.is_alphanumeric() || *c == ' ' || *c == '-')
+        .collect::<String>();
+
+    let json: Value =
+        serde_json::from_str(&card.data).map_err(|e| format!("角色卡数据无效: {}", e))?;
+    let mut json = card_spec::to_v3(&json);
+
+    // /cards/<uuid>/assets/... -> embeded://assets/...
+    charx::rewrite_asset_uris(
+        &mut json,
+        &format!("/cards/{}/assets/", card.id),
+        &format!("{}assets/", charx::EMBEDDED_PREFIX),
+    );
+
+    let mut assets = Vec::new();
+
+    // 当前封面作为主图标
+    let png_path = storage_dir.join("v1_source.png");
+    if png_path.exists() {
+        let cover = fs::read(&png_path)
             .await
             .map_err(|e| format!("Read PNG failed: {}", e))?;
-        Ok((format!("{}.png", safe_name), file_data))
-    } else {
-        // JSON Fallback
-        Ok((format!("{}.json", safe_name), card.data.into_bytes()))
+        let cover_path = "assets/icon/images/main.png";
+        charx::set_main_icon(
+            &mut json,
+            &format!("{}{}", charx::EMBEDDED_PREFIX, cover_path),
+            "png",
+        );
+        assets.push(charx::CharxAsset {
+            path: cover_path.to_string(),
+            data: cover,
+        });
+    }
+
+    // 导入时解包的资源
+    let assets_dir = storage_dir.join("assets");
+    let mut pending = vec![assets_dir.clone()];
+    while let Some(dir) = pending.pop() {
+        let Ok(mut entries) = fs::read_dir(&dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let Ok(relative) = path.strip_prefix(&storage_dir) else {
+                continue;
+            };
+            let data = fs::read(&path)
+                .await
+                .map_err(|e| format!("读取资源失败 {:?}: {}", path, e))?;
+            assets.push(charx::CharxAsset {
+                path: relative.to_string_lossy().replace('\\', "/"),
+                data,
+            });
+        }
     }
+
+    let data = charx::write_charx(&json, &assets)?;
+    Ok((format!("{}.charx", safe_name), data))
+}
+
+/// 按导出格式生成文件：png (默认) | json | charx
+///
+/// `spec` 指定导出的规范版本：v3 | v2 | v1，CHARX 仅支持 V3。
+async fn get_card_export_data(
+    db: &DatabaseConnection,
+    card: character_card::Model,
+    format: Option<&str>,
+    spec: Option<CardSpec>,
+) -> Result<(String, Vec<u8>), String> {
+    match format {
+        Some("charx") => _get_card_charx_data(card).await,
+        Some("json") => _get_card_json_data(card, spec),
+        _ => _get_card_file_data(db, card, spec).await,
+    }
+}
+
+/// 校验导出参数
+fn parse_export_options(
+    format: Option<&str>,
+    spec: Option<&str>,
+) -> Result<Option<CardSpec>, (StatusCode, String)> {
+    let spec = match spec {
+        Some(s) => Some(CardSpec::parse(s).ok_or((
+            StatusCode::BAD_REQUEST,
+            format!("不支持的规范版本: {}（可选 v1 / v2 / v3）", s),
+        ))?),
+        None => None,
+    };
+    if format == Some("charx") && spec.is_some_and(|s| s != CardSpec::V3) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "CHARX 格式仅支持 V3 规范".to_string(),
+        ));
+    }
+    Ok(spec)
+}
+
+#[derive(Deserialize)]
+pub struct ExportCardQuery {
+    pub format: Option<String>,
+    pub spec: Option<String>,
 }
 
 pub async fn export_card(
     State(db): State<DatabaseConnection>,
     Path(id): Path<Uuid>,
+    Query(query): Query<ExportCardQuery>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     let card = character_card::Entity::find_by_id(id)
         .one(&db)
@@ -1601,7 +2014,9 @@ pub async fn export_card(
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
         .ok_or((StatusCode::NOT_FOUND, "Card not found".to_string()))?;
 
-    let (filename, data) = _get_card_file_data(&db, card)
+    let spec = parse_export_options(query.format.as_deref(), query.spec.as_deref())?;
+
+    let (filename, data) = get_card_export_data(&db, card, query.format.as_deref(), spec)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
 
@@ -1614,6 +2029,8 @@ pub async fn export_card(
     );
     if filename.ends_with(".json") {
         headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+    } else if filename.ends_with(".charx") {
+        headers.insert(header::CONTENT_TYPE, "application/zip".parse().unwrap());
     } else {
         headers.insert(header::CONTENT_TYPE, "image/png".parse().unwrap());
     }
@@ -1621,140 +2038,502 @@ pub async fn export_card(
     Ok((headers, Body::from(data)))
 }
 
+/// 批量导出任务类型
+pub const EXPORT_JOB: &str = "card_export";
+
 #[derive(Deserialize)]
 pub struct BatchExportRequest {
+    #[serde(default)]
     pub ids: Vec<Uuid>,
+    /// 筛选表达式，与 ids 二选一（同时给出时取交集）
+    pub filter: Option<String>,
+    pub format: Option<String>,
+    pub spec: Option<String>,
+    /// 为 true 时立即返回任务，完成后从 `/api/jobs/{id}/download` 下载
+    #[serde(default)]
+    pub background: bool,
+}
+
+/// 批量导出任务参数（入队时已解析出卡片 ID）
+#[derive(Serialize, Deserialize)]
+struct ExportJobPayload {
+    ids: Vec<Uuid>,
+    format: Option<String>,
+    spec: Option<String>,
 }
 
+/// POST /api/cards/batch/export - 批量导出为 zip
+///
+/// 导出在后台任务中进行；默认等待任务完成后直接返回文件
 pub async fn batch_export_cards(
     State(db): State<DatabaseConnection>,
     Json(payload): Json<BatchExportRequest>,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
-    if payload.ids.is_empty() {
+) -> Result<Response, (StatusCode, String)> {
+    parse_export_options(payload.format.as_deref(), payload.spec.as_deref())?;
+    let ids = resolve_batch_ids(&db, payload.ids, payload.filter.as_deref()).await?;
+    if ids.is_empty() {
         return Err((StatusCode::BAD_REQUEST, "No cards selected".to_string()));
     }
 
-    // Find all cards
+    let job_payload = ExportJobPayload {
+        ids,
+        format: payload.format,
+        spec: payload.spec,
+    };
+    if payload.background {
+        let job = job_queue::enqueue(&db, EXPORT_JOB, serde_json::json!(job_payload), 2)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        return Ok((StatusCode::ACCEPTED, Json(job)).into_response());
+    }
+
+    // 同步导出直接在请求中执行，不排在后台任务之后；下载完即删除
+    let job = job_queue::run_now(
+        &db,
+        EXPORT_JOB,
+        serde_json::json!(job_payload),
+        crate::api::jobs::run,
+    )
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if job.status != JobStatus::Succeeded.as_str() {
+        if let Err(e) = job_queue::remove_finished(&db, job.id).await {
+            tracing::warn!("Failed to remove export job {}: {}", job.id, e);
+        }
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            job.error.unwrap_or_else(|| "导出失败".to_string()),
+        ));
+    }
+    crate::api::jobs::artifact_response_once(db, &job).await
+}
+
+/// 批量导出任务：逐张生成导出文件并写入 zip
+pub async fn run_export_job(ctx: JobContext) -> Result<Value, JobError> {
+    let payload: ExportJobPayload = serde_json::from_value(ctx.payload.clone())
+        .map_err(|e| JobError::Fatal(format!("任务参数无效: {}", e)))?;
+    let spec = parse_export_options(payload.format.as_deref(), payload.spec.as_deref())
+        .map_err(|(_, e)| JobError::Fatal(e))?;
+
     let cards = character_card::Entity::find()
         .filter(character_card::Column::Id.is_in(payload.ids))
-        .all(&db)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .all(&ctx.db)
+        .await?;
+    let total = cards.len();
+
+    // 文件与 zip 写入在阻塞线程中进行，这里只负责并发生成各张卡的导出数据
+    let path = ctx.artifact_path();
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<(String, Vec<u8>)>(16);
+    let writer = tokio::task::spawn_blocking(move || -> Result<u64, String> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("创建目录失败: {}", e))?;
+        }
+        let file =
+            std::fs::File::create(&path).map_err(|e| format!("创建导出文件失败: {}", e))?;
+        let mut zip_writer = zip::ZipWriter::new(std::io::BufWriter::new(file));
+        let options =
+            FileOptions::<()>::default().compression_method(zip::CompressionMethod::Deflated);
+        let zip_err = |e: String| format!("Zip error: {}", e);
+
+        let mut names = std::collections::HashSet::new();
+        while let Some((filename, data)) = rx.blocking_recv() {
+            let filename = unique_entry_name(&mut names, filename);
+            zip_writer
+                .start_file(filename, options)
+                .map_err(|e| zip_err(e.to_string()))?;
+            zip_writer
+                .write_all(&data)
+                .map_err(|e| zip_err(e.to_string()))?;
+        }
+        zip_writer.finish().map_err(|e| zip_err(e.to_string()))?;
+        Ok(std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0))
+    });
 
     // Process concurrently
-    let results: Vec<Result<(String, Vec<u8>), String>> = futures::stream::iter(cards)
+    let mut results = futures::stream::iter(cards)
         .map(|card| {
-            let db = db.clone();
-            async move { _get_card_file_data(&db, card).await }
+            let db = ctx.db.clone();
+            let format = payload.format.clone();
+            async move { get_card_export_data(&db, card, format.as_deref(), spec).await }
         })
-        .buffer_unordered(10)
-        .collect()
-        .await;
-
-    // Create Zip
-    let mut zip_writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
-    let options = FileOptions::<()>::default().compression_method(zip::CompressionMethod::Deflated);
-
-    let mut names = std::collections::HashSet::new();
-
-    for res in results {
-        if let Ok((mut filename, data)) = res {
-            // Deduplicate logic
-            let mut i = 1;
-            // Handle filenames without logic stems carefully ifneeded, but safe_name ensures alphanumeric.
-            // Split extension
-            let (stem, ext) = if let Some(idx) = filename.rfind('.') {
-                (&filename[..idx], &filename[idx + 1..])
-            } else {
-                (filename.as_str(), "")
-            };
-
-            let stem = stem.to_string();
-            let ext = ext.to_string();
-
-            while names.contains(&filename) {
-                filename = format!("{}_{}.{}", stem, i, ext);
-                i += 1;
+        .buffer_unordered(10);
+
+    let mut done = 0;
+    let mut exported = 0;
+    while let Some(res) = results.next().await {
+        done += 1;
+        match res {
+            Ok(entry) => {
+                // 写入线程出错退出时停止生成，错误在下面取回
+                if tx.send(entry).await.is_err() {
+                    break;
+                }
+                exported += 1;
             }
-            names.insert(filename.clone());
-
-            let _ = zip_writer.start_file(filename, options);
-            let _ = zip_writer.write_all(&data);
+            Err(e) => tracing::warn!("Batch export skipped a card: {}", e),
         }
+        ctx.progress(
+            done as f64 / total.max(1) as f64,
+            format!("已导出 {}/{}", done, total),
+        )
+        .await;
     }
+    drop(tx);
 
-    let cursor = zip_writer.finish().map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Zip error: {}", e),
-        )
-    })?;
-    let zip_buffer = cursor.into_inner();
+    let size = writer
+        .await
+        .map_err(|e| JobError::Retry(e.to_string()))?
+        .map_err(JobError::Retry)?;
+
+    Ok(serde_json::json!({
+        "file_name": "batch_export.zip",
+        "content_type": "application/zip",
+        "size": size,
+        "exported": exported,
+        "total": total,
+    }))
+}
 
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        header::CONTENT_DISPOSITION,
-        "attachment; filename=\"batch_export.zip\"".parse().unwrap(),
-    );
-    headers.insert(header::CONTENT_TYPE, "application/zip".parse().unwrap());
+/// 压缩包内文件名去重：重名时在扩展名前追加 _1、_2……
+fn unique_entry_name(names: &mut std::collections::HashSet<String>, filename: String) -> String {
+    let (stem, ext) = match filename.rfind('.') {
+        Some(idx) => (filename[..idx].to_string(), filename[idx + 1..].to_string()),
+        None => (filename.clone(), String::new()),
+    };
 
-    Ok((headers, Body::from(zip_buffer)))
+    let mut candidate = filename;
+    let mut i = 1;
+    while names.contains(&candidate) {
+        candidate = format!("{}_{}.{}", stem, i, ext);
+        i += 1;
+    }
+    names.insert(candidate.clone());
+    candidate
 }
 
 #[derive(Deserialize)]
 pub struct BatchUpdateCategoryRequest {
+    #[serde(default)]
     pub ids: Vec<Uuid>,
+    /// 筛选表达式，与 ids 二选一（同时给出时取交集）
+    pub filter: Option<String>,
     pub category_id: Option<Uuid>,
 }
 
 pub async fn batch_update_category(
     State(db): State<DatabaseConnection>,
     Json(payload): Json<BatchUpdateCategoryRequest>,
-) -> Result<StatusCode, (StatusCode, String)> {
-    if payload.ids.is_empty() {
-        return Ok(StatusCode::OK);
+) -> Result<Json<BatchOperationResponse>, (StatusCode, String)> {
+    let ids = resolve_batch_ids(&db, payload.ids, payload.filter.as_deref()).await?;
+    if ids.is_empty() {
+        return Ok(Json(BatchOperationResponse::default()));
     }
 
-    // 批量更新
-    character_card::Entity::update_many()
+    let txn = db
+        .begin()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // 记录原分类，用于撤销
+    let operation_id = operation_journal::record(
+        &txn,
+        NewOperation {
+            kind: "card_batch_category",
+            target: JournalTarget::Card,
+            summary: format!("批量移动 {} 张角色卡的分类", ids.len()),
+            columns: &["category_id"],
+            ids: &ids,
+        },
+    )
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // 批量更新；同时刷新 updated_at，使客户端持有的旧 ETag 失效
+    let result = character_card::Entity::update_many()
         .col_expr(
             character_card::Column::CategoryId,
             payload.category_id.into(),
         )
-        .filter(character_card::Column::Id.is_in(payload.ids))
-        .exec(&db)
+        .col_expr(
+            character_card::Column::UpdatedAt,
+            sea_orm::sea_query::Expr::value(chrono::Utc::now().naive_utc()),
+        )
+        .filter(character_card::Column::Id.is_in(ids.clone()))
+        .exec(&txn)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    operation_journal::record_after(&txn, operation_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    txn.commit()
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     invalidate_cache();
-    Ok(StatusCode::OK)
+    event_bus::publish(AppEvent::CardsUpdated { ids });
+    Ok(Json(BatchOperationResponse {
+        updated: result.rows_affected,
+        operation_id: Some(operation_id),
+    }))
+}
+
+/// 批量操作结果，`operation_id` 可用于 `POST /api/operations/{id}/undo`
+#[derive(Serialize, Default)]
+pub struct BatchOperationResponse {
+    pub updated: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub operation_id: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct BatchEditRequest {
+    #[serde(default)]
+    pub ids: Vec<Uuid>,
+    /// 筛选表达式，与 ids 二选一（同时给出时取交集）
+    pub filter: Option<String>,
+    pub operation: BulkOperation,
+    /// 默认只预览，为 false 时才写入
+    #[serde(default = "default_dry_run")]
+    pub dry_run: bool,
+}
+
+fn default_dry_run() -> bool {
+    true
+}
+
+#[derive(Serialize)]
+pub struct BatchEditCard {
+    pub card_id: Uuid,
+    pub name: String,
+    pub changes: Vec<FieldChange>,
+    /// 写入前保存的版本号，可据此恢复
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version_number: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct BatchEditResponse {
+    pub dry_run: bool,
+    /// 批次 ID，写在版本备注中
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub batch_id: Option<Uuid>,
+    /// 可用于 `POST /api/operations/{id}/undo` 整批撤销
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub operation_id: Option<Uuid>,
+    /// 选中的卡片数
+    pub matched: usize,
+    /// 有变更的卡片数
+    pub affected: usize,
+    pub cards: Vec<BatchEditCard>,
+}
+
+/// POST /api/cards/batch/edit - 批量编辑（默认预览）
+///
+/// 写入时每张受影响的卡片先保存一个版本，并记录操作日志，可整批撤销
+pub async fn batch_edit(
+    State(db): State<DatabaseConnection>,
+    Json(payload): Json<BatchEditRequest>,
+) -> Result<Json<BatchEditResponse>, (StatusCode, String)> {
+    let mut operation = payload.operation;
+    // 与其他写入标签的入口一致，先按别名规则规范化
+    if let BulkOperation::AddTags { tags } = &mut operation {
+        *tags = tag_admin::apply_aliases(&db, std::mem::take(tags))
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+    let prepared = PreparedOperation::new(operation).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let ids = resolve_batch_ids(&db, payload.ids, payload.filter.as_deref()).await?;
+
+    let cards = if ids.is_empty() {
+        Vec::new()
+    } else {
+        character_card::Entity::find()
+            .filter(character_card::Column::Id.is_in(ids))
+            .filter(character_card::Column::DeletedAt.is_null())
+            .order_by_asc(character_card::Column::Name)
+            .all(&db)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    };
+    let matched = cards.len();
+
+    let planned: Vec<_> = cards
+        .into_iter()
+        .filter_map(|card| card_bulk_edit::plan(&prepared, &card).map(|edit| (card, edit)))
+        .collect();
+
+    if payload.dry_run {
+        return Ok(Json(BatchEditResponse {
+            dry_run: true,
+            batch_id: None,
+            operation_id: None,
+            matched,
+            affected: planned.len(),
+            cards: planned
+                .into_iter()
+                .map(|(card, edit)| BatchEditCard {
+                    card_id: card.id,
+                    name: card.name,
+                    changes: edit.changes,
+                    version_number: None,
+                })
+                .collect(),
+        }));
+    }
+
+    let batch_id = Uuid::new_v4();
+    let note = card_bulk_edit::version_note(prepared.operation(), batch_id);
+    // 整批在一个事务中执行，中途失败时不留下部分修改与多余的版本
+    let txn = db
+        .begin()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    // 记录整批修改前的列值，可通过操作日志一次撤销
+    let operation_id = if planned.is_empty() {
+        None
+    } else {
+        let ids: Vec<Uuid> = planned.iter().map(|(card, _)| card.id).collect();
+        let id = operation_journal::record(
+            &txn,
+            NewOperation {
+                kind: "card_batch_edit",
+                target: JournalTarget::Card,
+                summary: format!(
+                    "批量编辑 {} 张角色卡：{}",
+                    ids.len(),
+                    prepared.operation().describe()
+                ),
+                columns: &card_bulk_edit::JOURNAL_COLUMNS,
+                ids: &ids,
+            },
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        Some(id)
+    };
+    let mut results = Vec::with_capacity(planned.len());
+    for (card, mut edit) in planned {
+        let version_number = crate::api::versions::next_version_number(&txn, card.id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        version_store::insert_version(
+            &txn,
+            NewVersion {
+                character_id: card.id,
+                version_number: version_number.clone(),
+                note: Some(note.clone()),
+                data: card.data.clone(),
+                is_auto: false,
+            },
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("保存版本失败: {}", e)))?;
+
+        let changes = std::mem::take(&mut edit.changes);
+        let updated = card_bulk_edit::apply(&txn, card, edit)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        results.push(BatchEditCard {
+            card_id: updated.id,
+            name: updated.name,
+            changes,
+            version_number: Some(version_number),
+        });
+    }
+    if let Some(id) = operation_id {
+        operation_journal::record_after(&txn, id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+    txn.commit()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if !results.is_empty() {
+        invalidate_cache();
+        event_bus::publish(AppEvent::CardsUpdated {
+            ids: results.iter().map(|r| r.card_id).collect(),
+        });
+    }
+    Ok(Json(BatchEditResponse {
+        dry_run: false,
+        batch_id: Some(batch_id),
+        operation_id,
+        matched,
+        affected: results.len(),
+        cards: results,
+    }))
 }
 
 #[derive(Deserialize)]
 pub struct BatchDeleteRequest {
+    #[serde(default)]
     pub ids: Vec<Uuid>,
+    /// 筛选表达式，与 ids 二选一（同时给出时取交集）
+    pub filter: Option<String>,
 }
 
 pub async fn batch_soft_delete(
     State(db): State<DatabaseConnection>,
     Json(payload): Json<BatchDeleteRequest>,
-) -> Result<StatusCode, (StatusCode, String)> {
-    if payload.ids.is_empty() {
-        return Ok(StatusCode::OK);
+) -> Result<Json<BatchOperationResponse>, (StatusCode, String)> {
+    let ids = resolve_batch_ids(&db, payload.ids, payload.filter.as_deref()).await?;
+    if ids.is_empty() {
+        return Ok(Json(BatchOperationResponse::default()));
     }
 
-    character_card::Entity::update_many()
+    let txn = db
+        .begin()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let operation_id = operation_journal::record(
+        &txn,
+        NewOperation {
+            kind: "card_batch_delete",
+            target: JournalTarget::Card,
+            summary: format!("批量删除 {} 张角色卡", ids.len()),
+            columns: &["deleted_at"],
+            ids: &ids,
+        },
+    )
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let now = chrono::Utc::now().naive_utc();
+    let result = character_card::Entity::update_many()
         .col_expr(
             character_card::Column::DeletedAt,
-            sea_orm::sea_query::Expr::value(chrono::Utc::now().naive_utc()),
+            sea_orm::sea_query::Expr::value(now),
         )
-        .filter(character_card::Column::Id.is_in(payload.ids))
-        .exec(&db)
+        .col_expr(
+            character_card::Column::UpdatedAt,
+            sea_orm::sea_query::Expr::value(now),
+        )
+        .filter(character_card::Column::Id.is_in(ids.clone()))
+        .exec(&txn)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    operation_journal::record_after(&txn, operation_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    txn.commit()
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     invalidate_cache();
-    Ok(StatusCode::OK)
+    event_bus::publish(AppEvent::CardsDeleted {
+        ids,
+        permanent: false,
+    });
+    Ok(Json(BatchOperationResponse {
+        updated: result.rows_affected,
+        operation_id: Some(operation_id),
+    }))
 }
 
 /// DELETE /api/cards/:id - 软删除
@@ -1774,6 +2553,10 @@ pub async fn soft_delete(
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     invalidate_cache();
+    event_bus::publish(AppEvent::CardsDeleted {
+        ids: vec![id],
+        permanent: false,
+    });
     Ok(StatusCode::OK)
 }
 
@@ -1881,6 +2664,7 @@ pub async fn restore_card(
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     invalidate_cache();
+    event_bus::publish(AppEvent::CardRestored { id });
     Ok(StatusCode::OK)
 }
 
@@ -1905,6 +2689,10 @@ pub async fn permanent_delete(
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     invalidate_cache();
+    event_bus::publish(AppEvent::CardsDeleted {
+        ids: vec![id],
+        permanent: true,
+    });
     Ok(StatusCode::OK)
 }
 
@@ -1936,12 +2724,16 @@ pub async fn batch_delete_trash(
 
     // 批量删除数据库记录
     character_card::Entity::delete_many()
-        .filter(character_card::Column::Id.is_in(payload.ids))
+        .filter(character_card::Column::Id.is_in(payload.ids.clone()))
         .exec(&db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     invalidate_cache();
+    event_bus::publish(AppEvent::CardsDeleted {
+        ids: payload.ids,
+        permanent: true,
+    });
     Ok(StatusCode::OK)
 }
 
@@ -1981,12 +2773,16 @@ pub async fn clear_trash(
 
     // 3. 批量删除数据库记录
     character_card::Entity::delete_many()
-        .filter(character_card::Column::Id.is_in(ids))
+        .filter(character_card::Column::Id.is_in(ids.clone()))
         .exec(&db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     invalidate_cache();
+    event_bus::publish(AppEvent::CardsDeleted {
+        ids,
+        permanent: true,
+    });
     Ok(Json(ClearTrashResponse {
         deleted_count: count,
     }))
@@ -2003,28 +2799,17 @@ pub struct TagStatsResponse {
 pub async fn tag_stats(
     State(db): State<DatabaseConnection>,
 ) -> Result<Json<TagStatsResponse>, (StatusCode, String)> {
-    // 获取所有未删除卡片的标签
-    let rows: Vec<(String,)> = character_card::Entity::find()
-        .select_only()
-        .column(character_card::Column::Tags)
+    let tag_counts = tag_index::card_tag_counts(&db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let total_cards = character_card::Entity::find()
         .filter(character_card::Column::DeletedAt.is_null())
-        .into_tuple()
-        .all(&db)
+        .count(&db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    // 统计标签
-    let mut tag_counts: std::collections::HashMap<String, u32> = std::collections::HashMap::new();
-    for (tags_json,) in &rows {
-        if let Ok(tags) = serde_json::from_str::<Vec<String>>(tags_json) {
-            for tag in tags {
-                *tag_counts.entry(tag).or_insert(0) += 1;
-            }
-        }
-    }
-
     Ok(Json(TagStatsResponse {
-        tags: tag_counts,
-        total_cards: rows.len() as u64,
+        tags: tag_counts.into_iter().collect(),
+        total_cards,
     }))
 }
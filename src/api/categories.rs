@@ -141,6 +141,7 @@ pub async fn delete(
     for card in cards {
         let mut active: character_card::ActiveModel = card.into();
         active.category_id = Set(None);
+        active.updated_at = Set(chrono::Utc::now().naive_utc());
         active
             .update(&db)
             .await
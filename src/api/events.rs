@@ -0,0 +1,36 @@
+//! 实时变更通知 API
+//!
+//! GET /api/events 以 SSE 推送事件总线上的事件。浏览器的 EventSource 无法设置请求头，
+//! 可通过 `?token=` 传递 JWT，校验逻辑与其他受保护接口相同。
+
+use std::{convert::Infallible, time::Duration};
+
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures::stream::{self, Stream};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::services::event_bus::{self, AppEvent};
+
+/// GET /api/events - 订阅变更事件 (SSE)
+pub async fn stream() -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let rx = event_bus::subscribe();
+
+    let stream = stream::unfold(rx, |mut rx| async move {
+        let event = match rx.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::debug!("Event subscriber lagged, skipped {} events", skipped);
+                AppEvent::Resync
+            }
+            Err(RecvError::Closed) => return None,
+        };
+        let data = serde_json::to_string(&event).unwrap_or_default();
+        Some((Ok(Event::default().data(data)), rx))
+    });
+
+    Sse::new(stream).keep_alive(
+        KeepAlive::new()
+            .interval(Duration::from_secs(15))
+            .text("keep-alive"),
+    )
+}
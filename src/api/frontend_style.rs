@@ -3,9 +3,10 @@
 //! 提供前端样式（皮皮美化工作台）的 CRUD 操作
 
 use crate::entities::frontend_style;
+use crate::utils::etag;
 use axum::{
     extract::{Path, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::IntoResponse,
     Json,
 };
@@ -128,7 +129,10 @@ pub async fn get_style(
             )
         })?;
 
-    Ok(Json(StyleResponse::from(style)))
+    Ok(etag::with_etag(
+        &etag::from_updated_at(&style.updated_at),
+        Json(StyleResponse::from(style)),
+    ))
 }
 
 /// POST /api/frontend-styles - 创建新样式
@@ -160,10 +164,11 @@ pub async fn create_style(
     Ok((StatusCode::CREATED, Json(StyleResponse::from(result))))
 }
 
-/// PUT /api/frontend-styles/:id - 更新样式
+/// PUT /api/frontend-styles/:id - 更新样式（支持 `If-Match`，数据已过期时返回 412 与当前数据）
 pub async fn update_style(
     State(db): State<DatabaseConnection>,
     Path(id): Path<Uuid>,
+    headers: HeaderMap,
     Json(payload): Json<UpdateStyleRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
     let style = frontend_style::Entity::find_by_id(id)
@@ -182,6 +187,15 @@ pub async fn update_style(
             )
         })?;
 
+    let current_etag = etag::from_updated_at(&style.updated_at);
+    if !etag::if_match_satisfied(&headers, &current_etag) {
+        return Ok(etag::precondition_failed(
+            &current_etag,
+            Json(StyleResponse::from(style)),
+        ));
+    }
+
+    let read_at = style.updated_at;
     let mut active: frontend_style::ActiveModel = style.into();
 
     if let Some(name) = payload.name {
@@ -205,14 +219,38 @@ pub async fn update_style(
 
     active.updated_at = Set(Utc::now().naive_utc());
 
-    let result = active.update(&db).await.map_err(|e| {
+    let db_err = |e: sea_orm::DbErr| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(json!({ "error": e.to_string() })),
         )
-    })?;
+    };
+    let Some(result) =
+        etag::update_if_unchanged(&db, active, frontend_style::Column::UpdatedAt, read_at)
+            .await
+            .map_err(db_err)?
+    else {
+        // 检查之后被其他请求修改
+        let current = frontend_style::Entity::find_by_id(id)
+            .one(&db)
+            .await
+            .map_err(db_err)?
+            .ok_or_else(|| {
+                (
+                    StatusCode::NOT_FOUND,
+                    Json(json!({ "error": "样式不存在" })),
+                )
+            })?;
+        return Ok(etag::precondition_failed(
+            &etag::from_updated_at(&current.updated_at),
+            Json(StyleResponse::from(current)),
+        ));
+    };
 
-    Ok(Json(StyleResponse::from(result)))
+    Ok(etag::with_etag(
+        &etag::from_updated_at(&result.updated_at),
+        Json(StyleResponse::from(result)),
+    ))
 }
 
 /// DELETE /api/frontend-styles/:id - 删除样式
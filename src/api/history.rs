@@ -1,10 +1,12 @@
 use crate::entities::{chat_history, prelude::*};
+use crate::services::search_index;
+use crate::utils::etag;
 use anyhow::Result;
 use axum::{
     body::Body,
     extract::{Multipart, Path, State},
-    http::StatusCode,
-    response::Json,
+    http::{HeaderMap, StatusCode},
+    response::{Json, Response},
 };
 use chrono::Utc;
 use sea_orm::*;
@@ -204,6 +206,10 @@ pub async fn upload_history(
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    if let Err(e) = search_index::index_chat(&db, &saved, &data).await {
+        tracing::warn!("Failed to index chat history {}: {}", saved.id, e);
+    }
+
     Ok(Json(ChatHistoryDto::from(saved)))
 }
 
@@ -395,13 +401,23 @@ pub async fn get_history_content(
     State(db): State<DatabaseConnection>,
     Path((card_id, history_id)): Path<(Uuid, Uuid)>,
     Query(query): Query<GetContentQuery>,
-) -> Result<Body, (StatusCode, String)> {
+) -> Result<Response, (StatusCode, String)> {
     let history = ChatHistory::find_by_id(history_id)
         .one(&db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
         .ok_or((StatusCode::NOT_FOUND, "History not found".to_string()))?;
 
+    let current_etag = etag::from_updated_at(&history.updated_at);
+    let body = read_history_content(card_id, history, query).await?;
+    Ok(etag::with_etag(&current_etag, body))
+}
+
+async fn read_history_content(
+    card_id: Uuid,
+    history: chat_history::Model,
+    query: GetContentQuery,
+) -> Result<Body, (StatusCode, String)> {
     let target_file_name = if query.source.unwrap_or(false) {
         history.source_file_name.ok_or((
             StatusCode::NOT_FOUND,
@@ -689,17 +705,27 @@ pub async fn get_history_content(
     })?))
 }
 
+/// 覆盖聊天记录内容（支持 `If-Match`，数据已过期时返回 412 与当前记录信息）
 pub async fn update_history_content(
     State(db): State<DatabaseConnection>,
     Path((card_id, history_id)): Path<(Uuid, Uuid)>,
+    headers: HeaderMap,
     mut multipart: Multipart,
-) -> Result<Json<ChatHistoryDto>, (StatusCode, String)> {
+) -> Result<Response, (StatusCode, String)> {
     let history = ChatHistory::find_by_id(history_id)
         .one(&db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
         .ok_or((StatusCode::NOT_FOUND, "History not found".to_string()))?;
 
+    let current_etag = etag::from_updated_at(&history.updated_at);
+    if !etag::if_match_satisfied(&headers, &current_etag) {
+        return Ok(etag::precondition_failed(
+            &current_etag,
+            Json(ChatHistoryDto::from(history)),
+        ));
+    }
+
     let card_dir = crate::utils::paths::get_data_path("cards").join(card_id.to_string());
 
     let mut file_data: Option<Vec<u8>> = None;
@@ -722,21 +748,48 @@ pub async fn update_history_content(
     let data = file_data.ok_or((StatusCode::BAD_REQUEST, "Missing file content".to_string()))?;
     let file_size = data.len() as i64;
 
-    // Overwrite existing file
+    // 先在事务中条件更新记录，成功后再覆盖文件；写文件失败时回滚。
+    // 事务持有写锁，并发的另一次覆盖会等待提交后因 updated_at 不符而返回 412
     let file_path = card_dir.join(&history.file_name);
-    fs::write(&file_path, &data)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
-    // Update DB
+    let read_at = history.updated_at;
     let mut active: chat_history::ActiveModel = history.into();
     active.file_size = Set(file_size);
     active.updated_at = Set(Utc::now().naive_utc());
 
-    let updated = active
-        .update(&db)
+    let txn = db
+        .begin()
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let Some(updated) =
+        etag::update_if_unchanged(&txn, active, chat_history::Column::UpdatedAt, read_at)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    else {
+        drop(txn);
+        let current = ChatHistory::find_by_id(history_id)
+            .one(&db)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .ok_or((StatusCode::NOT_FOUND, "History not found".to_string()))?;
+        return Ok(etag::precondition_failed(
+            &etag::from_updated_at(&current.updated_at),
+            Json(ChatHistoryDto::from(current)),
+        ));
+    };
 
-    Ok(Json(ChatHistoryDto::from(updated)))
+    fs::write(&file_path, &data)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    txn.commit()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if let Err(e) = search_index::index_chat(&db, &updated, &data).await {
+        tracing::warn!("Failed to index chat history {}: {}", updated.id, e);
+    }
+
+    Ok(etag::with_etag(
+        &etag::from_updated_at(&updated.updated_at),
+        Json(ChatHistoryDto::from(updated)),
+    ))
 }
@@ -12,7 +12,7 @@ use chrono::{TimeZone, Utc};
 use image::{DynamicImage, GenericImageView};
 use sea_orm::{
     ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, ModelTrait, QueryFilter,
-    QueryOrder, Set,
+    QueryOrder, Set, TransactionTrait,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
@@ -20,6 +20,9 @@ use tokio::fs;
 use uuid::Uuid;
 
 use crate::entities::image as image_entity;
+use crate::services::event_bus::{self, AppEvent};
+use crate::services::operation_journal::{self, JournalTarget, NewOperation};
+use crate::services::tag_index;
 
 // ==================== 请求/响应结构 ====================
 
@@ -410,7 +413,7 @@ pub async fn list(
         use sea_orm::Condition;
         let search_condition = Condition::any()
             .add(image_entity::Column::Title.contains(search))
-            .add(image_entity::Column::Tags.contains(search))
+            .add(tag_index::image_tag_contains(search))
             .add(image_entity::Column::AiPrompt.contains(search));
         select = select.filter(search_condition);
     }
@@ -634,6 +637,11 @@ pub async fn import(
         imported_ids.push(id);
     }
 
+    if !imported_ids.is_empty() {
+        event_bus::publish(AppEvent::ImagesCreated {
+            ids: imported_ids.clone(),
+        });
+    }
     Ok((
         StatusCode::CREATED,
         Json(json!({ "imported": imported_ids.len(), "ids": imported_ids })),
@@ -702,6 +710,7 @@ pub async fn update(
         )
     })?;
 
+    event_bus::publish(AppEvent::ImagesUpdated { ids: vec![id] });
     Ok(Json(ImageResponse::from(result)))
 }
 
@@ -740,6 +749,7 @@ pub async fn delete(
         )
     })?;
 
+    event_bus::publish(AppEvent::ImagesDeleted { ids: vec![id] });
     Ok(StatusCode::NO_CONTENT)
 }
 
@@ -760,6 +770,9 @@ pub async fn batch_delete(
         }
     }
 
+    event_bus::publish(AppEvent::ImagesDeleted {
+        ids: payload.ids.clone(),
+    });
     Ok(Json(json!({ "deleted": payload.ids.len() })))
 }
 
@@ -768,15 +781,52 @@ pub async fn batch_category(
     State(db): State<DatabaseConnection>,
     Json(payload): Json<BatchCategoryRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let db_err = |e: sea_orm::DbErr| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": e.to_string() })),
+        )
+    };
+    let txn = db.begin().await.map_err(db_err)?;
+
+    // 记录原分类，用于撤销
+    let operation_id = operation_journal::record(
+        &txn,
+        NewOperation {
+            kind: "image_batch_category",
+            target: JournalTarget::Image,
+            summary: format!("批量移动 {} 张图片的分类", payload.ids.len()),
+            columns: &["category_id"],
+            ids: &payload.ids,
+        },
+    )
+    .await
+    .map_err(db_err)?;
+
     for id in &payload.ids {
-        if let Ok(Some(img)) = image_entity::Entity::find_by_id(*id).one(&db).await {
+        if let Some(img) = image_entity::Entity::find_by_id(*id)
+            .one(&txn)
+            .await
+            .map_err(db_err)?
+        {
             let mut active: image_entity::ActiveModel = img.into();
             active.category_id = Set(payload.category_id);
-            let _ = active.update(&db).await;
+            active.update(&txn).await.map_err(db_err)?;
         }
     }
 
-    Ok(Json(json!({ "updated": payload.ids.len() })))
+    operation_journal::record_after(&txn, operation_id)
+        .await
+        .map_err(db_err)?;
+
+    txn.commit().await.map_err(db_err)?;
+
+    event_bus::publish(AppEvent::ImagesUpdated {
+        ids: payload.ids.clone(),
+    });
+    Ok(Json(
+        json!({ "updated": payload.ids.len(), "operation_id": operation_id }),
+    ))
 }
 
 /// PATCH /api/images/batch/update - 批量更新
@@ -784,8 +834,45 @@ pub async fn batch_update(
     State(db): State<DatabaseConnection>,
     Json(payload): Json<BatchUpdateRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let db_err = |e: sea_orm::DbErr| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": e.to_string() })),
+        )
+    };
+    let mut columns = Vec::new();
+    if payload.is_ai.is_some() {
+        columns.push("is_ai");
+    }
+    if payload.is_authorized.is_some() {
+        columns.push("is_authorized");
+    }
+    // 没有要修改的字段时不写入操作日志
+    if columns.is_empty() {
+        return Ok(Json(json!({ "updated": 0, "operation_id": null })));
+    }
+
+    let txn = db.begin().await.map_err(db_err)?;
+
+    let operation_id = operation_journal::record(
+        &txn,
+        NewOperation {
+            kind: "image_batch_update",
+            target: JournalTarget::Image,
+            summary: format!("批量更新 {} 张图片的属性", payload.ids.len()),
+            columns: &columns,
+            ids: &payload.ids,
+        },
+    )
+    .await
+    .map_err(db_err)?;
+
     for id in &payload.ids {
-        if let Ok(Some(img)) = image_entity::Entity::find_by_id(*id).one(&db).await {
+        if let Some(img) = image_entity::Entity::find_by_id(*id)
+            .one(&txn)
+            .await
+            .map_err(db_err)?
+        {
             let mut active: image_entity::ActiveModel = img.into();
             if let Some(is_ai) = payload.is_ai {
                 active.is_ai = Set(is_ai);
@@ -793,11 +880,22 @@ pub async fn batch_update(
             if let Some(is_authorized) = payload.is_authorized {
                 active.is_authorized = Set(is_authorized);
             }
-            let _ = active.update(&db).await;
+            active.update(&txn).await.map_err(db_err)?;
         }
     }
 
-    Ok(Json(json!({ "updated": payload.ids.len() })))
+    operation_journal::record_after(&txn, operation_id)
+        .await
+        .map_err(db_err)?;
+
+    txn.commit().await.map_err(db_err)?;
+
+    event_bus::publish(AppEvent::ImagesUpdated {
+        ids: payload.ids.clone(),
+    });
+    Ok(Json(
+        json!({ "updated": payload.ids.len(), "operation_id": operation_id }),
+    ))
 }
 
 /// POST /api/images/batch/export - 批量导出
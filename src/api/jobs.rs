@@ -0,0 +1,178 @@
+//! 后台任务 API
+//!
+//! 列出任务、订阅单个任务的进度 (SSE)、取消或删除任务、下载任务产出的文件。
+//! 各类任务的执行函数由 `run` 按类型分派。
+
+use std::convert::Infallible;
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
+};
+use futures::{
+    future::BoxFuture,
+    stream::{self, Stream, StreamExt},
+    FutureExt,
+};
+use sea_orm::{DatabaseConnection, EntityTrait};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use crate::api::{ai, backup, cards};
+use crate::entities::job;
+use crate::services::job_queue::{
+    self, CancelOutcome, JobContext, JobError, JobStatus, RemoveOnDrop,
+};
+
+#[derive(Deserialize)]
+pub struct ListJobsQuery {
+    pub kind: Option<String>,
+    pub status: Option<String>,
+    pub limit: Option<u64>,
+}
+
+/// 按任务类型分派执行函数（在 `job_queue::start` 时注册）
+pub fn run(ctx: JobContext) -> BoxFuture<'static, Result<Value, JobError>> {
+    match ctx.kind.as_str() {
+        ai::DOCTOR_JOB => ai::run_doctor_job(ctx).boxed(),
+        ai::OVERVIEW_BATCH_JOB => ai::run_overview_batch_job(ctx).boxed(),
+        cards::EXPORT_JOB => cards::run_export_job(ctx).boxed(),
+        backup::BACKUP_JOB => backup::run_backup_job(ctx).boxed(),
+        other => {
+            let message = format!("未知的任务类型: {}", other);
+            async move { Err(JobError::Fatal(message)) }.boxed()
+        }
+    }
+}
+
+/// GET /api/jobs - 最近的任务
+pub async fn list(
+    State(db): State<DatabaseConnection>,
+    Query(query): Query<ListJobsQuery>,
+) -> Result<Json<Vec<job::Model>>, (StatusCode, String)> {
+    let limit = query.limit.unwrap_or(50).min(200);
+    job_queue::list(&db, query.kind.as_deref(), query.status.as_deref(), limit)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// GET /api/jobs/{id}/events - 任务状态流 (SSE)，每条消息为完整的任务记录，任务结束后关闭
+pub async fn events(
+    State(db): State<DatabaseConnection>,
+    Path(id): Path<Uuid>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)> {
+    find_job(&db, id).await?;
+    Ok(event_stream(db, id))
+}
+
+/// 任务状态的 SSE 响应，事件名为任务状态，数据为完整的任务记录
+pub fn event_stream(
+    db: DatabaseConnection,
+    id: Uuid,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let stream = job_queue::watch(db, id).map(|model| {
+        let data = serde_json::to_string(&model).unwrap_or_default();
+        Ok(Event::default().event(model.status).data(data))
+    });
+
+    Sse::new(stream).keep_alive(
+        KeepAlive::new()
+            .interval(Duration::from_secs(15))
+            .text("keep-alive"),
+    )
+}
+
+/// DELETE /api/jobs/{id} - 取消未结束的任务；已结束的任务则删除记录与产出文件
+pub async fn cancel(
+    State(db): State<DatabaseConnection>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, (StatusCode, String)> {
+    let outcome = job_queue::cancel_or_remove(&db, id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    match outcome {
+        CancelOutcome::NotFound => Err((StatusCode::NOT_FOUND, "任务不存在".to_string())),
+        CancelOutcome::Cancelled(model) => Ok((StatusCode::ACCEPTED, Json(model)).into_response()),
+        CancelOutcome::Removed => Ok(StatusCode::NO_CONTENT.into_response()),
+    }
+}
+
+/// GET /api/jobs/{id}/download - 下载任务产出的文件
+pub async fn download(
+    State(db): State<DatabaseConnection>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, (StatusCode, String)> {
+    let model = find_job(&db, id).await?;
+    if model.status != JobStatus::Succeeded.as_str() {
+        return Err((StatusCode::CONFLICT, "任务尚未完成".to_string()));
+    }
+    let (headers, file) = open_artifact(&model).await?;
+    Ok((headers, Body::from_stream(ReaderStream::new(file))).into_response())
+}
+
+/// 以附件形式返回同步执行的任务的产出文件，发送完（或连接断开）后删除任务记录与文件
+pub async fn artifact_response_once(
+    db: DatabaseConnection,
+    model: &job::Model,
+) -> Result<Response, (StatusCode, String)> {
+    let (headers, file) = open_artifact(model).await?;
+    let cleanup = RemoveOnDrop::new(db, model.id);
+    // 文件读完并关闭后再删除（Windows 上不能删除打开中的文件）
+    let body = ReaderStream::new(file).chain(stream::once(async move {
+        drop(cleanup);
+        Ok(Default::default())
+    }));
+    Ok((headers, Body::from_stream(body)).into_response())
+}
+
+/// 打开已完成任务的产出文件，返回附件响应头
+async fn open_artifact(
+    model: &job::Model,
+) -> Result<(HeaderMap, tokio::fs::File), (StatusCode, String)> {
+    let result: Value = model
+        .result
+        .as_deref()
+        .and_then(|r| serde_json::from_str(r).ok())
+        .unwrap_or_default();
+    let file_name = result
+        .get("file_name")
+        .and_then(|v| v.as_str())
+        .ok_or((StatusCode::NOT_FOUND, "该任务没有产出文件".to_string()))?;
+    let content_type = result
+        .get("content_type")
+        .and_then(|v| v.as_str())
+        .unwrap_or("application/octet-stream");
+
+    let file = tokio::fs::File::open(job_queue::artifact_path(model.id))
+        .await
+        .map_err(|_| (StatusCode::NOT_FOUND, "文件已被清理".to_string()))?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        content_type
+            .parse()
+            .unwrap_or(header::HeaderValue::from_static("application/octet-stream")),
+    );
+    if let Ok(value) = format!("attachment; filename=\"{}\"", file_name).parse() {
+        headers.insert(header::CONTENT_DISPOSITION, value);
+    }
+    Ok((headers, file))
+}
+
+async fn find_job(db: &DatabaseConnection, id: Uuid) -> Result<job::Model, (StatusCode, String)> {
+    job::Entity::find_by_id(id)
+        .one(db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "任务不存在".to_string()))
+}
@@ -0,0 +1,95 @@
+//! 角色卡检查 API
+//!
+//! 按 V2/V3 规范检查角色卡结构，返回问题列表
+
+use crate::api::cards::resolve_batch_ids;
+use crate::entities::character_card;
+use crate::services::card_lint::{self, LintFinding, Severity};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+#[derive(Serialize)]
+pub struct LintReport {
+    pub id: Uuid,
+    pub name: String,
+    /// 无 error 级别问题
+    pub valid: bool,
+    pub error_count: usize,
+    pub warning_count: usize,
+    pub findings: Vec<LintFinding>,
+}
+
+impl LintReport {
+    fn from_card(card: &character_card::Model) -> Self {
+        let findings = match serde_json::from_str::<Value>(&card.data) {
+            Ok(json) => card_lint::lint_card(&json),
+            Err(e) => vec![LintFinding {
+                severity: Severity::Error,
+                code: "invalid-json",
+                path: "$".to_string(),
+                message: format!("角色卡数据不是合法的 JSON: {}", e),
+            }],
+        };
+
+        let count = |s: Severity| findings.iter().filter(|f| f.severity == s).count();
+        let error_count = count(Severity::Error);
+        let warning_count = count(Severity::Warning);
+
+        Self {
+            id: card.id,
+            name: card.name.clone(),
+            valid: error_count == 0,
+            error_count,
+            warning_count,
+            findings,
+        }
+    }
+}
+
+/// POST /api/cards/:id/lint - 检查单张角色卡
+pub async fn lint_card(
+    State(db): State<DatabaseConnection>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<LintReport>, (StatusCode, String)> {
+    let card = character_card::Entity::find_by_id(id)
+        .one(&db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "角色卡不存在".to_string()))?;
+
+    Ok(Json(LintReport::from_card(&card)))
+}
+
+#[derive(Deserialize)]
+pub struct BatchLintRequest {
+    #[serde(default)]
+    pub ids: Vec<Uuid>,
+    /// 筛选表达式，与 ids 二选一（同时给出时取交集）
+    pub filter: Option<String>,
+}
+
+/// POST /api/cards/batch/lint - 批量检查角色卡
+pub async fn batch_lint_cards(
+    State(db): State<DatabaseConnection>,
+    Json(payload): Json<BatchLintRequest>,
+) -> Result<Json<Vec<LintReport>>, (StatusCode, String)> {
+    let ids = resolve_batch_ids(&db, payload.ids, payload.filter.as_deref()).await?;
+    if ids.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "No cards selected".to_string()));
+    }
+
+    let cards = character_card::Entity::find()
+        .filter(character_card::Column::Id.is_in(ids))
+        .all(&db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(cards.iter().map(LintReport::from_card).collect()))
+}
@@ -7,13 +7,20 @@ pub mod backup;
 pub mod cards;
 pub mod categories;
 pub mod dashboard;
+pub mod events;
 pub mod frontend_style;
 pub mod history;
 pub mod image_categories;
 pub mod images;
+pub mod jobs;
+pub mod lint;
+pub mod operations;
+pub mod prompts;
 pub mod quick_reply;
+pub mod search;
 pub mod settings;
 pub mod system;
+pub mod tags;
 pub mod theater;
 pub mod upload;
 pub mod versions;
@@ -50,9 +57,27 @@ pub fn routes(db: DatabaseConnection, config: ConfigState) -> Router {
         .route("/gacha/confirm", post(dashboard::confirm_gacha))
         // 上传
         .route("/upload", post(upload::upload_image))
+        // 批量操作日志
+        .route("/operations", get(operations::list))
+        .route("/operations/{id}/undo", post(operations::undo))
+        // 后台任务
+        .route("/jobs", get(jobs::list))
+        .route("/jobs/{id}", delete(jobs::cancel))
+        .route("/jobs/{id}/download", get(jobs::download))
+        // 全文搜索
+        .route("/search", get(search::search))
         // 角色卡
         .route("/cards/all", get(cards::list_all))
         .route("/cards/stats/tags", get(cards::tag_stats))
+        .route("/cards/tags/rename", post(tags::rename))
+        .route("/cards/tags/merge", post(tags::merge))
+        .route("/cards/tags/delete", post(tags::delete))
+        .route(
+            "/cards/tags/aliases",
+            get(tags::list_aliases)
+                .put(tags::set_alias)
+                .delete(tags::delete_alias),
+        )
         .route("/cards", get(cards::list))
         .route("/cards/import", post(cards::import))
         .route("/cards/debug_import", post(cards::debug_import))
@@ -68,11 +93,16 @@ pub fn routes(db: DatabaseConnection, config: ConfigState) -> Router {
         .route("/cards/batch/category", put(cards::batch_update_category))
         .route("/cards/batch/delete", post(cards::batch_soft_delete))
         .route("/cards/batch/export", post(cards::batch_export_cards))
+        .route("/cards/batch/edit", post(cards::batch_edit))
+        // 角色卡检查
+        .route("/cards/{id}/lint", post(lint::lint_card))
+        .route("/cards/batch/lint", post(lint::batch_lint_cards))
         // 角色卡版本管理
         .route(
             "/cards/{id}/versions",
             get(versions::list_versions).post(versions::create_version),
         )
+        .route("/cards/{id}/versions/merge", post(versions::merge_versions))
         .route(
             "/cards/{id}/versions/{version_id}/restore",
             post(versions::restore_version),
@@ -81,6 +111,10 @@ pub fn routes(db: DatabaseConnection, config: ConfigState) -> Router {
             "/cards/{id}/versions/{version_id}",
             delete(versions::delete_version),
         )
+        .route(
+            "/cards/{id}/versions/{a}/diff/{b}",
+            get(versions::diff_versions),
+        )
         // 聊天记录
         .route(
             "/cards/{id}/history",
@@ -165,6 +199,16 @@ pub fn routes(db: DatabaseConnection, config: ConfigState) -> Router {
         )
         .route("/ai/test", post(ai::test_connection))
         .route("/ai/models", get(ai::list_models_proxy))
+        .route("/ai/usage", get(ai::usage_summary))
+        .route("/ai/routing", get(ai::get_routing).put(ai::update_routing))
+        .route("/ai/prompts", get(prompts::list))
+        .route(
+            "/ai/prompts/{feature}",
+            get(prompts::get)
+                .put(prompts::update)
+                .delete(prompts::reset),
+        )
+        .route("/ai/prompts/{feature}/preview", post(prompts::preview))
         .route("/ai/card/overview", post(ai::generate_overview))
         .route("/ai/execute", post(ai::execute_feature))
         // 小皮医生
@@ -204,7 +248,15 @@ pub fn routes(db: DatabaseConnection, config: ConfigState) -> Router {
         .layer(CompressionLayer::new());
 
     // 2. 不需要压缩的路由 (流式传输)
-    let streaming_routes = Router::new().route("/backup/export", get(backup::export_backup));
+    let streaming_routes = Router::new()
+        .route(
+            "/backup/export",
+            get(backup::export_backup).post(backup::start_backup_job),
+        )
+        .route("/jobs/{id}/events", get(jobs::events))
+        .route("/ai/card/overview/batch", post(ai::batch_generate_overview))
+        // 实时变更通知
+        .route("/events", get(events::stream));
 
     // 3. 备份导入路由 (使用 BackupState，包含 config)
     // 禁用默认 body 大小限制，允许上传任意大小的备份文件
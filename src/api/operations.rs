@@ -0,0 +1,71 @@
+//! 批量操作日志 API
+//!
+//! 列出最近的批量操作，并可整批撤销
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::api::dashboard::invalidate_cache;
+use crate::entities::operation;
+use crate::services::event_bus::{self, AppEvent};
+use crate::services::operation_journal::{self, JournalTarget, UndoError, JOURNAL_LIMIT};
+
+#[derive(Deserialize)]
+pub struct ListOperationsQuery {
+    pub limit: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct UndoResponse {
+    pub operation: operation::Model,
+    /// 恢复的行数（已被永久删除的行会跳过）
+    pub restored: u64,
+}
+
+/// GET /api/operations - 最近的批量操作
+pub async fn list(
+    State(db): State<DatabaseConnection>,
+    Query(query): Query<ListOperationsQuery>,
+) -> Result<Json<Vec<operation::Model>>, (StatusCode, String)> {
+    let limit = query.limit.unwrap_or(50).min(JOURNAL_LIMIT);
+    operation_journal::list(&db, limit)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// POST /api/operations/{id}/undo - 撤销批量操作
+pub async fn undo(
+    State(db): State<DatabaseConnection>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<UndoResponse>, (StatusCode, String)> {
+    let (operation, restored) = operation_journal::undo(&db, id)
+        .await
+        .map_err(|e| match e {
+            UndoError::NotFound => (StatusCode::NOT_FOUND, "操作记录不存在".to_string()),
+            UndoError::AlreadyUndone => (StatusCode::CONFLICT, "该操作已撤销".to_string()),
+            UndoError::Conflict(ids) => (
+                StatusCode::CONFLICT,
+                format!("{} 项在该操作之后又被修改，无法撤销", ids.len()),
+            ),
+            UndoError::Db(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?;
+
+    invalidate_cache();
+    let restored_count = restored.len() as u64;
+    if operation.target == JournalTarget::Image.as_str() {
+        event_bus::publish(AppEvent::ImagesUpdated { ids: restored });
+    } else {
+        event_bus::publish(AppEvent::CardsUpdated { ids: restored });
+    }
+    Ok(Json(UndoResponse {
+        operation,
+        restored: restored_count,
+    }))
+}
@@ -0,0 +1,196 @@
+//! AI 提示词模板 API
+//!
+//! 查看、修改、恢复各功能的提示词模板，并可用真实角色卡预览渲染结果
+
+use std::collections::BTreeMap;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use sea_orm::{DatabaseConnection, EntityTrait};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::entities::{ai_channel, character_card};
+use crate::services::prompt_template::{
+    self, PromptTemplate, Rendered, TemplateOverride, Variables,
+};
+
+fn db_err(e: sea_orm::DbErr) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+async fn find_template(
+    db: &DatabaseConnection,
+    feature: &str,
+) -> Result<PromptTemplate, (StatusCode, String)> {
+    prompt_template::load(db, feature)
+        .await
+        .map_err(db_err)?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("未知的功能: {}", feature)))
+}
+
+/// GET /api/ai/prompts - 所有功能的提示词模板
+pub async fn list(
+    State(db): State<DatabaseConnection>,
+) -> Result<Json<Vec<PromptTemplate>>, (StatusCode, String)> {
+    prompt_template::list(&db).await.map(Json).map_err(db_err)
+}
+
+/// GET /api/ai/prompts/{feature} - 单个功能的提示词模板
+pub async fn get(
+    State(db): State<DatabaseConnection>,
+    Path(feature): Path<String>,
+) -> Result<Json<PromptTemplate>, (StatusCode, String)> {
+    find_template(&db, &feature).await.map(Json)
+}
+
+/// 修改模板；整体替换，为空的项恢复内置值
+#[derive(Deserialize)]
+pub struct UpdatePromptRequest {
+    pub system_prompt: Option<String>,
+    pub user_prompt: Option<String>,
+    pub channel_id: Option<Uuid>,
+    pub temperature: Option<f64>,
+}
+
+/// 空白模板视为未设置
+fn non_blank(template: Option<String>) -> Option<String> {
+    template.filter(|t| !t.trim().is_empty())
+}
+
+/// PUT /api/ai/prompts/{feature} - 修改提示词模板与渠道、温度
+pub async fn update(
+    State(db): State<DatabaseConnection>,
+    Path(feature): Path<String>,
+    Json(payload): Json<UpdatePromptRequest>,
+) -> Result<Json<PromptTemplate>, (StatusCode, String)> {
+    if prompt_template::builtin(&feature).is_none() {
+        return Err((StatusCode::NOT_FOUND, format!("未知的功能: {}", feature)));
+    }
+
+    let system_prompt = non_blank(payload.system_prompt);
+    let user_prompt = non_blank(payload.user_prompt);
+    for (label, template) in [("系统提示词", &system_prompt), ("用户提示词", &user_prompt)]
+    {
+        if let Some(template) = template {
+            prompt_template::validate(template)
+                .map_err(|e| (StatusCode::BAD_REQUEST, format!("{}: {}", label, e)))?;
+        }
+    }
+    if let Some(temperature) = payload.temperature {
+        if !(0.0..=2.0).contains(&temperature) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "温度必须在 0 到 2 之间".to_string(),
+            ));
+        }
+    }
+    if let Some(channel_id) = payload.channel_id {
+        ai_channel::Entity::find_by_id(channel_id)
+            .one(&db)
+            .await
+            .map_err(db_err)?
+            .ok_or((StatusCode::BAD_REQUEST, "渠道不存在".to_string()))?;
+    }
+
+    prompt_template::save(
+        &db,
+        &feature,
+        TemplateOverride {
+            system_prompt,
+            user_prompt,
+            channel_id: payload.channel_id,
+            temperature: payload.temperature,
+        },
+    )
+    .await
+    .map_err(db_err)?;
+    find_template(&db, &feature).await.map(Json)
+}
+
+/// DELETE /api/ai/prompts/{feature} - 恢复内置模板
+pub async fn reset(
+    State(db): State<DatabaseConnection>,
+    Path(feature): Path<String>,
+) -> Result<Json<PromptTemplate>, (StatusCode, String)> {
+    if prompt_template::builtin(&feature).is_none() {
+        return Err((StatusCode::NOT_FOUND, format!("未知的功能: {}", feature)));
+    }
+    prompt_template::reset(&db, &feature)
+        .await
+        .map_err(db_err)?;
+    find_template(&db, &feature).await.map(Json)
+}
+
+/// 预览请求；未传入的模板使用当前生效的模板
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct PreviewRequest {
+    /// 用该角色卡填充变量
+    pub card_id: Option<Uuid>,
+    pub system_prompt: Option<String>,
+    pub user_prompt: Option<String>,
+    /// 额外的变量，覆盖角色卡生成的同名变量
+    pub variables: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+pub struct PreviewResponse {
+    pub system: Option<Rendered>,
+    pub user: Option<Rendered>,
+    pub variables: Variables,
+}
+
+/// POST /api/ai/prompts/{feature}/preview - 渲染提示词模板
+pub async fn preview(
+    State(db): State<DatabaseConnection>,
+    Path(feature): Path<String>,
+    Json(payload): Json<PreviewRequest>,
+) -> Result<Json<PreviewResponse>, (StatusCode, String)> {
+    let template = find_template(&db, &feature).await?;
+    let global_prompt = prompt_template::global_prompt(&db).await.map_err(db_err)?;
+
+    let mut variables = match payload.card_id {
+        Some(card_id) => {
+            let card = character_card::Entity::find_by_id(card_id)
+                .one(&db)
+                .await
+                .map_err(db_err)?
+                .ok_or((StatusCode::NOT_FOUND, "角色卡不存在".to_string()))?;
+            match feature.as_str() {
+                prompt_template::OVERVIEW => {
+                    let system_tags = crate::services::tag_index::card_tag_names(&db)
+                        .await
+                        .map_err(db_err)?;
+                    prompt_template::overview_variables(&card, &system_tags, &global_prompt)
+                }
+                prompt_template::DOCTOR => {
+                    prompt_template::doctor_variables(&card, &global_prompt).variables
+                }
+                _ => Variables::new(),
+            }
+        }
+        None => Variables::new(),
+    };
+    variables
+        .entry("global_prompt".to_string())
+        .or_insert(global_prompt);
+    variables.extend(payload.variables);
+
+    let render = |override_template: Option<String>, current: Option<String>| {
+        override_template
+            .or(current)
+            .map(|t| prompt_template::render(&t, &variables))
+    };
+    let system = render(payload.system_prompt, template.system_prompt);
+    let user = render(payload.user_prompt, template.user_prompt);
+
+    Ok(Json(PreviewResponse {
+        system,
+        user,
+        variables,
+    }))
+}
@@ -0,0 +1,66 @@
+//! 全文搜索 API
+//!
+//! 统一搜索角色卡（含世界书条目与开场白）、世界书、小剧场、图库提示词与聊天记录
+
+use crate::services::search_index::{self, SearchHit, SearchOptions};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    /// 逗号分隔的类型：card,world_info,theater,image,chat
+    pub kind: Option<String>,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct SearchResponse {
+    pub query: String,
+    pub hits: Vec<SearchHit>,
+}
+
+/// 搜索
+pub async fn search(
+    State(db): State<DatabaseConnection>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<SearchResponse>, (StatusCode, String)> {
+    let kinds: Vec<String> = query
+        .kind
+        .as_deref()
+        .unwrap_or("")
+        .split(',')
+        .map(|k| k.trim().to_string())
+        .filter(|k| !k.is_empty())
+        .collect();
+    if let Some(unknown) = kinds
+        .iter()
+        .find(|k| !search_index::KINDS.contains(&k.as_str()))
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("未知的搜索类型: {}", unknown),
+        ));
+    }
+
+    let options = SearchOptions {
+        query: query.q.trim(),
+        kinds,
+        limit: query.limit.unwrap_or(20).clamp(1, 100),
+        offset: query.offset.unwrap_or(0),
+    };
+    let hits = search_index::search(&db, &options)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(SearchResponse {
+        query: options.query.to_string(),
+        hits,
+    }))
+}
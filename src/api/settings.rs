@@ -33,6 +33,14 @@ pub struct Settings {
     pub ai_config_global: Option<String>,
     /// 全局提示词
     pub global_prompt: Option<String>,
+    /// 修改卡片前自动保存版本快照
+    pub version_auto_snapshot: bool,
+    /// 自动快照合并窗口（分钟），窗口内的连续修改只保留一份快照
+    pub version_snapshot_window_minutes: i64,
+    /// 自动快照保留最近 N 份
+    pub version_keep_last: i64,
+    /// 更早的自动快照每天保留一份，保留 M 天
+    pub version_keep_daily_days: i64,
 }
 
 /// 获取设置
@@ -54,6 +62,10 @@ pub async fn get(State(db): State<DatabaseConnection>) -> Json<Settings> {
         avatar: None,
         ai_config_global: None,
         global_prompt: None,
+        version_auto_snapshot: false,
+        version_snapshot_window_minutes: 10,
+        version_keep_last: 20,
+        version_keep_daily_days: 30,
     };
 
     // Apply values from DB
@@ -70,6 +82,14 @@ pub async fn get(State(db): State<DatabaseConnection>) -> Json<Settings> {
             "user_avatar" => s.avatar = Some(setting.value),
             "ai_config_global" => s.ai_config_global = Some(setting.value),
             "global_prompt" => s.global_prompt = Some(setting.value),
+            "version_auto_snapshot" => s.version_auto_snapshot = setting.value == "true",
+            "version_snapshot_window_minutes" => {
+                s.version_snapshot_window_minutes = setting.value.parse().unwrap_or(10)
+            }
+            "version_keep_last" => s.version_keep_last = setting.value.parse().unwrap_or(20),
+            "version_keep_daily_days" => {
+                s.version_keep_daily_days = setting.value.parse().unwrap_or(30)
+            }
             _ => {}
         }
     }
@@ -83,8 +103,22 @@ pub async fn update(
     Json(payload): Json<Value>,
 ) -> Result<Json<Settings>, StatusCode> {
     if let Value::Object(map) = payload {
+        // 先校验全部数值设置，避免部分写入
+        for (k, v) in &map {
+            if crate::services::version_snapshot::setting_range(k).is_some() {
+                let value = match v {
+                    Value::String(s) => s.clone(),
+                    Value::Number(n) => n.to_string(),
+                    _ => continue,
+                };
+                if crate::services::version_snapshot::clamp_setting(k, &value).is_none() {
+                    return Err(StatusCode::BAD_REQUEST);
+                }
+            }
+        }
+
         for (k, v) in map {
-            let val_str = match v {
+            let mut val_str = match v {
                 Value::String(s) => s,
                 Value::Number(n) => n.to_string(),
                 Value::Bool(b) => b.to_string(),
@@ -103,9 +137,18 @@ pub async fn update(
                 "avatar" => "user_avatar", // Map 'avatar' to 'user_avatar'
                 "ai_config_global" => "ai_config_global",
                 "global_prompt" => "global_prompt",
+                "version_auto_snapshot" => "version_auto_snapshot",
+                "version_snapshot_window_minutes" => "version_snapshot_window_minutes",
+                "version_keep_last" => "version_keep_last",
+                "version_keep_daily_days" => "version_keep_daily_days",
                 _ => continue,
             };
 
+            // 快照策略的数值设置截断到允许范围，避免计算时间间隔时溢出
+            if let Some(v) = crate::services::version_snapshot::clamp_setting(key_db, &val_str) {
+                val_str = v.to_string();
+            }
+
             // If updating avatar, delete the old file
             if key_db == "user_avatar" {
                 if let Ok(Some(old_setting)) =
@@ -0,0 +1,120 @@
+//! 标签管理 API
+//!
+//! 全库重命名、合并、删除标签，以及导入与 AI 生成标签时自动套用的别名规则
+
+use axum::{extract::State, http::StatusCode, Json};
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+
+use crate::api::dashboard::invalidate_cache;
+use crate::entities::tag_alias;
+use crate::services::tag_admin;
+
+#[derive(Serialize)]
+pub struct TagOperationResponse {
+    /// 被改写的角色卡数量
+    pub affected: u64,
+}
+
+#[derive(Deserialize)]
+pub struct RenameTagRequest {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Deserialize)]
+pub struct MergeTagsRequest {
+    pub sources: Vec<String>,
+    pub target: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteTagRequest {
+    pub tag: String,
+}
+
+#[derive(Deserialize)]
+pub struct SetAliasRequest {
+    pub alias: String,
+    pub target: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteAliasRequest {
+    pub alias: String,
+}
+
+fn done(affected: u64) -> Json<TagOperationResponse> {
+    if affected > 0 {
+        invalidate_cache();
+    }
+    Json(TagOperationResponse { affected })
+}
+
+/// POST /api/cards/tags/rename - 全库重命名标签
+pub async fn rename(
+    State(db): State<DatabaseConnection>,
+    Json(payload): Json<RenameTagRequest>,
+) -> Result<Json<TagOperationResponse>, (StatusCode, String)> {
+    tag_admin::rename_tag(&db, &payload.from, &payload.to)
+        .await
+        .map(done)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+/// POST /api/cards/tags/merge - 将多个标签合并为一个
+pub async fn merge(
+    State(db): State<DatabaseConnection>,
+    Json(payload): Json<MergeTagsRequest>,
+) -> Result<Json<TagOperationResponse>, (StatusCode, String)> {
+    tag_admin::merge_tags(&db, &payload.sources, &payload.target)
+        .await
+        .map(done)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+/// POST /api/cards/tags/delete - 从所有角色卡中移除标签
+pub async fn delete(
+    State(db): State<DatabaseConnection>,
+    Json(payload): Json<DeleteTagRequest>,
+) -> Result<Json<TagOperationResponse>, (StatusCode, String)> {
+    tag_admin::delete_tag(&db, &payload.tag)
+        .await
+        .map(done)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+/// GET /api/cards/tags/aliases - 别名规则列表
+pub async fn list_aliases(
+    State(db): State<DatabaseConnection>,
+) -> Result<Json<Vec<tag_alias::Model>>, (StatusCode, String)> {
+    tag_admin::list_aliases(&db)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// PUT /api/cards/tags/aliases - 新增或修改别名规则
+pub async fn set_alias(
+    State(db): State<DatabaseConnection>,
+    Json(payload): Json<SetAliasRequest>,
+) -> Result<Json<tag_alias::Model>, (StatusCode, String)> {
+    tag_admin::set_alias(&db, &payload.alias, &payload.target)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+/// DELETE /api/cards/tags/aliases - 删除别名规则
+pub async fn delete_alias(
+    State(db): State<DatabaseConnection>,
+    Json(payload): Json<DeleteAliasRequest>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let deleted = tag_admin::delete_alias(&db, &payload.alias)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !deleted {
+        return Err((StatusCode::NOT_FOUND, "别名规则不存在".to_string()));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
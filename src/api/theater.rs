@@ -3,10 +3,12 @@
 //! 提供小剧场的增删改查、导入导出功能
 
 use crate::entities::{prelude::*, theater};
+use crate::services::event_bus::{self, AppEvent};
+use crate::utils::etag;
 use axum::{
     body::Body,
     extract::{Multipart, Path, Query, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::{Json, Response},
 };
 use chrono::Utc;
@@ -196,6 +198,9 @@ pub async fn create_theater(
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    event_bus::publish(AppEvent::TheatersCreated {
+        ids: vec![saved.id],
+    });
     Ok(Json(TheaterDto::from(saved)))
 }
 
@@ -203,28 +208,41 @@ pub async fn create_theater(
 pub async fn get_theater(
     State(db): State<DatabaseConnection>,
     Path(id): Path<Uuid>,
-) -> Result<Json<TheaterDto>, (StatusCode, String)> {
+) -> Result<Response, (StatusCode, String)> {
     let theater = Theater::find_by_id(id)
         .one(&db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
         .ok_or((StatusCode::NOT_FOUND, "小剧场不存在".to_string()))?;
 
-    Ok(Json(TheaterDto::from(theater)))
+    Ok(etag::with_etag(
+        &etag::from_updated_at(&theater.updated_at),
+        Json(TheaterDto::from(theater)),
+    ))
 }
 
-/// 更新小剧场
+/// 更新小剧场（支持 `If-Match`，数据已过期时返回 412 与当前数据）
 pub async fn update_theater(
     State(db): State<DatabaseConnection>,
     Path(id): Path<Uuid>,
+    headers: HeaderMap,
     Json(payload): Json<TheaterPayload>,
-) -> Result<Json<TheaterDto>, (StatusCode, String)> {
+) -> Result<Response, (StatusCode, String)> {
     let theater = Theater::find_by_id(id)
         .one(&db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
         .ok_or((StatusCode::NOT_FOUND, "小剧场不存在".to_string()))?;
 
+    let current_etag = etag::from_updated_at(&theater.updated_at);
+    if !etag::if_match_satisfied(&headers, &current_etag) {
+        return Ok(etag::precondition_failed(
+            &current_etag,
+            Json(TheaterDto::from(theater)),
+        ));
+    }
+
+    let read_at = theater.updated_at;
     let mut active: theater::ActiveModel = theater.into();
 
     if let Some(title) = payload.title {
@@ -241,12 +259,27 @@ pub async fn update_theater(
     }
     active.updated_at = Set(Utc::now().naive_utc());
 
-    let updated = active
-        .update(&db)
+    let Some(updated) = etag::update_if_unchanged(&db, active, theater::Column::UpdatedAt, read_at)
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    else {
+        // 检查之后被其他请求修改
+        let current = Theater::find_by_id(id)
+            .one(&db)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .ok_or((StatusCode::NOT_FOUND, "小剧场不存在".to_string()))?;
+        return Ok(etag::precondition_failed(
+            &etag::from_updated_at(&current.updated_at),
+            Json(TheaterDto::from(current)),
+        ));
+    };
 
-    Ok(Json(TheaterDto::from(updated)))
+    event_bus::publish(AppEvent::TheaterUpdated { id });
+    Ok(etag::with_etag(
+        &etag::from_updated_at(&updated.updated_at),
+        Json(TheaterDto::from(updated)),
+    ))
 }
 
 /// 删除小剧场
@@ -263,6 +296,7 @@ pub async fn delete_theater(
         return Err((StatusCode::NOT_FOUND, "小剧场不存在".to_string()));
     }
 
+    event_bus::publish(AppEvent::TheatersDeleted { ids: vec![id] });
     Ok(StatusCode::NO_CONTENT)
 }
 
@@ -281,11 +315,12 @@ pub async fn batch_delete_theaters(
     }
 
     Theater::delete_many()
-        .filter(theater::Column::Id.is_in(payload.ids))
+        .filter(theater::Column::Id.is_in(payload.ids.clone()))
         .exec(&db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    event_bus::publish(AppEvent::TheatersDeleted { ids: payload.ids });
     Ok(StatusCode::NO_CONTENT)
 }
 
@@ -318,7 +353,7 @@ pub async fn import_theaters(
     // 解析小剧场
     let theaters = parse_theater_txt(&file_content);
     let total = theaters.len();
-    let mut success = 0;
+    let mut created_ids = Vec::new();
     let mut failed = 0;
 
     let now = Utc::now().naive_utc();
@@ -335,11 +370,15 @@ pub async fn import_theaters(
         };
 
         match theater.insert(&db).await {
-            Ok(_) => success += 1,
+            Ok(saved) => created_ids.push(saved.id),
             Err(_) => failed += 1,
         }
     }
 
+    let success = created_ids.len();
+    if !created_ids.is_empty() {
+        event_bus::publish(AppEvent::TheatersCreated { ids: created_ids });
+    }
     Ok(Json(ImportResult {
         total,
         success,
@@ -1,13 +1,22 @@
 use crate::entities::{character_card, character_versions, prelude::*};
+use crate::services::card_diff::{self, FieldDiff};
+use crate::services::card_fields::CardFields;
+use crate::services::card_merge::{self, MergeConflict};
+use crate::services::event_bus::{self, AppEvent};
+use crate::services::version_snapshot;
+use crate::services::version_store::{self, NewVersion};
+use crate::utils::token::calculate_card_tokens;
 use axum::{
     extract::{Path, State},
     http::StatusCode,
     Json,
 };
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set,
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect, Set, TransactionTrait,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use uuid::Uuid;
 
 #[derive(Deserialize)]
@@ -41,17 +50,15 @@ pub async fn create_version(
         ))?;
 
     // 2. 创建版本记录
-    let version = character_versions::ActiveModel {
-        id: Set(Uuid::new_v4()),
-        character_id: Set(card.id),
-        version_number: Set(payload.version_number),
-        note: Set(payload.note),
-        data: Set(card.data), // Snapshot current data
-        created_at: Set(chrono::Utc::now().naive_utc()),
+    let version = NewVersion {
+        character_id: card.id,
+        version_number: payload.version_number,
+        note: payload.note,
+        data: card.data, // Snapshot current data
+        is_auto: false,
     };
 
-    let saved = version
-        .insert(&db)
+    let saved = version_store::insert_version(&db, version)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
@@ -63,25 +70,58 @@ pub async fn create_version(
     }))
 }
 
+/// 生成下一个版本号：在已有的 `V<n>` 中取最大值加一
+pub async fn next_version_number<C: ConnectionTrait>(
+    db: &C,
+    card_id: Uuid,
+) -> Result<String, sea_orm::DbErr> {
+    let numbers: Vec<String> = CharacterVersion::find()
+        .select_only()
+        .column(character_versions::Column::VersionNumber)
+        .filter(character_versions::Column::CharacterId.eq(card_id))
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    let max = numbers
+        .iter()
+        .filter_map(|n| n.trim().strip_prefix(['V', 'v']))
+        .filter_map(|n| n.parse::<u32>().ok())
+        .max()
+        .unwrap_or(0);
+
+    Ok(format!("V{}", max + 1))
+}
+
 ///获取版本列表
 pub async fn list_versions(
     State(db): State<DatabaseConnection>,
     Path(card_id): Path<Uuid>,
 ) -> Result<Json<Vec<VersionResponse>>, (StatusCode, String)> {
-    let versions = CharacterVersion::find()
-        .filter(character_versions::Column::CharacterId.eq(card_id))
-        .order_by_desc(character_versions::Column::CreatedAt)
-        .all(&db)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    // 只读取元数据列，不加载版本内容
+    let versions: Vec<(Uuid, String, Option<String>, chrono::NaiveDateTime)> =
+        CharacterVersion::find()
+            .select_only()
+            .columns([
+                character_versions::Column::Id,
+                character_versions::Column::VersionNumber,
+                character_versions::Column::Note,
+                character_versions::Column::CreatedAt,
+            ])
+            .filter(character_versions::Column::CharacterId.eq(card_id))
+            .order_by_desc(character_versions::Column::CreatedAt)
+            .into_tuple()
+            .all(&db)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     let response = versions
         .into_iter()
-        .map(|v| VersionResponse {
-            id: v.id,
-            version_number: v.version_number,
-            note: v.note,
-            created_at: v.created_at.to_string(),
+        .map(|(id, version_number, note, created_at)| VersionResponse {
+            id,
+            version_number,
+            note,
+            created_at: created_at.to_string(),
         })
         .collect();
 
@@ -89,13 +129,14 @@ pub async fn list_versions(
 }
 
 /// 恢复版本
+///
+/// 覆盖前总会保存当前状态，不受 `version_auto_snapshot` 开关影响
 pub async fn restore_version(
     State(db): State<DatabaseConnection>,
     Path((card_id, version_id)): Path<(Uuid, Uuid)>,
 ) -> Result<StatusCode, (StatusCode, String)> {
     // 1. 获取目标版本数据
-    let version = CharacterVersion::find_by_id(version_id)
-        .one(&db)
+    let version = version_store::find_version(&db, version_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
         .ok_or((StatusCode::NOT_FOUND, "Version not found".to_string()))?;
@@ -115,109 +156,86 @@ pub async fn restore_version(
             "Character card not found".to_string(),
         ))?;
 
-    // 3. 覆盖数据
+    // 3. 先保存当前状态，再覆盖数据
+    let txn = db
+        .begin()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    version_snapshot::snapshot_current(
+        &txn,
+        &card,
+        &format!("恢复 {} 前的自动快照", version.version_number),
+    )
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    apply_snapshot(&txn, card, version.data, version.version_number).await?;
+    txn.commit()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    event_bus::publish(AppEvent::CardUpdated { id: card_id });
+    Ok(StatusCode::OK)
+}
+
+/// 将版本快照写回角色卡，并同步冗余字段与 token 统计
+async fn apply_snapshot<C: ConnectionTrait>(
+    db: &C,
+    card: character_card::Model,
+    data: String,
+    version_number: String,
+) -> Result<(), (StatusCode, String)> {
     let mut card_active: character_card::ActiveModel = card.into();
 
     // Parse the JSON snapshot to extract denormalized fields
-    let json: serde_json::Value = serde_json::from_str(&version.data).map_err(|e| {
+    let json: serde_json::Value = serde_json::from_str(&data).map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("Failed to parse version snapshot: {}", e),
         )
     })?;
 
-    // Normalize V2/V3 structure (logic from src/api/cards.rs)
-    let card_data = if let Some(d) = json.get("data") {
-        if d.is_object() {
-            d
-        } else {
-            &json
-        }
-    } else {
-        &json
-    };
-
-    // Extract fields similar to how we do in save_card / import
-    let name = card_data
-        .get("name")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string());
-    let description = card_data
-        .get("description")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string());
-
-    // Author: creator -> creator_notes (matching cards.rs)
-    let author = card_data
-        .get("creator")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string())
-        .or_else(|| {
-            card_data
-                .get("creator_notes")
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string())
-        });
-
-    let spec = json
-        .get("spec")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string());
-    let spec_version = json
-        .get("spec_version")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string());
-
-    // Tags: Array OR String (comma separated)
-    let tags_json = if let Some(tags_value) = card_data.get("tags") {
-        if tags_value.is_array() {
-            serde_json::to_string_pretty(tags_value).unwrap_or_else(|_| "[]".to_string())
-        } else if let Some(tags_str) = tags_value.as_str() {
-            let tags: Vec<&str> = tags_str
-                .split(',')
-                .map(|s| s.trim())
-                .filter(|s| !s.is_empty())
-                .collect();
-            serde_json::to_string_pretty(&tags).unwrap_or_else(|_| "[]".to_string())
-        } else {
-            "[]".to_string()
-        }
-    } else {
-        "[]".to_string()
-    };
+    // Extract denormalized fields (same as import)
+    let fields = CardFields::extract(&json);
 
     // Update the card record
-    if let Some(n) = name {
+    if let Some(n) = fields.name {
         card_active.name = Set(n);
     }
-    if let Some(d) = description {
+    if let Some(d) = fields.description {
         card_active.description = Set(Some(d));
     }
-    if let Some(a) = author {
+    if let Some(a) = fields.author {
         card_active.author = Set(Some(a));
     }
-    card_active.tags = Set(tags_json);
-    if let Some(s) = spec {
+    card_active.tags = Set(fields.tags_json);
+    if let Some(s) = fields.spec {
         card_active.spec = Set(Some(s));
     }
-    if let Some(sv) = spec_version {
+    if let Some(sv) = fields.spec_version {
         card_active.spec_version = Set(Some(sv));
     }
 
+    let counts = calculate_card_tokens(&json);
+    card_active.token_count_total = Set(Some(counts.total));
+    card_active.token_count_spec = Set(Some(counts.spec));
+    card_active.token_count_wb = Set(Some(counts.wb));
+    card_active.token_count_other = Set(Some(counts.other));
+
     // Always update the full data blob
-    card_active.data = Set(version.data);
+    card_active.data = Set(data);
+    // PNG 中的元数据已过期，导出时重新注入
+    card_active.metadata_modified = Set(true);
 
     // Explicitly set the card's displayed version to match the restored snapshot
-    card_active.version = Set(Some(version.version_number));
+    card_active.version = Set(Some(version_number));
 
     card_active.updated_at = Set(chrono::Utc::now().naive_utc());
 
     card_active
-        .update(&db)
+        .update(db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
-    Ok(StatusCode::OK)
+    Ok(())
 }
 
 /// 删除版本
@@ -226,14 +244,198 @@ pub async fn delete_version(
     Path((_card_id, version_id)): Path<(Uuid, Uuid)>,
 ) -> Result<StatusCode, (StatusCode, String)> {
     // 不严格检查 character_id 匹配，只要 ID 对即可删除
-    let res = CharacterVersion::delete_by_id(version_id)
-        .exec(&db)
+    let deleted = version_store::delete_versions(&db, &[version_id])
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    if res.rows_affected == 0 {
+    if deleted == 0 {
         return Err((StatusCode::NOT_FOUND, "Version not found".to_string()));
     }
 
     Ok(StatusCode::NO_CONTENT)
 }
+
+#[derive(Serialize)]
+pub struct DiffSide {
+    /// 版本 ID，当前卡片为 None
+    pub id: Option<Uuid>,
+    /// 版本号，当前卡片为 "current"
+    pub version_number: String,
+    pub token_count: i32,
+}
+
+#[derive(Serialize)]
+pub struct VersionDiffResponse {
+    pub from: DiffSide,
+    pub to: DiffSide,
+    pub token_delta: i32,
+    pub fields: Vec<FieldDiff>,
+}
+
+/// 读取版本快照；`current` 表示角色卡当前数据
+async fn load_snapshot(
+    db: &DatabaseConnection,
+    card_id: Uuid,
+    version: &str,
+) -> Result<(DiffSide, serde_json::Value), (StatusCode, String)> {
+    let (id, version_number, data) = if version == "current" {
+        let card = CharacterCard::find_by_id(card_id)
+            .one(db)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .ok_or((
+                StatusCode::NOT_FOUND,
+                "Character card not found".to_string(),
+            ))?;
+        (None, "current".to_string(), card.data)
+    } else {
+        let version_id = Uuid::parse_str(version).map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("无效的版本 ID: {}", version),
+            )
+        })?;
+        let v = version_store::find_version(db, version_id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .ok_or((StatusCode::NOT_FOUND, "Version not found".to_string()))?;
+        if v.character_id != card_id {
+            return Err((StatusCode::BAD_REQUEST, "Version mismatch".to_string()));
+        }
+        (Some(v.id), v.version_number, v.data)
+    };
+
+    let json: serde_json::Value = serde_json::from_str(&data).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to parse version snapshot: {}", e),
+        )
+    })?;
+    let side = DiffSide {
+        id,
+        version_number,
+        token_count: calculate_card_tokens(&json).total,
+    };
+    Ok((side, json))
+}
+
+/// 比较两个版本 (b 可为 current)
+pub async fn diff_versions(
+    State(db): State<DatabaseConnection>,
+    Path((card_id, a, b)): Path<(Uuid, String, String)>,
+) -> Result<Json<VersionDiffResponse>, (StatusCode, String)> {
+    let (from, old) = load_snapshot(&db, card_id, &a).await?;
+    let (to, new) = load_snapshot(&db, card_id, &b).await?;
+
+    let fields = card_diff::diff_cards(&old, &new);
+
+    Ok(Json(VersionDiffResponse {
+        token_delta: to.token_count - from.token_count,
+        from,
+        to,
+        fields,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct MergeVersionsRequest {
+    /// 共同祖先版本
+    pub base: String,
+    /// 参与合并的两个版本，可为 current
+    pub ours: String,
+    pub theirs: String,
+    /// 冲突的处理结果：冲突路径 -> 采用的值
+    #[serde(default)]
+    pub resolutions: HashMap<String, serde_json::Value>,
+    pub note: Option<String>,
+    /// 仅预览合并结果，不保存
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Serialize)]
+pub struct MergeVersionsResponse {
+    pub merged: serde_json::Value,
+    pub conflicts: Vec<MergeConflict>,
+    /// 无冲突且非预览时，保存的新版本
+    pub version: Option<VersionResponse>,
+}
+
+/// 三方合并版本，无冲突时保存为新版本并应用到角色卡
+///
+/// 与恢复相同，应用前总会保存当前状态，不受 `version_auto_snapshot` 开关影响
+pub async fn merge_versions(
+    State(db): State<DatabaseConnection>,
+    Path(card_id): Path<Uuid>,
+    Json(payload): Json<MergeVersionsRequest>,
+) -> Result<Json<MergeVersionsResponse>, (StatusCode, String)> {
+    let (_, base) = load_snapshot(&db, card_id, &payload.base).await?;
+    let (ours_side, ours) = load_snapshot(&db, card_id, &payload.ours).await?;
+    let (theirs_side, theirs) = load_snapshot(&db, card_id, &payload.theirs).await?;
+
+    let result = card_merge::merge_cards(&base, &ours, &theirs, &payload.resolutions);
+
+    if !result.conflicts.is_empty() || payload.dry_run {
+        return Ok(Json(MergeVersionsResponse {
+            merged: result.card,
+            conflicts: result.conflicts,
+            version: None,
+        }));
+    }
+
+    let card = CharacterCard::find_by_id(card_id)
+        .one(&db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            "Character card not found".to_string(),
+        ))?;
+
+    let data = serde_json::to_string_pretty(&result.card)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let version_number = next_version_number(&db, card_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let note = payload.note.unwrap_or_else(|| {
+        format!(
+            "合并 {} 与 {}",
+            ours_side.version_number, theirs_side.version_number
+        )
+    });
+
+    let txn = db
+        .begin()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    version_snapshot::snapshot_current(&txn, &card, "合并前的自动快照")
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let version = NewVersion {
+        character_id: card_id,
+        version_number: version_number.clone(),
+        note: Some(note),
+        data: data.clone(),
+        is_auto: false,
+    };
+    let saved = version_store::insert_version(&txn, version)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    apply_snapshot(&txn, card, data, version_number).await?;
+    txn.commit()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    event_bus::publish(AppEvent::CardUpdated { id: card_id });
+
+    Ok(Json(MergeVersionsResponse {
+        merged: result.card,
+        conflicts: Vec::new(),
+        version: Some(VersionResponse {
+            id: saved.id,
+            version_number: saved.version_number,
+            note: saved.note,
+            created_at: saved.created_at.to_string(),
+        }),
+    }))
+}
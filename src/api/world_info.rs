@@ -1,6 +1,6 @@
 use axum::{
     extract::{Multipart, Path, Query, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::{IntoResponse, Json},
 };
 use sea_orm::{ActiveModelTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryOrder, Set};
@@ -10,6 +10,8 @@ use uuid::Uuid;
 
 use crate::api::dashboard::invalidate_cache;
 use crate::entities::world_info;
+use crate::services::event_bus::{self, AppEvent};
+use crate::utils::etag;
 
 #[derive(Deserialize)]
 pub struct UpdateWorldInfoSchema {
@@ -47,6 +49,7 @@ pub async fn import(
     mut multipart: Multipart,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     let mut results = Vec::new();
+    let mut created_ids = Vec::new();
 
     while let Ok(Some(field)) = multipart.next_field().await {
         let file_name = field.file_name().unwrap_or("unknown.json").to_string();
@@ -97,7 +100,8 @@ pub async fn import(
             .to_string();
 
         match save_world_info_to_db(&db, name, json_data).await {
-            Ok(_) => {
+            Ok(id) => {
+                created_ids.push(id);
                 results.push(ImportResult {
                     file_name,
                     status: "success".to_string(),
@@ -115,8 +119,9 @@ pub async fn import(
     }
 
     // Invalidate cache if any success
-    if results.iter().any(|r| r.status == "success") {
+    if !created_ids.is_empty() {
         invalidate_cache();
+        event_bus::publish(AppEvent::WorldInfoCreated { ids: created_ids });
     }
     Ok(Json(results))
 }
@@ -125,7 +130,7 @@ async fn save_world_info_to_db(
     db: &DatabaseConnection,
     name: String,
     json: Value,
-) -> Result<(), String> {
+) -> Result<Uuid, String> {
     let uuid = Uuid::new_v4();
 
     // 格式化 JSON（保持原始键顺序）
@@ -144,7 +149,7 @@ async fn save_world_info_to_db(
         .insert(db)
         .await
         .map_err(|e| format!("DB Error: {}", e))?;
-    Ok(())
+    Ok(uuid)
 }
 
 // --- List ---
@@ -194,21 +199,33 @@ pub async fn get_details(
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
         .ok_or((StatusCode::NOT_FOUND, "World Info not found".to_string()))?;
 
-    Ok(Json(item))
+    Ok(etag::with_etag(
+        &etag::from_updated_at(&item.updated_at),
+        Json(item),
+    ))
 }
 
 // --- Update ---
 pub async fn update(
     State(db): State<DatabaseConnection>,
     Path(id): Path<Uuid>,
+    headers: HeaderMap,
     Json(payload): Json<UpdateWorldInfoSchema>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-    let mut item: world_info::ActiveModel = world_info::Entity::find_by_id(id)
+    let existing = world_info::Entity::find_by_id(id)
         .one(&db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-        .ok_or((StatusCode::NOT_FOUND, "World Info not found".to_string()))?
-        .into();
+        .ok_or((StatusCode::NOT_FOUND, "World Info not found".to_string()))?;
+
+    // 客户端数据已过期时返回 412 与当前数据
+    let current_etag = etag::from_updated_at(&existing.updated_at);
+    if !etag::if_match_satisfied(&headers, &current_etag) {
+        return Ok(etag::precondition_failed(&current_etag, Json(existing)));
+    }
+
+    let read_at = existing.updated_at;
+    let mut item: world_info::ActiveModel = existing.into();
 
     if let Some(name) = payload.name {
         item.name = Set(name);
@@ -222,13 +239,29 @@ pub async fn update(
 
     item.updated_at = Set(chrono::Utc::now().naive_utc());
 
-    let updated = item
-        .update(&db)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let Some(updated) =
+        etag::update_if_unchanged(&db, item, world_info::Column::UpdatedAt, read_at)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    else {
+        // 检查之后被其他请求修改
+        let current = world_info::Entity::find_by_id(id)
+            .one(&db)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .ok_or((StatusCode::NOT_FOUND, "World Info not found".to_string()))?;
+        return Ok(etag::precondition_failed(
+            &etag::from_updated_at(&current.updated_at),
+            Json(current),
+        ));
+    };
 
     invalidate_cache();
-    Ok(Json(updated))
+    event_bus::publish(AppEvent::WorldInfoUpdated { id });
+    Ok(etag::with_etag(
+        &etag::from_updated_at(&updated.updated_at),
+        Json(updated),
+    ))
 }
 
 // --- Delete ---
@@ -242,5 +275,6 @@ pub async fn delete(
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     invalidate_cache();
+    event_bus::publish(AppEvent::WorldInfoDeleted { id });
     Ok(StatusCode::NO_CONTENT)
 }
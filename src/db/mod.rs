@@ -159,5 +159,8 @@ pub async fn init_database() -> anyhow::Result<DatabaseConnection> {
     migration::Migrator::up(&db, None).await?;
     info!("数据库迁移完成");
 
+    // 密钥文件不可用时，继续运行会让已加密的 API 密钥全部失效
+    crate::utils::crypto::init_local_key()?;
+
     Ok(db)
 }
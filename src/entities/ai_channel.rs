@@ -13,6 +13,12 @@ pub struct Model {
     pub api_key: String,
     pub model_id: String,
     pub is_active: bool,
+    /// 接口类型：openai / anthropic / gemini / ollama
+    pub provider: String,
+    /// 每百万输入 token 单价，未设置时不估算费用
+    pub input_price: Option<f64>,
+    /// 每百万输出 token 单价
+    pub output_price: Option<f64>,
     pub created_at: DateTime,
     pub updated_at: DateTime,
 }
@@ -0,0 +1,32 @@
+//! `SeaORM` Entity - AI 调用用量
+
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "ai_usage")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    /// 测试未保存的渠道时为空
+    pub channel_id: Option<Uuid>,
+    /// 调用时的渠道名，渠道删除后仍可辨认
+    pub channel_name: String,
+    pub model: String,
+    /// overview | doctor | execute | test
+    pub feature: String,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub latency_ms: i64,
+    /// ok | error | cancelled
+    pub status: String,
+    pub error: Option<String>,
+    /// 按调用时的渠道单价估算，未设置单价时为空
+    pub cost: Option<f64>,
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
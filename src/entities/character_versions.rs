@@ -12,6 +12,17 @@ pub struct Model {
     #[sea_orm(column_type = "Text")]
     pub data: String,
     pub created_at: DateTime,
+    /// 自动快照（保留策略只清理自动快照，手动命名的版本始终保留）
+    pub is_auto: bool,
+    /// 存储方式：plain | full | delta，见 `services::version_store`
+    pub storage: String,
+    /// delta 版本的基准版本
+    pub base_id: Option<Uuid>,
+    /// 压缩后的关键帧或补丁
+    #[sea_orm(column_type = "Blob", nullable)]
+    pub payload: Option<Vec<u8>>,
+    /// 距最近关键帧的补丁数
+    pub depth: i32,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
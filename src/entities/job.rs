@@ -0,0 +1,37 @@
+//! `SeaORM` Entity - 后台任务
+
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "jobs")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    /// 任务类型，如 doctor、card_export、backup
+    pub kind: String,
+    /// 任务参数（JSON）
+    #[sea_orm(column_type = "Text")]
+    pub payload: String,
+    /// queued | running | succeeded | failed | cancelled
+    pub status: String,
+    /// 进度 0.0 ~ 1.0
+    pub progress: f64,
+    pub message: Option<String>,
+    /// 执行结果（JSON）
+    #[sea_orm(column_type = "Text", nullable)]
+    pub result: Option<String>,
+    pub error: Option<String>,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    /// 最早可执行时间，重试退避时推后
+    pub run_after: DateTime,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+    pub finished_at: Option<DateTime>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
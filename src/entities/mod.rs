@@ -3,6 +3,8 @@
 //! 导出所有 SeaORM 实体定义
 
 pub mod ai_channel;
+pub mod ai_usage;
+pub mod card_tag;
 pub mod category;
 pub mod character_card;
 pub mod character_versions;
@@ -11,13 +13,22 @@ pub mod doctor_task;
 pub mod frontend_style;
 pub mod image;
 pub mod image_category;
+pub mod image_tag;
+pub mod job;
+pub mod operation;
+pub mod operation_item;
+pub mod prompt_template;
 pub mod quick_reply;
 pub mod setting;
+pub mod tag;
+pub mod tag_alias;
 pub mod theater;
 pub mod world_info;
 
 pub mod prelude {
     pub use super::ai_channel::Entity as AiChannel;
+    pub use super::ai_usage::Entity as AiUsage;
+    pub use super::card_tag::Entity as CardTag;
     pub use super::category::Entity as Category;
     pub use super::character_card::Entity as CharacterCard;
     pub use super::character_versions::Entity as CharacterVersion;
@@ -26,8 +37,15 @@ pub mod prelude {
     pub use super::frontend_style::Entity as FrontendStyle;
     pub use super::image::Entity as Image;
     pub use super::image_category::Entity as ImageCategory;
+    pub use super::image_tag::Entity as ImageTag;
+    pub use super::job::Entity as Job;
+    pub use super::operation::Entity as Operation;
+    pub use super::operation_item::Entity as OperationItem;
+    pub use super::prompt_template::Entity as PromptTemplate;
     pub use super::quick_reply::Entity as QuickReply;
     pub use super::setting::Entity as Setting;
+    pub use super::tag::Entity as Tag;
+    pub use super::tag_alias::Entity as TagAlias;
     pub use super::theater::Entity as Theater;
     pub use super::world_info::Entity as WorldInfo;
 }
@@ -0,0 +1,37 @@
+//! `SeaORM` Entity - 批量操作日志
+
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "operations")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    /// 操作类型，如 card_batch_category
+    pub kind: String,
+    /// 被修改的表：card | image
+    pub target: String,
+    pub summary: String,
+    /// 被修改的列（JSON 数组），撤销时只恢复这些列
+    #[serde(skip)]
+    #[sea_orm(column_type = "Text")]
+    pub columns: String,
+    pub item_count: i32,
+    pub created_at: DateTime,
+    pub undone_at: Option<DateTime>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "super::operation_item::Entity")]
+    Items,
+}
+
+impl Related<super::operation_item::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Items.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,37 @@
+//! `SeaORM` Entity - 批量操作日志中的单行
+
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "operation_items")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub operation_id: Uuid,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub row_id: Uuid,
+    /// 操作前被修改列的值（JSON 对象）
+    #[sea_orm(column_type = "Text")]
+    pub before: String,
+    /// 操作后被修改列的值（JSON 对象），撤销前用于检测之后的修改
+    #[sea_orm(column_type = "Text", nullable)]
+    pub after: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::operation::Entity",
+        from = "Column::OperationId",
+        to = "super::operation::Column::Id"
+    )]
+    Operation,
+}
+
+impl Related<super::operation::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Operation.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,27 @@
+//! `SeaORM` Entity - AI 功能提示词模板
+
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "prompt_templates")]
+pub struct Model {
+    /// 功能标识，如 overview、doctor、translate
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub feature: String,
+    /// 为空时使用内置模板
+    #[sea_orm(column_type = "Text", nullable)]
+    pub system_prompt: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub user_prompt: Option<String>,
+    /// 为空时按渠道路由选择
+    pub channel_id: Option<Uuid>,
+    /// 为空时使用功能的默认温度
+    pub temperature: Option<f64>,
+    pub updated_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,38 @@
+//! `SeaORM` Entity - 标签字典
+//!
+//! 由数据库触发器根据 `character_cards.tags` / `image.tags` 维护，应用只读
+
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "tags")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    #[sea_orm(unique)]
+    pub name: String,
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "super::card_tag::Entity")]
+    CardTags,
+    #[sea_orm(has_many = "super::image_tag::Entity")]
+    ImageTags,
+}
+
+impl Related<super::card_tag::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CardTags.def()
+    }
+}
+
+impl Related<super::image_tag::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ImageTags.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,20 @@
+//! `SeaORM` Entity - 标签别名规则
+
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "tag_aliases")]
+pub struct Model {
+    /// 别名（不区分 ASCII 大小写）
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub alias: String,
+    /// 替换为的标签
+    pub target: String,
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -26,7 +26,10 @@ pub async fn create_app(db: DatabaseConnection, mode: RunMode, config: ConfigSta
         .allow_origin(Any)
         .allow_methods(Any)
         .allow_headers(Any)
-        .expose_headers([axum::http::header::CONTENT_DISPOSITION]);
+        .expose_headers([
+            axum::http::header::CONTENT_DISPOSITION,
+            axum::http::header::ETAG,
+        ]);
 
     // Public routes (Auth + Public Settings)
     let public_api = Router::new()
@@ -34,6 +37,13 @@ pub async fn create_app(db: DatabaseConnection, mode: RunMode, config: ConfigSta
         .route("/settings", get(api::settings::get).with_state(db.clone()))
         .route("/health", get(|| async { "OK" }));
 
+    // 后台清理过期的自动版本快照
+    services::version_snapshot::spawn_retention_task(db.clone());
+    // 补建聊天记录的搜索索引
+    services::search_index::spawn_chat_backfill(db.clone());
+    // 启动后台任务队列
+    services::job_queue::start(db.clone(), api::jobs::run);
+
     // Protected routes (using db state only)
     let protected_api = api::routes(db.clone(), config.clone()).layer(
         middleware::from_fn_with_state(config.clone(), utils::auth_middleware::auth),
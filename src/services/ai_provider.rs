@@ -0,0 +1,1504 @@
+//! AI 渠道的接口适配
+//!
+//! 各类服务商的请求格式不同，`AiProvider` 把对话与模型列表统一为同一套结构：
+//! - `openai`：OpenAI 兼容的 `/chat/completions`（默认，base_url 需包含 `/v1`）
+//! - `anthropic`：Anthropic Messages API `/messages`
+//! - `gemini`：Gemini `models/{model}:generateContent`
+//! - `ollama`：Ollama 原生 `/api/chat`
+//!
+//! 流式对话统一转换为 `StreamEvent`（文本片段、用量、结束原因）。OpenAI 兼容接口请求流式用量
+//! （`stream_options.include_usage`），不支持该参数的接口返回 400 时去掉后重试。
+//!
+//! 所有地址均由 base_url 拼接，便于指向本地的模拟服务。
+
+use std::collections::VecDeque;
+
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::entities::ai_channel;
+use crate::utils::crypto;
+
+/// Anthropic Messages API 版本
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// Anthropic 要求必须给出 max_tokens
+const ANTHROPIC_DEFAULT_MAX_TOKENS: u32 = 4096;
+/// 流式响应中单行的最大长度，超过时中止读取，避免异常响应占满内存
+const MAX_STREAM_LINE: usize = 4 * 1024 * 1024;
+
+/// 渠道接口类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    #[default]
+    OpenAi,
+    Anthropic,
+    Gemini,
+    Ollama,
+}
+
+impl ProviderKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::OpenAi => "openai",
+            Self::Anthropic => "anthropic",
+            Self::Gemini => "gemini",
+            Self::Ollama => "ollama",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "openai" => Some(Self::OpenAi),
+            "anthropic" => Some(Self::Anthropic),
+            "gemini" => Some(Self::Gemini),
+            "ollama" => Some(Self::Ollama),
+            _ => None,
+        }
+    }
+}
+
+/// 对话消息，role 为 system / user / assistant
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self {
+            role: "system".to_string(),
+            content: content.into(),
+        }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: "user".to_string(),
+            content: content.into(),
+        }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self {
+            role: "assistant".to_string(),
+            content: content.into(),
+        }
+    }
+
+    /// 从 OpenAI 格式的消息转换，content 为数组时拼接其中的文本部分
+    pub fn from_openai(value: &Value) -> Self {
+        let role = value
+            .get("role")
+            .and_then(|r| r.as_str())
+            .unwrap_or("user")
+            .to_string();
+        let content = match value.get("content") {
+            Some(Value::String(s)) => s.clone(),
+            Some(Value::Array(parts)) => parts
+                .iter()
+                .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            _ => String::new(),
+        };
+        Self { role, content }
+    }
+}
+
+/// 对话请求
+#[derive(Debug, Clone, Default)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    pub temperature: Option<f64>,
+    pub max_tokens: Option<u32>,
+    /// 要求模型只输出 JSON（各家以各自的方式声明）
+    pub json_mode: bool,
+}
+
+/// Token 用量
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// 对话结果
+#[derive(Debug, Clone)]
+pub struct ChatResponse {
+    pub content: String,
+    pub finish_reason: Option<String>,
+    pub usage: Option<TokenUsage>,
+    /// 服务商返回的原始 JSON，用于调试
+    pub raw: Value,
+}
+
+impl ChatResponse {
+    /// 转换为 OpenAI `/chat/completions` 格式，前端按该格式读取结果
+    pub fn to_openai_json(&self, model: &str) -> Value {
+        let usage = self.usage.map(|u| {
+            json!({
+                "prompt_tokens": u.prompt_tokens,
+                "completion_tokens": u.completion_tokens,
+                "total_tokens": u.prompt_tokens + u.completion_tokens,
+            })
+        });
+        json!({
+            "object": "chat.completion",
+            "model": model,
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": self.content},
+                "finish_reason": self.finish_reason,
+            }],
+            "usage": usage,
+        })
+    }
+}
+
+/// 流式对话的增量事件，`usage` 与 `finish` 的先后顺序因服务商而异
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    /// 文本片段
+    Delta { text: String },
+    /// Token 用量
+    Usage(TokenUsage),
+    /// 结束原因
+    Finish { reason: Option<String> },
+}
+
+/// 流式对话的事件流，丢弃即断开与服务商的连接
+pub type ChatStream = BoxStream<'static, Result<StreamEvent, ProviderError>>;
+
+/// 调用服务商接口的错误
+#[derive(Debug, thiserror::Error)]
+pub enum ProviderError {
+    #[error("Request failed: {0}")]
+    Network(String),
+    #[error("API Error (HTTP {status}): {body}")]
+    Http { status: u16, body: String },
+    #[error("Invalid JSON response: {0}")]
+    Parse(String),
+    /// 流式响应中途返回的错误
+    #[error("Stream error: {0}")]
+    Stream(String),
+    /// 所有候选渠道都处于熔断状态，未发出请求
+    #[error("AI 渠道暂不可用: {0}")]
+    Unavailable(String),
+    /// 保存的 API 密钥无法解密（如在其他设备恢复了备份），未发出请求
+    #[error("渠道 API 密钥无法使用: {0}，请重新填写")]
+    Credentials(String),
+}
+
+impl ProviderError {
+    /// 网络错误、限流、服务端错误与响应解析失败可以重试
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Network(_) | Self::Parse(_) => true,
+            Self::Http { status, .. } => *status == 429 || *status >= 500,
+            Self::Stream(_) | Self::Credentials(_) => false,
+            Self::Unavailable(_) => true,
+        }
+    }
+
+    /// 临时性故障（连接失败、超时、限流、服务端错误），同一渠道稍后重试可能成功
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Network(_) => true,
+            Self::Http { status, .. } => matches!(*status, 408 | 429) || *status >= 500,
+            _ => false,
+        }
+    }
+
+    /// 请求本身有误（如参数错误、上下文过长），换渠道重试也无济于事
+    pub fn is_request_error(&self) -> bool {
+        match self {
+            Self::Http { status, .. } => {
+                (400..500).contains(status) && !matches!(*status, 401 | 402 | 403 | 404 | 408 | 429)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ProviderError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Network(e.to_string())
+    }
+}
+
+#[async_trait]
+pub trait AiProvider: Send + Sync {
+    /// 发送一次对话请求（非流式）
+    async fn chat(&self, request: &ChatRequest) -> Result<ChatResponse, ProviderError>;
+
+    /// 发送流式对话请求；服务商返回非 2xx 时直接返回错误
+    async fn chat_stream(&self, request: &ChatRequest) -> Result<ChatStream, ProviderError>;
+
+    /// 列出可用的模型 ID
+    async fn list_models(&self) -> Result<Vec<String>, ProviderError>;
+}
+
+/// 按接口类型创建适配器
+pub fn build(
+    kind: ProviderKind,
+    base_url: &str,
+    api_key: &str,
+    client: reqwest::Client,
+) -> Box<dyn AiProvider> {
+    let endpoint = Endpoint {
+        client,
+        base_url: base_url.trim_end_matches('/').to_string(),
+        api_key: api_key.to_string(),
+    };
+    match kind {
+        ProviderKind::OpenAi => Box::new(OpenAiProvider(endpoint)),
+        ProviderKind::Anthropic => Box::new(AnthropicProvider(endpoint)),
+        ProviderKind::Gemini => Box::new(GeminiProvider(endpoint)),
+        ProviderKind::Ollama => Box::new(OllamaProvider(endpoint)),
+    }
+}
+
+/// 为已保存的渠道解密 API 密钥并创建适配器，未知的类型按 OpenAI 兼容处理
+pub fn for_channel(
+    channel: &ai_channel::Model,
+    client: reqwest::Client,
+) -> Result<Box<dyn AiProvider>, ProviderError> {
+    let kind = ProviderKind::parse(&channel.provider).unwrap_or_default();
+    let api_key = crypto::decrypt_api_key(&channel.api_key)
+        .map_err(|e| ProviderError::Credentials(e.to_string()))?;
+    Ok(build(kind, &channel.base_url, &api_key, client))
+}
+
+struct Endpoint {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: String,
+}
+
+impl Endpoint {
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+}
+
+/// 发送请求并读取 JSON 响应，非 2xx 时返回响应正文
+async fn send_json(request: reqwest::RequestBuilder) -> Result<Value, ProviderError> {
+    let res = request.send().await?;
+    let status = res.status();
+    let raw_text = res.text().await?;
+
+    if !status.is_success() {
+        return Err(ProviderError::Http {
+            status: status.as_u16(),
+            body: raw_text,
+        });
+    }
+
+    serde_json::from_str(&raw_text).map_err(|e| ProviderError::Parse(e.to_string()))
+}
+
+/// 流式响应正文的分帧方式
+#[derive(Clone, Copy)]
+enum Framing {
+    /// `text/event-stream`，只读取 `data:` 行
+    Sse,
+    /// 每行一个 JSON 对象
+    NdJson,
+}
+
+/// 发送流式请求，把响应正文逐行解析为事件
+///
+/// `parse` 把每条 JSON 消息转换为零到多个事件，返回错误时流在该错误之后结束
+async fn send_stream<P>(
+    request: reqwest::RequestBuilder,
+    framing: Framing,
+    parse: P,
+) -> Result<ChatStream, ProviderError>
+where
+    P: FnMut(&Value) -> Result<Vec<StreamEvent>, ProviderError> + Send + 'static,
+{
+    let res = request.send().await?;
+    let status = res.status();
+    if !status.is_success() {
+        return Err(ProviderError::Http {
+            status: status.as_u16(),
+            body: res.text().await.unwrap_or_default(),
+        });
+    }
+
+    let state = StreamReader {
+        res,
+        framing,
+        parse,
+        buf: Vec::new(),
+        pending: VecDeque::new(),
+        done: false,
+    };
+    let stream = stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(item) = state.pending.pop_front() {
+                return Some((item, state));
+            }
+            if state.done {
+                return None;
+            }
+            match state.res.chunk().await {
+                Ok(Some(bytes)) => {
+                    state.buf.extend_from_slice(&bytes);
+                    while let Some(pos) = state.buf.iter().position(|b| *b == b'\n') {
+                        let line: Vec<u8> = state.buf.drain(..=pos).collect();
+                        state.feed(&line);
+                    }
+                    if state.buf.len() > MAX_STREAM_LINE && !state.done {
+                        state.buf = Vec::new();
+                        state.pending.push_back(Err(ProviderError::Stream(format!(
+                            "单行超过 {} 字节",
+                            MAX_STREAM_LINE
+                        ))));
+                        state.done = true;
+                    }
+                }
+                Ok(None) => {
+                    let rest = std::mem::take(&mut state.buf);
+                    state.feed(&rest);
+                    state.done = true;
+                }
+                Err(e) => {
+                    state.pending.push_back(Err(e.into()));
+                    state.done = true;
+                }
+            }
+        }
+    });
+    Ok(stream.boxed())
+}
+
+struct StreamReader<P> {
+    res: reqwest::Response,
+    framing: Framing,
+    parse: P,
+    /// 尚未凑成整行的字节
+    buf: Vec<u8>,
+    pending: VecDeque<Result<StreamEvent, ProviderError>>,
+    done: bool,
+}
+
+impl<P> StreamReader<P>
+where
+    P: FnMut(&Value) -> Result<Vec<StreamEvent>, ProviderError>,
+{
+    fn feed(&mut self, line: &[u8]) {
+        if self.done {
+            return;
+        }
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim();
+        let payload = match self.framing {
+            Framing::Sse => match line.strip_prefix("data:") {
+                Some(data) => data.trim(),
+                None => return,
+            },
+            Framing::NdJson => line,
+        };
+        if payload.is_empty() || payload == "[DONE]" {
+            return;
+        }
+
+        let result = serde_json::from_str::<Value>(payload)
+            .map_err(|e| ProviderError::Parse(e.to_string()))
+            .and_then(|value| (self.parse)(&value));
+        match result {
+            Ok(events) => self.pending.extend(events.into_iter().map(Ok)),
+            Err(e) => {
+                self.pending.push_back(Err(e));
+                self.done = true;
+            }
+        }
+    }
+}
+
+/// 把 system 消息拆出来，供单独声明系统提示词的接口使用
+fn split_system(messages: &[ChatMessage]) -> (Option<String>, Vec<&ChatMessage>) {
+    let system: Vec<&str> = messages
+        .iter()
+        .filter(|m| m.role == "system")
+        .map(|m| m.content.as_str())
+        .collect();
+    let rest = messages.iter().filter(|m| m.role != "system").collect();
+    let system = (!system.is_empty()).then(|| system.join("\n\n"));
+    (system, rest)
+}
+
+fn as_u64(value: &Value) -> u64 {
+    value.as_u64().unwrap_or(0)
+}
+
+fn delta(text: Option<&str>) -> Option<StreamEvent> {
+    text.filter(|t| !t.is_empty()).map(|t| StreamEvent::Delta {
+        text: t.to_string(),
+    })
+}
+
+/// OpenAI 兼容接口
+struct OpenAiProvider(Endpoint);
+
+impl OpenAiProvider {
+    /// `include_usage` 只用于流式请求，要求在最后一个分片中返回用量
+    fn chat_request(
+        &self,
+        request: &ChatRequest,
+        stream: bool,
+        include_usage: bool,
+    ) -> reqwest::RequestBuilder {
+        let mut body = json!({
+            "model": request.model,
+            "messages": request.messages,
+        });
+        if let Some(temperature) = request.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        if let Some(max_tokens) = request.max_tokens {
+            body["max_tokens"] = json!(max_tokens);
+        }
+        if request.json_mode {
+            body["response_format"] = json!({"type": "json_object"});
+        }
+        if stream {
+            body["stream"] = json!(true);
+            if include_usage {
+                body["stream_options"] = json!({"include_usage": true});
+            }
+        }
+
+        self.0
+            .client
+            .post(self.0.url("/chat/completions"))
+            .bearer_auth(&self.0.api_key)
+            .json(&body)
+    }
+}
+
+fn openai_events(chunk: &Value) -> Result<Vec<StreamEvent>, ProviderError> {
+    if let Some(error) = chunk.get("error") {
+        return Err(ProviderError::Stream(error.to_string()));
+    }
+
+    let mut events = Vec::new();
+    if let Some(choice) = chunk["choices"].get(0) {
+        events.extend(delta(choice["delta"]["content"].as_str()));
+        if let Some(reason) = choice["finish_reason"].as_str() {
+            events.push(StreamEvent::Finish {
+                reason: Some(reason.to_string()),
+            });
+        }
+    }
+    if let Some(usage) = chunk.get("usage").filter(|u| u.is_object()) {
+        events.push(StreamEvent::Usage(TokenUsage {
+            prompt_tokens: as_u64(&usage["prompt_tokens"]),
+            completion_tokens: as_u64(&usage["completion_tokens"]),
+        }));
+    }
+    Ok(events)
+}
+
+#[async_trait]
+impl AiProvider for OpenAiProvider {
+    async fn chat(&self, request: &ChatRequest) -> Result<ChatResponse, ProviderError> {
+        let raw = send_json(self.chat_request(request, false, false)).await?;
+
+        let choice = &raw["choices"][0];
+        Ok(ChatResponse {
+            content: choice["message"]["content"]
+                .as_str()
+                .unwrap_or("")
+                .to_string(),
+            finish_reason: choice["finish_reason"].as_str().map(|s| s.to_string()),
+            usage: raw.get("usage").map(|u| TokenUsage {
+                prompt_tokens: as_u64(&u["prompt_tokens"]),
+                completion_tokens: as_u64(&u["completion_tokens"]),
+            }),
+            raw,
+        })
+    }
+
+    async fn chat_stream(&self, request: &ChatRequest) -> Result<ChatStream, ProviderError> {
+        let result = send_stream(
+            self.chat_request(request, true, true),
+            Framing::Sse,
+            openai_events,
+        )
+        .await;
+        match result {
+            // 部分兼容接口不认识 stream_options 而返回 400，去掉后重试一次（不再统计用量）；
+            // 其他 400（如上下文过长、模型无效）原样返回
+            Err(ProviderError::Http { status: 400, body })
+                if body.contains("stream_options") || body.contains("include_usage") =>
+            {
+                send_stream(
+                    self.chat_request(request, true, false),
+                    Framing::Sse,
+                    openai_events,
+                )
+                .await
+            }
+            result => result,
+        }
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        let raw = send_json(
+            self.0
+                .client
+                .get(self.0.url("/models"))
+                .bearer_auth(&self.0.api_key),
+        )
+        .await?;
+        Ok(collect_ids(&raw["data"], "id"))
+    }
+}
+
+/// Anthropic Messages API
+struct AnthropicProvider(Endpoint);
+
+impl AnthropicProvider {
+    fn request(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        builder
+            .header("x-api-key", &self.0.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+    }
+
+    fn chat_request(&self, request: &ChatRequest, stream: bool) -> reqwest::RequestBuilder {
+        let (system, messages) = split_system(&request.messages);
+        let mut body = json!({
+            "model": request.model,
+            "messages": messages,
+            "max_tokens": request.max_tokens.unwrap_or(ANTHROPIC_DEFAULT_MAX_TOKENS),
+        });
+        if let Some(system) = system {
+            body["system"] = json!(system);
+        }
+        if let Some(temperature) = request.temperature {
+            // Anthropic 的 temperature 范围为 0~1
+            body["temperature"] = json!(temperature.min(1.0));
+        }
+        if stream {
+            body["stream"] = json!(true);
+        }
+
+        self.request(self.0.client.post(self.0.url("/messages")))
+            .json(&body)
+    }
+}
+
+#[async_trait]
+impl AiProvider for AnthropicProvider {
+    async fn chat(&self, request: &ChatRequest) -> Result<ChatResponse, ProviderError> {
+        let raw = send_json(self.chat_request(request, false)).await?;
+
+        let content = raw["content"]
+            .as_array()
+            .map(|blocks| {
+                blocks
+                    .iter()
+                    .filter(|b| b["type"] == "text")
+                    .filter_map(|b| b["text"].as_str())
+                    .collect::<String>()
+            })
+            .unwrap_or_default();
+        Ok(ChatResponse {
+            content,
+            finish_reason: raw["stop_reason"].as_str().map(|s| s.to_string()),
+            usage: raw.get("usage").map(|u| TokenUsage {
+                prompt_tokens: as_u64(&u["input_tokens"]),
+                completion_tokens: as_u64(&u["output_tokens"]),
+            }),
+            raw,
+        })
+    }
+
+    async fn chat_stream(&self, request: &ChatRequest) -> Result<ChatStream, ProviderError> {
+        // 输入 token 数在 message_start 中给出，输出 token 数在 message_delta 中给出
+        let mut input_tokens = 0;
+        let parse = move |event: &Value| match event["type"].as_str() {
+            Some("message_start") => {
+                input_tokens = as_u64(&event["message"]["usage"]["input_tokens"]);
+                Ok(Vec::new())
+            }
+            Some("content_block_delta") => {
+                Ok(delta(event["delta"]["text"].as_str()).into_iter().collect())
+            }
+            Some("message_delta") => Ok(vec![
+                StreamEvent::Usage(TokenUsage {
+                    prompt_tokens: input_tokens,
+                    completion_tokens: as_u64(&event["usage"]["output_tokens"]),
+                }),
+                StreamEvent::Finish {
+                    reason: event["delta"]["stop_reason"]
+                        .as_str()
+                        .map(|s| s.to_string()),
+                },
+            ]),
+            Some("error") => Err(ProviderError::Stream(event["error"].to_string())),
+            _ => Ok(Vec::new()),
+        };
+        send_stream(self.chat_request(request, true), Framing::Sse, parse).await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        let raw = send_json(self.request(self.0.client.get(self.0.url("/models")))).await?;
+        Ok(collect_ids(&raw["data"], "id"))
+    }
+}
+
+/// Gemini generateContent
+struct GeminiProvider(Endpoint);
+
+impl GeminiProvider {
+    fn chat_request(&self, request: &ChatRequest, stream: bool) -> reqwest::RequestBuilder {
+        let (system, messages) = split_system(&request.messages);
+        let contents: Vec<Value> = messages
+            .iter()
+            .map(|m| {
+                let role = if m.role == "assistant" {
+                    "model"
+                } else {
+                    "user"
+                };
+                json!({"role": role, "parts": [{"text": m.content}]})
+            })
+            .collect();
+
+        let mut generation_config = json!({});
+        if let Some(temperature) = request.temperature {
+            generation_config["temperature"] = json!(temperature);
+        }
+        if let Some(max_tokens) = request.max_tokens {
+            generation_config["maxOutputTokens"] = json!(max_tokens);
+        }
+        if request.json_mode {
+            generation_config["responseMimeType"] = json!("application/json");
+        }
+
+        let mut body = json!({
+            "contents": contents,
+            "generationConfig": generation_config,
+            "safetySettings": [
+                {"category": "HARM_CATEGORY_HARASSMENT", "threshold": "BLOCK_NONE"},
+                {"category": "HARM_CATEGORY_HATE_SPEECH", "threshold": "BLOCK_NONE"},
+                {"category": "HARM_CATEGORY_SEXUALLY_EXPLICIT", "threshold": "BLOCK_NONE"},
+                {"category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": "BLOCK_NONE"}
+            ],
+        });
+        if let Some(system) = system {
+            body["systemInstruction"] = json!({"parts": [{"text": system}]});
+        }
+
+        let model = request.model.trim_start_matches("models/");
+        let path = if stream {
+            format!("/models/{}:streamGenerateContent?alt=sse", model)
+        } else {
+            format!("/models/{}:generateContent", model)
+        };
+        self.0
+            .client
+            .post(self.0.url(&path))
+            .header("x-goog-api-key", &self.0.api_key)
+            .json(&body)
+    }
+}
+
+/// 拼接候选结果中的文本
+fn gemini_text(candidate: &Value) -> String {
+    candidate["content"]["parts"]
+        .as_array()
+        .map(|parts| {
+            parts
+                .iter()
+                .filter_map(|p| p["text"].as_str())
+                .collect::<String>()
+        })
+        .unwrap_or_default()
+}
+
+fn gemini_usage(usage: &Value) -> TokenUsage {
+    TokenUsage {
+        prompt_tokens: as_u64(&usage["promptTokenCount"]),
+        completion_tokens: as_u64(&usage["candidatesTokenCount"]),
+    }
+}
+
+fn gemini_events(chunk: &Value) -> Result<Vec<StreamEvent>, ProviderError> {
+    if let Some(error) = chunk.get("error") {
+        return Err(ProviderError::Stream(error.to_string()));
+    }
+
+    let candidate = &chunk["candidates"][0];
+    let mut events: Vec<StreamEvent> = delta(Some(&gemini_text(candidate))).into_iter().collect();
+    // 每个分片都带有累计用量，只在最后一片输出
+    if let Some(reason) = candidate["finishReason"].as_str() {
+        if let Some(usage) = chunk.get("usageMetadata") {
+            events.push(StreamEvent::Usage(gemini_usage(usage)));
+        }
+        events.push(StreamEvent::Finish {
+            reason: Some(reason.to_string()),
+        });
+    }
+    Ok(events)
+}
+
+#[async_trait]
+impl AiProvider for GeminiProvider {
+    async fn chat(&self, request: &ChatRequest) -> Result<ChatResponse, ProviderError> {
+        let raw = send_json(self.chat_request(request, false)).await?;
+
+        let candidate = &raw["candidates"][0];
+        Ok(ChatResponse {
+            content: gemini_text(candidate),
+            finish_reason: candidate["finishReason"].as_str().map(|s| s.to_string()),
+            usage: raw.get("usageMetadata").map(gemini_usage),
+            raw,
+        })
+    }
+
+    async fn chat_stream(&self, request: &ChatRequest) -> Result<ChatStream, ProviderError> {
+        send_stream(
+            self.chat_request(request, true),
+            Framing::Sse,
+            gemini_events,
+        )
+        .await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        let raw = send_json(
+            self.0
+                .client
+                .get(self.0.url("/models"))
+                .header("x-goog-api-key", &self.0.api_key),
+        )
+        .await?;
+        Ok(collect_ids(&raw["models"], "name")
+            .into_iter()
+            .map(|name| name.trim_start_matches("models/").to_string())
+            .collect())
+    }
+}
+
+/// Ollama 原生接口
+struct OllamaProvider(Endpoint);
+
+impl OllamaProvider {
+    /// 本地部署通常没有密钥，经反向代理时才需要
+    fn request(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if self.0.api_key.is_empty() {
+            builder
+        } else {
+            builder.bearer_auth(&self.0.api_key)
+        }
+    }
+
+    fn chat_request(&self, request: &ChatRequest, stream: bool) -> reqwest::RequestBuilder {
+        let mut options = json!({});
+        if let Some(temperature) = request.temperature {
+            options["temperature"] = json!(temperature);
+        }
+        if let Some(max_tokens) = request.max_tokens {
+            options["num_predict"] = json!(max_tokens);
+        }
+        let mut body = json!({
+            "model": request.model,
+            "messages": request.messages,
+            "stream": stream,
+            "options": options,
+        });
+        if request.json_mode {
+            body["format"] = json!("json");
+        }
+
+        self.request(self.0.client.post(self.0.url("/api/chat")))
+            .json(&body)
+    }
+}
+
+fn ollama_usage(message: &Value) -> TokenUsage {
+    TokenUsage {
+        prompt_tokens: as_u64(&message["prompt_eval_count"]),
+        completion_tokens: as_u64(&message["eval_count"]),
+    }
+}
+
+fn ollama_events(message: &Value) -> Result<Vec<StreamEvent>, ProviderError> {
+    if let Some(error) = message["error"].as_str() {
+        return Err(ProviderError::Stream(error.to_string()));
+    }
+
+    let mut events: Vec<StreamEvent> = delta(message["message"]["content"].as_str())
+        .into_iter()
+        .collect();
+    if message["done"].as_bool() == Some(true) {
+        events.push(StreamEvent::Usage(ollama_usage(message)));
+        events.push(StreamEvent::Finish {
+            reason: message["done_reason"].as_str().map(|s| s.to_string()),
+        });
+    }
+    Ok(events)
+}
+
+#[async_trait]
+impl AiProvider for OllamaProvider {
+    async fn chat(&self, request: &ChatRequest) -> Result<ChatResponse, ProviderError> {
+        let raw = send_json(self.chat_request(request, false)).await?;
+
+        Ok(ChatResponse {
+            content: raw["message"]["content"].as_str().unwrap_or("").to_string(),
+            finish_reason: raw["done_reason"].as_str().map(|s| s.to_string()),
+            usage: Some(ollama_usage(&raw)),
+            raw,
+        })
+    }
+
+    async fn chat_stream(&self, request: &ChatRequest) -> Result<ChatStream, ProviderError> {
+        send_stream(
+            self.chat_request(request, true),
+            Framing::NdJson,
+            ollama_events,
+        )
+        .await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        let raw = send_json(self.request(self.0.client.get(self.0.url("/api/tags")))).await?;
+        Ok(collect_ids(&raw["models"], "name"))
+    }
+}
+
+/// 从模型列表中取出指定字段
+fn collect_ids(list: &Value, field: &str) -> Vec<String> {
+    list.as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|m| m[field].as_str().map(|s| s.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{
+        body::{to_bytes, Body},
+        extract::{Request, State},
+        http::{header, HeaderMap, StatusCode},
+        response::Response,
+        Json, Router,
+    };
+
+    use super::*;
+
+    /// 模拟服务收到的请求
+    struct Captured {
+        method: String,
+        path_and_query: String,
+        headers: HeaderMap,
+        body: Value,
+    }
+
+    struct Mock {
+        status: StatusCode,
+        content_type: &'static str,
+        body: String,
+        requests: Mutex<Vec<Captured>>,
+    }
+
+    async fn respond(State(mock): State<Arc<Mock>>, req: Request) -> Response {
+        let (parts, body) = req.into_parts();
+        let bytes = to_bytes(body, usize::MAX).await.unwrap();
+        mock.requests.lock().unwrap().push(Captured {
+            method: parts.method.to_string(),
+            path_and_query: parts.uri.path_and_query().unwrap().to_string(),
+            headers: parts.headers,
+            body: serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        });
+        Response::builder()
+            .status(mock.status)
+            .header(header::CONTENT_TYPE, mock.content_type)
+            .body(Body::from(mock.body.clone()))
+            .unwrap()
+    }
+
+    /// 启动返回固定响应的本地服务，返回其地址
+    async fn serve(
+        status: u16,
+        content_type: &'static str,
+        body: impl Into<String>,
+    ) -> (String, Arc<Mock>) {
+        let mock = Arc::new(Mock {
+            status: StatusCode::from_u16(status).unwrap(),
+            content_type,
+            body: body.into(),
+            requests: Mutex::new(Vec::new()),
+        });
+        let app = Router::new().fallback(respond).with_state(mock.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}", addr), mock)
+    }
+
+    fn provider(kind: ProviderKind, base_url: &str, api_key: &str) -> Box<dyn AiProvider> {
+        build(kind, base_url, api_key, reqwest::Client::new())
+    }
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            model: "test-model".to_string(),
+            messages: vec![
+                ChatMessage::system("be brief"),
+                ChatMessage::user("hi"),
+                ChatMessage::assistant("hello"),
+                ChatMessage::user("again"),
+            ],
+            temperature: Some(1.5),
+            max_tokens: Some(64),
+            json_mode: true,
+        }
+    }
+
+    fn sse(events: &[Value]) -> String {
+        events.iter().map(|e| format!("data: {}\n\n", e)).collect()
+    }
+
+    fn last_request(mock: &Mock) -> Captured {
+        mock.requests
+            .lock()
+            .unwrap()
+            .pop()
+            .expect("no request received")
+    }
+
+    fn header_value<'a>(captured: &'a Captured, name: &str) -> Option<&'a str> {
+        captured.headers.get(name).and_then(|v| v.to_str().ok())
+    }
+
+    async fn collect(stream: ChatStream) -> Vec<Result<StreamEvent, ProviderError>> {
+        stream.collect().await
+    }
+
+    fn texts(events: &[Result<StreamEvent, ProviderError>]) -> String {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                Ok(StreamEvent::Delta { text }) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn usage(events: &[Result<StreamEvent, ProviderError>]) -> Option<(u64, u64)> {
+        events.iter().find_map(|e| match e {
+            Ok(StreamEvent::Usage(u)) => Some((u.prompt_tokens, u.completion_tokens)),
+            _ => None,
+        })
+    }
+
+    fn finish(events: &[Result<StreamEvent, ProviderError>]) -> Option<String> {
+        events.iter().find_map(|e| match e {
+            Ok(StreamEvent::Finish { reason }) => reason.clone(),
+            _ => None,
+        })
+    }
+
+    #[tokio::test]
+    async fn openai_chat() {
+        let (base, mock) = serve(
+            200,
+            "application/json",
+            json!({
+                "choices": [{"message": {"role": "assistant", "content": "pong"}, "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 12, "completion_tokens": 3}
+            })
+            .to_string(),
+        )
+        .await;
+
+        let res = provider(ProviderKind::OpenAi, &format!("{}/v1/", base), "sk-test")
+            .chat(&request())
+            .await
+            .unwrap();
+        assert_eq!(res.content, "pong");
+        assert_eq!(res.finish_reason.as_deref(), Some("stop"));
+        let usage = res.usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (12, 3));
+
+        let req = last_request(&mock);
+        assert_eq!(req.method, "POST");
+        assert_eq!(req.path_and_query, "/v1/chat/completions");
+        assert_eq!(header_value(&req, "authorization"), Some("Bearer sk-test"));
+        assert_eq!(req.body["model"], "test-model");
+        assert_eq!(req.body["messages"].as_array().unwrap().len(), 4);
+        assert_eq!(
+            req.body["messages"][0],
+            json!({"role": "system", "content": "be brief"})
+        );
+        assert_eq!(req.body["temperature"], 1.5);
+        assert_eq!(req.body["max_tokens"], 64);
+        assert_eq!(req.body["response_format"], json!({"type": "json_object"}));
+        assert!(req.body.get("stream").is_none());
+    }
+
+    #[tokio::test]
+    async fn openai_stream() {
+        let body = sse(&[
+            json!({"choices": [{"delta": {"content": "po"}}]}),
+            json!({"choices": [{"delta": {"content": "ng"}, "finish_reason": "stop"}]}),
+            json!({"choices": [], "usage": {"prompt_tokens": 5, "completion_tokens": 2}}),
+        ]) + "data: [DONE]\n\n";
+        let (base, mock) = serve(200, "text/event-stream", body).await;
+
+        let stream = provider(ProviderKind::OpenAi, &base, "sk-test")
+            .chat_stream(&request())
+            .await
+            .unwrap();
+        let events = collect(stream).await;
+        assert!(events.iter().all(|e| e.is_ok()));
+        assert_eq!(texts(&events), "pong");
+        assert_eq!(finish(&events).as_deref(), Some("stop"));
+        assert_eq!(usage(&events), Some((5, 2)));
+
+        let req = last_request(&mock);
+        assert_eq!(req.path_and_query, "/chat/completions");
+        assert_eq!(req.body["stream"], true);
+        assert_eq!(req.body["stream_options"], json!({"include_usage": true}));
+    }
+
+    #[tokio::test]
+    async fn openai_stream_retries_without_stream_options() {
+        // 模拟不认识 stream_options 的兼容接口
+        let requests = Arc::new(Mutex::new(Vec::<Value>::new()));
+        let recorded = requests.clone();
+        let app = Router::new().fallback(move |Json(body): Json<Value>| {
+            let recorded = recorded.clone();
+            async move {
+                let rejected = body.get("stream_options").is_some();
+                recorded.lock().unwrap().push(body);
+                if rejected {
+                    (StatusCode::BAD_REQUEST, "unknown field `stream_options`".to_string())
+                } else {
+                    (StatusCode::OK, sse(&[json!({"choices": [{"delta": {"content": "ok"}, "finish_reason": "stop"}]})]))
+                }
+            }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let stream = provider(ProviderKind::OpenAi, &base, "sk-test")
+            .chat_stream(&request())
+            .await
+            .unwrap();
+        let events = collect(stream).await;
+        assert_eq!(texts(&events), "ok");
+        assert_eq!(usage(&events), None);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].get("stream_options").is_some());
+        assert!(requests[1].get("stream_options").is_none());
+        assert_eq!(requests[1]["stream"], true);
+    }
+
+    #[tokio::test]
+    async fn openai_stream_bad_request_not_retried() {
+        let (base, mock) = serve(
+            400,
+            "application/json",
+            json!({"error": {"message": "maximum context length exceeded"}}).to_string(),
+        )
+        .await;
+
+        let err = match provider(ProviderKind::OpenAi, &base, "sk-test")
+            .chat_stream(&request())
+            .await
+        {
+            Err(e) => e,
+            Ok(_) => panic!("expected an error"),
+        };
+        match err {
+            ProviderError::Http { status, body } => {
+                assert_eq!(status, 400);
+                assert!(body.contains("maximum context length"));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert_eq!(mock.requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn stream_line_too_long() {
+        let body = format!("data: {}", "x".repeat(MAX_STREAM_LINE + 1));
+        let (base, _mock) = serve(200, "text/event-stream", body).await;
+
+        let stream = provider(ProviderKind::OpenAi, &base, "sk-test")
+            .chat_stream(&request())
+            .await
+            .unwrap();
+        let events = collect(stream).await;
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], Err(ProviderError::Stream(_))));
+    }
+
+    #[tokio::test]
+    async fn openai_error_response() {
+        let (base, _mock) = serve(
+            401,
+            "application/json",
+            json!({"error": {"message": "invalid key"}}).to_string(),
+        )
+        .await;
+
+        let err = provider(ProviderKind::OpenAi, &base, "bad")
+            .chat(&request())
+            .await
+            .unwrap_err();
+        match err {
+            ProviderError::Http { status, body } => {
+                assert_eq!(status, 401);
+                assert!(body.contains("invalid key"));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn openai_stream_error_chunk() {
+        let body = sse(&[
+            json!({"choices": [{"delta": {"content": "partial"}}]}),
+            json!({"error": {"message": "overloaded"}}),
+            json!({"choices": [{"delta": {"content": "ignored"}}]}),
+        ]);
+        let (base, _mock) = serve(200, "text/event-stream", body).await;
+
+        let stream = provider(ProviderKind::OpenAi, &base, "sk-test")
+            .chat_stream(&request())
+            .await
+            .unwrap();
+        let events = collect(stream).await;
+        assert_eq!(texts(&events), "partial");
+        assert!(
+            matches!(events.last(), Some(Err(ProviderError::Stream(e))) if e.contains("overloaded"))
+        );
+    }
+
+    #[tokio::test]
+    async fn openai_list_models() {
+        let (base, mock) = serve(
+            200,
+            "application/json",
+            json!({"data": [{"id": "a"}, {"id": "b"}]}).to_string(),
+        )
+        .await;
+
+        let models = provider(ProviderKind::OpenAi, &base, "sk-test")
+            .list_models()
+            .await
+            .unwrap();
+        assert_eq!(models, vec!["a", "b"]);
+        let req = last_request(&mock);
+        assert_eq!(req.method, "GET");
+        assert_eq!(req.path_and_query, "/models");
+        assert_eq!(header_value(&req, "authorization"), Some("Bearer sk-test"));
+    }
+
+    #[tokio::test]
+    async fn anthropic_chat() {
+        let (base, mock) = serve(
+            200,
+            "application/json",
+            json!({
+                "content": [
+                    {"type": "thinking", "thinking": "hidden"},
+                    {"type": "text", "text": "po"},
+                    {"type": "text", "text": "ng"}
+                ],
+                "stop_reason": "end_turn",
+                "usage": {"input_tokens": 20, "output_tokens": 4}
+            })
+            .to_string(),
+        )
+        .await;
+
+        let mut chat = request();
+        chat.max_tokens = None;
+        let res = provider(ProviderKind::Anthropic, &base, "ak-test")
+            .chat(&chat)
+            .await
+            .unwrap();
+        assert_eq!(res.content, "pong");
+        assert_eq!(res.finish_reason.as_deref(), Some("end_turn"));
+        let usage = res.usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (20, 4));
+
+        let req = last_request(&mock);
+        assert_eq!(req.path_and_query, "/messages");
+        assert_eq!(header_value(&req, "x-api-key"), Some("ak-test"));
+        assert_eq!(
+            header_value(&req, "anthropic-version"),
+            Some(ANTHROPIC_VERSION)
+        );
+        assert!(header_value(&req, "authorization").is_none());
+        assert_eq!(req.body["system"], "be brief");
+        let messages = req.body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert!(messages.iter().all(|m| m["role"] != "system"));
+        assert_eq!(req.body["max_tokens"], ANTHROPIC_DEFAULT_MAX_TOKENS);
+        assert_eq!(req.body["temperature"], 1.0);
+    }
+
+    #[tokio::test]
+    async fn anthropic_stream() {
+        let body = [
+            ("message_start", json!({"type": "message_start", "message": {"usage": {"input_tokens": 9}}})),
+            ("content_block_delta", json!({"type": "content_block_delta", "delta": {"type": "text_delta", "text": "po"}})),
+            ("ping", json!({"type": "ping"})),
+            ("content_block_delta", json!({"type": "content_block_delta", "delta": {"type": "text_delta", "text": "ng"}})),
+            ("message_delta", json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 2}})),
+            ("message_stop", json!({"type": "message_stop"})),
+        ]
+        .iter()
+        .map(|(name, data)| format!("event: {}\ndata: {}\n\n", name, data))
+        .collect::<String>();
+        let (base, mock) = serve(200, "text/event-stream", body).await;
+
+        let stream = provider(ProviderKind::Anthropic, &base, "ak-test")
+            .chat_stream(&request())
+            .await
+            .unwrap();
+        let events = collect(stream).await;
+        assert!(events.iter().all(|e| e.is_ok()));
+        assert_eq!(texts(&events), "pong");
+        assert_eq!(usage(&events), Some((9, 2)));
+        assert_eq!(finish(&events).as_deref(), Some("end_turn"));
+        assert_eq!(last_request(&mock).body["stream"], true);
+    }
+
+    #[tokio::test]
+    async fn anthropic_error_response() {
+        let (base, _mock) = serve(
+            529,
+            "application/json",
+            json!({"type": "error", "error": {"type": "overloaded_error"}}).to_string(),
+        )
+        .await;
+
+        let err = provider(ProviderKind::Anthropic, &base, "ak-test")
+            .chat_stream(&request())
+            .await
+            .err()
+            .unwrap();
+        assert!(
+            matches!(&err, ProviderError::Http { status: 529, body } if body.contains("overloaded_error"))
+        );
+        assert!(err.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn gemini_chat() {
+        let (base, mock) = serve(
+            200,
+            "application/json",
+            json!({
+                "candidates": [{
+                    "content": {"parts": [{"text": "po"}, {"text": "ng"}]},
+                    "finishReason": "STOP"
+                }],
+                "usageMetadata": {"promptTokenCount": 7, "candidatesTokenCount": 2}
+            })
+            .to_string(),
+        )
+        .await;
+
+        let mut chat = request();
+        chat.model = "models/gemini-test".to_string();
+        let res = provider(ProviderKind::Gemini, &base, "g-test")
+            .chat(&chat)
+            .await
+            .unwrap();
+        assert_eq!(res.content, "pong");
+        assert_eq!(res.finish_reason.as_deref(), Some("STOP"));
+        let usage = res.usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (7, 2));
+
+        let req = last_request(&mock);
+        assert_eq!(req.path_and_query, "/models/gemini-test:generateContent");
+        assert_eq!(header_value(&req, "x-goog-api-key"), Some("g-test"));
+        assert!(header_value(&req, "authorization").is_none());
+        assert_eq!(
+            req.body["systemInstruction"],
+            json!({"parts": [{"text": "be brief"}]})
+        );
+        let contents = req.body["contents"].as_array().unwrap();
+        let roles: Vec<&str> = contents.iter().filter_map(|c| c["role"].as_str()).collect();
+        assert_eq!(roles, vec!["user", "model", "user"]);
+        assert_eq!(contents[0]["parts"][0]["text"], "hi");
+        assert_eq!(req.body["generationConfig"]["temperature"], 1.5);
+        assert_eq!(req.body["generationConfig"]["maxOutputTokens"], 64);
+        assert_eq!(
+            req.body["generationConfig"]["responseMimeType"],
+            "application/json"
+        );
+    }
+
+    #[tokio::test]
+    async fn gemini_stream() {
+        let body = sse(&[
+            json!({"candidates": [{"content": {"parts": [{"text": "po"}]}}], "usageMetadata": {"promptTokenCount": 7, "candidatesTokenCount": 1}}),
+            json!({"candidates": [{"content": {"parts": [{"text": "ng"}]}, "finishReason": "STOP"}], "usageMetadata": {"promptTokenCount": 7, "candidatesTokenCount": 2}}),
+        ]);
+        let (base, mock) = serve(200, "text/event-stream", body).await;
+
+        let stream = provider(ProviderKind::Gemini, &base, "g-test")
+            .chat_stream(&request())
+            .await
+            .unwrap();
+        let events = collect(stream).await;
+        assert_eq!(texts(&events), "pong");
+        // 只输出最后一片的累计用量
+        let usages = events
+            .iter()
+            .filter(|e| matches!(e, Ok(StreamEvent::Usage(_))))
+            .count();
+        assert_eq!(usages, 1);
+        assert_eq!(usage(&events), Some((7, 2)));
+        assert_eq!(finish(&events).as_deref(), Some("STOP"));
+        assert_eq!(
+            last_request(&mock).path_and_query,
+            "/models/test-model:streamGenerateContent?alt=sse"
+        );
+    }
+
+    #[tokio::test]
+    async fn gemini_error_response() {
+        let (base, _mock) = serve(
+            400,
+            "application/json",
+            json!({"error": {"code": 400, "message": "API key not valid"}}).to_string(),
+        )
+        .await;
+
+        let err = provider(ProviderKind::Gemini, &base, "bad")
+            .chat(&request())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, ProviderError::Http { status: 400, body } if body.contains("API key not valid"))
+        );
+        assert!(err.is_request_error());
+    }
+
+    #[tokio::test]
+    async fn ollama_chat() {
+        let (base, mock) = serve(
+            200,
+            "application/json",
+            json!({
+                "message": {"role": "assistant", "content": "pong"},
+                "done": true,
+                "done_reason": "stop",
+                "prompt_eval_count": 11,
+                "eval_count": 6
+            })
+            .to_string(),
+        )
+        .await;
+
+        let res = provider(ProviderKind::Ollama, &base, "")
+            .chat(&request())
+            .await
+            .unwrap();
+        assert_eq!(res.content, "pong");
+        assert_eq!(res.finish_reason.as_deref(), Some("stop"));
+        let usage = res.usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (11, 6));
+
+        let req = last_request(&mock);
+        assert_eq!(req.path_and_query, "/api/chat");
+        assert!(header_value(&req, "authorization").is_none());
+        assert_eq!(req.body["stream"], false);
+        assert_eq!(req.body["messages"].as_array().unwrap().len(), 4);
+        assert_eq!(
+            req.body["options"],
+            json!({"temperature": 1.5, "num_predict": 64})
+        );
+        assert_eq!(req.body["format"], "json");
+    }
+
+    #[tokio::test]
+    async fn ollama_stream() {
+        let body = [
+            json!({"message": {"content": "po"}, "done": false}),
+            json!({"message": {"content": "ng"}, "done": false}),
+            json!({"message": {"content": ""}, "done": true, "done_reason": "stop", "prompt_eval_count": 3, "eval_count": 2}),
+        ]
+        .iter()
+        .map(|v| format!("{}\n", v))
+        .collect::<String>();
+        let (base, mock) = serve(200, "application/x-ndjson", body).await;
+
+        let stream = provider(ProviderKind::Ollama, &base, "proxy-key")
+            .chat_stream(&request())
+            .await
+            .unwrap();
+        let events = collect(stream).await;
+        assert!(events.iter().all(|e| e.is_ok()));
+        assert_eq!(texts(&events), "pong");
+        assert_eq!(usage(&events), Some((3, 2)));
+        assert_eq!(finish(&events).as_deref(), Some("stop"));
+
+        let req = last_request(&mock);
+        assert_eq!(req.body["stream"], true);
+        assert_eq!(
+            header_value(&req, "authorization"),
+            Some("Bearer proxy-key")
+        );
+    }
+
+    #[tokio::test]
+    async fn ollama_stream_error() {
+        let body = format!(
+            "{}\n{}\n",
+            json!({"message": {"content": "po"}, "done": false}),
+            json!({"error": "model not found"})
+        );
+        let (base, _mock) = serve(200, "application/x-ndjson", body).await;
+
+        let stream = provider(ProviderKind::Ollama, &base, "")
+            .chat_stream(&request())
+            .await
+            .unwrap();
+        let events = collect(stream).await;
+        assert_eq!(texts(&events), "po");
+        assert!(
+            matches!(events.last(), Some(Err(ProviderError::Stream(e))) if e == "model not found")
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_json_response() {
+        let (base, _mock) = serve(200, "application/json", "<html>gateway</html>").await;
+
+        let err = provider(ProviderKind::Ollama, &base, "")
+            .list_models()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ProviderError::Parse(_)));
+    }
+}
@@ -0,0 +1,526 @@
+//! AI 渠道路由
+//!
+//! 每个功能（概览、小皮医生、通用调用）可以配置一组渠道：
+//! - `fallback`：按顺序使用，前一个失败时切换到下一个
+//! - `weighted`：按权重随机排序后依次尝试，用于在多把 key 之间分摊请求
+//!
+//! 未配置的功能只使用全局渠道 `ai_config_global`。同一渠道遇到临时性故障（连接失败、超时、
+//! 429、5xx）时按指数退避重试；连续失败的渠道会被熔断一段时间，期间直接跳过。
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use rand::Rng;
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::entities::{ai_channel, setting};
+use crate::services::ai_provider::{self, ChatRequest, ChatResponse, ChatStream, ProviderError};
+use crate::services::ai_usage::{self, UsageChannel, UsageRecorder};
+
+/// 保存路由配置的设置项
+pub const ROUTING_SETTING: &str = "ai_routing";
+/// 全局默认渠道的设置项
+pub const GLOBAL_CHANNEL_SETTING: &str = "ai_config_global";
+
+/// 可单独配置路由的功能
+pub const ROUTED_FEATURES: [&str; 3] = [
+    ai_usage::FEATURE_OVERVIEW,
+    ai_usage::FEATURE_DOCTOR,
+    ai_usage::FEATURE_EXECUTE,
+];
+
+/// 同一渠道遇到临时性故障时的最多重试次数
+const MAX_RETRIES: u32 = 2;
+/// 第一次重试前的等待时间，之后每次翻倍
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+/// 连续失败多少次后熔断
+const BREAKER_THRESHOLD: u32 = 3;
+/// 熔断持续时间，到期后放行一次试探请求
+const BREAKER_COOLDOWN: Duration = Duration::from_secs(60);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// 两次收到数据之间的最长间隔，流式与非流式请求都适用
+const READ_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// 渠道的使用方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutingMode {
+    #[default]
+    Fallback,
+    Weighted,
+}
+
+impl RoutingMode {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Fallback => "故障转移",
+            Self::Weighted => "加权轮询",
+        }
+    }
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteTarget {
+    pub channel_id: Uuid,
+    /// 仅在 `weighted` 模式下生效
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+/// 单个功能的路由配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoutingPolicy {
+    #[serde(default)]
+    pub mode: RoutingMode,
+    #[serde(default)]
+    pub channels: Vec<RouteTarget>,
+}
+
+/// 各功能的路由配置，键为功能名
+pub type RoutingConfig = BTreeMap<String, RoutingPolicy>;
+
+/// 解析路由失败
+#[derive(Debug, thiserror::Error)]
+pub enum RouteError {
+    #[error("没有配置全局AI模型，请到设置页面完成配置")]
+    NotConfigured,
+    #[error("配置的AI渠道已不存在，请重新配置")]
+    ChannelMissing,
+    #[error("路由中没有可用的AI渠道，请检查渠道是否已启用")]
+    NoActiveChannel,
+    #[error(transparent)]
+    Db(#[from] DbErr),
+}
+
+/// 读取路由配置，未保存或格式有误时返回空配置
+pub async fn load_config(db: &DatabaseConnection) -> Result<RoutingConfig, DbErr> {
+    let value = setting::Entity::find_by_id(ROUTING_SETTING)
+        .one(db)
+        .await?
+        .map(|s| s.value);
+    Ok(value
+        .and_then(|v| serde_json::from_str(&v).ok())
+        .unwrap_or_default())
+}
+
+/// 保存路由配置
+pub async fn save_config(db: &DatabaseConnection, config: &RoutingConfig) -> Result<(), DbErr> {
+    let value = serde_json::to_string(config).unwrap_or_else(|_| "{}".to_string());
+    let model = setting::ActiveModel {
+        key: Set(ROUTING_SETTING.to_string()),
+        value: Set(value),
+        updated_at: Set(chrono::Utc::now().naive_utc()),
+    };
+    setting::Entity::insert(model)
+        .on_conflict(
+            sea_orm::sea_query::OnConflict::column(setting::Column::Key)
+                .update_columns([setting::Column::Value, setting::Column::UpdatedAt])
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+    Ok(())
+}
+
+/// 某个功能解析出的候选渠道
+pub struct ChannelRoute {
+    feature: &'static str,
+    mode: RoutingMode,
+    targets: Vec<(ai_channel::Model, u32)>,
+    client: reqwest::Client,
+}
+
+/// 请求成功时返回的结果与实际使用的渠道
+pub struct Served<T> {
+    pub value: T,
+    pub channel: ai_channel::Model,
+}
+
+/// 按功能解析候选渠道：有路由配置时使用其中已启用的渠道，否则使用全局渠道
+pub async fn resolve(
+    db: &DatabaseConnection,
+    feature: &'static str,
+) -> Result<ChannelRoute, RouteError> {
+    resolve_with_override(db, feature, None).await
+}
+
+/// 同 [`resolve`]，但提示词模板指定了渠道时优先使用该渠道
+///
+/// 指定的渠道排在最前，失败后按功能的路由配置（或全局渠道）继续故障转移；
+/// 指定的渠道已停用或不存在时直接使用功能的路由。
+pub async fn resolve_with_override(
+    db: &DatabaseConnection,
+    feature: &'static str,
+    channel_id: Option<Uuid>,
+) -> Result<ChannelRoute, RouteError> {
+    let pinned = match channel_id {
+        Some(channel_id) => {
+            ai_channel::Entity::find_by_id(channel_id)
+                .filter(ai_channel::Column::IsActive.eq(true))
+                .one(db)
+                .await?
+        }
+        None => None,
+    };
+    let Some(pinned) = pinned else {
+        let (mode, targets) = resolve_policy(db, feature).await?;
+        return Ok(ChannelRoute::new(feature, mode, targets));
+    };
+
+    // 后备渠道按配置顺序尝试（加权配置也是如此）；功能路由不可用时只使用指定的渠道
+    let pinned_id = pinned.id;
+    let mut targets = vec![(pinned, 1)];
+    match resolve_policy(db, feature).await {
+        Ok((_, rest)) => targets.extend(rest.into_iter().filter(|(c, _)| c.id != pinned_id)),
+        Err(RouteError::Db(e)) => return Err(e.into()),
+        Err(_) => {}
+    }
+    Ok(ChannelRoute::new(feature, RoutingMode::Fallback, targets))
+}
+
+/// 功能的路由配置中已启用的渠道；未配置时使用全局渠道
+async fn resolve_policy(
+    db: &DatabaseConnection,
+    feature: &'static str,
+) -> Result<(RoutingMode, Vec<(ai_channel::Model, u32)>), RouteError> {
+    let policy = load_config(db)
+        .await?
+        .remove(feature)
+        .filter(|p| !p.channels.is_empty());
+
+    match policy {
+        Some(policy) => {
+            let ids: Vec<Uuid> = policy.channels.iter().map(|t| t.channel_id).collect();
+            let channels = ai_channel::Entity::find()
+                .filter(ai_channel::Column::Id.is_in(ids))
+                .filter(ai_channel::Column::IsActive.eq(true))
+                .all(db)
+                .await?;
+            let targets: Vec<(ai_channel::Model, u32)> = policy
+                .channels
+                .iter()
+                .filter_map(|t| {
+                    channels
+                        .iter()
+                        .find(|c| c.id == t.channel_id)
+                        .map(|c| (c.clone(), t.weight.max(1)))
+                })
+                .collect();
+            if targets.is_empty() {
+                return Err(RouteError::NoActiveChannel);
+            }
+            Ok((policy.mode, targets))
+        }
+        None => {
+            let channel_id = setting::Entity::find_by_id(GLOBAL_CHANNEL_SETTING)
+                .one(db)
+                .await?
+                .and_then(|s| Uuid::parse_str(&s.value).ok())
+                .ok_or(RouteError::NotConfigured)?;
+            let channel = ai_channel::Entity::find_by_id(channel_id)
+                .one(db)
+                .await?
+                .ok_or(RouteError::ChannelMissing)?;
+            Ok((RoutingMode::Fallback, vec![(channel, 1)]))
+        }
+    }
+}
+
+impl ChannelRoute {
+    fn new(
+        feature: &'static str,
+        mode: RoutingMode,
+        targets: Vec<(ai_channel::Model, u32)>,
+    ) -> Self {
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .read_timeout(READ_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self {
+            feature,
+            mode,
+            targets,
+            client,
+        }
+    }
+
+    /// 第一个候选渠道
+    pub fn primary(&self) -> &ai_channel::Model {
+        &self.targets[0].0
+    }
+
+    /// 供日志显示的路由说明
+    pub fn describe(&self) -> String {
+        if self.targets.len() == 1 {
+            let channel = self.primary();
+            return format!("{} (Model: {})", channel.name, channel.model_id);
+        }
+        let names: Vec<String> = self
+            .targets
+            .iter()
+            .map(|(c, weight)| match self.mode {
+                RoutingMode::Fallback => c.name.clone(),
+                RoutingMode::Weighted => format!("{}×{}", c.name, weight),
+            })
+            .collect();
+        format!("{}: {}", self.mode.label(), names.join(" → "))
+    }
+
+    /// 本次请求的尝试顺序；加权模式下按权重随机排序（权重越大越可能排在前面）
+    fn attempt_order(&self) -> Vec<&ai_channel::Model> {
+        match self.mode {
+            RoutingMode::Fallback => self.targets.iter().map(|(c, _)| c).collect(),
+            RoutingMode::Weighted => {
+                let mut rng = rand::thread_rng();
+                let mut keyed: Vec<(f64, &ai_channel::Model)> = self
+                    .targets
+                    .iter()
+                    .map(|(c, weight)| (rng.gen::<f64>().powf(1.0 / *weight as f64), c))
+                    .collect();
+                keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+                keyed.into_iter().map(|(_, c)| c).collect()
+            }
+        }
+    }
+
+    /// 非流式对话：依次尝试各渠道，返回第一个成功的结果
+    pub async fn chat(
+        &self,
+        db: &DatabaseConnection,
+        request: &ChatRequest,
+        logs: &mut Vec<String>,
+    ) -> Result<Served<ChatResponse>, ProviderError> {
+        self.run(db, request, logs, |provider, request| async move {
+            provider.chat(&request).await
+        })
+        .await
+    }
+
+    /// 流式对话：只在建立连接前切换渠道，流开始后的错误直接交给调用方
+    pub async fn chat_stream(
+        &self,
+        db: &DatabaseConnection,
+        request: &ChatRequest,
+        logs: &mut Vec<String>,
+    ) -> Result<Served<ChatStream>, ProviderError> {
+        self.run(db, request, logs, |provider, request| async move {
+            provider.chat_stream(&request).await
+        })
+        .await
+    }
+
+    async fn run<T, F, Fut>(
+        &self,
+        db: &DatabaseConnection,
+        request: &ChatRequest,
+        logs: &mut Vec<String>,
+        call: F,
+    ) -> Result<Served<T>, ProviderError>
+    where
+        T: Tracked,
+        F: Fn(Box<dyn ai_provider::AiProvider>, ChatRequest) -> Fut,
+        Fut: std::future::Future<Output = Result<T, ProviderError>>,
+    {
+        let mut last_error = None;
+        let mut skipped = Vec::new();
+
+        for channel in self.attempt_order() {
+            if !breaker_allows(channel.id) {
+                note(logs, format!("渠道 {} 熔断中，跳过", channel.name));
+                skipped.push(channel.name.clone());
+                continue;
+            }
+
+            let request = ChatRequest {
+                model: channel.model_id.clone(),
+                ..request.clone()
+            };
+            let mut attempt = 0;
+            let error = loop {
+                logs.push(format!(
+                    "正在请求渠道 {}: {} ({}, Model: {})",
+                    channel.name, channel.base_url, channel.provider, channel.model_id
+                ));
+                let provider = match ai_provider::for_channel(channel, self.client.clone()) {
+                    Ok(provider) => provider,
+                    Err(e) => break e,
+                };
+                let usage = UsageRecorder::start(db, UsageChannel::from(channel), self.feature);
+                match T::track(usage, call(provider, request.clone()).await) {
+                    Ok(value) => {
+                        breaker_success(channel.id);
+                        logs.push(format!("本次请求由渠道 {} 完成", channel.name));
+                        return Ok(Served {
+                            value,
+                            channel: channel.clone(),
+                        });
+                    }
+                    Err(e) if e.is_transient() && attempt < MAX_RETRIES => {
+                        let delay = retry_delay(attempt);
+                        attempt += 1;
+                        note(
+                            logs,
+                            format!(
+                                "渠道 {} 请求失败: {}，{}ms 后第 {} 次重试",
+                                channel.name,
+                                e,
+                                delay.as_millis(),
+                                attempt
+                            ),
+                        );
+                        tokio::time::sleep(delay).await;
+                    }
+                    Err(e) => break e,
+                }
+            };
+
+            // 请求本身有误时换渠道也没用，也不算渠道故障
+            if error.is_request_error() {
+                return Err(error);
+            }
+            breaker_failure(channel.id);
+            note(logs, format!("渠道 {} 不可用: {}", channel.name, error));
+            last_error = Some(error);
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            ProviderError::Unavailable(format!(
+                "{} 连续失败，已暂停使用，请稍后再试",
+                skipped.join("、")
+            ))
+        }))
+    }
+}
+
+/// 把调用结果交给用量记录器：非流式立即记录，流式在流结束时记录
+trait Tracked: Sized {
+    fn track(
+        usage: UsageRecorder,
+        result: Result<Self, ProviderError>,
+    ) -> Result<Self, ProviderError>;
+}
+
+impl Tracked for ChatResponse {
+    fn track(
+        usage: UsageRecorder,
+        result: Result<Self, ProviderError>,
+    ) -> Result<Self, ProviderError> {
+        usage.finish(&result);
+        result
+    }
+}
+
+impl Tracked for ChatStream {
+    fn track(
+        usage: UsageRecorder,
+        result: Result<Self, ProviderError>,
+    ) -> Result<Self, ProviderError> {
+        usage.track(result)
+    }
+}
+
+/// 重要的路由事件同时写入日志与返回给前端的日志
+fn note(logs: &mut Vec<String>, message: String) {
+    tracing::warn!("{}", message);
+    logs.push(message);
+}
+
+/// 指数退避，附加最多 25% 的随机抖动，避免多个请求同时重试
+fn retry_delay(attempt: u32) -> Duration {
+    let base = RETRY_BASE_DELAY * 2u32.pow(attempt);
+    let jitter = rand::thread_rng().gen_range(0..=base.as_millis() as u64 / 4);
+    base + Duration::from_millis(jitter)
+}
+
+// ==================== 熔断 ====================
+
+#[derive(Default)]
+struct Breaker {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+static BREAKERS: Lazy<Mutex<HashMap<Uuid, Breaker>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 渠道当前的熔断状态
+#[derive(Debug, Serialize)]
+pub struct BreakerStatus {
+    pub channel_id: Uuid,
+    /// 连续失败次数
+    pub failures: u32,
+    pub open: bool,
+    /// 距离下次试探请求的秒数
+    pub retry_in_secs: Option<u64>,
+}
+
+/// 未熔断或熔断已到期时放行；到期后只放行一次试探请求，其余请求继续跳过
+fn breaker_allows(channel_id: Uuid) -> bool {
+    let mut breakers = BREAKERS.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(breaker) = breakers.get_mut(&channel_id) else {
+        return true;
+    };
+    match breaker.open_until {
+        None => true,
+        Some(until) if Instant::now() >= until => {
+            breaker.open_until = Some(Instant::now() + BREAKER_COOLDOWN);
+            true
+        }
+        Some(_) => false,
+    }
+}
+
+fn breaker_success(channel_id: Uuid) {
+    let mut breakers = BREAKERS.lock().unwrap_or_else(|e| e.into_inner());
+    breakers.remove(&channel_id);
+}
+
+fn breaker_failure(channel_id: Uuid) {
+    let mut breakers = BREAKERS.lock().unwrap_or_else(|e| e.into_inner());
+    let breaker = breakers.entry(channel_id).or_default();
+    breaker.failures += 1;
+    if breaker.failures >= BREAKER_THRESHOLD {
+        if breaker.open_until.is_none() {
+            tracing::warn!(
+                "渠道 {} 连续失败 {} 次，熔断 {} 秒",
+                channel_id,
+                breaker.failures,
+                BREAKER_COOLDOWN.as_secs()
+            );
+        }
+        breaker.open_until = Some(Instant::now() + BREAKER_COOLDOWN);
+    }
+}
+
+/// 手动恢复渠道（如连接测试成功后）
+pub fn reset_breaker(channel_id: Uuid) {
+    breaker_success(channel_id);
+}
+
+/// 有失败记录的渠道的熔断状态
+pub fn breaker_statuses() -> Vec<BreakerStatus> {
+    let breakers = BREAKERS.lock().unwrap_or_else(|e| e.into_inner());
+    let now = Instant::now();
+    breakers
+        .iter()
+        .map(|(id, breaker)| {
+            let remaining = breaker
+                .open_until
+                .and_then(|until| until.checked_duration_since(now));
+            BreakerStatus {
+                channel_id: *id,
+                failures: breaker.failures,
+                open: remaining.is_some(),
+                retry_in_secs: remaining.map(|d| d.as_secs()),
+            }
+        })
+        .collect()
+}
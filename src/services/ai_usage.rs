@@ -0,0 +1,396 @@
+//! AI 调用用量记录
+//!
+//! 每次调用模型前创建 `UsageRecorder`，拿到结果后交给它；记录器被丢弃时在后台写入一行
+//! `ai_usage`。请求中途被取消（客户端断开、任务取消）时同样会记下一条 `cancelled`。
+//! 费用按调用时渠道设置的每百万 token 单价估算。
+
+use std::time::Instant;
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use futures::stream::{self, StreamExt};
+use sea_orm::{
+    ConnectionTrait, DatabaseBackend, DatabaseConnection, DbErr, EntityTrait, Set, Statement, Value,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::entities::{ai_channel, ai_usage};
+use crate::services::ai_provider::{
+    ChatResponse, ChatStream, ProviderError, StreamEvent, TokenUsage,
+};
+
+/// 角色卡概览（单张与批量）
+pub const FEATURE_OVERVIEW: &str = "overview";
+/// 小皮医生，每轮对话记一条
+pub const FEATURE_DOCTOR: &str = "doctor";
+/// `/api/ai/execute` 通用调用
+pub const FEATURE_EXECUTE: &str = "execute";
+/// 连通性测试
+pub const FEATURE_TEST: &str = "test";
+
+pub const FEATURES: [&str; 4] = [
+    FEATURE_OVERVIEW,
+    FEATURE_DOCTOR,
+    FEATURE_EXECUTE,
+    FEATURE_TEST,
+];
+
+/// 错误信息最多保存的字符数
+const MAX_ERROR_CHARS: usize = 500;
+
+/// 记录用量所需的渠道信息
+#[derive(Debug, Clone)]
+pub struct UsageChannel {
+    /// 测试未保存的渠道时为空
+    pub id: Option<Uuid>,
+    pub name: String,
+    pub model: String,
+    pub input_price: Option<f64>,
+    pub output_price: Option<f64>,
+}
+
+impl UsageChannel {
+    /// 未保存的渠道（如设置页的连接测试），以接口地址作为名称
+    pub fn transient(base_url: &str, model: &str) -> Self {
+        Self {
+            id: None,
+            name: base_url.to_string(),
+            model: model.to_string(),
+            input_price: None,
+            output_price: None,
+        }
+    }
+}
+
+impl From<&ai_channel::Model> for UsageChannel {
+    fn from(channel: &ai_channel::Model) -> Self {
+        Self {
+            id: Some(channel.id),
+            name: channel.name.clone(),
+            model: channel.model_id.clone(),
+            input_price: channel.input_price,
+            output_price: channel.output_price,
+        }
+    }
+}
+
+/// 按每百万 token 单价估算费用，两项单价都未设置时返回 None
+pub fn estimate_cost(
+    usage: TokenUsage,
+    input_price: Option<f64>,
+    output_price: Option<f64>,
+) -> Option<f64> {
+    if input_price.is_none() && output_price.is_none() {
+        return None;
+    }
+    let input = usage.prompt_tokens as f64 * input_price.unwrap_or(0.0);
+    let output = usage.completion_tokens as f64 * output_price.unwrap_or(0.0);
+    Some((input + output) / 1_000_000.0)
+}
+
+enum Outcome {
+    Ok,
+    Error(String),
+}
+
+/// 单次调用的用量记录器，丢弃时写入数据库
+pub struct UsageRecorder {
+    db: DatabaseConnection,
+    channel: UsageChannel,
+    feature: &'static str,
+    started: Instant,
+    usage: TokenUsage,
+    outcome: Option<Outcome>,
+}
+
+impl UsageRecorder {
+    /// 在发出请求前创建，从此刻开始计时
+    pub fn start(db: &DatabaseConnection, channel: UsageChannel, feature: &'static str) -> Self {
+        Self {
+            db: db.clone(),
+            channel,
+            feature,
+            started: Instant::now(),
+            usage: TokenUsage::default(),
+            outcome: None,
+        }
+    }
+
+    /// 记录非流式调用的结果
+    pub fn finish(mut self, result: &Result<ChatResponse, ProviderError>) {
+        match result {
+            Ok(response) => {
+                if let Some(usage) = response.usage {
+                    self.usage = usage;
+                }
+                self.outcome = Some(Outcome::Ok);
+            }
+            Err(e) => self.outcome = Some(Outcome::Error(e.to_string())),
+        }
+    }
+
+    /// 包装流式调用：累计流中的用量，流结束、出错或被丢弃时记录
+    pub fn track(
+        mut self,
+        result: Result<ChatStream, ProviderError>,
+    ) -> Result<ChatStream, ProviderError> {
+        let events = match result {
+            Ok(events) => events,
+            Err(e) => {
+                self.outcome = Some(Outcome::Error(e.to_string()));
+                return Err(e);
+            }
+        };
+
+        let tracked = stream::unfold((events, self), |(mut events, mut recorder)| async move {
+            match events.next().await {
+                Some(item) => {
+                    match &item {
+                        Ok(StreamEvent::Usage(usage)) => recorder.usage = *usage,
+                        Ok(_) => {}
+                        Err(e) => recorder.outcome = Some(Outcome::Error(e.to_string())),
+                    }
+                    Some((item, (events, recorder)))
+                }
+                None => {
+                    recorder.outcome.get_or_insert(Outcome::Ok);
+                    None
+                }
+            }
+        });
+        Ok(tracked.boxed())
+    }
+}
+
+impl Drop for UsageRecorder {
+    fn drop(&mut self) {
+        let (status, error) = match self.outcome.take() {
+            Some(Outcome::Ok) => ("ok", None),
+            Some(Outcome::Error(e)) => ("error", Some(e.chars().take(MAX_ERROR_CHARS).collect())),
+            None => ("cancelled", None),
+        };
+        let model = ai_usage::ActiveModel {
+            id: Set(Uuid::new_v4()),
+            channel_id: Set(self.channel.id),
+            channel_name: Set(self.channel.name.clone()),
+            model: Set(self.channel.model.clone()),
+            feature: Set(self.feature.to_string()),
+            prompt_tokens: Set(self.usage.prompt_tokens as i64),
+            completion_tokens: Set(self.usage.completion_tokens as i64),
+            latency_ms: Set(self.started.elapsed().as_millis() as i64),
+            status: Set(status.to_string()),
+            error: Set(error),
+            cost: Set(estimate_cost(
+                self.usage,
+                self.channel.input_price,
+                self.channel.output_price,
+            )),
+            created_at: Set(chrono::Utc::now().naive_utc()),
+        };
+
+        // 运行时已关闭（进程退出）时放弃记录
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let db = self.db.clone();
+        handle.spawn(async move {
+            if let Err(e) = ai_usage::Entity::insert(model)
+                .exec_without_returning(&db)
+                .await
+            {
+                tracing::warn!("记录 AI 用量失败: {}", e);
+            }
+        });
+    }
+}
+
+/// 用量统计的筛选条件，日期为闭区间，按 `tz_offset` 指定的时区划分
+pub struct UsageFilter {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub channel_id: Option<Uuid>,
+    pub feature: Option<String>,
+    /// 相对 UTC 的分钟数，东八区为 480
+    pub tz_offset: i32,
+}
+
+/// 一组调用的合计
+#[derive(Debug, Default, Serialize)]
+pub struct UsageStats {
+    pub calls: i64,
+    pub errors: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    /// 未设置单价的调用不计入
+    pub cost: f64,
+    pub avg_latency_ms: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DayUsage {
+    pub day: String,
+    #[serde(flatten)]
+    pub stats: UsageStats,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChannelUsage {
+    pub channel_id: Option<Uuid>,
+    /// 渠道仍存在时为当前名称
+    pub channel_name: String,
+    #[serde(flatten)]
+    pub stats: UsageStats,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeatureUsage {
+    pub feature: String,
+    #[serde(flatten)]
+    pub stats: UsageStats,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageSummary {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub total: UsageStats,
+    pub by_day: Vec<DayUsage>,
+    pub by_channel: Vec<ChannelUsage>,
+    pub by_feature: Vec<FeatureUsage>,
+}
+
+const STATS_COLUMNS: &str = "COUNT(*) AS calls,
+    COALESCE(SUM(CASE WHEN u.status = 'error' THEN 1 ELSE 0 END), 0) AS errors,
+    COALESCE(SUM(u.prompt_tokens), 0) AS prompt_tokens,
+    COALESCE(SUM(u.completion_tokens), 0) AS completion_tokens,
+    COALESCE(SUM(u.cost), 0.0) AS cost,
+    COALESCE(AVG(u.latency_ms), 0.0) AS avg_latency_ms";
+
+fn read_stats(row: &sea_orm::QueryResult) -> Result<UsageStats, DbErr> {
+    Ok(UsageStats {
+        calls: row.try_get("", "calls")?,
+        errors: row.try_get("", "errors")?,
+        prompt_tokens: row.try_get("", "prompt_tokens")?,
+        completion_tokens: row.try_get("", "completion_tokens")?,
+        cost: row.try_get("", "cost")?,
+        avg_latency_ms: row.try_get("", "avg_latency_ms")?,
+    })
+}
+
+/// 本地日期零点对应的 UTC 时间，与 `created_at` 的文本格式一致以便比较；超出日期范围时返回 None
+fn utc_bound(day: NaiveDate, tz_offset: i32) -> Option<String> {
+    let local: NaiveDateTime = day.and_hms_opt(0, 0, 0)?;
+    let utc = local.checked_sub_signed(Duration::minutes(tz_offset as i64))?;
+    Some(utc.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+impl UsageFilter {
+    /// 查询区间 `[from 零点, to 次日零点)` 对应的 UTC 时间；日期超出可表示的范围时返回 None
+    pub fn utc_range(&self) -> Option<(String, String)> {
+        Some((
+            utc_bound(self.from, self.tz_offset)?,
+            utc_bound(self.to.succ_opt()?, self.tz_offset)?,
+        ))
+    }
+}
+
+/// 按日、渠道、功能汇总用量
+pub async fn summarize(
+    db: &DatabaseConnection,
+    filter: &UsageFilter,
+) -> Result<UsageSummary, DbErr> {
+    let (start, end) = filter
+        .utc_range()
+        .ok_or_else(|| DbErr::Custom("日期超出范围".to_string()))?;
+    let mut conditions = vec!["u.created_at >= ?", "u.created_at < ?"];
+    let mut values: Vec<Value> = vec![start.into(), end.into()];
+    if let Some(channel_id) = filter.channel_id {
+        conditions.push("u.channel_id = ?");
+        values.push(channel_id.into());
+    }
+    if let Some(feature) = &filter.feature {
+        conditions.push("u.feature = ?");
+        values.push(feature.clone().into());
+    }
+    let where_clause = conditions.join(" AND ");
+
+    let query = |sql: String| {
+        db.query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            sql,
+            values.clone(),
+        ))
+    };
+
+    let total = query(format!(
+        "SELECT {STATS_COLUMNS} FROM ai_usage u WHERE {where_clause}"
+    ))
+    .await?
+    .first()
+    .map(read_stats)
+    .transpose()?
+    .unwrap_or_default();
+
+    // created_at 带有小数秒，截取到秒后再交给 date()
+    let day_expr = format!(
+        "date(substr(u.created_at, 1, 19), '{:+} minutes')",
+        filter.tz_offset
+    );
+    let by_day = query(format!(
+        "SELECT {day_expr} AS day, {STATS_COLUMNS} FROM ai_usage u
+         WHERE {where_clause} GROUP BY day ORDER BY day"
+    ))
+    .await?
+    .iter()
+    .map(|row| {
+        Ok(DayUsage {
+            day: row.try_get("", "day")?,
+            stats: read_stats(row)?,
+        })
+    })
+    .collect::<Result<Vec<_>, DbErr>>()?;
+
+    // 未保存渠道的测试没有 channel_id，按记录的名称（接口地址）分组
+    let by_channel = query(format!(
+        "SELECT u.channel_id AS channel_id,
+                COALESCE(MAX(c.name), MAX(u.channel_name)) AS channel_name, {STATS_COLUMNS}
+         FROM ai_usage u LEFT JOIN ai_channels c ON c.id = u.channel_id
+         WHERE {where_clause}
+         GROUP BY u.channel_id, CASE WHEN u.channel_id IS NULL THEN u.channel_name END
+         ORDER BY cost DESC, calls DESC"
+    ))
+    .await?
+    .iter()
+    .map(|row| {
+        Ok(ChannelUsage {
+            channel_id: row.try_get("", "channel_id")?,
+            channel_name: row.try_get("", "channel_name")?,
+            stats: read_stats(row)?,
+        })
+    })
+    .collect::<Result<Vec<_>, DbErr>>()?;
+
+    let by_feature = query(format!(
+        "SELECT u.feature AS feature, {STATS_COLUMNS} FROM ai_usage u
+         WHERE {where_clause} GROUP BY u.feature
+         ORDER BY cost DESC, calls DESC"
+    ))
+    .await?
+    .iter()
+    .map(|row| {
+        Ok(FeatureUsage {
+            feature: row.try_get("", "feature")?,
+            stats: read_stats(row)?,
+        })
+    })
+    .collect::<Result<Vec<_>, DbErr>>()?;
+
+    Ok(UsageSummary {
+        from: filter.from,
+        to: filter.to,
+        total,
+        by_day,
+        by_channel,
+        by_feature,
+    })
+}
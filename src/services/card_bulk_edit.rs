@@ -0,0 +1,475 @@
+//! 批量编辑
+//!
+//! 对一组角色卡执行同一个操作。`plan` 只计算变更（用于预览），`apply` 写入数据库；
+//! JSON 字段的写法与 `cards::update` 一致（V1 根级 / V2、V3 的 data 下）。
+
+use regex::{NoExpand, Regex, RegexBuilder};
+use sea_orm::{ActiveModelTrait, ConnectionTrait, DbErr, Set};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::card_fields::CardFields;
+use crate::entities::character_card;
+use crate::utils::token::calculate_card_tokens;
+
+/// 预览中变更前后各保留的字符数
+const EXCERPT_CONTEXT_CHARS: usize = 60;
+/// 评分上限（五星）
+pub const MAX_RATING: f64 = 5.0;
+
+/// 查找替换可选的字段，`lorebook` 表示世界书条目内容
+pub const REPLACE_FIELDS: [&str; 11] = [
+    "name",
+    "description",
+    "personality",
+    "scenario",
+    "first_mes",
+    "mes_example",
+    "alternate_greetings",
+    "creator_notes",
+    "system_prompt",
+    "post_history_instructions",
+    "lorebook",
+];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BulkOperation {
+    SetCreator {
+        value: String,
+    },
+    AddTags {
+        tags: Vec<String>,
+    },
+    RemoveTags {
+        tags: Vec<String>,
+    },
+    SetRating {
+        value: f64,
+    },
+    SetCoverBlur {
+        value: bool,
+    },
+    AppendCreatorNotes {
+        text: String,
+    },
+    Replace {
+        find: String,
+        #[serde(default)]
+        replace: String,
+        /// 按正则表达式匹配，替换文本中可用 `$1` 引用分组
+        #[serde(default)]
+        regex: bool,
+        #[serde(default)]
+        case_insensitive: bool,
+        fields: Vec<String>,
+    },
+}
+
+impl BulkOperation {
+    /// 简短描述，用于版本备注
+    pub fn describe(&self) -> String {
+        match self {
+            Self::SetCreator { value } => format!("设置创作者为 {}", value),
+            Self::AddTags { tags } => format!("添加标签 {}", tags.join(", ")),
+            Self::RemoveTags { tags } => format!("移除标签 {}", tags.join(", ")),
+            Self::SetRating { value } => format!("设置评分为 {}", value),
+            Self::SetCoverBlur { value } => {
+                format!("{}封面模糊", if *value { "开启" } else { "关闭" })
+            }
+            Self::AppendCreatorNotes { .. } => "追加创作者备注".to_string(),
+            Self::Replace { find, replace, .. } => format!("将「{}」替换为「{}」", find, replace),
+        }
+    }
+}
+
+/// 校验后的操作（正则已编译）
+pub struct PreparedOperation {
+    op: BulkOperation,
+    pattern: Option<Regex>,
+}
+
+impl PreparedOperation {
+    pub fn new(op: BulkOperation) -> Result<Self, String> {
+        let pattern = match &op {
+            BulkOperation::AddTags { tags } | BulkOperation::RemoveTags { tags } => {
+                if tags.iter().all(|t| t.trim().is_empty()) {
+                    return Err("没有提供标签".to_string());
+                }
+                None
+            }
+            BulkOperation::SetRating { value } if !(0.0..=MAX_RATING).contains(value) => {
+                return Err(format!("评分必须在 0 到 {} 之间", MAX_RATING));
+            }
+            BulkOperation::AppendCreatorNotes { text } if text.is_empty() => {
+                return Err("追加内容不能为空".to_string());
+            }
+            BulkOperation::Replace {
+                find,
+                regex,
+                case_insensitive,
+                fields,
+                ..
+            } => {
+                if find.is_empty() {
+                    return Err("查找内容不能为空".to_string());
+                }
+                if fields.is_empty() {
+                    return Err("没有选择要替换的字段".to_string());
+                }
+                if let Some(f) = fields
+                    .iter()
+                    .find(|f| !REPLACE_FIELDS.contains(&f.as_str()))
+                {
+                    return Err(format!("不支持的字段: {}", f));
+                }
+                let source = if *regex {
+                    find.clone()
+                } else {
+                    regex::escape(find)
+                };
+                let pattern = RegexBuilder::new(&source)
+                    .case_insensitive(*case_insensitive)
+                    .build()
+                    .map_err(|e| format!("正则表达式错误: {}", e))?;
+                Some(pattern)
+            }
+            _ => None,
+        };
+        Ok(Self { op, pattern })
+    }
+
+    pub fn operation(&self) -> &BulkOperation {
+        &self.op
+    }
+}
+
+/// 单个字段的变更（长文本只保留变更附近的片段）
+#[derive(Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: String,
+    pub after: String,
+}
+
+/// 单张卡片的变更
+pub struct CardEdit {
+    pub changes: Vec<FieldChange>,
+    /// 修改后的卡片 JSON（None 表示 JSON 未变）
+    data: Option<Value>,
+    tags: Option<Vec<String>>,
+    author: Option<String>,
+    rating: Option<f64>,
+    cover_blur: Option<bool>,
+}
+
+/// 计算操作对卡片的变更，无变化时返回 None
+pub fn plan(prepared: &PreparedOperation, card: &character_card::Model) -> Option<CardEdit> {
+    let mut json: Value = serde_json::from_str(&card.data).unwrap_or(Value::Null);
+    let mut edit = CardEdit {
+        changes: Vec::new(),
+        data: None,
+        tags: None,
+        author: None,
+        rating: None,
+        cover_blur: None,
+    };
+    let mut json_modified = false;
+
+    match &prepared.op {
+        BulkOperation::SetCreator { value } => {
+            let before = card.author.clone().unwrap_or_default();
+            if before == *value {
+                return None;
+            }
+            set_json(&mut json, "data.creator", Value::String(value.clone()));
+            json_modified = true;
+            edit.author = Some(value.clone());
+            edit.push("creator", &before, value);
+        }
+        BulkOperation::AddTags { tags } | BulkOperation::RemoveTags { tags } => {
+            let old: Vec<String> = serde_json::from_str(&card.tags).unwrap_or_default();
+            let wanted: Vec<&str> = tags
+                .iter()
+                .map(|t| t.trim())
+                .filter(|t| !t.is_empty())
+                .collect();
+            // 标签字典不区分大小写，与 `tag_admin` 一致按小写比较
+            let new: Vec<String> = if matches!(prepared.op, BulkOperation::AddTags { .. }) {
+                let mut new = old.clone();
+                for tag in wanted {
+                    let key = tag.to_lowercase();
+                    if !new.iter().any(|t| t.trim().to_lowercase() == key) {
+                        new.push(tag.to_string());
+                    }
+                }
+                new
+            } else {
+                let wanted: Vec<String> = wanted.iter().map(|t| t.to_lowercase()).collect();
+                old.iter()
+                    .filter(|t| !wanted.contains(&t.trim().to_lowercase()))
+                    .cloned()
+                    .collect()
+            };
+            if new == old {
+                return None;
+            }
+            set_json(&mut json, "tags", serde_json::json!(new));
+            set_json(&mut json, "data.tags", serde_json::json!(new));
+            json_modified = true;
+            edit.push("tags", &old.join(", "), &new.join(", "));
+            edit.tags = Some(new);
+        }
+        BulkOperation::SetRating { value } => {
+            if card.rating == *value {
+                return None;
+            }
+            edit.rating = Some(*value);
+            edit.push("rating", &card.rating.to_string(), &value.to_string());
+        }
+        BulkOperation::SetCoverBlur { value } => {
+            if card.cover_blur == *value {
+                return None;
+            }
+            edit.cover_blur = Some(*value);
+            edit.push(
+                "cover_blur",
+                &card.cover_blur.to_string(),
+                &value.to_string(),
+            );
+        }
+        BulkOperation::AppendCreatorNotes { text } => {
+            let before = ["data.creator_notes", "creatorcomment"]
+                .iter()
+                .find_map(|path| get_json_str(&json, path))
+                .unwrap_or_default();
+            let mut after = before.clone();
+            if !after.is_empty() && !after.ends_with('\n') {
+                after.push('\n');
+            }
+            after.push_str(text);
+            set_json(&mut json, "creatorcomment", Value::String(after.clone()));
+            set_json(
+                &mut json,
+                "data.creator_notes",
+                Value::String(after.clone()),
+            );
+            json_modified = true;
+            edit.push("creator_notes", &before, &after);
+        }
+        BulkOperation::Replace {
+            replace,
+            regex,
+            fields,
+            ..
+        } => {
+            let pattern = prepared.pattern.as_ref()?;
+            let mut replace_in = |label: String, value: &mut Value| {
+                let Some(text) = value.as_str() else {
+                    return;
+                };
+                let replaced = if *regex {
+                    pattern.replace_all(text, replace.as_str())
+                } else {
+                    pattern.replace_all(text, NoExpand(replace))
+                };
+                if replaced != text {
+                    let replaced = replaced.into_owned();
+                    edit.push(&label, text, &replaced);
+                    *value = Value::String(replaced);
+                    json_modified = true;
+                }
+            };
+
+            for field in fields {
+                // V1 在根级，V2/V3 在 data 下，两处都存在时一并修改
+                for base in ["", "data"] {
+                    let Some(obj) = json_at_mut(&mut json, base) else {
+                        continue;
+                    };
+                    match field.as_str() {
+                        "alternate_greetings" => {
+                            if let Some(Value::Array(items)) = obj.get_mut(field) {
+                                for (i, item) in items.iter_mut().enumerate() {
+                                    replace_in(format!("alternate_greetings[{}]", i), item);
+                                }
+                            }
+                        }
+                        "lorebook" => {
+                            let entries = obj
+                                .get_mut("character_book")
+                                .and_then(|b| b.get_mut("entries"))
+                                .and_then(|e| e.as_array_mut());
+                            for (i, entry) in entries.into_iter().flatten().enumerate() {
+                                if let Some(content) = entry.get_mut("content") {
+                                    replace_in(
+                                        format!("character_book.entries[{}].content", i),
+                                        content,
+                                    );
+                                }
+                            }
+                        }
+                        // V1 的创作者备注字段名不同
+                        "creator_notes" if base.is_empty() => {
+                            if let Some(value) = obj.get_mut("creatorcomment") {
+                                replace_in(field.clone(), value);
+                            }
+                        }
+                        _ => {
+                            if let Some(value) = obj.get_mut(field) {
+                                replace_in(field.clone(), value);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if edit.changes.is_empty() {
+        return None;
+    }
+    if json_modified {
+        edit.data = Some(json);
+    }
+    Some(edit)
+}
+
+/// `apply` 可能修改的列，用于操作日志
+pub const JOURNAL_COLUMNS: [&str; 12] = [
+    "data",
+    "tags",
+    "author",
+    "rating",
+    "cover_blur",
+    "name",
+    "description",
+    "token_count_total",
+    "token_count_spec",
+    "token_count_wb",
+    "token_count_other",
+    "metadata_modified",
+];
+
+/// 写入变更；调用方负责事先保存版本快照与操作日志
+pub async fn apply<C: ConnectionTrait>(
+    db: &C,
+    card: character_card::Model,
+    edit: CardEdit,
+) -> Result<character_card::Model, DbErr> {
+    let mut active: character_card::ActiveModel = card.into();
+
+    if let Some(json) = &edit.data {
+        let fields = CardFields::extract(json);
+        if let Some(name) = fields.name {
+            active.name = Set(name);
+        }
+        active.description = Set(fields.description);
+        active.data = Set(serde_json::to_string_pretty(json).unwrap_or_default());
+        active.metadata_modified = Set(true);
+
+        let counts = calculate_card_tokens(json);
+        active.token_count_total = Set(Some(counts.total));
+        active.token_count_spec = Set(Some(counts.spec));
+        active.token_count_wb = Set(Some(counts.wb));
+        active.token_count_other = Set(Some(counts.other));
+    }
+    if let Some(tags) = edit.tags {
+        active.tags = Set(serde_json::to_string_pretty(&tags).unwrap_or_else(|_| "[]".to_string()));
+    }
+    if let Some(author) = edit.author {
+        active.author = Set(Some(author));
+    }
+    if let Some(rating) = edit.rating {
+        active.rating = Set(rating);
+    }
+    if let Some(cover_blur) = edit.cover_blur {
+        active.cover_blur = Set(cover_blur);
+    }
+    active.updated_at = Set(chrono::Utc::now().naive_utc());
+
+    active.update(db).await
+}
+
+impl CardEdit {
+    /// 记录变更；V2 卡片根级与 data 下镜像的相同变更只保留一条
+    fn push(&mut self, field: &str, before: &str, after: &str) {
+        let (before, after) = excerpt(before, after);
+        if self
+            .changes
+            .iter()
+            .any(|c| c.field == field && c.before == before && c.after == after)
+        {
+            return;
+        }
+        self.changes.push(FieldChange {
+            field: field.to_string(),
+            before,
+            after,
+        });
+    }
+}
+
+/// 截取变更附近的片段
+fn excerpt(before: &str, after: &str) -> (String, String) {
+    let a: Vec<char> = before.chars().collect();
+    let b: Vec<char> = after.chars().collect();
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let cut = |chars: &[char]| {
+        let start = prefix.saturating_sub(EXCERPT_CONTEXT_CHARS);
+        let end = (chars.len() - suffix + EXCERPT_CONTEXT_CHARS).min(chars.len());
+        let mut s = String::new();
+        if start > 0 {
+            s.push('…');
+        }
+        s.extend(&chars[start..end]);
+        if end < chars.len() {
+            s.push('…');
+        }
+        s
+    };
+    (cut(&a), cut(&b))
+}
+
+/// `path` 为 "" 时取根节点，否则取根下的对象
+fn json_at_mut<'a>(
+    json: &'a mut Value,
+    path: &str,
+) -> Option<&'a mut serde_json::Map<String, Value>> {
+    if path.is_empty() {
+        json.as_object_mut()
+    } else {
+        json.get_mut(path)?.as_object_mut()
+    }
+}
+
+fn get_json_str(json: &Value, path: &str) -> Option<String> {
+    let value = match path.strip_prefix("data.") {
+        Some(key) => json.get("data")?.get(key)?,
+        None => json.get(path)?,
+    };
+    value.as_str().map(|s| s.to_string())
+}
+
+/// 与 `cards::update` 相同：`data.key` 写入 data 对象（不存在时忽略），其余写入根级
+fn set_json(json: &mut Value, path: &str, value: Value) {
+    let (base, key) = match path.strip_prefix("data.") {
+        Some(key) => ("data", key),
+        None => ("", path),
+    };
+    if let Some(obj) = json_at_mut(json, base) {
+        obj.insert(key.to_string(), value);
+    }
+}
+
+/// 版本备注
+pub fn version_note(op: &BulkOperation, batch_id: Uuid) -> String {
+    format!("批量编辑前：{}（批次 {}）", op.describe(), batch_id)
+}
@@ -0,0 +1,419 @@
+//! 角色卡版本差异
+//!
+//! 两侧先规范化为 chara_card_v3，再按字段比较：正文字段、每条备用开场白、
+//! 按 uid / comment 对齐的世界书条目、按 id / scriptName 对齐的正则脚本。
+//! 长文本给出行级差异块，每个字段附带 token 变化量。
+
+use serde::Serialize;
+use serde_json::Value;
+
+use super::card_spec;
+use crate::utils::token::count_tokens;
+
+/// 按字段比较的正文字段
+pub const TEXT_FIELDS: [&str; 11] = [
+    "name",
+    "description",
+    "personality",
+    "scenario",
+    "first_mes",
+    "mes_example",
+    "creator_notes",
+    "system_prompt",
+    "post_history_instructions",
+    "creator",
+    "character_version",
+];
+
+/// 超过该长度或包含换行的文本输出行级差异，而不是完整的新旧值
+const LONG_TEXT_CHARS: usize = 120;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+#[derive(Serialize)]
+pub struct HunkLine {
+    /// context | delete | insert
+    pub op: &'static str,
+    pub text: String,
+}
+
+/// 行级差异块（行号从 1 开始）
+#[derive(Serialize)]
+pub struct TextHunk {
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    pub lines: Vec<HunkLine>,
+}
+
+#[derive(Serialize)]
+pub struct FieldDiff {
+    /// 字段路径，例如 `description`、`alternate_greetings[1]`、`character_book.entries[uid=3].content`
+    pub path: String,
+    /// 便于展示的名称（世界书条目 comment、正则脚本名等）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub change: ChangeKind,
+    /// 短字段给出完整的新旧值
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<Value>,
+    /// 长文本给出行级差异
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hunks: Option<Vec<TextHunk>>,
+    pub token_delta: i32,
+}
+
+/// 比较两张卡片
+pub fn diff_cards(old: &Value, new: &Value) -> Vec<FieldDiff> {
+    let old = card_spec::to_v3(old);
+    let new = card_spec::to_v3(new);
+    let old_data = &old["data"];
+    let new_data = &new["data"];
+
+    let mut diffs = Vec::new();
+
+    for key in TEXT_FIELDS {
+        diff_value(
+            &mut diffs,
+            key.to_string(),
+            None,
+            old_data.get(key),
+            new_data.get(key),
+        );
+    }
+    diff_value(
+        &mut diffs,
+        "tags".to_string(),
+        None,
+        old_data.get("tags"),
+        new_data.get("tags"),
+    );
+
+    // 备用开场白逐条比较
+    let empty = Vec::new();
+    let old_greetings = array_of(old_data.get("alternate_greetings"), &empty);
+    let new_greetings = array_of(new_data.get("alternate_greetings"), &empty);
+    for i in 0..old_greetings.len().max(new_greetings.len()) {
+        diff_value(
+            &mut diffs,
+            format!("alternate_greetings[{}]", i),
+            None,
+            old_greetings.get(i),
+            new_greetings.get(i),
+        );
+    }
+
+    // 世界书条目
+    let old_entries = array_of(
+        old_data
+            .get("character_book")
+            .and_then(|b| b.get("entries")),
+        &empty,
+    );
+    let new_entries = array_of(
+        new_data
+            .get("character_book")
+            .and_then(|b| b.get("entries")),
+        &empty,
+    );
+    for pair in match_items(old_entries, new_entries, entry_id, entry_comment) {
+        let label = pair
+            .old
+            .or(pair.new)
+            .and_then(entry_comment)
+            .or_else(|| pair.old.or(pair.new).and_then(first_key));
+        diff_object(
+            &mut diffs,
+            format!("character_book.entries[{}]", pair.key),
+            label,
+            pair.old,
+            pair.new,
+        );
+    }
+
+    // 正则脚本
+    let ext_scripts = |data: &Value| {
+        data.get("extensions")
+            .and_then(|e| e.get("regex_scripts"))
+            .cloned()
+    };
+    let old_scripts_value = ext_scripts(old_data);
+    let new_scripts_value = ext_scripts(new_data);
+    let old_scripts = array_of(old_scripts_value.as_ref(), &empty);
+    let new_scripts = array_of(new_scripts_value.as_ref(), &empty);
+    for pair in match_items(old_scripts, new_scripts, script_id, script_name) {
+        let label = pair.old.or(pair.new).and_then(script_name);
+        diff_object(
+            &mut diffs,
+            format!("extensions.regex_scripts[{}]", pair.key),
+            label,
+            pair.old,
+            pair.new,
+        );
+    }
+
+    diffs
+}
+
+/// 对齐后的一对条目
+pub struct MatchedPair<'a> {
+    /// 用于路径展示的标识，例如 `uid=3`、`comment=xxx`、`#2`
+    pub key: String,
+    pub old: Option<&'a Value>,
+    pub new: Option<&'a Value>,
+}
+
+/// 按 id 对齐，剩余的按名称对齐，仍未对齐的视为新增/删除
+pub fn match_items<'a>(
+    old: &'a [Value],
+    new: &'a [Value],
+    id_of: fn(&Value) -> Option<String>,
+    name_of: fn(&Value) -> Option<String>,
+) -> Vec<MatchedPair<'a>> {
+    let mut pairs = Vec::new();
+    let mut new_used = vec![false; new.len()];
+    let mut old_unmatched = Vec::new();
+
+    for (i, o) in old.iter().enumerate() {
+        let found = id_of(o).and_then(|id| {
+            new.iter()
+                .enumerate()
+                .position(|(j, n)| !new_used[j] && id_of(n).as_deref() == Some(id.as_str()))
+                .map(|j| (j, id))
+        });
+        match found {
+            Some((j, id)) => {
+                new_used[j] = true;
+                pairs.push(MatchedPair {
+                    key: id,
+                    old: Some(o),
+                    new: Some(&new[j]),
+                });
+            }
+            None => old_unmatched.push(i),
+        }
+    }
+
+    for i in old_unmatched {
+        let o = &old[i];
+        let found = name_of(o).filter(|n| !n.is_empty()).and_then(|name| {
+            new.iter()
+                .enumerate()
+                .position(|(j, n)| !new_used[j] && name_of(n).as_deref() == Some(name.as_str()))
+        });
+        let key = id_of(o)
+            .or_else(|| name_of(o).map(|n| format!("comment={}", n)))
+            .unwrap_or_else(|| format!("#{}", i));
+        match found {
+            Some(j) => {
+                new_used[j] = true;
+                pairs.push(MatchedPair {
+                    key,
+                    old: Some(o),
+                    new: Some(&new[j]),
+                });
+            }
+            None => pairs.push(MatchedPair {
+                key,
+                old: Some(o),
+                new: None,
+            }),
+        }
+    }
+
+    for (j, n) in new.iter().enumerate() {
+        if new_used[j] {
+            continue;
+        }
+        let key = id_of(n)
+            .or_else(|| name_of(n).map(|c| format!("comment={}", c)))
+            .unwrap_or_else(|| format!("#{}", j));
+        pairs.push(MatchedPair {
+            key,
+            old: None,
+            new: Some(n),
+        });
+    }
+
+    pairs
+}
+
+/// 世界书条目标识：uid（SillyTavern 世界书）优先，其次 id（V2 规范）
+pub fn entry_id(entry: &Value) -> Option<String> {
+    for key in ["uid", "id"] {
+        match entry.get(key) {
+            Some(Value::Number(n)) => return Some(format!("{}={}", key, n)),
+            Some(Value::String(s)) if !s.is_empty() => return Some(format!("{}={}", key, s)),
+            _ => {}
+        }
+    }
+    None
+}
+
+pub fn entry_comment(entry: &Value) -> Option<String> {
+    entry
+        .get("comment")
+        .and_then(|c| c.as_str())
+        .filter(|c| !c.is_empty())
+        .map(|c| c.to_string())
+}
+
+fn first_key(entry: &Value) -> Option<String> {
+    entry
+        .get("keys")
+        .and_then(|k| k.as_array())
+        .and_then(|k| k.first())
+        .and_then(|k| k.as_str())
+        .map(|k| k.to_string())
+}
+
+pub fn script_id(script: &Value) -> Option<String> {
+    script
+        .get("id")
+        .and_then(|i| i.as_str())
+        .filter(|i| !i.is_empty())
+        .map(|i| format!("id={}", i))
+}
+
+pub fn script_name(script: &Value) -> Option<String> {
+    script
+        .get("scriptName")
+        .and_then(|n| n.as_str())
+        .filter(|n| !n.is_empty())
+        .map(|n| n.to_string())
+}
+
+fn array_of<'a>(value: Option<&'a Value>, empty: &'a Vec<Value>) -> &'a Vec<Value> {
+    value.and_then(|v| v.as_array()).unwrap_or(empty)
+}
+
+/// 对象整体新增/删除时输出一条记录，修改时逐个子字段比较
+fn diff_object(
+    diffs: &mut Vec<FieldDiff>,
+    path: String,
+    label: Option<String>,
+    old: Option<&Value>,
+    new: Option<&Value>,
+) {
+    match (old, new) {
+        (Some(Value::Object(o)), Some(Value::Object(n))) => {
+            let keys = o
+                .keys()
+                .chain(n.keys().filter(|k| !o.contains_key(*k)))
+                .cloned()
+                .collect::<Vec<_>>();
+            for key in keys {
+                diff_value(
+                    diffs,
+                    format!("{}.{}", path, key),
+                    label.clone(),
+                    o.get(&key),
+                    n.get(&key),
+                );
+            }
+        }
+        _ => diff_value(diffs, path, label, old, new),
+    }
+}
+
+fn diff_value(
+    diffs: &mut Vec<FieldDiff>,
+    path: String,
+    label: Option<String>,
+    old: Option<&Value>,
+    new: Option<&Value>,
+) {
+    let old = old.filter(|v| !v.is_null());
+    let new = new.filter(|v| !v.is_null());
+    if old == new {
+        return;
+    }
+
+    let change = match (old, new) {
+        (None, Some(_)) => ChangeKind::Added,
+        (Some(_), None) => ChangeKind::Removed,
+        _ => ChangeKind::Modified,
+    };
+    let old_text = old.map(value_text).unwrap_or_default();
+    let new_text = new.map(value_text).unwrap_or_default();
+    let token_delta = count_tokens(&new_text) - count_tokens(&old_text);
+
+    let is_long = |v: Option<&Value>| {
+        v.and_then(|v| v.as_str())
+            .is_some_and(|s| s.contains('\n') || s.chars().count() > LONG_TEXT_CHARS)
+    };
+
+    if change == ChangeKind::Modified && (is_long(old) || is_long(new)) {
+        diffs.push(FieldDiff {
+            path,
+            label,
+            change,
+            old: None,
+            new: None,
+            hunks: Some(text_hunks(&old_text, &new_text)),
+            token_delta,
+        });
+    } else {
+        diffs.push(FieldDiff {
+            path,
+            label,
+            change,
+            old: old.cloned(),
+            new: new.cloned(),
+            hunks: None,
+            token_delta,
+        });
+    }
+}
+
+/// 值的文本形式，用于 token 计数与行级差异
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(arr) if arr.iter().all(|v| v.is_string()) => arr
+            .iter()
+            .filter_map(|v| v.as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        other => serde_json::to_string_pretty(other).unwrap_or_default(),
+    }
+}
+
+/// 行级差异
+pub fn text_hunks(old: &str, new: &str) -> Vec<TextHunk> {
+    let patch = diffy::create_patch(old, new);
+    patch
+        .hunks()
+        .iter()
+        .map(|hunk| TextHunk {
+            old_start: hunk.old_range().start(),
+            old_lines: hunk.old_range().len(),
+            new_start: hunk.new_range().start(),
+            new_lines: hunk.new_range().len(),
+            lines: hunk
+                .lines()
+                .iter()
+                .map(|line| {
+                    let (op, text) = match line {
+                        diffy::Line::Context(t) => ("context", t),
+                        diffy::Line::Delete(t) => ("delete", t),
+                        diffy::Line::Insert(t) => ("insert", t),
+                    };
+                    HunkLine {
+                        op,
+                        text: text.trim_end_matches('\n').to_string(),
+                    }
+                })
+                .collect(),
+        })
+        .collect()
+}
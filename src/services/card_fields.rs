@@ -0,0 +1,62 @@
+//! 角色卡冗余字段提取
+//!
+//! `character_cards` 表中的 name / description / author / tags 等列是从卡片 JSON 冗余出来的，
+//! 导入、版本恢复等写入完整 JSON 的场景统一从这里提取，保证口径一致。
+
+use serde_json::Value;
+
+/// 从卡片 JSON 提取的冗余字段
+pub struct CardFields {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// 创作者：creator，缺失时退回 creator_notes
+    pub author: Option<String>,
+    pub spec: Option<String>,
+    pub spec_version: Option<String>,
+    /// JSON 数组格式的标签
+    pub tags_json: String,
+}
+
+/// V2/V3 取 `data`，V1 取根节点
+pub fn card_data(json: &Value) -> &Value {
+    match json.get("data") {
+        Some(d) if d.is_object() => d,
+        _ => json,
+    }
+}
+
+impl CardFields {
+    pub fn extract(json: &Value) -> Self {
+        let card_data = card_data(json);
+        let get_str =
+            |v: &Value, key: &str| v.get(key).and_then(|v| v.as_str()).map(|s| s.to_string());
+
+        Self {
+            name: get_str(card_data, "name"),
+            description: get_str(card_data, "description"),
+            author: get_str(card_data, "creator").or_else(|| get_str(card_data, "creator_notes")),
+            spec: get_str(json, "spec"),
+            spec_version: get_str(json, "spec_version"),
+            tags_json: extract_tags_json(card_data),
+        }
+    }
+}
+
+/// 提取 tags（可能是数组或逗号分隔的字符串）
+fn extract_tags_json(card_data: &Value) -> String {
+    let Some(tags_value) = card_data.get("tags") else {
+        return "[]".to_string();
+    };
+    if tags_value.is_array() {
+        serde_json::to_string_pretty(tags_value).unwrap_or_else(|_| "[]".to_string())
+    } else if let Some(tags_str) = tags_value.as_str() {
+        let tags: Vec<&str> = tags_str
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .collect();
+        serde_json::to_string_pretty(&tags).unwrap_or_else(|_| "[]".to_string())
+    } else {
+        "[]".to_string()
+    }
+}
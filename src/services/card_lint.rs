@@ -0,0 +1,492 @@
+//! 角色卡结构检查
+//!
+//! 按 V2/V3 规范检查角色卡 JSON，输出带严重级别与 JSON 路径的问题列表，
+//! 用于在导出到 SillyTavern 之前发现无法加载或行为异常的卡片。
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// 问题严重级别
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// 单条检查结果
+#[derive(Debug, Clone, Serialize)]
+pub struct LintFinding {
+    pub severity: Severity,
+    /// 规则标识，例如 `missing-field`
+    pub code: &'static str,
+    /// JSON 路径，例如 `$.data.character_book.entries[3].keys`
+    pub path: String,
+    pub message: String,
+}
+
+/// 根字段与 data 字段的对应关系（root, data）
+const MIRRORED_FIELDS: [(&str, &str); 8] = [
+    ("name", "name"),
+    ("description", "description"),
+    ("personality", "personality"),
+    ("scenario", "scenario"),
+    ("first_mes", "first_mes"),
+    ("mes_example", "mes_example"),
+    ("creatorcomment", "creator_notes"),
+    ("tags", "tags"),
+];
+
+/// data 中应为字符串的字段
+const STRING_FIELDS: [&str; 11] = [
+    "name",
+    "description",
+    "personality",
+    "scenario",
+    "first_mes",
+    "mes_example",
+    "creator_notes",
+    "system_prompt",
+    "post_history_instructions",
+    "creator",
+    "character_version",
+];
+
+struct Linter {
+    findings: Vec<LintFinding>,
+}
+
+impl Linter {
+    fn push(&mut self, severity: Severity, code: &'static str, path: String, message: String) {
+        self.findings.push(LintFinding {
+            severity,
+            code,
+            path,
+            message,
+        });
+    }
+}
+
+/// 检查角色卡 JSON
+pub fn lint_card(card: &Value) -> Vec<LintFinding> {
+    let mut linter = Linter {
+        findings: Vec::new(),
+    };
+
+    let Some(root) = card.as_object() else {
+        linter.push(
+            Severity::Error,
+            "invalid-root",
+            "$".to_string(),
+            "角色卡根节点必须是 JSON 对象".to_string(),
+        );
+        return linter.findings;
+    };
+
+    // 有 data 时按 V2/V3 检查 data，否则按 V1 平铺结构检查根节点
+    let (data, data_path) = match root.get("data") {
+        Some(Value::Object(d)) => (d, "$.data"),
+        Some(_) => {
+            linter.push(
+                Severity::Error,
+                "invalid-type",
+                "$.data".to_string(),
+                "data 必须是对象".to_string(),
+            );
+            (root, "$")
+        }
+        None => (root, "$"),
+    };
+
+    lint_spec(&mut linter, card);
+    lint_required(&mut linter, data, data_path);
+    lint_types(&mut linter, data, data_path);
+    if let Some(book) = data.get("character_book") {
+        lint_character_book(&mut linter, book, &format!("{}.character_book", data_path));
+    }
+    if let Some(scripts) = data.get("extensions").and_then(|e| e.get("regex_scripts")) {
+        lint_regex_scripts(
+            &mut linter,
+            scripts,
+            &format!("{}.extensions.regex_scripts", data_path),
+        );
+    }
+    lint_macros(&mut linter, data, data_path);
+    if data_path == "$.data" {
+        lint_mirrored(&mut linter, root, data);
+    }
+
+    // 严重的排在前面
+    linter
+        .findings
+        .sort_by_key(|f| std::cmp::Reverse(f.severity));
+    linter.findings
+}
+
+fn lint_spec(linter: &mut Linter, card: &Value) {
+    let spec = card.get("spec").and_then(|s| s.as_str());
+    let has_data = card.get("data").is_some_and(|d| d.is_object());
+    match spec {
+        Some("chara_card_v2") | Some("chara_card_v3") => {
+            if !has_data {
+                linter.push(
+                    Severity::Error,
+                    "missing-field",
+                    "$.data".to_string(),
+                    format!("{} 规范要求 data 对象", spec.unwrap_or_default()),
+                );
+            }
+            if card.get("spec_version").and_then(|v| v.as_str()).is_none() {
+                linter.push(
+                    Severity::Warning,
+                    "missing-field",
+                    "$.spec_version".to_string(),
+                    "缺少 spec_version".to_string(),
+                );
+            }
+        }
+        Some(other) => linter.push(
+            Severity::Warning,
+            "unknown-spec",
+            "$.spec".to_string(),
+            format!("未知的规范标识: {}", other),
+        ),
+        None if has_data => linter.push(
+            Severity::Warning,
+            "missing-field",
+            "$.spec".to_string(),
+            "存在 data 对象但缺少 spec 标识".to_string(),
+        ),
+        None => {}
+    }
+}
+
+fn lint_required(linter: &mut Linter, data: &serde_json::Map<String, Value>, path: &str) {
+    for key in ["name", "first_mes"] {
+        let empty = match data.get(key) {
+            Some(Value::String(s)) => s.trim().is_empty(),
+            Some(_) => false, // 类型错误由 lint_types 报告
+            None => true,
+        };
+        if empty {
+            let severity = if key == "name" {
+                Severity::Error
+            } else {
+                Severity::Warning
+            };
+            linter.push(
+                severity,
+                "missing-field",
+                format!("{}.{}", path, key),
+                format!("缺少 {} 或内容为空", key),
+            );
+        }
+    }
+}
+
+fn lint_types(linter: &mut Linter, data: &serde_json::Map<String, Value>, path: &str) {
+    for key in STRING_FIELDS {
+        if let Some(v) = data.get(key) {
+            if !v.is_string() && !v.is_null() {
+                linter.push(
+                    Severity::Error,
+                    "invalid-type",
+                    format!("{}.{}", path, key),
+                    format!("{} 应为字符串", key),
+                );
+            }
+        }
+    }
+
+    match data.get("tags") {
+        Some(Value::Array(tags)) => {
+            for (i, tag) in tags.iter().enumerate() {
+                if !tag.is_string() {
+                    linter.push(
+                        Severity::Warning,
+                        "invalid-type",
+                        format!("{}.tags[{}]", path, i),
+                        "标签应为字符串".to_string(),
+                    );
+                }
+            }
+        }
+        Some(Value::String(_)) => linter.push(
+            Severity::Error,
+            "invalid-type",
+            format!("{}.tags", path),
+            "tags 应为数组，而不是字符串".to_string(),
+        ),
+        Some(Value::Null) | None => {}
+        Some(_) => linter.push(
+            Severity::Error,
+            "invalid-type",
+            format!("{}.tags", path),
+            "tags 应为数组".to_string(),
+        ),
+    }
+
+    if let Some(greetings) = data.get("alternate_greetings") {
+        match greetings.as_array() {
+            Some(list) => {
+                for (i, g) in list.iter().enumerate() {
+                    if !g.is_string() {
+                        linter.push(
+                            Severity::Error,
+                            "invalid-type",
+                            format!("{}.alternate_greetings[{}]", path, i),
+                            "备用开场白应为字符串".to_string(),
+                        );
+                    }
+                }
+            }
+            None => linter.push(
+                Severity::Error,
+                "invalid-type",
+                format!("{}.alternate_greetings", path),
+                "alternate_greetings 应为数组".to_string(),
+            ),
+        }
+    }
+
+    if let Some(ext) = data.get("extensions") {
+        if !ext.is_object() {
+            linter.push(
+                Severity::Error,
+                "invalid-type",
+                format!("{}.extensions", path),
+                "extensions 应为对象".to_string(),
+            );
+        }
+    }
+}
+
+fn lint_character_book(linter: &mut Linter, book: &Value, path: &str) {
+    if book.is_null() {
+        return;
+    }
+    let Some(book) = book.as_object() else {
+        linter.push(
+            Severity::Error,
+            "invalid-type",
+            path.to_string(),
+            "character_book 应为对象".to_string(),
+        );
+        return;
+    };
+
+    let entries = match book.get("entries") {
+        Some(Value::Array(entries)) => entries,
+        _ => {
+            linter.push(
+                Severity::Error,
+                "missing-field",
+                format!("{}.entries", path),
+                "世界书缺少 entries 数组".to_string(),
+            );
+            return;
+        }
+    };
+
+    for (i, entry) in entries.iter().enumerate() {
+        let entry_path = format!("{}.entries[{}]", path, i);
+        let Some(obj) = entry.as_object() else {
+            linter.push(
+                Severity::Error,
+                "invalid-type",
+                entry_path,
+                "世界书条目应为对象".to_string(),
+            );
+            continue;
+        };
+
+        let constant = obj
+            .get("constant")
+            .and_then(|c| c.as_bool())
+            .unwrap_or(false);
+        match obj.get("keys") {
+            Some(Value::Array(keys)) => {
+                let has_key = keys
+                    .iter()
+                    .any(|k| k.as_str().is_some_and(|s| !s.trim().is_empty()));
+                if !has_key && !constant {
+                    linter.push(
+                        Severity::Warning,
+                        "empty-keys",
+                        format!("{}.keys", entry_path),
+                        "非常驻条目没有任何触发关键词，永远不会被激活".to_string(),
+                    );
+                }
+            }
+            Some(_) => linter.push(
+                Severity::Error,
+                "invalid-type",
+                format!("{}.keys", entry_path),
+                "keys 应为字符串数组".to_string(),
+            ),
+            None => linter.push(
+                Severity::Error,
+                "missing-field",
+                format!("{}.keys", entry_path),
+                "世界书条目缺少 keys".to_string(),
+            ),
+        }
+
+        match obj.get("content") {
+            Some(Value::String(s)) if s.trim().is_empty() => linter.push(
+                Severity::Warning,
+                "empty-content",
+                format!("{}.content", entry_path),
+                "世界书条目内容为空".to_string(),
+            ),
+            Some(Value::String(_)) => {}
+            Some(_) => linter.push(
+                Severity::Error,
+                "invalid-type",
+                format!("{}.content", entry_path),
+                "content 应为字符串".to_string(),
+            ),
+            None => linter.push(
+                Severity::Error,
+                "missing-field",
+                format!("{}.content", entry_path),
+                "世界书条目缺少 content".to_string(),
+            ),
+        }
+
+        if obj.get("use_regex").and_then(|u| u.as_bool()) == Some(true) {
+            if let Some(Value::Array(keys)) = obj.get("keys") {
+                for (k, key) in keys.iter().enumerate() {
+                    if let Some(pattern) = key.as_str() {
+                        check_regex(linter, pattern, format!("{}.keys[{}]", entry_path, k));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn lint_regex_scripts(linter: &mut Linter, scripts: &Value, path: &str) {
+    let Some(scripts) = scripts.as_array() else {
+        linter.push(
+            Severity::Error,
+            "invalid-type",
+            path.to_string(),
+            "regex_scripts 应为数组".to_string(),
+        );
+        return;
+    };
+
+    for (i, script) in scripts.iter().enumerate() {
+        let script_path = format!("{}[{}]", path, i);
+        match script.get("findRegex") {
+            Some(Value::String(pattern)) if pattern.is_empty() => linter.push(
+                Severity::Warning,
+                "empty-regex",
+                format!("{}.findRegex", script_path),
+                "正则脚本的 findRegex 为空".to_string(),
+            ),
+            Some(Value::String(pattern)) => {
+                check_regex(linter, pattern, format!("{}.findRegex", script_path))
+            }
+            _ => linter.push(
+                Severity::Error,
+                "missing-field",
+                format!("{}.findRegex", script_path),
+                "正则脚本缺少 findRegex".to_string(),
+            ),
+        }
+    }
+}
+
+/// 校验 JS 风格正则（`/pattern/flags` 或裸 pattern）
+///
+/// 服务端使用 Rust regex 引擎，不支持环视与反向引用，这类语法只给出提示而不报错。
+fn check_regex(linter: &mut Linter, raw: &str, path: String) {
+    let (pattern, flags) = split_js_regex(raw);
+
+    let mut builder = regex::RegexBuilder::new(pattern);
+    builder
+        .case_insensitive(flags.contains('i'))
+        .multi_line(flags.contains('m'))
+        .dot_matches_new_line(flags.contains('s'));
+
+    if let Err(e) = builder.build() {
+        let message = e.to_string();
+        if message.contains("look-around") || message.contains("backreferences") {
+            linter.push(
+                Severity::Info,
+                "unchecked-regex",
+                path,
+                "正则使用了环视或反向引用，服务端无法校验".to_string(),
+            );
+        } else {
+            let first_line = message
+                .lines()
+                .rfind(|l| l.starts_with("error:"))
+                .unwrap_or("语法错误")
+                .to_string();
+            linter.push(
+                Severity::Error,
+                "invalid-regex",
+                path,
+                format!("无效的正则表达式: {}", first_line),
+            );
+        }
+    }
+}
+
+fn split_js_regex(raw: &str) -> (&str, &str) {
+    if let Some(rest) = raw.strip_prefix('/') {
+        if let Some(end) = rest.rfind('/') {
+            let flags = &rest[end + 1..];
+            if flags.chars().all(|c| c.is_ascii_alphabetic()) {
+                return (&rest[..end], flags);
+            }
+        }
+    }
+    (raw, "")
+}
+
+/// `description` 描述的是角色本身，出现 `{{user}}` 却没有 `{{char}}` 时多半是写反了
+fn lint_macros(linter: &mut Linter, data: &serde_json::Map<String, Value>, path: &str) {
+    for key in ["description", "personality"] {
+        let Some(text) = data.get(key).and_then(|v| v.as_str()) else {
+            continue;
+        };
+        let lower = text.to_lowercase();
+        if lower.contains("{{user}}") && !lower.contains("{{char}}") {
+            linter.push(
+                Severity::Warning,
+                "suspicious-macro",
+                format!("{}.{}", path, key),
+                format!(
+                    "{} 中使用了 {{{{user}}}} 但没有 {{{{char}}}}，请确认是否应为 {{{{char}}}}",
+                    key
+                ),
+            );
+        }
+    }
+}
+
+/// 混合结构中根字段与 data 字段不一致时，不同前端会读到不同内容
+fn lint_mirrored(
+    linter: &mut Linter,
+    root: &serde_json::Map<String, Value>,
+    data: &serde_json::Map<String, Value>,
+) {
+    for (root_key, data_key) in MIRRORED_FIELDS {
+        let (Some(root_value), Some(data_value)) = (root.get(root_key), data.get(data_key)) else {
+            continue;
+        };
+        if root_value != data_value {
+            linter.push(
+                Severity::Warning,
+                "mirror-mismatch",
+                format!("$.{}", root_key),
+                format!("根字段 {} 与 data.{} 不一致", root_key, data_key),
+            );
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! 导入时的同卡匹配
+//!
+//! 以「角色名 + 创作者」判定是否为同一张卡的不同版本，可选再用正文相似度过滤，
+//! 避免同名但内容完全不同的卡被误合并。
+
+use serde_json::Value;
+use std::collections::HashSet;
+
+use super::card_fields::card_data;
+
+/// 参与相似度计算的正文字段
+const CONTENT_FIELDS: [&str; 5] = [
+    "description",
+    "personality",
+    "scenario",
+    "first_mes",
+    "mes_example",
+];
+
+/// 名称 / 创作者的比较口径，与 SQLite 的 `lower(trim(x))` 一致，以便在 SQL 中按索引查找：
+/// 去掉首尾空格，只忽略 ASCII 字母的大小写，不做其他 Unicode 规范化
+pub fn normalize_key(s: &str) -> String {
+    s.trim_matches(' ').to_ascii_lowercase()
+}
+
+/// 两张卡正文的相似度 (0.0 ~ 1.0)
+///
+/// 使用字符二元组的 Dice 系数，对中英文都适用，且对局部改写不敏感。
+pub fn content_similarity(a: &Value, b: &Value) -> f64 {
+    let a = bigrams(&content_text(a));
+    let b = bigrams(&content_text(b));
+
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    let common = a.intersection(&b).count();
+    (2 * common) as f64 / (a.len() + b.len()) as f64
+}
+
+fn content_text(card: &Value) -> String {
+    let data = card_data(card);
+    CONTENT_FIELDS
+        .iter()
+        .filter_map(|key| data.get(*key).and_then(|v| v.as_str()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn bigrams(text: &str) -> HashSet<(char, char)> {
+    let chars: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    chars.windows(2).map(|w| (w[0], w[1])).collect()
+}
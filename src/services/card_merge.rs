@@ -0,0 +1,314 @@
+//! 角色卡三方合并
+//!
+//! 以 base 为共同祖先合并 ours / theirs：字段、世界书条目与正则脚本分别三方比较，
+//! 只有一方修改时直接采用，双方都改了同一段文本时尝试按行合并，仍冲突的交由用户决定。
+//! 冲突路径与版本差异中的字段路径一致，便于前端对照展示。
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+use super::card_diff::{entry_comment, entry_id, script_id, script_name};
+use super::card_spec;
+
+/// 单个冲突
+#[derive(Serialize)]
+pub struct MergeConflict {
+    pub path: String,
+    pub base: Option<Value>,
+    pub ours: Option<Value>,
+    pub theirs: Option<Value>,
+}
+
+pub struct MergeResult {
+    /// 合并后的卡片；未解决的冲突暂取 ours 的值
+    pub card: Value,
+    /// 未解决的冲突
+    pub conflicts: Vec<MergeConflict>,
+}
+
+struct Merger<'a> {
+    /// 用户对冲突的选择：路径 -> 值（null 表示删除该条目）
+    resolutions: &'a HashMap<String, Value>,
+    conflicts: Vec<MergeConflict>,
+}
+
+/// 三方合并两张卡片
+///
+/// 输出保持 ours 的整体结构（含根级 V1 冗余字段），data 部分为合并结果。
+pub fn merge_cards(
+    base: &Value,
+    ours: &Value,
+    theirs: &Value,
+    resolutions: &HashMap<String, Value>,
+) -> MergeResult {
+    let base_v3 = card_spec::to_v3(base);
+    let ours_v3 = card_spec::to_v3(ours);
+    let theirs_v3 = card_spec::to_v3(theirs);
+
+    let mut merger = Merger {
+        resolutions,
+        conflicts: Vec::new(),
+    };
+    let data = merger.merge_data(
+        base_v3["data"].as_object(),
+        ours_v3["data"].as_object(),
+        theirs_v3["data"].as_object(),
+    );
+
+    let mut card = match ours {
+        Value::Object(_) if ours.get("data").is_some_and(|d| d.is_object()) => ours.clone(),
+        _ => ours_v3,
+    };
+    card["data"] = Value::Object(data);
+    card_spec::sync_root_fields(&mut card);
+
+    MergeResult {
+        card,
+        conflicts: merger.conflicts,
+    }
+}
+
+impl Merger<'_> {
+    fn merge_data(
+        &mut self,
+        base: Option<&Map<String, Value>>,
+        ours: Option<&Map<String, Value>>,
+        theirs: Option<&Map<String, Value>>,
+    ) -> Map<String, Value> {
+        let mut out = Map::new();
+        for key in union_keys(&[ours, theirs, base]) {
+            let b = base.and_then(|m| m.get(&key));
+            let o = ours.and_then(|m| m.get(&key));
+            let t = theirs.and_then(|m| m.get(&key));
+
+            let merged = match key.as_str() {
+                "character_book" => self.merge_character_book(b, o, t),
+                "extensions" => self.merge_extensions(b, o, t),
+                _ => self.merge_value(key.clone(), b, o, t),
+            };
+            if let Some(v) = merged {
+                out.insert(key, v);
+            }
+        }
+        out
+    }
+
+    fn merge_character_book(
+        &mut self,
+        base: Option<&Value>,
+        ours: Option<&Value>,
+        theirs: Option<&Value>,
+    ) -> Option<Value> {
+        let (Some(o), Some(t)) = (ours.and_then(as_obj), theirs.and_then(as_obj)) else {
+            return self.merge_value("character_book".to_string(), base, ours, theirs);
+        };
+        let b = base.and_then(as_obj);
+
+        let mut out = Map::new();
+        for key in union_keys(&[Some(o), Some(t), b]) {
+            let bv = b.and_then(|m| m.get(&key));
+            let merged = if key == "entries" {
+                self.merge_list(
+                    "character_book.entries",
+                    bv,
+                    o.get(&key),
+                    t.get(&key),
+                    entry_id,
+                    entry_comment,
+                )
+            } else {
+                self.merge_value(
+                    format!("character_book.{}", key),
+                    bv,
+                    o.get(&key),
+                    t.get(&key),
+                )
+            };
+            if let Some(v) = merged {
+                out.insert(key, v);
+            }
+        }
+        Some(Value::Object(out))
+    }
+
+    fn merge_extensions(
+        &mut self,
+        base: Option<&Value>,
+        ours: Option<&Value>,
+        theirs: Option<&Value>,
+    ) -> Option<Value> {
+        let (Some(o), Some(t)) = (ours.and_then(as_obj), theirs.and_then(as_obj)) else {
+            return self.merge_value("extensions".to_string(), base, ours, theirs);
+        };
+        let b = base.and_then(as_obj);
+
+        let mut out = Map::new();
+        for key in union_keys(&[Some(o), Some(t), b]) {
+            let bv = b.and_then(|m| m.get(&key));
+            let merged = if key == "regex_scripts" {
+                self.merge_list(
+                    "extensions.regex_scripts",
+                    bv,
+                    o.get(&key),
+                    t.get(&key),
+                    script_id,
+                    script_name,
+                )
+            } else {
+                self.merge_value(format!("extensions.{}", key), bv, o.get(&key), t.get(&key))
+            };
+            if let Some(v) = merged {
+                out.insert(key, v);
+            }
+        }
+        Some(Value::Object(out))
+    }
+
+    /// 合并对象数组（世界书条目、正则脚本），按标识对齐后逐项三方合并
+    fn merge_list(
+        &mut self,
+        path: &str,
+        base: Option<&Value>,
+        ours: Option<&Value>,
+        theirs: Option<&Value>,
+        id_of: fn(&Value) -> Option<String>,
+        name_of: fn(&Value) -> Option<String>,
+    ) -> Option<Value> {
+        let (Some(o), Some(t)) = (
+            ours.and_then(|v| v.as_array()),
+            theirs.and_then(|v| v.as_array()),
+        ) else {
+            return self.merge_value(path.to_string(), base, ours, theirs);
+        };
+        let empty = Vec::new();
+        let b = base.and_then(|v| v.as_array()).unwrap_or(&empty);
+
+        let b = keyed(b, id_of, name_of);
+        let o = keyed(o, id_of, name_of);
+        let t = keyed(t, id_of, name_of);
+
+        // 顺序：ours 中的条目，再追加仅 theirs 中存在的条目（新增，或被 ours 删除）
+        let mut keys: Vec<String> = o.iter().map(|(k, _)| k.clone()).collect();
+        for (k, _) in &t {
+            if !keys.contains(k) {
+                keys.push(k.clone());
+            }
+        }
+
+        let mut out = Vec::new();
+        for key in keys {
+            let item_path = format!("{}[{}]", path, key);
+            let (bv, ov, tv) = (find(&b, &key), find(&o, &key), find(&t, &key));
+
+            let merged = match (
+                bv.and_then(as_obj),
+                ov.and_then(as_obj),
+                tv.and_then(as_obj),
+            ) {
+                // 双方都保留了该条目：逐字段合并
+                (_, Some(om), Some(tm)) if ov != tv => {
+                    let bm = bv.and_then(as_obj);
+                    let mut item = Map::new();
+                    for field in union_keys(&[Some(om), Some(tm), bm]) {
+                        let merged = self.merge_value(
+                            format!("{}.{}", item_path, field),
+                            bm.and_then(|m| m.get(&field)),
+                            om.get(&field),
+                            tm.get(&field),
+                        );
+                        if let Some(v) = merged {
+                            item.insert(field, v);
+                        }
+                    }
+                    Some(Value::Object(item))
+                }
+                _ => self.merge_value(item_path, bv, ov, tv),
+            };
+            if let Some(v) = merged {
+                out.push(v);
+            }
+        }
+        Some(Value::Array(out))
+    }
+
+    /// 单个值的三方合并，返回 None 表示删除
+    fn merge_value(
+        &mut self,
+        path: String,
+        base: Option<&Value>,
+        ours: Option<&Value>,
+        theirs: Option<&Value>,
+    ) -> Option<Value> {
+        if ours == theirs {
+            return ours.cloned();
+        }
+        if ours == base {
+            return theirs.cloned();
+        }
+        if theirs == base {
+            return ours.cloned();
+        }
+
+        // 双方都改了同一段文本：尝试按行合并
+        if let (Some(Value::String(b)), Some(Value::String(o)), Some(Value::String(t))) =
+            (base, ours, theirs)
+        {
+            if let Ok(merged) = diffy::merge(b, o, t) {
+                return Some(Value::String(merged));
+            }
+        }
+
+        if let Some(choice) = self.resolutions.get(&path) {
+            return (!choice.is_null()).then(|| choice.clone());
+        }
+
+        self.conflicts.push(MergeConflict {
+            path,
+            base: base.cloned(),
+            ours: ours.cloned(),
+            theirs: theirs.cloned(),
+        });
+        ours.cloned()
+    }
+}
+
+fn find<'a>(list: &[(String, &'a Value)], key: &str) -> Option<&'a Value> {
+    list.iter().find(|(k, _)| k == key).map(|(_, v)| *v)
+}
+
+fn as_obj(v: &Value) -> Option<&Map<String, Value>> {
+    v.as_object()
+}
+
+/// 各对象键的并集，按出现顺序
+fn union_keys(maps: &[Option<&Map<String, Value>>]) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::new();
+    for map in maps.iter().flatten() {
+        for key in map.keys() {
+            if !keys.contains(key) {
+                keys.push(key.clone());
+            }
+        }
+    }
+    keys
+}
+
+/// 为列表项生成对齐用的标识：id 优先，其次名称，都没有时用下标；重复时追加序号
+fn keyed(
+    list: &[Value],
+    id_of: fn(&Value) -> Option<String>,
+    name_of: fn(&Value) -> Option<String>,
+) -> Vec<(String, &Value)> {
+    let mut out: Vec<(String, &Value)> = Vec::with_capacity(list.len());
+    for (i, item) in list.iter().enumerate() {
+        let mut key = id_of(item)
+            .or_else(|| name_of(item).map(|n| format!("comment={}", n)))
+            .unwrap_or_else(|| format!("#{}", i));
+        if out.iter().any(|(k, _)| *k == key) {
+            key = format!("{}#{}", key, i);
+        }
+        out.push((key, item));
+    }
+    out
+}
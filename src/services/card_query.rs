@@ -0,0 +1,381 @@
+//! 角色卡筛选语法
+//!
+//! 空格分隔的条件之间为「且」，`-` 前缀表示取反，值含空格时用双引号：
+//!
+//! - `tag:奇幻`：包含标签；`tag:a,b` 包含任一；多个 `tag:` 表示全部包含；`-tag:nsfw` 排除
+//! - `rating:>=4`、`rating:3..5`
+//! - `tokens:<2000`（总数），`tokens.spec:`、`tokens.wb:`、`tokens.other:` 对应各分项
+//! - `source:import|local`、`author:xxx`、`name:xxx`、`spec:v1|v2|v3`
+//! - `has:lorebook`、`has:chat`、`has:quickreply`
+//! - `created:>=2024-01-01`、`updated:2024-01-01..2024-06-30`（按天，含两端）
+//! - 其余词按名称、描述、作者、标签、概览模糊搜索
+//!
+//! 列表、服务端分页列表与批量操作共用同一套解析结果。
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use sea_orm::sea_query::{Expr, SimpleExpr};
+use sea_orm::{ColumnTrait, Condition};
+
+use super::card_spec::{CardSpec, SPEC_V2, SPEC_V3};
+use super::tag_index::card_has_tag;
+use crate::entities::character_card::Column;
+
+/// 解析后的筛选条件
+#[derive(Debug, Default)]
+pub struct CardFilter {
+    clauses: Vec<Clause>,
+}
+
+#[derive(Debug)]
+struct Clause {
+    negated: bool,
+    term: Term,
+}
+
+#[derive(Debug)]
+enum Term {
+    Text(String),
+    /// 任一标签
+    Tag(Vec<String>),
+    Rating(NumRange),
+    Tokens(Column, NumRange),
+    Source(String),
+    Author(String),
+    Name(String),
+    Spec(CardSpec),
+    Has(Feature),
+    Created(DateRange),
+    Updated(DateRange),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Feature {
+    Lorebook,
+    Chat,
+    QuickReply,
+}
+
+/// 数值范围，边界为 (值, 是否包含)
+#[derive(Debug, Default)]
+struct NumRange {
+    min: Option<(f64, bool)>,
+    max: Option<(f64, bool)>,
+}
+
+/// 时间范围 [start, end)
+#[derive(Debug, Default)]
+struct DateRange {
+    start: Option<NaiveDateTime>,
+    end: Option<NaiveDateTime>,
+}
+
+impl CardFilter {
+    /// 解析筛选表达式，错误信息可直接返回给前端
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut clauses = Vec::new();
+        for token in tokenize(input)? {
+            let (negated, token) = match token.strip_prefix('-') {
+                Some(rest) if !rest.is_empty() => (true, rest.to_string()),
+                _ => (false, token),
+            };
+            clauses.push(Clause {
+                negated,
+                term: parse_term(&token)?,
+            });
+        }
+        Ok(Self { clauses })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clauses.is_empty()
+    }
+
+    /// 转换为 SeaORM 查询条件
+    pub fn condition(&self) -> Condition {
+        self.clauses.iter().fold(Condition::all(), |cond, clause| {
+            let c = clause.term.condition();
+            cond.add(if clause.negated { c.not() } else { c })
+        })
+    }
+}
+
+/// 排序字段：name | created_at | updated_at | rating | tokens
+pub fn sort_column(sort: &str) -> Option<Column> {
+    match sort {
+        "name" => Some(Column::Name),
+        "created_at" => Some(Column::CreatedAt),
+        "updated_at" => Some(Column::UpdatedAt),
+        "rating" => Some(Column::Rating),
+        "tokens" => Some(Column::TokenCountTotal),
+        _ => None,
+    }
+}
+
+impl Term {
+    fn condition(&self) -> Condition {
+        match self {
+            Term::Text(text) => Condition::any()
+                .add(Column::Name.contains(text))
+                .add(Column::Description.contains(text))
+                .add(Column::Author.contains(text))
+                .add(Column::Tags.contains(text))
+                .add(Column::CustomSummary.contains(text)),
+            Term::Tag(tags) => tags
+                .iter()
+                .fold(Condition::any(), |cond, tag| cond.add(card_has_tag(tag))),
+            Term::Rating(range) => range.condition(Column::Rating),
+            Term::Tokens(column, range) => range.condition(*column),
+            Term::Source(source) => Condition::all().add(Column::Source.eq(source.as_str())),
+            Term::Author(author) => Condition::all().add(Column::Author.contains(author)),
+            Term::Name(name) => Condition::all().add(Column::Name.contains(name)),
+            Term::Spec(spec) => match spec {
+                CardSpec::V3 => Condition::all().add(Column::Spec.eq(SPEC_V3)),
+                CardSpec::V2 => Condition::all().add(Column::Spec.eq(SPEC_V2)),
+                CardSpec::V1 => Condition::all().add(
+                    Condition::any()
+                        .add(Column::Spec.is_null())
+                        .add(Column::Spec.is_not_in([SPEC_V2, SPEC_V3])),
+                ),
+            },
+            Term::Has(feature) => Condition::all().add(feature.expr()),
+            Term::Created(range) => range.condition(Column::CreatedAt),
+            Term::Updated(range) => range.condition(Column::UpdatedAt),
+        }
+    }
+}
+
+impl Feature {
+    fn expr(self) -> SimpleExpr {
+        match self {
+            // V2/V3 在 data.character_book，部分 V1 导出放在根级
+            Feature::Lorebook => Expr::cust(
+                "(json_valid(character_cards.data) AND COALESCE(json_array_length(character_cards.data, '$.data.character_book.entries'), json_array_length(character_cards.data, '$.character_book.entries'), 0) > 0)",
+            ),
+            Feature::Chat => Expr::cust(
+                "EXISTS (SELECT 1 FROM chat_histories WHERE chat_histories.card_id = character_cards.id)",
+            ),
+            Feature::QuickReply => Expr::cust(
+                "EXISTS (SELECT 1 FROM quick_replies WHERE quick_replies.card_id = character_cards.id)",
+            ),
+        }
+    }
+}
+
+impl NumRange {
+    fn condition(&self, column: Column) -> Condition {
+        let mut cond = Condition::all();
+        if let Some((v, inclusive)) = self.min {
+            cond = cond.add(if inclusive {
+                column.gte(v)
+            } else {
+                column.gt(v)
+            });
+        }
+        if let Some((v, inclusive)) = self.max {
+            cond = cond.add(if inclusive {
+                column.lte(v)
+            } else {
+                column.lt(v)
+            });
+        }
+        cond
+    }
+}
+
+impl DateRange {
+    fn condition(&self, column: Column) -> Condition {
+        let mut cond = Condition::all();
+        if let Some(start) = self.start {
+            cond = cond.add(column.gte(start));
+        }
+        if let Some(end) = self.end {
+            cond = cond.add(column.lt(end));
+        }
+        cond
+    }
+}
+
+/// 按空格切分，双引号内的空格保留
+fn tokenize(input: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    for c in input.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if in_quotes {
+        return Err("引号未闭合".to_string());
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
+
+fn parse_term(token: &str) -> Result<Term, String> {
+    let Some((key, value)) = token.split_once(':') else {
+        return Ok(Term::Text(token.to_string()));
+    };
+    let key = key.to_ascii_lowercase();
+    if value.is_empty() {
+        return Err(format!("条件 {} 缺少值", key));
+    }
+
+    let term = match key.as_str() {
+        "tag" | "tags" => {
+            let tags: Vec<String> = value
+                .split(',')
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect();
+            if tags.is_empty() {
+                return Err("条件 tag 缺少值".to_string());
+            }
+            Term::Tag(tags)
+        }
+        "rating" => Term::Rating(parse_num_range(value)?),
+        "tokens" => Term::Tokens(Column::TokenCountTotal, parse_num_range(value)?),
+        "tokens.spec" => Term::Tokens(Column::TokenCountSpec, parse_num_range(value)?),
+        "tokens.wb" => Term::Tokens(Column::TokenCountWb, parse_num_range(value)?),
+        "tokens.other" => Term::Tokens(Column::TokenCountOther, parse_num_range(value)?),
+        "source" => match value {
+            "import" | "local" => Term::Source(value.to_string()),
+            _ => return Err(format!("未知的来源: {}（可选 import、local）", value)),
+        },
+        "author" => Term::Author(value.to_string()),
+        "name" => Term::Name(value.to_string()),
+        "spec" => {
+            Term::Spec(CardSpec::parse(value).ok_or_else(|| format!("未知的规范版本: {}", value))?)
+        }
+        "has" => Term::Has(match value.to_ascii_lowercase().as_str() {
+            "lorebook" | "worldbook" | "book" => Feature::Lorebook,
+            "chat" | "history" => Feature::Chat,
+            "quickreply" | "qr" => Feature::QuickReply,
+            _ => {
+                return Err(format!(
+                    "未知的 has 条件: {}（可选 lorebook、chat、quickreply）",
+                    value
+                ))
+            }
+        }),
+        "created" => Term::Created(parse_date_range(value)?),
+        "updated" => Term::Updated(parse_date_range(value)?),
+        // 未知的键按普通文本处理，例如搜索含冒号的名称
+        _ => Term::Text(token.to_string()),
+    };
+    Ok(term)
+}
+
+/// 比较运算符及其后的值
+fn split_op(value: &str) -> (&str, &str) {
+    for op in [">=", "<=", ">", "<", "="] {
+        if let Some(rest) = value.strip_prefix(op) {
+            return (op, rest);
+        }
+    }
+    ("=", value)
+}
+
+fn parse_num_range(value: &str) -> Result<NumRange, String> {
+    let num = |s: &str| {
+        s.trim()
+            .parse::<f64>()
+            .map_err(|_| format!("无效的数值: {}", s))
+    };
+
+    if let Some((lo, hi)) = value.split_once("..") {
+        return Ok(NumRange {
+            min: (!lo.is_empty())
+                .then(|| num(lo))
+                .transpose()?
+                .map(|v| (v, true)),
+            max: (!hi.is_empty())
+                .then(|| num(hi))
+                .transpose()?
+                .map(|v| (v, true)),
+        });
+    }
+
+    let (op, rest) = split_op(value);
+    let v = num(rest)?;
+    Ok(match op {
+        ">=" => NumRange {
+            min: Some((v, true)),
+            max: None,
+        },
+        ">" => NumRange {
+            min: Some((v, false)),
+            max: None,
+        },
+        "<=" => NumRange {
+            min: None,
+            max: Some((v, true)),
+        },
+        "<" => NumRange {
+            min: None,
+            max: Some((v, false)),
+        },
+        _ => NumRange {
+            min: Some((v, true)),
+            max: Some((v, true)),
+        },
+    })
+}
+
+fn parse_date_range(value: &str) -> Result<DateRange, String> {
+    let date = |s: &str| {
+        NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d")
+            .map_err(|_| format!("无效的日期: {}（格式 YYYY-MM-DD）", s))
+    };
+    let day_start = |d: NaiveDate| d.and_hms_opt(0, 0, 0).unwrap_or_default();
+    let next_day = |d: NaiveDate| {
+        d.checked_add_signed(Duration::days(1))
+            .map(day_start)
+            .ok_or_else(|| format!("日期超出范围: {}", d))
+    };
+
+    if let Some((lo, hi)) = value.split_once("..") {
+        return Ok(DateRange {
+            start: (!lo.is_empty())
+                .then(|| date(lo))
+                .transpose()?
+                .map(day_start),
+            end: (!hi.is_empty())
+                .then(|| date(hi).and_then(next_day))
+                .transpose()?,
+        });
+    }
+
+    let (op, rest) = split_op(value);
+    let d = date(rest)?;
+    Ok(match op {
+        ">=" => DateRange {
+            start: Some(day_start(d)),
+            end: None,
+        },
+        ">" => DateRange {
+            start: Some(next_day(d)?),
+            end: None,
+        },
+        "<=" => DateRange {
+            start: None,
+            end: Some(next_day(d)?),
+        },
+        "<" => DateRange {
+            start: None,
+            end: Some(day_start(d)),
+        },
+        _ => DateRange {
+            start: Some(day_start(d)),
+            end: Some(next_day(d)?),
+        },
+    })
+}
@@ -0,0 +1,227 @@
+//! 角色卡规范版本转换
+//!
+//! 数据库中保存的是导入时的原始 JSON，可能是 V1 平铺结构、V2/V3 的 `data` 包装结构，
+//! 或 SillyTavern 导出的「根字段 + data」混合结构。本模块统一规范化为 chara_card_v3，
+//! 并支持降级为 chara_card_v2 或 V1 平铺结构。
+
+use serde_json::{json, Map, Value};
+
+pub const SPEC_V2: &str = "chara_card_v2";
+pub const SPEC_V3: &str = "chara_card_v3";
+
+/// V1 平铺结构的字段
+const V1_FIELDS: [&str; 6] = [
+    "name",
+    "description",
+    "personality",
+    "scenario",
+    "first_mes",
+    "mes_example",
+];
+
+/// V2 `data` 中的字段
+const V2_FIELDS: [&str; 14] = [
+    "name",
+    "description",
+    "personality",
+    "scenario",
+    "first_mes",
+    "mes_example",
+    "creator_notes",
+    "system_prompt",
+    "post_history_instructions",
+    "alternate_greetings",
+    "character_book",
+    "tags",
+    "creator",
+    "character_version",
+];
+
+/// V3 新增且 V2 不认识的字段
+const V3_ONLY_FIELDS: [&str; 7] = [
+    "assets",
+    "nickname",
+    "creator_notes_multilingual",
+    "source",
+    "group_only_greetings",
+    "creation_date",
+    "modification_date",
+];
+
+/// 规范版本
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CardSpec {
+    V1,
+    V2,
+    V3,
+}
+
+impl CardSpec {
+    /// 解析查询参数：v1 | v2 | v3
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "v1" | "1" => Some(Self::V1),
+            "v2" | "2" | SPEC_V2 => Some(Self::V2),
+            "v3" | "3" | SPEC_V3 => Some(Self::V3),
+            _ => None,
+        }
+    }
+
+    /// 识别卡片 JSON 的规范版本
+    pub fn detect(card: &Value) -> Self {
+        match card.get("spec").and_then(|s| s.as_str()) {
+            Some(SPEC_V3) => Self::V3,
+            Some(SPEC_V2) => Self::V2,
+            _ if card.get("data").is_some_and(|d| d.is_object()) => Self::V2,
+            _ => Self::V1,
+        }
+    }
+}
+
+/// 按目标规范转换
+pub fn convert(card: &Value, spec: CardSpec) -> Value {
+    match spec {
+        CardSpec::V1 => to_v1(card),
+        CardSpec::V2 => to_v2(card),
+        CardSpec::V3 => to_v3(card),
+    }
+}
+
+/// 规范化为 chara_card_v3
+pub fn to_v3(card: &Value) -> Value {
+    let mut data = match card.get("data").and_then(|d| d.as_object()) {
+        Some(d) => d.clone(),
+        None => Map::new(),
+    };
+    let root = card.as_object();
+
+    // 根字段补齐 data 中缺失的字段（V1 平铺或混合结构）
+    if let Some(root) = root {
+        for key in V1_FIELDS {
+            if !data.contains_key(key) {
+                if let Some(v) = root.get(key) {
+                    data.insert(key.to_string(), v.clone());
+                }
+            }
+        }
+        if !data.contains_key("creator_notes") {
+            if let Some(v) = root.get("creatorcomment") {
+                data.insert("creator_notes".to_string(), v.clone());
+            }
+        }
+        if !data.contains_key("tags") {
+            if let Some(v) = root.get("tags").filter(|t| t.is_array()) {
+                data.insert("tags".to_string(), v.clone());
+            }
+        }
+    }
+
+    for key in V2_FIELDS {
+        if data.contains_key(key) {
+            continue;
+        }
+        let default = match key {
+            "alternate_greetings" | "tags" => json!([]),
+            "character_book" => continue,
+            _ => json!(""),
+        };
+        data.insert(key.to_string(), default);
+    }
+    if !data.get("extensions").is_some_and(|e| e.is_object()) {
+        data.insert("extensions".to_string(), json!({}));
+    }
+    if !data.contains_key("group_only_greetings") {
+        data.insert("group_only_greetings".to_string(), json!([]));
+    }
+
+    json!({
+        "spec": SPEC_V3,
+        "spec_version": "3.0",
+        "data": Value::Object(data)
+    })
+}
+
+/// 降级为 chara_card_v2
+///
+/// 与 SillyTavern 一致，根级同时写入 V1 字段，兼容只读取平铺结构的旧版前端。
+pub fn to_v2(card: &Value) -> Value {
+    let v3 = to_v3(card);
+    let mut data = v3["data"].as_object().cloned().unwrap_or_default();
+
+    for key in V3_ONLY_FIELDS {
+        data.remove(key);
+    }
+    if let Some(book) = data.get_mut("character_book") {
+        downgrade_character_book(book);
+    }
+
+    let mut output = v1_fields(&data);
+    output.insert("spec".to_string(), json!(SPEC_V2));
+    output.insert("spec_version".to_string(), json!("2.0"));
+    output.insert("data".to_string(), Value::Object(data));
+    Value::Object(output)
+}
+
+/// 降级为 V1 平铺结构
+pub fn to_v1(card: &Value) -> Value {
+    let v3 = to_v3(card);
+    let data = v3["data"].as_object().cloned().unwrap_or_default();
+    Value::Object(v1_fields(&data))
+}
+
+/// 将 data 中的值同步到根级已有的 V1 冗余字段（混合结构）
+pub fn sync_root_fields(card: &mut Value) {
+    let Some(data) = card.get("data").and_then(|d| d.as_object()).cloned() else {
+        return;
+    };
+    let Some(root) = card.as_object_mut() else {
+        return;
+    };
+
+    let mirrored = V1_FIELDS
+        .iter()
+        .map(|&k| (k, k))
+        .chain([("creatorcomment", "creator_notes"), ("tags", "tags")]);
+    for (root_key, data_key) in mirrored {
+        if !root.contains_key(root_key) {
+            continue;
+        }
+        if let Some(v) = data.get(data_key) {
+            root.insert(root_key.to_string(), v.clone());
+        }
+    }
+}
+
+fn v1_fields(data: &Map<String, Value>) -> Map<String, Value> {
+    V1_FIELDS
+        .iter()
+        .map(|&key| {
+            let v = data
+                .get(key)
+                .filter(|v| v.is_string())
+                .cloned()
+                .unwrap_or_else(|| json!(""));
+            (key.to_string(), v)
+        })
+        .collect()
+}
+
+/// V3 世界书条目新增 `use_regex`，且 `position` 等字段取值更宽，V2 中移除不认识的部分
+fn downgrade_character_book(book: &mut Value) {
+    let Some(entries) = book.get_mut("entries").and_then(|e| e.as_array_mut()) else {
+        return;
+    };
+    for entry in entries {
+        let Some(obj) = entry.as_object_mut() else {
+            continue;
+        };
+        obj.remove("use_regex");
+        let position_valid = matches!(
+            obj.get("position").and_then(|p| p.as_str()),
+            Some("before_char") | Some("after_char")
+        );
+        if !position_valid {
+            obj.remove("position");
+        }
+    }
+}
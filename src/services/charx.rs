@@ -0,0 +1,236 @@
+//! CHARX 角色卡归档 (.charx)
+//!
+//! CHARX 是 V3 规范定义的 zip 归档格式：根目录的 `card.json` 为 chara_card_v3 数据，
+//! 资源文件存放在 `assets/` 目录下，卡片内通过 `embeded://<路径>` 引用。
+
+use serde_json::Value;
+use std::io::{Cursor, Read, Write};
+use zip::write::SimpleFileOptions;
+
+/// 归档内资源 URI 前缀（规范中的拼写即为 embeded）
+pub const EMBEDDED_PREFIX: &str = "embeded://";
+/// 默认资源（即角色卡封面）
+pub const DEFAULT_ASSET_URI: &str = "ccdefault:";
+/// 单个条目解压后的最大字节数
+pub const MAX_ENTRY_SIZE: u64 = 64 * 1024 * 1024;
+/// 整个归档解压后的最大字节数
+pub const MAX_TOTAL_SIZE: u64 = 512 * 1024 * 1024;
+
+/// 单个资源文件
+pub struct CharxAsset {
+    /// 归档内的相对路径，例如 `assets/icon/images/main.png`
+    pub path: String,
+    pub data: Vec<u8>,
+}
+
+/// 解析后的 CHARX 归档
+pub struct CharxArchive {
+    pub card: Value,
+    pub assets: Vec<CharxAsset>,
+}
+
+/// 读取 CHARX 归档
+pub fn read_charx(data: &[u8]) -> Result<CharxArchive, String> {
+    let mut archive =
+        zip::ZipArchive::new(Cursor::new(data)).map_err(|e| format!("无效的 CHARX 归档: {}", e))?;
+
+    let mut card: Option<Value> = None;
+    let mut assets = Vec::new();
+    let mut total: u64 = 0;
+
+    for i in 0..archive.len() {
+        let mut file = archive
+            .by_index(i)
+            .map_err(|e| format!("读取归档条目失败: {}", e))?;
+        if file.is_dir() {
+            continue;
+        }
+
+        // 拒绝越界路径 (../ 或绝对路径)
+        let path = match file.enclosed_name() {
+            Some(p) => p.to_string_lossy().replace('\\', "/"),
+            None => {
+                tracing::warn!("CHARX 条目路径非法，已跳过: {}", file.name());
+                continue;
+            }
+        };
+
+        // 条目头中的大小不可信，按实际解压出的字节数限制
+        let limit = MAX_ENTRY_SIZE.min(MAX_TOTAL_SIZE - total);
+        let mut buf = Vec::new();
+        (&mut file)
+            .take(limit + 1)
+            .read_to_end(&mut buf)
+            .map_err(|e| format!("解压 {} 失败: {}", path, e))?;
+        if buf.len() as u64 > limit {
+            return Err(if limit == MAX_ENTRY_SIZE {
+                format!(
+                    "CHARX 条目 {} 超过 {} MB",
+                    path,
+                    MAX_ENTRY_SIZE / 1024 / 1024
+                )
+            } else {
+                format!("CHARX 归档解压后超过 {} MB", MAX_TOTAL_SIZE / 1024 / 1024)
+            });
+        }
+        total += buf.len() as u64;
+
+        if path == "card.json" {
+            let json: Value =
+                serde_json::from_slice(&buf).map_err(|e| format!("card.json 无效: {}", e))?;
+            card = Some(json);
+        } else if path.starts_with("assets/") {
+            assets.push(CharxAsset { path, data: buf });
+        }
+        // 其他文件（如 module.risum）不属于角色卡数据，忽略
+    }
+
+    let card = card.ok_or_else(|| "CHARX 归档中缺少 card.json".to_string())?;
+    Ok(CharxArchive { card, assets })
+}
+
+/// 写出 CHARX 归档
+pub fn write_charx(card: &Value, assets: &[CharxAsset]) -> Result<Vec<u8>, String> {
+    let mut zip_writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let json_options =
+        SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    // 图片等资源本身已压缩，直接存储
+    let asset_options =
+        SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+
+    let card_json =
+        serde_json::to_vec_pretty(card).map_err(|e| format!("序列化 card.json 失败: {}", e))?;
+    zip_writer
+        .start_file("card.json", json_options)
+        .map_err(|e| format!("Zip error: {}", e))?;
+    zip_writer
+        .write_all(&card_json)
+        .map_err(|e| format!("Zip error: {}", e))?;
+
+    let mut written = std::collections::HashSet::new();
+    for asset in assets {
+        if !written.insert(asset.path.as_str()) {
+            continue;
+        }
+        zip_writer
+            .start_file(asset.path.as_str(), asset_options)
+            .map_err(|e| format!("Zip error: {}", e))?;
+        zip_writer
+            .write_all(&asset.data)
+            .map_err(|e| format!("Zip error: {}", e))?;
+    }
+
+    let cursor = zip_writer
+        .finish()
+        .map_err(|e| format!("Zip error: {}", e))?;
+    Ok(cursor.into_inner())
+}
+
+/// 在 JSON 所有字符串中将引用 `from` 替换为 `to`
+///
+/// 资源引用既可能出现在 `data.assets[].uri`，也可能以 Markdown/HTML 形式嵌入正文。
+/// `from` 以 `/` 结尾时按目录前缀替换，否则只替换完整的 URI：`embeded://assets/icon`
+/// 不会改动 `embeded://assets/icon2/...` 或 `embeded://assets/icon.png`。
+pub fn rewrite_asset_uris(value: &mut Value, from: &str, to: &str) {
+    match value {
+        Value::String(s) if s.contains(from) => {
+            *s = replace_uri(s, from, to);
+        }
+        Value::Array(arr) => {
+            for v in arr {
+                rewrite_asset_uris(v, from, to);
+            }
+        }
+        Value::Object(map) => {
+            for (_, v) in map.iter_mut() {
+                rewrite_asset_uris(v, from, to);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn replace_uri(s: &str, from: &str, to: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut last = 0;
+    for (start, _) in s.match_indices(from) {
+        let end = start + from.len();
+        if !(from.ends_with('/') || ends_uri(&s[end..])) {
+            continue;
+        }
+        out.push_str(&s[last..start]);
+        out.push_str(to);
+        last = end;
+    }
+    out.push_str(&s[last..]);
+    out
+}
+
+/// 引用之后是否不再是路径的一部分：字符串结束，或遇到引号、括号、空白、查询参数等
+fn ends_uri(rest: &str) -> bool {
+    match rest.chars().next() {
+        None => true,
+        Some(c) => !(c.is_alphanumeric() || matches!(c, '/' | '-' | '_' | '.' | '~' | '%')),
+    }
+}
+
+/// 查找作为封面的主图标资源，返回其在归档内的路径
+///
+/// 规范约定 `type = icon` 且 `name = main` 的资源为主图标；没有时退回第一个 icon。
+pub fn find_main_icon(card: &Value) -> Option<String> {
+    let assets = card
+        .get("data")
+        .and_then(|d| d.get("assets"))
+        .and_then(|a| a.as_array())?;
+
+    let icons: Vec<&Value> = assets
+        .iter()
+        .filter(|a| a.get("type").and_then(|t| t.as_str()) == Some("icon"))
+        .collect();
+
+    let main = icons
+        .iter()
+        .find(|a| a.get("name").and_then(|n| n.as_str()) == Some("main"))
+        .or_else(|| icons.first())?;
+
+    main.get("uri")
+        .and_then(|u| u.as_str())
+        .and_then(|u| u.strip_prefix(EMBEDDED_PREFIX))
+        .map(|p| p.to_string())
+}
+
+/// 将主图标设置为指定 URI（不存在时插入到资源列表开头）
+pub fn set_main_icon(card: &mut Value, uri: &str, ext: &str) {
+    let Some(data) = card.get_mut("data").and_then(|d| d.as_object_mut()) else {
+        return;
+    };
+
+    let assets = data
+        .entry("assets")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    if !assets.is_array() {
+        *assets = Value::Array(Vec::new());
+    }
+    let list = assets.as_array_mut().unwrap();
+
+    let existing = list.iter_mut().find(|a| {
+        a.get("type").and_then(|t| t.as_str()) == Some("icon")
+            && a.get("name").and_then(|n| n.as_str()) == Some("main")
+    });
+
+    match existing {
+        Some(icon) => {
+            icon["uri"] = Value::String(uri.to_string());
+            icon["ext"] = Value::String(ext.to_string());
+        }
+        None => list.insert(
+            0,
+            serde_json::json!({
+                "type": "icon",
+                "uri": uri,
+                "name": "main",
+                "ext": ext
+            }),
+        ),
+    }
+}
@@ -0,0 +1,95 @@
+//! 进程内事件总线
+//!
+//! 处理函数在数据变更后调用 `publish`，`/api/events` 把事件以 SSE 推送给各客户端，
+//! 前端据此刷新列表和详情页。没有订阅者时事件直接丢弃。
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// 每个订阅者可积压的事件数，超出后该订阅者会收到 `resync`
+const CHANNEL_CAPACITY: usize = 256;
+
+static BUS: Lazy<broadcast::Sender<AppEvent>> =
+    Lazy::new(|| broadcast::channel(CHANNEL_CAPACITY).0);
+
+/// 推送给前端的变更事件
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AppEvent {
+    /// 新建或导入了角色卡
+    CardCreated {
+        id: Uuid,
+    },
+    /// 角色卡内容、封面或元数据被修改
+    CardUpdated {
+        id: Uuid,
+    },
+    /// 批量修改（分类、批量编辑、标签管理、撤销等）
+    CardsUpdated {
+        ids: Vec<Uuid>,
+    },
+    /// 移入回收站，`permanent` 为 true 时表示彻底删除
+    CardsDeleted {
+        ids: Vec<Uuid>,
+        permanent: bool,
+    },
+    /// 从回收站恢复
+    CardRestored {
+        id: Uuid,
+    },
+    ImagesCreated {
+        ids: Vec<Uuid>,
+    },
+    ImagesUpdated {
+        ids: Vec<Uuid>,
+    },
+    ImagesDeleted {
+        ids: Vec<Uuid>,
+    },
+    WorldInfoCreated {
+        ids: Vec<Uuid>,
+    },
+    WorldInfoUpdated {
+        id: Uuid,
+    },
+    WorldInfoDeleted {
+        id: Uuid,
+    },
+    TheatersCreated {
+        ids: Vec<Uuid>,
+    },
+    TheaterUpdated {
+        id: Uuid,
+    },
+    TheatersDeleted {
+        ids: Vec<Uuid>,
+    },
+    /// 小皮医生诊断完成
+    DoctorTaskCompleted {
+        card_id: Uuid,
+        task_id: Uuid,
+    },
+    /// 后台任务状态或进度变化
+    JobUpdated {
+        id: Uuid,
+        kind: String,
+        status: String,
+        progress: f64,
+        message: Option<String>,
+    },
+    /// 订阅者积压过多，部分事件已丢失，前端应整体刷新
+    Resync,
+}
+
+/// 发布事件
+pub fn publish(event: AppEvent) {
+    // 没有订阅者时 send 返回错误，属于正常情况
+    let _ = BUS.send(event);
+}
+
+/// 订阅之后发布的事件
+pub fn subscribe() -> broadcast::Receiver<AppEvent> {
+    BUS.subscribe()
+}
@@ -0,0 +1,670 @@
+//! 后台任务队列
+//!
+//! 任务保存在 `jobs` 表中，由固定数量的 worker 取出执行：
+//! - 失败时按指数退避重试，达到 `max_attempts` 后标记为 failed；
+//! - 排队或运行中的任务可以取消；
+//! - 启动时把上次退出时仍在运行的任务放回队列；
+//! - 同步请求用 `run_now` 在请求中直接执行，不等待 worker。
+//!
+//! 状态与进度变化通过事件总线广播 `job_updated`。产出文件的任务把文件写到
+//! `artifact_path(id)`，结果中记录下载文件名，由 `/api/jobs/{id}/download` 提供下载。
+//! 已结束的任务保留 `RETENTION_DAYS` 天，由定时清理删除。
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::stream::{self, Stream};
+use once_cell::sync::Lazy;
+use sea_orm::{
+    sea_query::{Expr, SimpleExpr},
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, Set,
+};
+use serde_json::Value;
+use tokio::sync::{broadcast::error::RecvError, Notify};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use super::event_bus::{self, AppEvent};
+use crate::entities::job;
+
+/// 同时执行的任务数
+const WORKER_COUNT: usize = 2;
+/// 没有新任务通知时的轮询间隔（用于拾取退避到期的任务）
+const POLL_INTERVAL_SECS: u64 = 5;
+/// 重试退避：5s、10s、20s……最长 10 分钟
+const RETRY_BASE_SECS: i64 = 5;
+const RETRY_MAX_SECS: i64 = 600;
+/// 已结束任务及其文件的保留天数
+const RETENTION_DAYS: i64 = 3;
+/// 清理过期任务的执行间隔
+const PRUNE_INTERVAL_SECS: u64 = 60 * 60;
+
+/// 正在运行的任务的取消令牌
+static RUNNING: Lazy<Mutex<HashMap<Uuid, CancellationToken>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+/// 有新任务入队时唤醒空闲 worker
+static WAKE: Lazy<Notify> = Lazy::new(Notify::new);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Succeeded => "succeeded",
+            Self::Failed => "failed",
+            Self::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "queued" => Some(Self::Queued),
+            "running" => Some(Self::Running),
+            "succeeded" => Some(Self::Succeeded),
+            "failed" => Some(Self::Failed),
+            "cancelled" => Some(Self::Cancelled),
+            _ => None,
+        }
+    }
+
+    /// 是否已结束（不会再变化）
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Succeeded | Self::Failed | Self::Cancelled)
+    }
+}
+
+/// 任务执行失败
+#[derive(Debug)]
+pub enum JobError {
+    /// 临时错误（网络、上游 5xx 等），按退避重试
+    Retry(String),
+    /// 重试也不会成功的错误（参数无效、数据不存在等）
+    Fatal(String),
+}
+
+impl From<DbErr> for JobError {
+    fn from(e: DbErr) -> Self {
+        Self::Retry(format!("数据库错误: {}", e))
+    }
+}
+
+/// 按任务类型分派执行的函数，结果写入 `jobs.result`
+pub type JobRunner = fn(JobContext) -> BoxFuture<'static, Result<Value, JobError>>;
+
+/// 传给任务执行函数的上下文
+#[derive(Clone)]
+pub struct JobContext {
+    pub db: DatabaseConnection,
+    pub id: Uuid,
+    pub kind: String,
+    pub payload: Value,
+    cancel: CancellationToken,
+}
+
+impl JobContext {
+    /// 更新进度（0.0 ~ 1.0）与说明文字
+    pub async fn progress(&self, progress: f64, message: impl Into<String>) {
+        let message = message.into();
+        let progress = progress.clamp(0.0, 1.0);
+        let result = job::Entity::update_many()
+            .col_expr(job::Column::Progress, Expr::value(progress))
+            .col_expr(job::Column::Message, Expr::value(message.clone()))
+            .col_expr(
+                job::Column::UpdatedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(job::Column::Id.eq(self.id))
+            .exec(&self.db)
+            .await;
+        if let Err(e) = result {
+            tracing::warn!("Failed to update job {} progress: {}", self.id, e);
+        }
+        event_bus::publish(AppEvent::JobUpdated {
+            id: self.id,
+            kind: self.kind.clone(),
+            status: JobStatus::Running.as_str().to_string(),
+            progress,
+            message: Some(message),
+        });
+    }
+
+    /// 任务是否已被取消；阻塞代码中应定期检查
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// 本任务产出文件的路径
+    pub fn artifact_path(&self) -> PathBuf {
+        artifact_path(self.id)
+    }
+}
+
+/// 任务产出文件的存放路径（位于 temp 目录，不参与备份）
+pub fn artifact_path(id: Uuid) -> PathBuf {
+    crate::utils::paths::get_data_path("temp")
+        .join("jobs")
+        .join(id.to_string())
+}
+
+/// 入队新任务
+pub async fn enqueue(
+    db: &DatabaseConnection,
+    kind: &str,
+    payload: Value,
+    max_attempts: i32,
+) -> Result<job::Model, DbErr> {
+    let now = chrono::Utc::now().naive_utc();
+    let model = job::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        kind: Set(kind.to_string()),
+        payload: Set(payload.to_string()),
+        status: Set(JobStatus::Queued.as_str().to_string()),
+        progress: Set(0.0),
+        message: Set(None),
+        result: Set(None),
+        error: Set(None),
+        attempts: Set(0),
+        max_attempts: Set(max_attempts.max(1)),
+        run_after: Set(now),
+        created_at: Set(now),
+        updated_at: Set(now),
+        finished_at: Set(None),
+    }
+    .insert(db)
+    .await?;
+
+    publish_update(&model);
+    WAKE.notify_one();
+    Ok(model)
+}
+
+/// 立即在当前调用中执行任务，不占用也不等待 worker，返回最终状态
+///
+/// 用于同步请求：任务记录照常写入，可通过任务接口查看进度与取消；失败不重试。
+pub async fn run_now(
+    db: &DatabaseConnection,
+    kind: &str,
+    payload: Value,
+    runner: JobRunner,
+) -> Result<job::Model, DbErr> {
+    let now = chrono::Utc::now().naive_utc();
+    let model = job::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        kind: Set(kind.to_string()),
+        payload: Set(payload.to_string()),
+        status: Set(JobStatus::Running.as_str().to_string()),
+        progress: Set(0.0),
+        message: Set(None),
+        result: Set(None),
+        error: Set(None),
+        attempts: Set(1),
+        max_attempts: Set(1),
+        run_after: Set(now),
+        created_at: Set(now),
+        updated_at: Set(now),
+        finished_at: Set(None),
+    }
+    .insert(db)
+    .await?;
+
+    let id = model.id;
+    run_job(db, runner, model).await;
+    job::Entity::find_by_id(id)
+        .one(db)
+        .await?
+        .ok_or_else(|| DbErr::RecordNotFound(format!("任务 {} 已被删除", id)))
+}
+
+/// 最近的任务，新的在前
+pub async fn list(
+    db: &DatabaseConnection,
+    kind: Option<&str>,
+    status: Option<&str>,
+    limit: u64,
+) -> Result<Vec<job::Model>, DbErr> {
+    let mut query = job::Entity::find();
+    if let Some(kind) = kind {
+        query = query.filter(job::Column::Kind.eq(kind));
+    }
+    if let Some(status) = status {
+        query = query.filter(job::Column::Status.eq(status));
+    }
+    query
+        .order_by_desc(job::Column::CreatedAt)
+        .limit(limit)
+        .all(db)
+        .await
+}
+
+/// 同类型尚未结束的任务，可用 `payload_filter`（SQL 表达式）进一步按参数筛选
+pub async fn find_active(
+    db: &DatabaseConnection,
+    kind: &str,
+    payload_filter: Option<SimpleExpr>,
+) -> Result<Option<job::Model>, DbErr> {
+    let mut query = job::Entity::find()
+        .filter(job::Column::Kind.eq(kind))
+        .filter(
+            job::Column::Status.is_in([JobStatus::Queued.as_str(), JobStatus::Running.as_str()]),
+        );
+    if let Some(filter) = payload_filter {
+        query = query.filter(filter);
+    }
+    query.one(db).await
+}
+
+/// `DELETE /api/jobs/{id}` 的处理结果
+pub enum CancelOutcome {
+    NotFound,
+    /// 已取消或已请求取消（运行中的任务由 worker 写入最终状态）
+    Cancelled(Box<job::Model>),
+    /// 已结束的任务，记录与产出文件已删除
+    Removed,
+}
+
+/// 取消未结束的任务；已结束的任务则删除记录和产出文件
+pub async fn cancel_or_remove(db: &DatabaseConnection, id: Uuid) -> Result<CancelOutcome, DbErr> {
+    let Some(model) = job::Entity::find_by_id(id).one(db).await? else {
+        return Ok(CancelOutcome::NotFound);
+    };
+
+    match JobStatus::parse(&model.status) {
+        Some(status) if !status.is_finished() => {
+            if status == JobStatus::Queued {
+                let now = chrono::Utc::now().naive_utc();
+                let result = job::Entity::update_many()
+                    .col_expr(
+                        job::Column::Status,
+                        Expr::value(JobStatus::Cancelled.as_str()),
+                    )
+                    .col_expr(job::Column::FinishedAt, Expr::value(now))
+                    .col_expr(job::Column::UpdatedAt, Expr::value(now))
+                    .filter(job::Column::Id.eq(id))
+                    .filter(job::Column::Status.eq(JobStatus::Queued.as_str()))
+                    .exec(db)
+                    .await?;
+                if result.rows_affected == 1 {
+                    let model = job::Entity::find_by_id(id).one(db).await?.unwrap_or(model);
+                    publish_update(&model);
+                    return Ok(CancelOutcome::Cancelled(Box::new(model)));
+                }
+                // 恰好被 worker 取走，按运行中处理
+            }
+
+            let token = RUNNING.lock().unwrap().get(&id).cloned();
+            match token {
+                Some(token) => token.cancel(),
+                None => {
+                    // 没有 worker 在执行（例如刚被放回队列），直接标记；
+                    // 期间已结束的任务保留其结果
+                    let mut active: job::ActiveModel = model.clone().into();
+                    let now = chrono::Utc::now().naive_utc();
+                    active.status = Set(JobStatus::Cancelled.as_str().to_string());
+                    active.finished_at = Set(Some(now));
+                    active.updated_at = Set(now);
+                    let model = match job::Entity::update(active)
+                        .filter(
+                            job::Column::Status
+                                .is_in([JobStatus::Queued.as_str(), JobStatus::Running.as_str()]),
+                        )
+                        .exec(db)
+                        .await
+                    {
+                        Ok(model) => model,
+                        Err(DbErr::RecordNotUpdated) => {
+                            job::Entity::find_by_id(id).one(db).await?.unwrap_or(model)
+                        }
+                        Err(e) => return Err(e),
+                    };
+                    publish_update(&model);
+                    return Ok(CancelOutcome::Cancelled(Box::new(model)));
+                }
+            }
+            Ok(CancelOutcome::Cancelled(Box::new(model)))
+        }
+        _ => {
+            remove_finished(db, id).await?;
+            Ok(CancelOutcome::Removed)
+        }
+    }
+}
+
+/// 删除已结束任务的记录与产出文件，未结束的任务不受影响；返回是否删除
+pub async fn remove_finished(db: &DatabaseConnection, id: Uuid) -> Result<bool, DbErr> {
+    let result = job::Entity::delete_many()
+        .filter(job::Column::Id.eq(id))
+        .filter(
+            job::Column::Status
+                .is_not_in([JobStatus::Queued.as_str(), JobStatus::Running.as_str()]),
+        )
+        .exec(db)
+        .await?;
+    if result.rows_affected == 0 {
+        return Ok(false);
+    }
+    remove_artifact(id).await;
+    Ok(true)
+}
+
+/// 析构时在后台删除已结束的任务，用于同步请求在响应发送完（或连接断开）后清理；
+/// 仍在运行的任务保留，之后由定时清理删除
+pub struct RemoveOnDrop {
+    db: DatabaseConnection,
+    id: Uuid,
+}
+
+impl RemoveOnDrop {
+    pub fn new(db: DatabaseConnection, id: Uuid) -> Self {
+        Self { db, id }
+    }
+}
+
+impl Drop for RemoveOnDrop {
+    fn drop(&mut self) {
+        let (db, id) = (self.db.clone(), self.id);
+        tokio::spawn(async move {
+            if let Err(e) = remove_finished(&db, id).await {
+                tracing::warn!("Failed to remove job {}: {}", id, e);
+            }
+        });
+    }
+}
+
+/// 任务状态流：先给出当前状态，之后每次变化给出最新状态，结束后终止
+pub fn watch(db: DatabaseConnection, id: Uuid) -> impl Stream<Item = job::Model> {
+    // 先订阅再读取，避免错过两者之间的变化
+    let rx = event_bus::subscribe();
+
+    stream::unfold(
+        (db, rx, false, false),
+        move |(db, mut rx, started, finished)| async move {
+            if finished {
+                return None;
+            }
+            if started {
+                loop {
+                    match rx.recv().await {
+                        Ok(AppEvent::JobUpdated { id: event_id, .. }) if event_id == id => break,
+                        Ok(_) => continue,
+                        Err(RecvError::Lagged(_)) => break,
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            }
+
+            let model = match job::Entity::find_by_id(id).one(&db).await {
+                Ok(Some(model)) => model,
+                Ok(None) => return None,
+                Err(e) => {
+                    tracing::warn!("Failed to load job {}: {}", id, e);
+                    return None;
+                }
+            };
+            let finished = JobStatus::parse(&model.status).is_none_or(|s| s.is_finished());
+            Some((model, (db, rx, true, finished)))
+        },
+    )
+}
+
+/// 恢复中断的任务、启动 worker，并定期清理过期任务
+pub fn start(db: DatabaseConnection, runner: JobRunner) {
+    tokio::spawn(async move {
+        match recover(&db).await {
+            Ok(0) => {}
+            Ok(n) => tracing::info!("已恢复 {} 个中断的后台任务", n),
+            Err(e) => tracing::warn!("恢复后台任务失败: {}", e),
+        }
+        for _ in 0..WORKER_COUNT {
+            tokio::spawn(worker_loop(db.clone(), runner));
+        }
+
+        // 首次立即执行
+        let mut interval = tokio::time::interval(Duration::from_secs(PRUNE_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            match prune(&db).await {
+                Ok(0) => {}
+                Ok(n) => tracing::info!("已清理 {} 个过期的后台任务", n),
+                Err(e) => tracing::warn!("清理后台任务失败: {}", e),
+            }
+        }
+    });
+}
+
+/// 上次退出时仍在运行的任务放回队列
+async fn recover(db: &DatabaseConnection) -> Result<u64, DbErr> {
+    let result = job::Entity::update_many()
+        .col_expr(job::Column::Status, Expr::value(JobStatus::Queued.as_str()))
+        .col_expr(
+            job::Column::RunAfter,
+            Expr::value(chrono::Utc::now().naive_utc()),
+        )
+        .filter(job::Column::Status.eq(JobStatus::Running.as_str()))
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected)
+}
+
+/// 将未结束的任务标记为已取消，返回影响的行数
+///
+/// 用于恢复备份后：备份中的任务记录（包括执行备份的任务本身）不应在重启后重新执行。
+pub async fn cancel_unfinished(db: &DatabaseConnection) -> Result<u64, DbErr> {
+    let now = chrono::Utc::now().naive_utc();
+    let result = job::Entity::update_many()
+        .col_expr(
+            job::Column::Status,
+            Expr::value(JobStatus::Cancelled.as_str()),
+        )
+        .col_expr(job::Column::Message, Expr::value("恢复备份后已取消"))
+        .col_expr(job::Column::FinishedAt, Expr::value(now))
+        .col_expr(job::Column::UpdatedAt, Expr::value(now))
+        .filter(
+            job::Column::Status.is_in([JobStatus::Queued.as_str(), JobStatus::Running.as_str()]),
+        )
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected)
+}
+
+/// 删除超过保留期的已结束任务及其产出文件
+async fn prune(db: &DatabaseConnection) -> Result<usize, DbErr> {
+    let cutoff = chrono::Utc::now().naive_utc() - chrono::Duration::days(RETENTION_DAYS);
+    let stale: Vec<Uuid> = job::Entity::find()
+        .select_only()
+        .column(job::Column::Id)
+        .filter(job::Column::FinishedAt.lt(cutoff))
+        .into_tuple()
+        .all(db)
+        .await?;
+    for id in &stale {
+        remove_artifact(*id).await;
+    }
+    if !stale.is_empty() {
+        job::Entity::delete_many()
+            .filter(job::Column::Id.is_in(stale.clone()))
+            .exec(db)
+            .await?;
+    }
+    Ok(stale.len())
+}
+
+async fn worker_loop(db: DatabaseConnection, runner: JobRunner) {
+    loop {
+        match claim_next(&db).await {
+            Ok(Some(model)) => run_job(&db, runner, model).await,
+            Ok(None) => {
+                tokio::select! {
+                    _ = WAKE.notified() => {}
+                    _ = tokio::time::sleep(Duration::from_secs(POLL_INTERVAL_SECS)) => {}
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Failed to claim job: {}", e);
+                tokio::time::sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await;
+            }
+        }
+    }
+}
+
+/// 取出一个到期的排队任务并标记为运行中
+async fn claim_next(db: &DatabaseConnection) -> Result<Option<job::Model>, DbErr> {
+    loop {
+        let now = chrono::Utc::now().naive_utc();
+        let Some(candidate) = job::Entity::find()
+            .filter(job::Column::Status.eq(JobStatus::Queued.as_str()))
+            .filter(job::Column::RunAfter.lte(now))
+            .order_by_asc(job::Column::CreatedAt)
+            .one(db)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        // 以状态为条件更新，多个 worker 同时取到同一任务时只有一个成功
+        let result = job::Entity::update_many()
+            .col_expr(
+                job::Column::Status,
+                Expr::value(JobStatus::Running.as_str()),
+            )
+            .col_expr(job::Column::Attempts, Expr::value(candidate.attempts + 1))
+            .col_expr(job::Column::UpdatedAt, Expr::value(now))
+            .filter(job::Column::Id.eq(candidate.id))
+            .filter(job::Column::Status.eq(JobStatus::Queued.as_str()))
+            .exec(db)
+            .await?;
+        if result.rows_affected == 1 {
+            return job::Entity::find_by_id(candidate.id).one(db).await;
+        }
+    }
+}
+
+async fn run_job(db: &DatabaseConnection, runner: JobRunner, model: job::Model) {
+    let token = CancellationToken::new();
+    RUNNING.lock().unwrap().insert(model.id, token.clone());
+    publish_update(&model);
+
+    let outcome = match serde_json::from_str::<Value>(&model.payload) {
+        Ok(payload) => {
+            let ctx = JobContext {
+                db: db.clone(),
+                id: model.id,
+                kind: model.kind.clone(),
+                payload,
+                cancel: token.clone(),
+            };
+            tokio::select! {
+                result = runner(ctx) => Some(result),
+                _ = token.cancelled() => None,
+            }
+        }
+        Err(e) => Some(Err(JobError::Fatal(format!("任务参数无效: {}", e)))),
+    };
+
+    RUNNING.lock().unwrap().remove(&model.id);
+    let id = model.id;
+    if let Err(e) = finish(db, model, outcome).await {
+        tracing::error!("Failed to record job {} result: {}", id, e);
+    }
+}
+
+/// 写入任务的执行结果；`None` 表示已取消
+async fn finish(
+    db: &DatabaseConnection,
+    model: job::Model,
+    outcome: Option<Result<Value, JobError>>,
+) -> Result<(), DbErr> {
+    let now = chrono::Utc::now().naive_utc();
+    let attempts = model.attempts;
+    let max_attempts = model.max_attempts;
+    let kind = model.kind.clone();
+    let id = model.id;
+    let mut active: job::ActiveModel = model.into();
+    active.updated_at = Set(now);
+
+    let succeeded = matches!(outcome, Some(Ok(_)));
+    match outcome {
+        Some(Ok(result)) => {
+            active.status = Set(JobStatus::Succeeded.as_str().to_string());
+            active.progress = Set(1.0);
+            active.result = Set(Some(result.to_string()));
+            active.error = Set(None);
+            active.finished_at = Set(Some(now));
+        }
+        Some(Err(JobError::Retry(e))) if attempts < max_attempts => {
+            let delay = (RETRY_BASE_SECS << (attempts - 1).clamp(0, 16)).min(RETRY_MAX_SECS);
+            tracing::warn!(
+                "Job {} ({}) failed, retrying in {}s: {}",
+                kind,
+                attempts,
+                delay,
+                e
+            );
+            active.status = Set(JobStatus::Queued.as_str().to_string());
+            active.run_after = Set(now + chrono::Duration::seconds(delay));
+            active.message = Set(Some(format!("{} 秒后重试", delay)));
+            active.error = Set(Some(e));
+        }
+        Some(Err(JobError::Retry(e) | JobError::Fatal(e))) => {
+            tracing::warn!("Job {} failed: {}", kind, e);
+            active.status = Set(JobStatus::Failed.as_str().to_string());
+            active.error = Set(Some(e));
+            active.finished_at = Set(Some(now));
+        }
+        None => {
+            active.status = Set(JobStatus::Cancelled.as_str().to_string());
+            active.message = Set(Some("已取消".to_string()));
+            active.finished_at = Set(Some(now));
+        }
+    }
+
+    // 只覆盖仍在运行的记录：已被取消（例如取消时 worker 尚未登记）的任务保持取消
+    let model = match job::Entity::update(active)
+        .filter(job::Column::Status.eq(JobStatus::Running.as_str()))
+        .exec(db)
+        .await
+    {
+        Ok(model) => model,
+        Err(DbErr::RecordNotUpdated) => {
+            remove_artifact(id).await;
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+    if !succeeded {
+        remove_artifact(model.id).await;
+    }
+    publish_update(&model);
+    Ok(())
+}
+
+async fn remove_artifact(id: Uuid) {
+    let path = artifact_path(id);
+    if path.exists() {
+        if let Err(e) = tokio::fs::remove_file(&path).await {
+            tracing::warn!("Failed to remove job artifact {:?}: {}", path, e);
+        }
+    }
+}
+
+fn publish_update(model: &job::Model) {
+    event_bus::publish(AppEvent::JobUpdated {
+        id: model.id,
+        kind: model.kind.clone(),
+        status: model.status.clone(),
+        progress: model.progress,
+        message: model.message.clone(),
+    });
+}
@@ -1,3 +1,26 @@
 //! 服务层模块入口
 //!
-//! 提供业务逻辑实现 (目前为空，后续功能实现时添加)
+//! 提供与 HTTP 无关的业务逻辑实现
+
+pub mod ai_provider;
+pub mod ai_router;
+pub mod ai_usage;
+pub mod card_bulk_edit;
+pub mod card_diff;
+pub mod card_fields;
+pub mod card_lint;
+pub mod card_match;
+pub mod card_merge;
+pub mod card_query;
+pub mod card_spec;
+pub mod charx;
+pub mod event_bus;
+pub mod job_queue;
+pub mod operation_journal;
+pub mod png_chunks;
+pub mod prompt_template;
+pub mod search_index;
+pub mod tag_admin;
+pub mod tag_index;
+pub mod version_snapshot;
+pub mod version_store;
@@ -0,0 +1,340 @@
+//! 批量操作日志
+//!
+//! 批量接口在同一事务中先调用 `record` 保存被修改列的原值，执行修改后再调用
+//! `record_after` 保存修改后的值；`undo` 在事务中确认各行仍是修改后的值，
+//! 再把这些列恢复原值（并刷新 `updated_at`）。日志只保留最近 `JOURNAL_LIMIT` 条。
+
+use std::str::FromStr;
+
+use sea_orm::{
+    ActiveModelBehavior, ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr,
+    EntityTrait, IntoActiveModel, ModelTrait, QueryFilter, QueryOrder, QuerySelect, Set,
+    TransactionTrait,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use crate::entities::{character_card, image, operation, operation_item};
+
+/// 保留的操作条数
+pub const JOURNAL_LIMIT: u64 = 200;
+
+/// 被批量修改的表
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalTarget {
+    Card,
+    Image,
+}
+
+impl JournalTarget {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Card => "card",
+            Self::Image => "image",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "card" => Some(Self::Card),
+            "image" => Some(Self::Image),
+            _ => None,
+        }
+    }
+}
+
+pub enum UndoError {
+    NotFound,
+    AlreadyUndone,
+    /// 这些行在操作之后又被修改过
+    Conflict(Vec<Uuid>),
+    Db(DbErr),
+}
+
+impl From<DbErr> for UndoError {
+    fn from(e: DbErr) -> Self {
+        Self::Db(e)
+    }
+}
+
+/// 待记录的批量操作
+pub struct NewOperation<'a> {
+    pub kind: &'a str,
+    pub target: JournalTarget,
+    pub summary: String,
+    /// 将被修改的列（与实体字段同名）
+    pub columns: &'a [&'a str],
+    pub ids: &'a [Uuid],
+}
+
+/// 记录操作前的列值，返回操作 ID；应与修改在同一事务中调用
+pub async fn record<C: ConnectionTrait>(conn: &C, op: NewOperation<'_>) -> Result<Uuid, DbErr> {
+    let columns: Vec<String> = op.columns.iter().map(|c| c.to_string()).collect();
+    let rows = snapshot(conn, op.target, op.ids, &columns).await?;
+
+    let id = Uuid::new_v4();
+    operation::ActiveModel {
+        id: Set(id),
+        kind: Set(op.kind.to_string()),
+        target: Set(op.target.as_str().to_string()),
+        summary: Set(op.summary),
+        columns: Set(serde_json::to_string(&columns).unwrap_or_else(|_| "[]".to_string())),
+        item_count: Set(rows.len() as i32),
+        created_at: Set(chrono::Utc::now().naive_utc()),
+        undone_at: Set(None),
+    }
+    .insert(conn)
+    .await?;
+
+    // SQLite 单条语句的参数个数有限，分批写入
+    for chunk in rows.chunks(300) {
+        operation_item::Entity::insert_many(chunk.iter().map(|(row_id, before)| {
+            operation_item::ActiveModel {
+                operation_id: Set(id),
+                row_id: Set(*row_id),
+                before: Set(before.clone()),
+                after: Set(None),
+            }
+        }))
+        .exec(conn)
+        .await?;
+    }
+
+    prune(conn).await?;
+    Ok(id)
+}
+
+/// 记录操作后的列值；应在修改之后、提交之前于同一事务中调用
+pub async fn record_after<C: ConnectionTrait>(conn: &C, id: Uuid) -> Result<(), DbErr> {
+    let Some(op) = operation::Entity::find_by_id(id).one(conn).await? else {
+        return Ok(());
+    };
+    let target = JournalTarget::parse(&op.target)
+        .ok_or_else(|| DbErr::Custom(format!("未知的操作对象: {}", op.target)))?;
+    let columns: Vec<String> = serde_json::from_str(&op.columns).unwrap_or_default();
+    let ids: Vec<Uuid> = operation_item::Entity::find()
+        .select_only()
+        .column(operation_item::Column::RowId)
+        .filter(operation_item::Column::OperationId.eq(id))
+        .into_tuple()
+        .all(conn)
+        .await?;
+
+    for (row_id, after) in snapshot(conn, target, &ids, &columns).await? {
+        operation_item::Entity::update_many()
+            .col_expr(
+                operation_item::Column::After,
+                sea_orm::sea_query::Expr::value(after),
+            )
+            .filter(operation_item::Column::OperationId.eq(id))
+            .filter(operation_item::Column::RowId.eq(row_id))
+            .exec(conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// 最近的操作，新的在前
+pub async fn list(db: &DatabaseConnection, limit: u64) -> Result<Vec<operation::Model>, DbErr> {
+    operation::Entity::find()
+        .order_by_desc(operation::Column::CreatedAt)
+        .limit(limit)
+        .all(db)
+        .await
+}
+
+/// 撤销操作，返回恢复的行 ID；已不存在的行跳过
+///
+/// 任一行被修改的列已不是操作后的值时整体放弃，返回 `UndoError::Conflict`。
+pub async fn undo(
+    db: &DatabaseConnection,
+    id: Uuid,
+) -> Result<(operation::Model, Vec<Uuid>), UndoError> {
+    let txn = db.begin().await?;
+
+    let op = operation::Entity::find_by_id(id)
+        .one(&txn)
+        .await?
+        .ok_or(UndoError::NotFound)?;
+    if op.undone_at.is_some() {
+        return Err(UndoError::AlreadyUndone);
+    }
+    let target = JournalTarget::parse(&op.target)
+        .ok_or_else(|| DbErr::Custom(format!("未知的操作对象: {}", op.target)))?;
+    let columns: Vec<String> = serde_json::from_str(&op.columns).unwrap_or_default();
+    let items = operation_item::Entity::find()
+        .filter(operation_item::Column::OperationId.eq(id))
+        .all(&txn)
+        .await?;
+
+    let (restored, conflicts) = match target {
+        JournalTarget::Card => {
+            restore_rows::<character_card::Entity, _>(&txn, &items, &columns).await?
+        }
+        JournalTarget::Image => restore_rows::<image::Entity, _>(&txn, &items, &columns).await?,
+    };
+    // 未提交的事务在返回时回滚
+    if !conflicts.is_empty() {
+        return Err(UndoError::Conflict(conflicts));
+    }
+
+    let mut active: operation::ActiveModel = op.into();
+    active.undone_at = Set(Some(chrono::Utc::now().naive_utc()));
+    let op = active.update(&txn).await?;
+
+    txn.commit().await?;
+    Ok((op, restored))
+}
+
+/// 删除超出保留条数的旧操作
+async fn prune<C: ConnectionTrait>(conn: &C) -> Result<(), DbErr> {
+    let stale: Vec<Uuid> = operation::Entity::find()
+        .select_only()
+        .column(operation::Column::Id)
+        .order_by_desc(operation::Column::CreatedAt)
+        .offset(JOURNAL_LIMIT)
+        .limit(1000)
+        .into_tuple()
+        .all(conn)
+        .await?;
+    if stale.is_empty() {
+        return Ok(());
+    }
+
+    operation_item::Entity::delete_many()
+        .filter(operation_item::Column::OperationId.is_in(stale.clone()))
+        .exec(conn)
+        .await?;
+    operation::Entity::delete_many()
+        .filter(operation::Column::Id.is_in(stale))
+        .exec(conn)
+        .await?;
+    Ok(())
+}
+
+async fn snapshot<C: ConnectionTrait>(
+    conn: &C,
+    target: JournalTarget,
+    ids: &[Uuid],
+    columns: &[String],
+) -> Result<Vec<(Uuid, String)>, DbErr> {
+    match target {
+        JournalTarget::Card => snapshot_rows::<character_card::Entity, _>(conn, ids, columns).await,
+        JournalTarget::Image => snapshot_rows::<image::Entity, _>(conn, ids, columns).await,
+    }
+}
+
+fn id_column<E: EntityTrait>() -> Result<E::Column, DbErr> {
+    E::Column::from_str("id").map_err(|_| DbErr::Custom("实体缺少 id 列".to_string()))
+}
+
+/// 读取各行被修改列的当前值：(行 ID, JSON 对象)
+async fn snapshot_rows<E, C>(
+    conn: &C,
+    ids: &[Uuid],
+    columns: &[String],
+) -> Result<Vec<(Uuid, String)>, DbErr>
+where
+    E: EntityTrait,
+    E::Model: Serialize,
+    C: ConnectionTrait,
+{
+    let id_col = id_column::<E>()?;
+    let mut rows = Vec::with_capacity(ids.len());
+    for chunk in ids.chunks(500) {
+        for model in E::find()
+            .filter(id_col.is_in(chunk.iter().copied()))
+            .all(conn)
+            .await?
+        {
+            let Value::Object(full) = to_json(&model)? else {
+                continue;
+            };
+            let Some(row_id) = full
+                .get("id")
+                .and_then(|v| v.as_str())
+                .and_then(|s| Uuid::parse_str(s).ok())
+            else {
+                continue;
+            };
+            rows.push((row_id, Value::Object(pick(&full, columns)).to_string()));
+        }
+    }
+    Ok(rows)
+}
+
+fn pick(full: &Map<String, Value>, columns: &[String]) -> Map<String, Value> {
+    columns
+        .iter()
+        .filter_map(|c| full.get(c).map(|v| (c.clone(), v.clone())))
+        .collect()
+}
+
+/// 把各行被修改的列恢复为记录的值，返回 (恢复的行 ID, 操作后又被修改的行 ID)
+async fn restore_rows<E, C>(
+    conn: &C,
+    items: &[operation_item::Model],
+    columns: &[String],
+) -> Result<(Vec<Uuid>, Vec<Uuid>), DbErr>
+where
+    E: EntityTrait,
+    E::Model: Serialize + DeserializeOwned + IntoActiveModel<E::ActiveModel>,
+    E::ActiveModel: ActiveModelTrait<Entity = E> + ActiveModelBehavior + Send,
+    C: ConnectionTrait,
+{
+    let id_col = id_column::<E>()?;
+    let names = columns;
+    let columns: Vec<E::Column> = columns
+        .iter()
+        .filter_map(|c| E::Column::from_str(c).ok())
+        .collect();
+
+    // 有 updated_at 列的表同时刷新，使客户端持有的旧 ETag 失效
+    let updated_at = E::Column::from_str("updated_at").ok();
+    let now = chrono::Utc::now().naive_utc();
+
+    let mut restored = Vec::with_capacity(items.len());
+    let mut conflicts = Vec::new();
+    for item in items {
+        let Some(current) = E::find().filter(id_col.eq(item.row_id)).one(conn).await? else {
+            continue;
+        };
+
+        let mut merged = to_json(&current)?;
+        // 旧记录没有操作后的值，不做检查
+        if let (Some(obj), Some(after)) = (merged.as_object(), item.after.as_deref()) {
+            let after: Value =
+                serde_json::from_str(after).map_err(|e| DbErr::Custom(e.to_string()))?;
+            if Value::Object(pick(obj, names)) != after {
+                conflicts.push(item.row_id);
+                continue;
+            }
+        }
+
+        // 在当前行上覆盖原值，再只把被修改的列标记为待更新
+        if let (Some(obj), Ok(Value::Object(before))) = (
+            merged.as_object_mut(),
+            serde_json::from_str::<Value>(&item.before),
+        ) {
+            obj.extend(before);
+        }
+        let original: E::Model =
+            serde_json::from_value(merged).map_err(|e| DbErr::Custom(e.to_string()))?;
+
+        let mut active = current.into_active_model();
+        for col in &columns {
+            active.set(*col, original.get(*col));
+        }
+        if let Some(col) = updated_at {
+            active.set(col, now.into());
+        }
+        active.update(conn).await?;
+        restored.push(item.row_id);
+    }
+    Ok((restored, conflicts))
+}
+
+fn to_json<M: Serialize>(model: &M) -> Result<Value, DbErr> {
+    serde_json::to_value(model).map_err(|e| DbErr::Custom(e.to_string()))
+}
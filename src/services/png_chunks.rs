@@ -0,0 +1,258 @@
+//! PNG 元数据块读写
+//!
+//! 角色卡以 base64 JSON 的形式存放在 PNG 文本块中（关键字 `ccv3` / `chara`）。
+//! 读取时支持 tEXt / zTXt / iTXt 三种文本块；写入时直接在原始字节流中替换文本块，
+//! 不重新编码像素，其余辅助块（颜色配置、时间戳等）原样保留。
+
+use base64::{engine::general_purpose, Engine as _};
+use flate2::read::ZlibDecoder;
+use std::io::Read;
+
+pub const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// V3 元数据关键字
+pub const KEYWORD_CCV3: &str = "ccv3";
+/// V1/V2 元数据关键字
+pub const KEYWORD_CHARA: &str = "chara";
+/// 压缩文本块解压后的最大字节数
+pub const MAX_TEXT_CHUNK: u64 = 64 * 1024 * 1024;
+
+/// 原始 PNG 块
+pub struct RawChunk<'a> {
+    pub kind: [u8; 4],
+    pub data: &'a [u8],
+    /// 块在原始字节流中的完整范围（含长度、类型与 CRC）
+    pub span: std::ops::Range<usize>,
+}
+
+/// 解析后的文本块
+pub struct TextChunk {
+    /// 来源块类型：tEXt / zTXt / iTXt
+    pub kind: &'static str,
+    pub keyword: String,
+    pub text: String,
+}
+
+/// 按顺序读取所有块
+pub fn read_chunks(data: &[u8]) -> Result<Vec<RawChunk<'_>>, String> {
+    if data.len() < 8 || data[..8] != PNG_SIGNATURE {
+        return Err("非法的 PNG 文件签名".to_string());
+    }
+
+    let mut chunks = Vec::new();
+    let mut offset = 8;
+    while offset + 8 <= data.len() {
+        let length = u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+        let kind: [u8; 4] = data[offset + 4..offset + 8].try_into().unwrap();
+        let data_start = offset + 8;
+        let data_end = data_start + length;
+
+        if data_end + 4 > data.len() {
+            tracing::warn!("PNG Chunk 越界，停止解析");
+            break;
+        }
+
+        chunks.push(RawChunk {
+            kind,
+            data: &data[data_start..data_end],
+            span: offset..data_end + 4,
+        });
+
+        if &kind == b"IEND" {
+            break;
+        }
+        offset = data_end + 4;
+    }
+
+    Ok(chunks)
+}
+
+/// 文本块的关键字（NUL 之前的原始字节），不解压正文；非文本块时返回 None
+pub fn text_chunk_keyword<'a>(chunk: &RawChunk<'a>) -> Option<&'a [u8]> {
+    if !matches!(&chunk.kind, b"tEXt" | b"zTXt" | b"iTXt") {
+        return None;
+    }
+    let null_pos = chunk.data.iter().position(|&b| b == 0)?;
+    Some(&chunk.data[..null_pos])
+}
+
+/// 解析单个文本块，非文本块或格式错误时返回 None
+pub fn parse_text_chunk(chunk: &RawChunk<'_>) -> Option<TextChunk> {
+    let null_pos = chunk.data.iter().position(|&b| b == 0)?;
+    let keyword = latin1_to_string(&chunk.data[..null_pos]);
+    let rest = &chunk.data[null_pos + 1..];
+
+    match &chunk.kind {
+        b"tEXt" => Some(TextChunk {
+            kind: "tEXt",
+            keyword,
+            text: latin1_to_string(rest),
+        }),
+        b"zTXt" => {
+            // 压缩方法 (1 byte，仅定义了 0 = zlib) + 压缩数据
+            let (&method, compressed) = rest.split_first()?;
+            if method != 0 {
+                return None;
+            }
+            let decompressed = inflate_logged(&keyword, compressed)?;
+            Some(TextChunk {
+                kind: "zTXt",
+                keyword,
+                text: latin1_to_string(&decompressed),
+            })
+        }
+        b"iTXt" => {
+            // 压缩标志 + 压缩方法 + 语言标签\0 + 翻译关键字\0 + UTF-8 文本
+            if rest.len() < 2 {
+                return None;
+            }
+            let compressed = rest[0] == 1;
+            let after_flags = &rest[2..];
+            let lang_end = after_flags.iter().position(|&b| b == 0)?;
+            let after_lang = &after_flags[lang_end + 1..];
+            let trans_end = after_lang.iter().position(|&b| b == 0)?;
+            let body = &after_lang[trans_end + 1..];
+
+            let text_bytes = if compressed {
+                inflate_logged(&keyword, body)?
+            } else {
+                body.to_vec()
+            };
+            Some(TextChunk {
+                kind: "iTXt",
+                keyword,
+                text: String::from_utf8(text_bytes).ok()?,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// 读取所有文本块
+pub fn read_text_chunks(data: &[u8]) -> Result<Vec<TextChunk>, String> {
+    Ok(read_chunks(data)?
+        .iter()
+        .filter_map(parse_text_chunk)
+        .collect())
+}
+
+/// 解码角色卡文本：标准为 base64，部分工具直接写入 JSON 原文
+pub fn decode_card_text(text: &str) -> Option<String> {
+    if let Ok(decoded) = general_purpose::STANDARD.decode(text.trim()) {
+        if let Ok(s) = String::from_utf8(decoded) {
+            return Some(s);
+        }
+    }
+    let trimmed = text.trim_start();
+    if trimmed.starts_with('{') {
+        return Some(text.to_string());
+    }
+    None
+}
+
+/// 提取角色卡 JSON，`ccv3` 优先于 `chara`
+pub fn extract_card_json(data: &[u8]) -> Result<String, String> {
+    let chunks = read_text_chunks(data)?;
+
+    for keyword in [KEYWORD_CCV3, KEYWORD_CHARA] {
+        for chunk in chunks.iter().filter(|c| c.keyword == keyword) {
+            if let Some(json) = decode_card_text(&chunk.text) {
+                return Ok(json);
+            }
+        }
+    }
+
+    Err("无效的角色卡图片：未找到元数据 (ccv3/chara)".to_string())
+}
+
+/// 写入角色卡元数据
+///
+/// 移除原有的 `ccv3` / `chara` 文本块（任意类型，只按关键字判断，无法解压的也一并移除），
+/// 在 IEND 前写入新的 tEXt 块。其余块按原始字节复制，像素数据不做任何改动。
+pub fn write_card_chunks(
+    png: &[u8],
+    ccv3_json: Option<&str>,
+    chara_json: Option<&str>,
+) -> Result<Vec<u8>, String> {
+    let chunks = read_chunks(png)?;
+    if !chunks.iter().any(|c| &c.kind == b"IEND") {
+        return Err("PNG 文件不完整：缺少 IEND".to_string());
+    }
+
+    let text_len = ccv3_json.map_or(0, str::len) + chara_json.map_or(0, str::len);
+    let mut output = Vec::with_capacity(png.len() + text_len * 2);
+    output.extend_from_slice(&PNG_SIGNATURE);
+
+    for chunk in &chunks {
+        let is_card_chunk = text_chunk_keyword(chunk)
+            .is_some_and(|k| k == KEYWORD_CCV3.as_bytes() || k == KEYWORD_CHARA.as_bytes());
+        if is_card_chunk {
+            continue;
+        }
+
+        if &chunk.kind == b"IEND" {
+            // 旧版前端只读取 chara，放在 ccv3 之前
+            if let Some(chara) = chara_json {
+                write_text_chunk(&mut output, KEYWORD_CHARA, chara);
+            }
+            if let Some(ccv3) = ccv3_json {
+                write_text_chunk(&mut output, KEYWORD_CCV3, ccv3);
+            }
+        }
+
+        output.extend_from_slice(&png[chunk.span.clone()]);
+    }
+
+    Ok(output)
+}
+
+/// 追加一个 tEXt 块，内容为 base64 编码的 JSON
+fn write_text_chunk(output: &mut Vec<u8>, keyword: &str, json: &str) {
+    let encoded = general_purpose::STANDARD.encode(json.as_bytes());
+
+    let mut body = Vec::with_capacity(keyword.len() + 1 + encoded.len());
+    body.extend_from_slice(keyword.as_bytes());
+    body.push(0);
+    body.extend_from_slice(encoded.as_bytes());
+
+    write_chunk(output, b"tEXt", &body);
+}
+
+fn write_chunk(output: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    output.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    output.extend_from_slice(kind);
+    output.extend_from_slice(body);
+
+    let mut crc = flate2::Crc::new();
+    crc.update(kind);
+    crc.update(body);
+    output.extend_from_slice(&crc.sum().to_be_bytes());
+}
+
+/// 解压 zlib 数据，超过 [`MAX_TEXT_CHUNK`] 时报错
+fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    ZlibDecoder::new(data)
+        .take(MAX_TEXT_CHUNK + 1)
+        .read_to_end(&mut out)
+        .map_err(|e| format!("解压失败: {}", e))?;
+    if out.len() as u64 > MAX_TEXT_CHUNK {
+        return Err(format!("解压后超过 {} MB", MAX_TEXT_CHUNK / 1024 / 1024));
+    }
+    Ok(out)
+}
+
+/// 解压失败的文本块记录日志后跳过
+fn inflate_logged(keyword: &str, data: &[u8]) -> Option<Vec<u8>> {
+    inflate(data)
+        .map_err(|e| tracing::warn!("PNG 文本块 {} 已跳过: {}", keyword, e))
+        .ok()
+}
+
+/// tEXt / zTXt 规定为 Latin-1 编码，但不少工具直接写入 UTF-8，优先按 UTF-8 解析
+fn latin1_to_string(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => s.to_string(),
+        Err(_) => bytes.iter().map(|&b| b as char).collect(),
+    }
+}
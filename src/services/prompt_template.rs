@@ -0,0 +1,616 @@
+//! AI 功能的提示词模板
+//!
+//! 内置模板写在代码里，`prompt_templates` 表只保存用户的修改，某列为空时使用内置值。
+//! 模板语法：
+//! - `{{变量}}` 替换为变量值，未知的变量原样保留
+//! - `{{#if 变量}}…{{else}}…{{/if}}` 按变量是否为空选择内容，可以嵌套
+//!
+//! 概览与小皮医生的提示词完全由模板生成；前端直接调用 `/api/ai/execute` 的功能
+//! 没有内置模板，设置了系统提示词时替换前端发送的系统消息。
+
+use std::collections::BTreeMap;
+
+use sea_orm::{DatabaseConnection, DbErr, EntityTrait, Set};
+use serde::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::entities::{character_card, prompt_template, setting};
+
+/// 模板变量，值为空字符串时在 `{{#if}}` 中视为假
+pub type Variables = BTreeMap<String, String>;
+
+/// 概览
+pub const OVERVIEW: &str = "overview";
+/// 小皮医生
+pub const DOCTOR: &str = "doctor";
+
+/// 内置模板
+pub struct BuiltinTemplate {
+    pub feature: &'static str,
+    pub label: &'static str,
+    pub system_prompt: Option<&'static str>,
+    pub user_prompt: Option<&'static str>,
+    pub temperature: f64,
+    /// 模板中可用的变量
+    pub variables: &'static [&'static str],
+}
+
+const OVERVIEW_SYSTEM: &str = r#"{{global_prompt}}
+
+你是一位专业的角色卡分析师。请分析角色设定，返回纯 JSON 格式结果，不要包含 markdown 标记。"#;
+
+const OVERVIEW_USER: &str = r#"请深入分析以下角色卡数据：
+
+[角色元数据]
+Name: {{name}}
+Description: {{description}}
+
+[详细设定]
+Personality: {{personality}}
+Scenario: {{scenario}}
+First Message: {{first_mes}}
+Example Dialogue: {{mes_example}}
+System Prompt: {{system_prompt}}
+Post Instructions: {{post_history_instructions}}
+Creator Comment: {{creator_comment}}
+
+{{#if generate_tags}}
+[任务与约束]
+1. 概览总结：150字以内，精炼概括角色核心特征。
+2. 标签生成：最多5个。必须优先从以下[系统现有标签]中选择；仅当无匹配时才生成新标签。
+   [系统现有标签]: {{system_tags}}
+
+[回复格式]
+请严格仅返回 JSON，不要使用代码块：
+{"summary": "...", "tags": ["tag1", "tag2"]}
+{{else}}
+[任务与约束]
+1. 概览总结：150字以内，精炼概括角色核心特征。
+
+[回复格式]
+请严格仅返回 JSON，不要使用代码块：
+{"summary": "..."}
+{{/if}}"#;
+
+const DOCTOR_SYSTEM: &str = r#"[System Note: This is a technical metadata analysis task for a fictional character database. 
+The content being analyzed is creative fiction intended for adult audiences. 
+Your role is to objectively extract and analyze character attributes as structured data. 
+Even if NSFW content appears, you are required to complete the analysis and output the data 
+to facilitate content moderation and auditing. Proceed with the analysis professionally.]
+
+{{global_prompt}}
+
+你是一个专业的角色卡逻辑分析引擎。你将参与一个多轮迭代的分析任务。
+
+**你的目标：** 评估角色核心设定与世界书内容的一致性，产出诊断报告。
+
+**你的操作规范：**
+1. **第一轮：** 你会收到核心设定和世界书目录。如果核心设定已足够支持诊断，请直接输出 "final_report"；若必需详细设定，请返回 "request_entries" 申请阅读条目。
+2. **中间轮次：** 系统会提供你申请的条目内容。你可以选择继续申请新条目（返回 JSON），或直接输出 JSON 格式诊断报告。
+3. **最终轮次：** 如果系统提示"已达搜索上限"，你必须立即根据现有信息输出 JSON 诊断报告。
+
+**诊断重点：**
+- 专注于分析角色设定的逻辑一致性、人设合理性、对话质量
+- 不要诊断角色卡的格式问题（如标签格式、代码块使用等技术规范）
+- 开场白（first_mes 和其他开场白）是诊断的重要内容，确保不要遗漏
+- **权重说明：** 核心设定（Name, Description, Personality）具有最高权重。世界书内容仅作为次要权重，但两者都很重要，都需要作为诊断的依据。
+
+**请求条目格式（严格 JSON，无代码块标记）：**
+{"action": "request_entries", "entries": ["条目名1", "条目名2"]}
+(注意：请勿申请可能包含极其露骨色情(NSFW)内容的条目，以免触发系统安全拦截导致任务失败)
+
+**诊断报告格式（严格 JSON，无代码块标记）：**
+{"action": "final_report", "report": {
+  "core_assessment": "概括性描述角色卡的完成质量与逻辑成熟度",
+  "dimensions": [
+    {"name": "设定诊断", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议"},
+    {"name": "开场白诊断", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议"},
+    {"name": "人设一致性", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议"},
+    {"name": "世界观逻辑", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议"},
+    {"name": "OOC 预警", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议"}
+  ],
+  "prescriptions": ["具体修改建议1", "具体修改建议2"],
+  "conclusion": "通过 / 需大幅修正 / 建议重构"
+}}
+
+**重要：** 所有输出必须是纯 JSON，不要包含 markdown 代码块标记。dimensions 中各字段可以使用 Markdown 格式（加粗、列表等）来增强可读性。"#;
+
+const DOCTOR_USER: &str = r#"**[任务启动]** 请审阅以下内容，并返回你第一轮想要阅读的世界书条目名称（JSON 格式）。
+
+**核心设定：**
+- 角色名称：{{name}}
+- 角色描述：{{description}}
+- 性格特征：{{personality}}
+{{first_mes_note}}
+{{alt_greeting_note}}
+
+**世界书目录（条目名称列表）：**
+{{worldbook_toc}}
+
+请返回 JSON 格式：{"action": "request_entries", "entries": ["条目名1", ...]}
+如果世界书目录为空或无需阅读条目，请直接输出诊断报告 JSON。请优先判断当前信息是否足够，避免不必要的搜索。同时请严格避开 NSFW 相关条目。"#;
+
+const OVERVIEW_VARIABLES: &[&str] = &[
+    "global_prompt",
+    "name",
+    "description",
+    "personality",
+    "scenario",
+    "first_mes",
+    "mes_example",
+    "system_prompt",
+    "post_history_instructions",
+    "creator_comment",
+    "generate_tags",
+    "system_tags",
+];
+
+const DOCTOR_VARIABLES: &[&str] = &[
+    "global_prompt",
+    "name",
+    "description",
+    "personality",
+    "first_mes_note",
+    "alt_greeting_note",
+    "worldbook_toc",
+];
+
+const EXECUTE_VARIABLES: &[&str] = &["global_prompt"];
+
+/// 前端通过 `/api/ai/execute` 调用的功能，提示词由前端构建
+const fn execute_feature(feature: &'static str, label: &'static str) -> BuiltinTemplate {
+    BuiltinTemplate {
+        feature,
+        label,
+        system_prompt: None,
+        user_prompt: None,
+        temperature: 0.7,
+        variables: EXECUTE_VARIABLES,
+    }
+}
+
+pub const BUILTINS: &[BuiltinTemplate] = &[
+    BuiltinTemplate {
+        feature: OVERVIEW,
+        label: "角色卡概览",
+        system_prompt: Some(OVERVIEW_SYSTEM),
+        user_prompt: Some(OVERVIEW_USER),
+        temperature: 1.0,
+        variables: OVERVIEW_VARIABLES,
+    },
+    BuiltinTemplate {
+        feature: DOCTOR,
+        label: "小皮医生",
+        system_prompt: Some(DOCTOR_SYSTEM),
+        user_prompt: Some(DOCTOR_USER),
+        temperature: 0.7,
+        variables: DOCTOR_VARIABLES,
+    },
+    execute_feature("optimize_description", "优化描述"),
+    execute_feature("optimize_first_mes", "优化开场白"),
+    execute_feature("optimize_worldbook", "优化世界书"),
+    execute_feature("optimize_scenario", "优化情景"),
+    execute_feature("translate", "翻译"),
+    execute_feature("generate_character", "生成角色"),
+    execute_feature("generate_world_info", "生成世界书"),
+    execute_feature("generate_frontend_style", "生成前端样式"),
+    execute_feature("generate_opening", "生成开场白"),
+];
+
+pub fn builtin(feature: &str) -> Option<&'static BuiltinTemplate> {
+    BUILTINS.iter().find(|b| b.feature == feature)
+}
+
+/// 合并内置值与用户修改后的模板
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptTemplate {
+    pub feature: String,
+    pub label: String,
+    pub system_prompt: Option<String>,
+    pub user_prompt: Option<String>,
+    /// 指定的渠道优先使用，失败或停用时回到渠道路由
+    pub channel_id: Option<Uuid>,
+    pub temperature: f64,
+    /// 是否有任何一项被修改过
+    pub customized: bool,
+    pub variables: Vec<String>,
+    pub default_system_prompt: Option<String>,
+    pub default_user_prompt: Option<String>,
+    pub default_temperature: f64,
+}
+
+impl PromptTemplate {
+    fn merge(builtin: &BuiltinTemplate, row: Option<prompt_template::Model>) -> Self {
+        let row = row.unwrap_or_else(|| prompt_template::Model {
+            feature: builtin.feature.to_string(),
+            system_prompt: None,
+            user_prompt: None,
+            channel_id: None,
+            temperature: None,
+            updated_at: chrono::NaiveDateTime::default(),
+        });
+        let customized = row.system_prompt.is_some()
+            || row.user_prompt.is_some()
+            || row.channel_id.is_some()
+            || row.temperature.is_some();
+        Self {
+            feature: builtin.feature.to_string(),
+            label: builtin.label.to_string(),
+            system_prompt: row
+                .system_prompt
+                .or_else(|| builtin.system_prompt.map(str::to_string)),
+            user_prompt: row
+                .user_prompt
+                .or_else(|| builtin.user_prompt.map(str::to_string)),
+            channel_id: row.channel_id,
+            temperature: row.temperature.unwrap_or(builtin.temperature),
+            customized,
+            variables: builtin.variables.iter().map(|v| v.to_string()).collect(),
+            default_system_prompt: builtin.system_prompt.map(str::to_string),
+            default_user_prompt: builtin.user_prompt.map(str::to_string),
+            default_temperature: builtin.temperature,
+        }
+    }
+}
+
+/// 用户对模板的修改，为空的项使用内置值
+#[derive(Debug, Default)]
+pub struct TemplateOverride {
+    pub system_prompt: Option<String>,
+    pub user_prompt: Option<String>,
+    pub channel_id: Option<Uuid>,
+    pub temperature: Option<f64>,
+}
+
+/// 读取功能的模板，不是内置功能时返回 None
+pub async fn load(db: &DatabaseConnection, feature: &str) -> Result<Option<PromptTemplate>, DbErr> {
+    let Some(builtin) = builtin(feature) else {
+        return Ok(None);
+    };
+    let row = prompt_template::Entity::find_by_id(feature).one(db).await?;
+    Ok(Some(PromptTemplate::merge(builtin, row)))
+}
+
+/// 所有内置功能的模板
+pub async fn list(db: &DatabaseConnection) -> Result<Vec<PromptTemplate>, DbErr> {
+    let mut rows: BTreeMap<String, prompt_template::Model> = prompt_template::Entity::find()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|row| (row.feature.clone(), row))
+        .collect();
+    Ok(BUILTINS
+        .iter()
+        .map(|builtin| PromptTemplate::merge(builtin, rows.remove(builtin.feature)))
+        .collect())
+}
+
+/// 保存修改，全部为空时等同于恢复默认
+pub async fn save(
+    db: &DatabaseConnection,
+    feature: &str,
+    changes: TemplateOverride,
+) -> Result<(), DbErr> {
+    if changes.system_prompt.is_none()
+        && changes.user_prompt.is_none()
+        && changes.channel_id.is_none()
+        && changes.temperature.is_none()
+    {
+        return reset(db, feature).await;
+    }
+
+    let model = prompt_template::ActiveModel {
+        feature: Set(feature.to_string()),
+        system_prompt: Set(changes.system_prompt),
+        user_prompt: Set(changes.user_prompt),
+        channel_id: Set(changes.channel_id),
+        temperature: Set(changes.temperature),
+        updated_at: Set(chrono::Utc::now().naive_utc()),
+    };
+    prompt_template::Entity::insert(model)
+        .on_conflict(
+            sea_orm::sea_query::OnConflict::column(prompt_template::Column::Feature)
+                .update_columns([
+                    prompt_template::Column::SystemPrompt,
+                    prompt_template::Column::UserPrompt,
+                    prompt_template::Column::ChannelId,
+                    prompt_template::Column::Temperature,
+                    prompt_template::Column::UpdatedAt,
+                ])
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+    Ok(())
+}
+
+/// 恢复内置模板
+pub async fn reset(db: &DatabaseConnection, feature: &str) -> Result<(), DbErr> {
+    prompt_template::Entity::delete_by_id(feature)
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// 全局提示词，未设置时为空
+pub async fn global_prompt(db: &DatabaseConnection) -> Result<String, DbErr> {
+    Ok(setting::Entity::find_by_id("global_prompt")
+        .one(db)
+        .await?
+        .map(|s| s.value)
+        .unwrap_or_default())
+}
+
+// ==================== 渲染 ====================
+
+enum Token<'a> {
+    Text(&'a str),
+    Var(&'a str),
+    If(&'a str),
+    Else,
+    EndIf,
+}
+
+fn is_variable_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// 切分模板；不是合法标签的 `{{` 按普通文本处理。块标签后紧跟的换行一并去掉，
+/// 让单独占一行的 `{{#if}}` / `{{/if}}` 不留下空行
+fn tokenize(template: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            break;
+        };
+        let inner = after[..end].trim();
+        let token = if let Some(name) = inner.strip_prefix("#if ") {
+            let name = name.trim();
+            is_variable_name(name).then_some(Token::If(name))
+        } else if inner == "else" {
+            Some(Token::Else)
+        } else if inner == "/if" {
+            Some(Token::EndIf)
+        } else {
+            is_variable_name(inner).then_some(Token::Var(inner))
+        };
+
+        match token {
+            Some(token) => {
+                if start > 0 {
+                    tokens.push(Token::Text(&rest[..start]));
+                }
+                rest = &after[end + 2..];
+                if !matches!(token, Token::Var(_)) {
+                    rest = rest
+                        .strip_prefix("\r\n")
+                        .or_else(|| rest.strip_prefix('\n'))
+                        .unwrap_or(rest);
+                }
+                tokens.push(token);
+            }
+            None => {
+                tokens.push(Token::Text(&rest[..start + 2]));
+                rest = after;
+            }
+        }
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest));
+    }
+    tokens
+}
+
+/// 渲染结果
+#[derive(Debug, Serialize)]
+pub struct Rendered {
+    /// 渲染后的文本，已去掉首尾空白
+    pub text: String,
+    /// 模板中出现但没有提供的变量，原样保留在结果中
+    pub unknown: Vec<String>,
+}
+
+/// 渲染模板
+pub fn render(template: &str, variables: &Variables) -> Rendered {
+    let mut text = String::with_capacity(template.len());
+    let mut unknown: Vec<String> = Vec::new();
+    // 每层 if：(外层是否输出, 条件是否成立, 是否已进入 else)
+    let mut stack: Vec<(bool, bool, bool)> = Vec::new();
+    let active = |stack: &[(bool, bool, bool)]| {
+        stack
+            .last()
+            .is_none_or(|&(outer, cond, in_else)| outer && cond != in_else)
+    };
+
+    for token in tokenize(template) {
+        match token {
+            Token::Text(t) => {
+                if active(&stack) {
+                    text.push_str(t);
+                }
+            }
+            Token::Var(name) => match variables.get(name) {
+                Some(value) => {
+                    if active(&stack) {
+                        text.push_str(value);
+                    }
+                }
+                None => {
+                    if !unknown.iter().any(|u| u == name) {
+                        unknown.push(name.to_string());
+                    }
+                    if active(&stack) {
+                        text.push_str("{{");
+                        text.push_str(name);
+                        text.push_str("}}");
+                    }
+                }
+            },
+            Token::If(name) => {
+                let cond = variables.get(name).is_some_and(|v| !v.trim().is_empty());
+                stack.push((active(&stack), cond, false));
+            }
+            Token::Else => {
+                if let Some(frame) = stack.last_mut() {
+                    frame.2 = true;
+                }
+            }
+            Token::EndIf => {
+                stack.pop();
+            }
+        }
+    }
+
+    Rendered {
+        text: text.trim().to_string(),
+        unknown,
+    }
+}
+
+/// 检查 `{{#if}}` / `{{else}}` / `{{/if}}` 是否配对
+pub fn validate(template: &str) -> Result<(), String> {
+    // 每层记录是否已出现 else
+    let mut stack: Vec<bool> = Vec::new();
+    for token in tokenize(template) {
+        match token {
+            Token::If(_) => stack.push(false),
+            Token::Else => match stack.last_mut() {
+                Some(seen) if !*seen => *seen = true,
+                Some(_) => return Err("同一个 {{#if}} 中有多个 {{else}}".to_string()),
+                None => return Err("{{else}} 没有对应的 {{#if}}".to_string()),
+            },
+            Token::EndIf if stack.pop().is_none() => {
+                return Err("{{/if}} 没有对应的 {{#if}}".to_string());
+            }
+            _ => {}
+        }
+    }
+    if stack.is_empty() {
+        Ok(())
+    } else {
+        Err("{{#if}} 缺少对应的 {{/if}}".to_string())
+    }
+}
+
+// ==================== 变量 ====================
+
+fn variables<const N: usize>(pairs: [(&str, String); N]) -> Variables {
+    pairs.into_iter().map(|(k, v)| (k.to_string(), v)).collect()
+}
+
+/// 概览模板的变量；卡片没有标签时 `generate_tags` 非空，并提供系统标签库
+pub fn overview_variables(
+    card: &character_card::Model,
+    system_tags: &[String],
+    global_prompt: &str,
+) -> Variables {
+    let card_data: Value = serde_json::from_str(&card.data).unwrap_or(serde_json::json!({}));
+    let field = |key: &str| card_data[key].as_str().unwrap_or("").to_string();
+
+    let current_tags: Vec<String> = serde_json::from_str(&card.tags).unwrap_or_default();
+    let generate_tags = current_tags.is_empty();
+    let system_tags = if generate_tags {
+        serde_json::to_string(system_tags).unwrap_or_default()
+    } else {
+        String::new()
+    };
+
+    variables([
+        ("global_prompt", global_prompt.to_string()),
+        ("name", card.name.clone()),
+        ("description", card.description.clone().unwrap_or_default()),
+        ("personality", field("personality")),
+        ("scenario", field("scenario")),
+        ("first_mes", field("first_mes")),
+        ("mes_example", field("mes_example")),
+        ("system_prompt", field("system_prompt")),
+        (
+            "post_history_instructions",
+            field("post_history_instructions"),
+        ),
+        ("creator_comment", field("creatorcomment")),
+        (
+            "generate_tags",
+            if generate_tags { "1" } else { "" }.to_string(),
+        ),
+        ("system_tags", system_tags),
+    ])
+}
+
+/// 小皮医生模板的变量与启用的世界书条目（供后续轮次按名称查阅）
+pub struct DoctorContext {
+    pub variables: Variables,
+    pub entries: Vec<Value>,
+}
+
+/// 判断开场白是否需要诊断（排除代码或极短内容）
+fn should_include_greeting(content: &str) -> bool {
+    content.len() > 20
+        && !content.trim().starts_with('<')
+        && !content.trim().starts_with('{')
+        && !content.trim().starts_with('[')
+}
+
+pub fn doctor_variables(card: &character_card::Model, global_prompt: &str) -> DoctorContext {
+    let card_data: Value = serde_json::from_str(&card.data).unwrap_or(serde_json::json!({}));
+    let v2_data = card_data.get("data").unwrap_or(&card_data);
+    let field = |key: &str| v2_data.get(key).and_then(|v| v.as_str()).unwrap_or("");
+
+    let first_mes = field("first_mes");
+    let alt_greeting = v2_data
+        .get("alternate_greetings")
+        .and_then(|v| v.as_array())
+        .and_then(|arr| arr.first())
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+
+    // 只提供启用的世界书条目
+    let entries: Vec<Value> = v2_data
+        .get("character_book")
+        .and_then(|cb| cb.get("entries"))
+        .and_then(|e| e.as_array())
+        .cloned()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|e| e.get("enabled").and_then(|v| v.as_bool()).unwrap_or(true))
+        .collect();
+
+    let worldbook_toc: Vec<String> = entries
+        .iter()
+        .filter_map(|e| e.get("comment").and_then(|c| c.as_str()))
+        .filter(|c| !c.is_empty())
+        .map(|c| format!("- {}", c))
+        .collect();
+    let worldbook_toc = if worldbook_toc.is_empty() {
+        "（无世界书条目）".to_string()
+    } else {
+        worldbook_toc.join("\n")
+    };
+
+    let first_mes_note = if should_include_greeting(first_mes) {
+        format!("- 首条消息：{}", first_mes)
+    } else {
+        "- 首条消息：（内容过短或为代码，跳过诊断）".to_string()
+    };
+    let alt_greeting_note = if should_include_greeting(alt_greeting) {
+        format!("- 其他开场白（第1个）：{}", alt_greeting)
+    } else {
+        "- 其他开场白（第1个）：（内容过短或为代码，跳过诊断）".to_string()
+    };
+
+    DoctorContext {
+        variables: variables([
+            ("global_prompt", global_prompt.to_string()),
+            ("name", card.name.clone()),
+            ("description", card.description.clone().unwrap_or_default()),
+            ("personality", field("personality").to_string()),
+            ("first_mes_note", first_mes_note),
+            ("alt_greeting_note", alt_greeting_note),
+            ("worldbook_toc", worldbook_toc),
+        ]),
+        entries,
+    }
+}
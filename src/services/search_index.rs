@@ -0,0 +1,330 @@
+//! 全文搜索
+//!
+//! 索引表 `search_index` 为 FTS5 + trigram 分词，角色卡、世界书、小剧场与图库由数据库触发器同步，
+//! 聊天记录正文在文件中，上传、修改时由这里写入。
+//!
+//! trigram 只能匹配不少于 3 个字符的词，更短的词（如两个字的中文词）退回 LIKE 过滤。
+
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseBackend, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, Statement, Value,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::entities::chat_history;
+
+/// 单条聊天记录写入索引的最大字符数
+const CHAT_INDEX_MAX_CHARS: usize = 2_000_000;
+/// 摘要中命中词前后保留的字符数
+const SNIPPET_CONTEXT_CHARS: usize = 40;
+/// 从数据库取出的正文片段：命中位置之前的字符数与片段总长度
+///
+/// 聊天记录正文可达数百万字符，只取命中附近的一段再做空白合并与高亮。
+const EXCERPT_LEAD_CHARS: usize = 200;
+const EXCERPT_CHARS: usize = 600;
+
+pub const KINDS: [&str; 5] = ["card", "world_info", "theater", "image", "chat"];
+
+/// 摘要片段，`highlight` 为命中词
+#[derive(Serialize)]
+pub struct SnippetSegment {
+    pub text: String,
+    pub highlight: bool,
+}
+
+#[derive(Serialize)]
+pub struct SearchHit {
+    /// card | world_info | theater | image | chat
+    pub kind: String,
+    pub id: Uuid,
+    /// 聊天记录所属的角色卡
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub card_id: Option<Uuid>,
+    pub title: String,
+    pub title_segments: Vec<SnippetSegment>,
+    pub snippet: Vec<SnippetSegment>,
+    /// 相关度，越大越相关
+    pub score: f64,
+}
+
+pub struct SearchOptions<'a> {
+    pub query: &'a str,
+    /// 为空表示全部类型
+    pub kinds: Vec<String>,
+    pub limit: u64,
+    pub offset: u64,
+}
+
+/// 执行搜索
+pub async fn search(
+    db: &DatabaseConnection,
+    options: &SearchOptions<'_>,
+) -> Result<Vec<SearchHit>, DbErr> {
+    let terms: Vec<String> = options
+        .query
+        .split_whitespace()
+        .map(|t| t.to_string())
+        .collect();
+    if terms.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let (long_terms, short_terms): (Vec<&String>, Vec<&String>) =
+        terms.iter().partition(|t| t.chars().count() >= 3);
+
+    let mut conditions = Vec::new();
+    // 前两个参数用于定位正文片段，见下方 excerpt
+    let mut values: Vec<Value> = vec![terms[0].clone().into(), terms[0].clone().into()];
+
+    let score = if long_terms.is_empty() {
+        "0.0"
+    } else {
+        let expr = long_terms
+            .iter()
+            .map(|t| format!("\"{}\"", t.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ");
+        conditions.push("search_index MATCH ?".to_string());
+        values.push(expr.into());
+        // 列权重：kind, ref_id, parent_id 不参与，标题命中权重更高
+        "-bm25(search_index, 0.0, 0.0, 0.0, 10.0, 1.0)"
+    };
+
+    for term in short_terms {
+        let pattern = format!("%{}%", escape_like(term));
+        conditions.push("(title LIKE ? ESCAPE '\\' OR body LIKE ? ESCAPE '\\')".to_string());
+        values.push(pattern.clone().into());
+        values.push(pattern.into());
+    }
+
+    let kinds: Vec<&String> = options
+        .kinds
+        .iter()
+        .filter(|k| KINDS.contains(&k.as_str()))
+        .collect();
+    if !kinds.is_empty() {
+        conditions.push(format!("kind IN ({})", vec!["?"; kinds.len()].join(", ")));
+        values.extend(kinds.iter().map(|k| Value::from(k.as_str())));
+    }
+
+    // 回收站中角色卡的聊天记录不返回
+    conditions.push(
+        "(kind != 'chat' OR parent_id IN (SELECT id FROM character_cards WHERE deleted_at IS NULL))"
+            .to_string(),
+    );
+
+    // 正文只取第一个词首次出现处附近的片段；trigram 不区分大小写，精确查找失败时再按小写查找
+    let excerpt = format!(
+        "substr(body, max(1, COALESCE(NULLIF(instr(body, ?), 0), NULLIF(instr(lower(body), lower(?)), 0), 1) - {}), {}) AS excerpt",
+        EXCERPT_LEAD_CHARS, EXCERPT_CHARS
+    );
+
+    let sql = format!(
+        "SELECT kind, ref_id, parent_id, title, {}, {} AS score FROM search_index WHERE {} ORDER BY score DESC, title LIMIT ? OFFSET ?",
+        excerpt,
+        score,
+        conditions.join(" AND ")
+    );
+    values.push(options.limit.into());
+    values.push(options.offset.into());
+
+    let rows = db
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            sql,
+            values,
+        ))
+        .await?;
+
+    let mut hits = Vec::with_capacity(rows.len());
+    for row in rows {
+        let title: String = row.try_get("", "title").unwrap_or_default();
+        let excerpt: String = row.try_get("", "excerpt").unwrap_or_default();
+        hits.push(SearchHit {
+            kind: row.try_get("", "kind")?,
+            id: row.try_get("", "ref_id")?,
+            card_id: row.try_get("", "parent_id").ok().flatten(),
+            title_segments: highlight(&title, &terms, None),
+            snippet: highlight(
+                &collapse_whitespace(&excerpt),
+                &terms,
+                Some(SNIPPET_CONTEXT_CHARS),
+            ),
+            title,
+            score: row.try_get("", "score").unwrap_or(0.0),
+        });
+    }
+    Ok(hits)
+}
+
+/// 写入（或替换）聊天记录的索引
+pub async fn index_chat(
+    db: &DatabaseConnection,
+    history: &chat_history::Model,
+    content: &[u8],
+) -> Result<(), DbErr> {
+    let body = chat_text(&history.format, content);
+
+    db.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "DELETE FROM search_index WHERE kind = 'chat' AND ref_id = ?",
+        [history.id.into()],
+    ))
+    .await?;
+    db.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "INSERT INTO search_index (kind, ref_id, parent_id, title, body) VALUES ('chat', ?, ?, ?, ?)",
+        [
+            history.id.into(),
+            history.card_id.into(),
+            history.display_name.clone().into(),
+            body.into(),
+        ],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// 为尚未建立索引的聊天记录补建索引（升级后首次启动）
+pub async fn backfill_chats(db: &DatabaseConnection) -> Result<usize, DbErr> {
+    let missing: Vec<Uuid> = db
+        .query_all(Statement::from_string(
+            DatabaseBackend::Sqlite,
+            "SELECT id FROM chat_histories WHERE id NOT IN (SELECT ref_id FROM search_index WHERE kind = 'chat')",
+        ))
+        .await?
+        .iter()
+        .filter_map(|row| row.try_get("", "id").ok())
+        .collect();
+    if missing.is_empty() {
+        return Ok(0);
+    }
+
+    let histories = chat_history::Entity::find()
+        .filter(chat_history::Column::Id.is_in(missing))
+        .all(db)
+        .await?;
+
+    let mut indexed = 0;
+    for history in histories {
+        let path = crate::utils::paths::get_data_path("cards")
+            .join(history.card_id.to_string())
+            .join(&history.file_name);
+        let Ok(content) = tokio::fs::read(&path).await else {
+            continue;
+        };
+        index_chat(db, &history, &content).await?;
+        indexed += 1;
+    }
+    Ok(indexed)
+}
+
+/// 启动时在后台补建聊天记录索引
+pub fn spawn_chat_backfill(db: DatabaseConnection) {
+    tokio::spawn(async move {
+        match backfill_chats(&db).await {
+            Ok(0) => {}
+            Ok(n) => tracing::info!("已为 {} 条聊天记录建立搜索索引", n),
+            Err(e) => tracing::warn!("聊天记录搜索索引补建失败: {}", e),
+        }
+    });
+}
+
+/// 聊天记录正文：jsonl 取每层的发言者与内容，txt 直接使用
+fn chat_text(format: &str, content: &[u8]) -> String {
+    let raw = String::from_utf8_lossy(content);
+    let text = if format == "jsonl" {
+        raw.lines()
+            .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+            .filter_map(|msg| {
+                let mes = msg.get("mes")?.as_str()?;
+                let name = msg.get("name").and_then(|n| n.as_str()).unwrap_or("");
+                Some(format!("{}: {}", name, mes))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    } else {
+        raw.into_owned()
+    };
+    text.chars().take(CHAT_INDEX_MAX_CHARS).collect()
+}
+
+/// 索引正文中空字段留下的连续换行合并为一个空格
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// 转义 LIKE 模式中的通配符，配合 `ESCAPE '\\'` 使用
+pub fn escape_like(term: &str) -> String {
+    term.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// 标出文本中的命中词（不区分大小写）；`context` 为 Some 时只截取第一个命中附近的片段
+fn highlight(text: &str, terms: &[String], context: Option<usize>) -> Vec<SnippetSegment> {
+    // 逐字符转小写，保持与原文的下标一一对应
+    let fold = |c: &char| c.to_lowercase().next().unwrap_or(*c);
+    let chars: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = chars.iter().map(fold).collect();
+    let needles: Vec<Vec<char>> = terms
+        .iter()
+        .map(|t| t.chars().collect::<Vec<_>>().iter().map(fold).collect())
+        .collect();
+
+    // 每个位置最长的命中长度
+    let match_at = |i: usize| {
+        needles
+            .iter()
+            .filter(|n| lower[i..].starts_with(n))
+            .map(|n| n.len())
+            .max()
+    };
+
+    let (start, end) = match context {
+        Some(ctx) => {
+            let first = (0..chars.len())
+                .find(|&i| match_at(i).is_some())
+                .unwrap_or(0);
+            (
+                first.saturating_sub(ctx),
+                (first + ctx * 2).min(chars.len()),
+            )
+        }
+        None => (0, chars.len()),
+    };
+
+    let mut segments: Vec<SnippetSegment> = Vec::new();
+    let mut push = |text: String, highlight: bool| {
+        if text.is_empty() {
+            return;
+        }
+        match segments.last_mut() {
+            Some(last) if last.highlight == highlight => last.text.push_str(&text),
+            _ => segments.push(SnippetSegment { text, highlight }),
+        }
+    };
+
+    if start > 0 {
+        push("…".to_string(), false);
+    }
+    let mut i = start;
+    while i < end {
+        match match_at(i) {
+            Some(len) => {
+                let stop = (i + len).min(chars.len());
+                push(chars[i..stop].iter().collect(), true);
+                i = stop;
+            }
+            None => {
+                push(chars[i].to_string(), false);
+                i += 1;
+            }
+        }
+    }
+    if end < chars.len() {
+        push("…".to_string(), false);
+    }
+    segments
+}
@@ -0,0 +1,315 @@
+//! 标签管理
+//!
+//! 全库重命名、合并、删除标签，以及别名规则。改写时与 `cards::update` 一致：
+//! 同时更新 `tags` 列与卡片 JSON 中的 `tags` / `data.tags`，并标记 `metadata_modified`。
+
+use std::collections::{HashMap, HashSet};
+
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseBackend, DatabaseConnection, DbErr,
+    EntityTrait, QueryFilter, QueryOrder, Set, Statement, TransactionTrait, Value as DbValue,
+};
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::event_bus::{self, AppEvent};
+use super::version_snapshot;
+use crate::entities::{character_card, tag_alias};
+use crate::utils::token::calculate_card_tokens;
+
+/// 全库重命名标签，返回受影响的卡片数
+pub async fn rename_tag(db: &DatabaseConnection, from: &str, to: &str) -> Result<u64, String> {
+    let from = non_empty(from)?;
+    let to = non_empty(to)?;
+    if from == to {
+        return Err("新旧标签名相同".to_string());
+    }
+    replace_tags(db, &[from.to_string()], Some(to))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 将多个标签合并为一个，返回受影响的卡片数
+pub async fn merge_tags(
+    db: &DatabaseConnection,
+    sources: &[String],
+    target: &str,
+) -> Result<u64, String> {
+    let target = non_empty(target)?;
+    let sources: Vec<String> = sources
+        .iter()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty() && s != target)
+        .collect();
+    if sources.is_empty() {
+        return Err("没有要合并的标签".to_string());
+    }
+    replace_tags(db, &sources, Some(target))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 从所有卡片中移除标签，返回受影响的卡片数
+pub async fn delete_tag(db: &DatabaseConnection, tag: &str) -> Result<u64, String> {
+    let tag = non_empty(tag)?;
+    replace_tags(db, &[tag.to_string()], None)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+fn non_empty(tag: &str) -> Result<&str, String> {
+    let tag = tag.trim();
+    if tag.is_empty() {
+        return Err("标签名不能为空".to_string());
+    }
+    Ok(tag)
+}
+
+/// 将 `sources` 中的标签替换为 `target`（None 表示删除），包括回收站中的卡片
+///
+/// 所有卡片的改写在同一事务中完成，中途失败时不留下部分改名的结果。
+async fn replace_tags(
+    db: &DatabaseConnection,
+    sources: &[String],
+    target: Option<&str>,
+) -> Result<u64, DbErr> {
+    let txn = db.begin().await?;
+    let placeholders = vec!["?"; sources.len()].join(", ");
+    let card_ids: Vec<Uuid> = txn
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!(
+                "SELECT DISTINCT card_tags.card_id AS id FROM card_tags JOIN tags ON tags.id = card_tags.tag_id WHERE tags.name IN ({})",
+                placeholders
+            ),
+            sources.iter().map(|s| DbValue::from(s.as_str())),
+        ))
+        .await?
+        .iter()
+        .filter_map(|row| row.try_get("", "id").ok())
+        .collect();
+
+    let cards = if card_ids.is_empty() {
+        Vec::new()
+    } else {
+        character_card::Entity::find()
+            .filter(character_card::Column::Id.is_in(card_ids))
+            .all(&txn)
+            .await?
+    };
+
+    // 标签字典不区分大小写，仅大小写不同的写法视为同一标签
+    let sources: Vec<String> = sources.iter().map(|s| s.to_lowercase()).collect();
+    let mut changed = Vec::new();
+    for card in cards {
+        let old_tags: Vec<String> = serde_json::from_str(&card.tags).unwrap_or_default();
+        let new_tags = dedup(old_tags.iter().filter_map(|t| {
+            if sources.contains(&t.trim().to_lowercase()) {
+                target.map(|t| t.to_string())
+            } else {
+                Some(t.clone())
+            }
+        }));
+        if new_tags == old_tags {
+            continue;
+        }
+        let id = card.id;
+        write_card_tags(&txn, card, &new_tags).await?;
+        changed.push(id);
+    }
+
+    // 目标与原标签仅大小写不同时，触发器沿用字典中的旧写法，需要显式改名
+    if let Some(target) = target.filter(|t| sources.contains(&t.to_lowercase())) {
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            "UPDATE tags SET name = ? WHERE name = ? COLLATE NOCASE",
+            [DbValue::from(target), DbValue::from(target)],
+        ))
+        .await?;
+    }
+    txn.commit().await?;
+
+    let affected = changed.len() as u64;
+    if !changed.is_empty() {
+        event_bus::publish(AppEvent::CardsUpdated { ids: changed });
+    }
+    Ok(affected)
+}
+
+/// 写入卡片标签：`tags` 列 + JSON 根级与 data 下的 tags
+async fn write_card_tags<C: ConnectionTrait>(
+    db: &C,
+    card: character_card::Model,
+    tags: &[String],
+) -> Result<(), DbErr> {
+    let mut json: Value = serde_json::from_str(&card.data).unwrap_or(Value::Null);
+    if let Some(obj) = json.get_mut("data").and_then(|d| d.as_object_mut()) {
+        obj.insert("tags".to_string(), serde_json::json!(tags));
+    }
+    if let Some(obj) = json.as_object_mut() {
+        obj.insert("tags".to_string(), serde_json::json!(tags));
+    }
+    let data = serde_json::to_string_pretty(&json).unwrap_or_else(|_| card.data.clone());
+
+    // 自动快照失败不影响保存
+    if data != card.data {
+        if let Err(e) = version_snapshot::auto_snapshot(db, &card).await {
+            tracing::error!("Failed to create auto snapshot for card {}: {}", card.id, e);
+        }
+    }
+
+    let counts = calculate_card_tokens(&json);
+    let mut active: character_card::ActiveModel = card.into();
+    active.tags = Set(serde_json::to_string_pretty(tags).unwrap_or_else(|_| "[]".to_string()));
+    active.data = Set(data);
+    active.metadata_modified = Set(true);
+    active.token_count_total = Set(Some(counts.total));
+    active.token_count_spec = Set(Some(counts.spec));
+    active.token_count_wb = Set(Some(counts.wb));
+    active.token_count_other = Set(Some(counts.other));
+    active.updated_at = Set(chrono::Utc::now().naive_utc());
+    active.update(db).await?;
+    Ok(())
+}
+
+/// 去重并保持原顺序
+fn dedup(tags: impl Iterator<Item = String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    tags.filter(|t| seen.insert(t.clone())).collect()
+}
+
+// ==================== 别名规则 ====================
+
+pub async fn list_aliases(db: &DatabaseConnection) -> Result<Vec<tag_alias::Model>, DbErr> {
+    tag_alias::Entity::find()
+        .order_by_asc(tag_alias::Column::Target)
+        .order_by_asc(tag_alias::Column::Alias)
+        .all(db)
+        .await
+}
+
+/// 新增或修改别名规则；以 `alias` 为目标的已有规则会改指向新目标，避免出现链式别名
+pub async fn set_alias(
+    db: &DatabaseConnection,
+    alias: &str,
+    target: &str,
+) -> Result<tag_alias::Model, String> {
+    let alias = non_empty(alias)?;
+    let target = non_empty(target)?;
+    if alias == target {
+        return Err("别名与目标标签相同".to_string());
+    }
+    // 仅大小写不同的规则用于统一写法，不视为链式别名
+    let case_only = alias.to_lowercase() == target.to_lowercase();
+    if !case_only
+        && tag_alias::Entity::find_by_id(target.to_string())
+            .one(db)
+            .await
+            .map_err(|e| e.to_string())?
+            .is_some()
+    {
+        return Err(format!("目标标签 {} 本身是别名", target));
+    }
+
+    // 改指向、删除旧规则与写入新规则在同一事务中完成
+    let txn = db.begin().await.map_err(|e| e.to_string())?;
+    tag_alias::Entity::update_many()
+        .col_expr(
+            tag_alias::Column::Target,
+            sea_orm::sea_query::Expr::value(target),
+        )
+        .filter(tag_alias::Column::Target.eq(alias))
+        .exec(&txn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    tag_alias::Entity::delete_by_id(alias.to_string())
+        .exec(&txn)
+        .await
+        .map_err(|e| e.to_string())?;
+    let saved = tag_alias::ActiveModel {
+        alias: Set(alias.to_string()),
+        target: Set(target.to_string()),
+        created_at: Set(chrono::Utc::now().naive_utc()),
+    }
+    .insert(&txn)
+    .await
+    .map_err(|e| e.to_string())?;
+    txn.commit().await.map_err(|e| e.to_string())?;
+    Ok(saved)
+}
+
+/// 删除别名规则，返回是否存在
+pub async fn delete_alias(db: &DatabaseConnection, alias: &str) -> Result<bool, DbErr> {
+    let res = tag_alias::Entity::delete_by_id(alias.trim().to_string())
+        .exec(db)
+        .await?;
+    Ok(res.rows_affected > 0)
+}
+
+/// 别名表：小写别名 -> 目标
+async fn load_aliases(db: &DatabaseConnection) -> Result<HashMap<String, String>, DbErr> {
+    Ok(tag_alias::Entity::find()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|a| (a.alias.to_lowercase(), a.target))
+        .collect())
+}
+
+fn resolve(aliases: &HashMap<String, String>, tags: Vec<String>) -> Vec<String> {
+    dedup(
+        tags.into_iter()
+            .map(|t| aliases.get(&t.trim().to_lowercase()).cloned().unwrap_or(t)),
+    )
+}
+
+/// 按别名规则替换标签
+pub async fn apply_aliases(
+    db: &DatabaseConnection,
+    tags: Vec<String>,
+) -> Result<Vec<String>, DbErr> {
+    let aliases = load_aliases(db).await?;
+    if aliases.is_empty() {
+        return Ok(tags);
+    }
+    Ok(resolve(&aliases, tags))
+}
+
+/// 按别名规则替换卡片 JSON 中已有的 `tags` / `data.tags`（数组或逗号分隔的字符串），用于导入
+pub async fn apply_aliases_to_json(db: &DatabaseConnection, json: &mut Value) -> Result<(), DbErr> {
+    let aliases = load_aliases(db).await?;
+    if aliases.is_empty() {
+        return Ok(());
+    }
+
+    let rewrite = |value: &mut Value| match value {
+        Value::Array(items) => {
+            let tags: Vec<String> = items
+                .iter()
+                .filter_map(|t| t.as_str().map(|s| s.to_string()))
+                .collect();
+            // 含非字符串元素时保持原样
+            if tags.len() == items.len() {
+                *value = serde_json::json!(resolve(&aliases, tags));
+            }
+        }
+        Value::String(s) => {
+            let tags = s
+                .split(',')
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect();
+            *s = resolve(&aliases, tags).join(", ");
+        }
+        _ => {}
+    };
+
+    if let Some(tags) = json.get_mut("tags") {
+        rewrite(tags);
+    }
+    if let Some(tags) = json.get_mut("data").and_then(|d| d.get_mut("tags")) {
+        rewrite(tags);
+    }
+    Ok(())
+}
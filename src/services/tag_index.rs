@@ -0,0 +1,54 @@
+//! 标签索引
+//!
+//! `tags` / `card_tags` / `image_tags` 由数据库触发器根据 JSON 形式的 tags 列同步，
+//! 这里提供基于索引的统计与筛选，无需逐条解析卡片。回收站中的角色卡不计入统计。
+
+use sea_orm::sea_query::{Expr, SimpleExpr};
+use sea_orm::{ConnectionTrait, DatabaseBackend, DatabaseConnection, DbErr, Statement};
+
+use super::search_index::escape_like;
+
+/// 角色卡标签及使用次数，按次数降序
+pub async fn card_tag_counts(db: &DatabaseConnection) -> Result<Vec<(String, u32)>, DbErr> {
+    let rows = db
+        .query_all(Statement::from_string(
+            DatabaseBackend::Sqlite,
+            "SELECT tags.name AS name, COUNT(*) AS count FROM card_tags
+             JOIN tags ON tags.id = card_tags.tag_id
+             JOIN character_cards ON character_cards.id = card_tags.card_id
+             WHERE character_cards.deleted_at IS NULL
+             GROUP BY tags.id ORDER BY count DESC, tags.name",
+        ))
+        .await?;
+    rows.iter()
+        .map(|row| {
+            let count: i64 = row.try_get("", "count")?;
+            Ok((row.try_get("", "name")?, count as u32))
+        })
+        .collect()
+}
+
+/// 角色卡使用中的全部标签名
+pub async fn card_tag_names(db: &DatabaseConnection) -> Result<Vec<String>, DbErr> {
+    Ok(card_tag_counts(db)
+        .await?
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect())
+}
+
+/// 角色卡带有指定标签（不区分 ASCII 大小写），用于 `character_cards` 查询
+pub fn card_has_tag(tag: &str) -> SimpleExpr {
+    Expr::cust_with_values(
+        "EXISTS (SELECT 1 FROM card_tags JOIN tags ON tags.id = card_tags.tag_id WHERE card_tags.card_id = character_cards.id AND tags.name = ? COLLATE NOCASE)",
+        [tag.trim().to_string()],
+    )
+}
+
+/// 图片带有名称包含 `keyword` 的标签（不区分 ASCII 大小写），用于 `image` 查询
+pub fn image_tag_contains(keyword: &str) -> SimpleExpr {
+    Expr::cust_with_values(
+        "EXISTS (SELECT 1 FROM image_tags JOIN tags ON tags.id = image_tags.tag_id WHERE image_tags.image_id = image.id AND tags.name LIKE ? ESCAPE '\\')",
+        [format!("%{}%", escape_like(keyword.trim()))],
+    )
+}
@@ -0,0 +1,234 @@
+//! 自动版本快照与保留策略
+//!
+//! 开启 `version_auto_snapshot` 后，每次修改卡片正文前先把旧的 `data` 存为自动快照；
+//! 合并窗口内的连续修改只保留第一份（即窗口开始前的状态）。
+//! 后台任务定期清理自动快照：保留最近 N 份，更早的每天保留一份、保留 M 天，
+//! 手动创建的命名版本不受影响。
+//!
+//! 恢复、合并等整体覆盖正文的操作通过 `snapshot_current` 无条件保存当前状态，
+//! 不受 `version_auto_snapshot` 开关影响。
+
+use chrono::{Duration, NaiveDate, Utc};
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use super::version_store::{self, NewVersion};
+use crate::entities::{character_card, character_versions, setting};
+
+/// 清理任务的执行间隔
+const PRUNE_INTERVAL_SECS: u64 = 60 * 60;
+/// 合并窗口上限（一周）
+const MAX_WINDOW_MINUTES: i64 = 7 * 24 * 60;
+/// 保留份数上限
+const MAX_KEEP_LAST: i64 = 10_000;
+/// 按天保留的天数上限（十年）
+const MAX_KEEP_DAILY_DAYS: i64 = 3650;
+
+/// 数值型策略设置的取值范围，写入设置与读取策略时都截断到该范围
+pub fn setting_range(key: &str) -> Option<(i64, i64)> {
+    match key {
+        "version_snapshot_window_minutes" => Some((0, MAX_WINDOW_MINUTES)),
+        "version_keep_last" => Some((0, MAX_KEEP_LAST)),
+        "version_keep_daily_days" => Some((0, MAX_KEEP_DAILY_DAYS)),
+        _ => None,
+    }
+}
+
+/// 解析并截断数值设置，无法解析时返回 None
+pub fn clamp_setting(key: &str, value: &str) -> Option<i64> {
+    let (min, max) = setting_range(key)?;
+    value.trim().parse::<i64>().ok().map(|v| v.clamp(min, max))
+}
+
+/// 自动快照策略（来自系统设置）
+#[derive(Debug, Clone)]
+pub struct SnapshotPolicy {
+    pub enabled: bool,
+    pub window_minutes: i64,
+    pub keep_last: usize,
+    pub keep_daily_days: i64,
+}
+
+impl Default for SnapshotPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window_minutes: 10,
+            keep_last: 20,
+            keep_daily_days: 30,
+        }
+    }
+}
+
+/// 从设置表读取策略
+pub async fn load_policy<C: ConnectionTrait>(db: &C) -> Result<SnapshotPolicy, DbErr> {
+    let rows = setting::Entity::find()
+        .filter(setting::Column::Key.starts_with("version_"))
+        .all(db)
+        .await?;
+
+    let mut policy = SnapshotPolicy::default();
+    for row in rows {
+        let key = row.key.as_str();
+        match key {
+            "version_auto_snapshot" => policy.enabled = row.value == "true",
+            "version_snapshot_window_minutes" => {
+                policy.window_minutes =
+                    clamp_setting(key, &row.value).unwrap_or(policy.window_minutes)
+            }
+            "version_keep_last" => {
+                policy.keep_last = clamp_setting(key, &row.value)
+                    .map(|v| v as usize)
+                    .unwrap_or(policy.keep_last)
+            }
+            "version_keep_daily_days" => {
+                policy.keep_daily_days =
+                    clamp_setting(key, &row.value).unwrap_or(policy.keep_daily_days)
+            }
+            _ => {}
+        }
+    }
+    Ok(policy)
+}
+
+/// 修改卡片前保存自动快照，返回是否新建了快照
+///
+/// 最近一个版本与当前数据相同，或最近的自动快照仍在合并窗口内时跳过。
+pub async fn auto_snapshot<C: ConnectionTrait>(
+    db: &C,
+    card: &character_card::Model,
+) -> Result<bool, DbErr> {
+    let policy = load_policy(db).await?;
+    if !policy.enabled {
+        return Ok(false);
+    }
+
+    let now = Utc::now().naive_utc();
+    let latest = character_versions::Entity::find()
+        .filter(character_versions::Column::CharacterId.eq(card.id))
+        .order_by_desc(character_versions::Column::CreatedAt)
+        .one(db)
+        .await?;
+
+    if let Some(latest) = latest {
+        let window = Duration::try_minutes(policy.window_minutes).unwrap_or_default();
+        if latest.is_auto && now - latest.created_at < window {
+            return Ok(false);
+        }
+    }
+
+    snapshot_current(db, card, "自动快照").await
+}
+
+/// 覆盖卡片前无条件保存当前状态，使恢复、合并等操作本身也可撤回
+///
+/// 最近一个版本与当前数据相同时跳过，返回是否新建了快照。
+pub async fn snapshot_current<C: ConnectionTrait>(
+    db: &C,
+    card: &character_card::Model,
+    note: &str,
+) -> Result<bool, DbErr> {
+    let latest = character_versions::Entity::find()
+        .filter(character_versions::Column::CharacterId.eq(card.id))
+        .order_by_desc(character_versions::Column::CreatedAt)
+        .one(db)
+        .await?;
+    if let Some(latest) = latest {
+        if version_store::load_data(db, &latest).await? == card.data {
+            return Ok(false);
+        }
+    }
+
+    // 自动快照使用时间戳编号，不占用 V1、V2… 的手动版本序号
+    let version = NewVersion {
+        character_id: card.id,
+        version_number: format!("AUTO-{}", Utc::now().naive_utc().format("%Y%m%d-%H%M%S")),
+        note: Some(note.to_string()),
+        data: card.data.clone(),
+        is_auto: true,
+    };
+    version_store::insert_version(db, version).await?;
+    Ok(true)
+}
+
+/// 按保留策略清理自动快照，返回删除的数量
+pub async fn prune_versions(
+    db: &DatabaseConnection,
+    policy: &SnapshotPolicy,
+) -> Result<u64, DbErr> {
+    let card_ids: Vec<Uuid> = character_versions::Entity::find()
+        .select_only()
+        .column(character_versions::Column::CharacterId)
+        .filter(character_versions::Column::IsAuto.eq(true))
+        .distinct()
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    // 超出日期范围时视为不按天保留
+    let daily_cutoff = Duration::try_days(policy.keep_daily_days.max(0))
+        .and_then(|d| Utc::now().date_naive().checked_sub_signed(d))
+        .unwrap_or(NaiveDate::MIN);
+    let mut deleted = 0;
+
+    for card_id in card_ids {
+        let snapshots: Vec<(Uuid, chrono::NaiveDateTime)> = character_versions::Entity::find()
+            .select_only()
+            .columns([
+                character_versions::Column::Id,
+                character_versions::Column::CreatedAt,
+            ])
+            .filter(character_versions::Column::CharacterId.eq(card_id))
+            .filter(character_versions::Column::IsAuto.eq(true))
+            .order_by_desc(character_versions::Column::CreatedAt)
+            .into_tuple()
+            .all(db)
+            .await?;
+
+        // 最近 N 份之后，每天只保留最新的一份，超过 M 天的全部删除
+        let mut kept_days: HashSet<NaiveDate> = HashSet::new();
+        let expired: Vec<Uuid> = snapshots
+            .into_iter()
+            .skip(policy.keep_last)
+            .filter(|(_, created_at)| {
+                let day = created_at.date();
+                !(day > daily_cutoff && kept_days.insert(day))
+            })
+            .map(|(id, _)| id)
+            .collect();
+
+        if expired.is_empty() {
+            continue;
+        }
+        deleted += version_store::delete_versions(db, &expired).await?;
+    }
+
+    Ok(deleted)
+}
+
+/// 启动后台清理任务
+pub fn spawn_retention_task(db: DatabaseConnection) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(PRUNE_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            let policy = match load_policy(&db).await {
+                Ok(p) => p,
+                Err(e) => {
+                    tracing::warn!("读取版本保留策略失败: {}", e);
+                    continue;
+                }
+            };
+            match prune_versions(&db, &policy).await {
+                Ok(0) => {}
+                Ok(n) => tracing::info!("已清理 {} 个过期的自动快照", n),
+                Err(e) => tracing::warn!("清理自动快照失败: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,139 @@
+//! 角色卡版本存储
+//!
+//! 版本不再逐条保存完整 JSON：每张卡的版本按时间组成链，定期保存压缩的完整关键帧，
+//! 其余版本只保存相对上一版本的行级补丁（同样压缩）。读取时沿链找到关键帧后依次应用补丁，
+//! 对版本列表、恢复、差异与合并接口透明。编码格式见 `migration::version_codec`。
+
+use migration::version_codec::{
+    self, apply_patch, compress, decompress, STORAGE_DELTA, STORAGE_FULL,
+};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    Set,
+};
+use uuid::Uuid;
+
+use crate::entities::character_versions;
+
+/// 待保存的版本
+pub struct NewVersion {
+    pub character_id: Uuid,
+    pub version_number: String,
+    pub note: Option<String>,
+    /// 完整的卡片 JSON
+    pub data: String,
+    pub is_auto: bool,
+}
+
+/// 保存版本，返回的模型中 `data` 为完整 JSON
+pub async fn insert_version<C: ConnectionTrait>(
+    db: &C,
+    version: NewVersion,
+) -> Result<character_versions::Model, DbErr> {
+    let latest = character_versions::Entity::find()
+        .filter(character_versions::Column::CharacterId.eq(version.character_id))
+        .order_by_desc(character_versions::Column::CreatedAt)
+        .one(db)
+        .await?;
+
+    let base = match latest {
+        Some(latest) if version_codec::can_extend(latest.depth) => {
+            let text = load_data(db, &latest).await?;
+            Some((latest.id, latest.depth, text))
+        }
+        _ => None,
+    };
+    let encoded = version_codec::encode(
+        &version.data,
+        base.as_ref()
+            .map(|(id, depth, t)| (*id, *depth, t.as_str())),
+    );
+
+    let model = character_versions::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        character_id: Set(version.character_id),
+        version_number: Set(version.version_number),
+        note: Set(version.note),
+        data: Set(String::new()),
+        created_at: Set(chrono::Utc::now().naive_utc()),
+        is_auto: Set(version.is_auto),
+        storage: Set(encoded.storage.to_string()),
+        base_id: Set(encoded.base_id),
+        payload: Set(Some(encoded.payload)),
+        depth: Set(encoded.depth),
+    };
+    let mut saved = model.insert(db).await?;
+    saved.data = version.data;
+    Ok(saved)
+}
+
+/// 按 ID 读取版本，`data` 填充为完整 JSON
+pub async fn find_version<C: ConnectionTrait>(
+    db: &C,
+    id: Uuid,
+) -> Result<Option<character_versions::Model>, DbErr> {
+    let Some(mut version) = character_versions::Entity::find_by_id(id).one(db).await? else {
+        return Ok(None);
+    };
+    version.data = load_data(db, &version).await?;
+    Ok(Some(version))
+}
+
+/// 还原版本的完整 JSON
+pub async fn load_data<C: ConnectionTrait>(
+    db: &C,
+    version: &character_versions::Model,
+) -> Result<String, DbErr> {
+    // 沿 base_id 回溯到关键帧，记录途经的补丁
+    let mut patches = Vec::new();
+    let mut current = version.clone();
+    let mut text = loop {
+        match current.storage.as_str() {
+            STORAGE_DELTA => {
+                let base_id = current
+                    .base_id
+                    .ok_or_else(|| DbErr::Custom(format!("版本 {} 缺少基准版本", current.id)))?;
+                patches.push(decompress(current.payload.as_deref())?);
+                current = character_versions::Entity::find_by_id(base_id)
+                    .one(db)
+                    .await?
+                    .ok_or_else(|| DbErr::Custom(format!("基准版本 {} 不存在", base_id)))?;
+            }
+            STORAGE_FULL => break decompress(current.payload.as_deref())?,
+            _ => break current.data,
+        }
+    };
+
+    for patch in patches.iter().rev() {
+        text = apply_patch(&text, patch)?;
+    }
+    Ok(text)
+}
+
+/// 删除版本；以被删版本为基准的补丁先改写为关键帧
+pub async fn delete_versions<C: ConnectionTrait>(db: &C, ids: &[Uuid]) -> Result<u64, DbErr> {
+    if ids.is_empty() {
+        return Ok(0);
+    }
+
+    let dependents = character_versions::Entity::find()
+        .filter(character_versions::Column::BaseId.is_in(ids.to_vec()))
+        .filter(character_versions::Column::Id.is_not_in(ids.to_vec()))
+        .all(db)
+        .await?;
+    for dependent in dependents {
+        let text = load_data(db, &dependent).await?;
+        let mut active: character_versions::ActiveModel = dependent.into();
+        active.storage = Set(STORAGE_FULL.to_string());
+        active.base_id = Set(None);
+        active.payload = Set(Some(compress(&text)));
+        active.depth = Set(0);
+        active.update(db).await?;
+    }
+
+    let result = character_versions::Entity::delete_many()
+        .filter(character_versions::Column::Id.is_in(ids.to_vec()))
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected)
+}
@@ -0,0 +1,54 @@
+//! API 密钥加密
+//!
+//! 渠道的 API 密钥以 AES-256-GCM 加密后存入数据库，密钥由数据目录下的
+//! `.api_key_secret` 经 HKDF-SHA256 派生。存储格式为 `enc:v1:` + base64(nonce || 密文)，
+//! 没有该前缀的值视为旧版明文。导出备份时可改用口令（PBKDF2-SHA256）派生的密钥重新加密。
+//!
+//! 密钥文件处理、派生与加解密与迁移共用 [`migration::api_key_cipher`]。
+
+use aes_gcm::aead::OsRng;
+use once_cell::sync::OnceCell;
+use rand::RngCore;
+use sha2::Sha256;
+
+pub use migration::api_key_cipher::{CipherError as CryptoError, SecretKey, ENCRYPTED_PREFIX};
+
+/// 口令派生密钥的迭代次数
+pub const PASSPHRASE_ITERATIONS: u32 = 210_000;
+
+static LOCAL_KEY: OnceCell<SecretKey> = OnceCell::new();
+
+/// 本机密钥，进程内只读取一次密钥文件
+pub fn local_key() -> Result<&'static SecretKey, CryptoError> {
+    LOCAL_KEY
+        .get_or_try_init(|| SecretKey::from_secret(&crate::utils::secret::get_api_key_secret()?))
+}
+
+/// 启动时加载本机密钥，密钥文件无法读取或创建时应中止启动
+pub fn init_local_key() -> Result<(), CryptoError> {
+    local_key().map(|_| ())
+}
+
+/// 由用户口令派生
+pub fn passphrase_key(passphrase: &str, salt: &[u8], iterations: u32) -> SecretKey {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, iterations, &mut key);
+    SecretKey::from_bytes(key)
+}
+
+/// 随机盐
+pub fn random_salt() -> [u8; 16] {
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    salt
+}
+
+/// 加密 API 密钥用于存储；空密钥（如本地 Ollama）原样保存
+pub fn encrypt_api_key(plain: &str) -> Result<String, CryptoError> {
+    local_key()?.encrypt_stored(plain)
+}
+
+/// 解密存储的 API 密钥，兼容未加密的旧值
+pub fn decrypt_api_key(stored: &str) -> Result<String, CryptoError> {
+    local_key()?.decrypt_stored(stored)
+}
@@ -0,0 +1,82 @@
+//! ETag / If-Match 乐观并发控制
+//!
+//! ETag 由 `updated_at` 生成。GET 返回 `ETag` 头；修改请求携带 `If-Match` 且与当前值不符时
+//! 返回 412，响应体为服务端当前数据，前端据此提示冲突。不带 `If-Match` 的请求照常处理。
+//! 写入时用 `update_if_unchanged` 再核对一次 `updated_at`，避免检查与写入之间的并发修改被覆盖。
+
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::NaiveDateTime;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, IntoActiveModel,
+    QueryFilter,
+};
+
+/// 由更新时间生成强 ETag
+pub fn from_updated_at(updated_at: &NaiveDateTime) -> String {
+    let nanos = updated_at
+        .and_utc()
+        .timestamp_nanos_opt()
+        .unwrap_or_else(|| updated_at.and_utc().timestamp_micros() * 1000);
+    format!("\"{:x}\"", nanos)
+}
+
+/// `If-Match` 缺失、为 `*` 或包含当前 ETag 时返回 true（弱比较，忽略 `W/` 前缀）
+pub fn if_match_satisfied(headers: &HeaderMap, current: &str) -> bool {
+    let values: Vec<&str> = headers
+        .get_all(header::IF_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+        .collect();
+    if values.is_empty() {
+        return true;
+    }
+    let current = current.trim_start_matches("W/");
+    values
+        .iter()
+        .any(|v| *v == "*" || v.trim_start_matches("W/") == current)
+}
+
+/// 为响应附加 ETag 头
+pub fn with_etag(etag: &str, body: impl IntoResponse) -> Response {
+    let mut response = body.into_response();
+    if let Ok(value) = HeaderValue::from_str(etag) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    response
+}
+
+/// 412 响应，`current` 为服务端当前数据
+pub fn precondition_failed(etag: &str, current: impl IntoResponse) -> Response {
+    with_etag(etag, (StatusCode::PRECONDITION_FAILED, current))
+}
+
+/// 条件写入：`UPDATE … WHERE id = ? AND updated_at = ?`，`read_at` 为读取时的更新时间
+///
+/// 行已被其他请求修改（影响行数为 0）时返回 `Ok(None)`，调用方应返回 412。
+pub async fn update_if_unchanged<A, C>(
+    conn: &C,
+    active: A,
+    updated_at: <A::Entity as EntityTrait>::Column,
+    read_at: NaiveDateTime,
+) -> Result<Option<<A::Entity as EntityTrait>::Model>, DbErr>
+where
+    A: ActiveModelTrait,
+    <A::Entity as EntityTrait>::Model: IntoActiveModel<A>,
+    C: ConnectionTrait,
+{
+    match <A::Entity as EntityTrait>::update(active)
+        .filter(updated_at.eq(read_at))
+        .exec(conn)
+        .await
+    {
+        Ok(model) => Ok(Some(model)),
+        Err(DbErr::RecordNotUpdated) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
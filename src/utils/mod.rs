@@ -1,7 +1,9 @@
 //! 工具模块入口
 
 pub mod auth_middleware;
+pub mod crypto;
 pub mod error;
+pub mod etag;
 pub mod hash;
 pub mod mode_detect;
 pub mod paths;
@@ -4,13 +4,38 @@ use std::fs;
 
 use tracing::info;
 
+use migration::api_key_cipher::{load_or_create_secret, CipherError};
+
+/// JWT 密钥文件
+pub const JWT_SECRET_FILE: &str = ".jwt_secret";
+/// API 密钥加密用的本地密钥文件
+pub use migration::api_key_cipher::SECRET_FILE as API_KEY_SECRET_FILE;
+
 /// 获取 JWT Secret
 /// 逻辑：
 /// 1. 检查数据目录下是否存在 .jwt_secret 文件
 /// 2. 如果存在，直接读取
 /// 3. 如果不存在，生成一个随机 32 位字符串，写入文件并返回
 pub fn get_jwt_secret() -> String {
-    let secret_path = crate::utils::paths::get_data_path(".jwt_secret");
+    load_or_generate(JWT_SECRET_FILE, "JWT Secret")
+}
+
+/// 获取 API 密钥加密用的本地密钥
+///
+/// 该文件丢失后，数据库中已加密的 API 密钥将无法解密，因此与 JWT Secret 不同：
+/// 读取或写入失败直接返回错误，已有文件绝不覆盖
+pub fn get_api_key_secret() -> Result<String, CipherError> {
+    let secret_path = crate::utils::paths::get_data_path(API_KEY_SECRET_FILE);
+    let existed = secret_path.exists();
+    let secret = load_or_create_secret(&secret_path)?;
+    if !existed {
+        info!("已生成新的 API 密钥加密密钥并保存至 {:?}", secret_path);
+    }
+    Ok(secret)
+}
+
+fn load_or_generate(file_name: &str, label: &str) -> String {
+    let secret_path = crate::utils::paths::get_data_path(file_name);
 
     if secret_path.exists() {
         match fs::read_to_string(&secret_path) {
@@ -21,7 +46,7 @@ pub fn get_jwt_secret() -> String {
                 }
             }
             Err(e) => {
-                tracing::warn!("无法读取 JWT Secret 文件: {}, 将重新生成", e);
+                tracing::warn!("无法读取 {} 文件: {}, 将重新生成", label, e);
             }
         }
     }
@@ -34,9 +59,9 @@ pub fn get_jwt_secret() -> String {
         .collect();
 
     if let Err(e) = fs::write(&secret_path, &secret) {
-        tracing::error!("无法写入 JWT Secret 文件: {}", e);
+        tracing::error!("无法写入 {} 文件: {}", label, e);
     } else {
-        info!("已生成新的 JWT Secret 并保存至 {:?}", secret_path);
+        info!("已生成新的 {} 并保存至 {:?}", label, secret_path);
     }
 
     secret
@@ -192,3 +192,17 @@ pub fn calculate_card_tokens(json: &Value) -> TokenCounts {
 
     counts
 }
+
+/// 计算单段文本的 token 数
+pub fn count_tokens(text: &str) -> i32 {
+    if text.is_empty() {
+        return 0;
+    }
+    match BPE.as_ref() {
+        Ok(bpe) => bpe.encode_with_special_tokens(text).len() as i32,
+        Err(e) => {
+            error!("Tokenizer initialization failed: {}", e);
+            0
+        }
+    }
+}